@@ -19,7 +19,11 @@ use bytes::{
     ByteOrder,
     LittleEndian,
 };
-use prost::Message;
+use prost::{
+    JsonMessage,
+    Message,
+    json,
+};
 
 use test_all_types::protobuf_test_messages::proto3::TestAllTypes;
 use test_all_types::{
@@ -66,24 +70,57 @@ fn main() {
 }
 
 fn handle_request(request: ConformanceRequest) -> conformance_response::Result {
-    match request.requested_output_format() {
-        Some(WireFormat::Json) => {
-            return conformance_response::Result::Skipped("JSON output is not supported".to_string());
-        },
-        None => {
+    let output_format = match request.requested_output_format() {
+        Some(WireFormat::Unspecified) | None => {
             return conformance_response::Result::ParseError("unrecognized requested output format".to_string());
         },
-        _ => (),
+        Some(output_format) => output_format,
     };
 
-    let buf = match request.payload {
+    let all_types = match request.payload {
         None => return conformance_response::Result::ParseError("no payload".to_string()),
-        Some(conformance_request::Payload::JsonPayload(_)) =>
-            return conformance_response::Result::Skipped("JSON input is not supported".to_string()),
-        Some(conformance_request::Payload::ProtobufPayload(buf)) => buf,
+        Some(conformance_request::Payload::JsonPayload(json)) => {
+            if json_has_any(&json) {
+                return conformance_response::Result::Skipped("google.protobuf.Any is not supported".to_string());
+            }
+            match TestAllTypes::decode_json(&json) {
+                Ok(all_types) => all_types,
+                Err(error) => return conformance_response::Result::ParseError(error.to_string()),
+            }
+        },
+        Some(conformance_request::Payload::ProtobufPayload(buf)) => {
+            if output_format == WireFormat::Protobuf {
+                return roundtrip_protobuf(&buf);
+            }
+            match TestAllTypes::decode(&mut Buf::take(Cursor::new(&buf), buf.len())) {
+                Ok(all_types) => all_types,
+                Err(error) => return conformance_response::Result::ParseError(error.to_string()),
+            }
+        },
     };
 
-    match roundtrip::<TestAllTypes>(&buf) {
+    match output_format {
+        WireFormat::Json => {
+            if has_any(&all_types) {
+                return conformance_response::Result::Skipped("google.protobuf.Any is not supported".to_string());
+            }
+            match all_types.encode_json() {
+                Ok(json) => conformance_response::Result::JsonPayload(json),
+                Err(error) => conformance_response::Result::SerializeError(error.to_string()),
+            }
+        },
+        _ => {
+            let mut buf = Vec::new();
+            match all_types.encode(&mut buf) {
+                Ok(()) => conformance_response::Result::ProtobufPayload(buf),
+                Err(error) => conformance_response::Result::SerializeError(error.to_string()),
+            }
+        },
+    }
+}
+
+fn roundtrip_protobuf(buf: &[u8]) -> conformance_response::Result {
+    match roundtrip::<TestAllTypes>(buf) {
         RoundtripResult::Ok(buf) => {
             conformance_response::Result::ProtobufPayload(buf)
         },
@@ -95,3 +132,27 @@ fn handle_request(request: ConformanceRequest) -> conformance_response::Result {
         },
     }
 }
+
+/// Returns `true` if the message holds a `google.protobuf.Any`, which has no JSON mapping in
+/// prost, since it requires a registry of message types.
+fn has_any(all_types: &TestAllTypes) -> bool {
+    all_types.optional_any.is_some() ||
+        !all_types.repeated_any.is_empty() ||
+        all_types.recursive_message.as_ref().map_or(false, |msg| has_any(msg))
+}
+
+/// Returns `true` if the JSON input sets a `google.protobuf.Any` field.
+fn json_has_any(json: &str) -> bool {
+    fn value_has_any(value: &json::Value) -> bool {
+        match *value {
+            json::Value::Object(ref fields) => fields.iter().any(|&(ref key, ref value)| {
+                match &key[..] {
+                    "optionalAny" | "optional_any" | "repeatedAny" | "repeated_any" => true,
+                    _ => value_has_any(value),
+                }
+            }),
+            _ => false,
+        }
+    }
+    json::Value::parse(json).map(|value| value_has_any(&value)).unwrap_or(false)
+}
@@ -64,7 +64,7 @@
 //!
 //! // Include the `items` module, which is generated from items.proto.
 //! pub mod items {
-//!     include!(concat!(env!("OUT_DIR"), "/items.rs"));
+//!     include!(concat!(env!("OUT_DIR"), "/snazzy.items.rs"));
 //! }
 //!
 //! pub fn create_large_shirt(color: String) -> items::Shirt {
@@ -81,16 +81,13 @@
 #![doc(html_root_url = "https://docs.rs/prost-build/0.1.1")]
 
 extern crate bytes;
-extern crate curl;
 extern crate prost;
 extern crate prost_codegen;
-extern crate tempdir;
-extern crate zip;
 
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::io::{
-    self,
     Cursor,
     Error,
     ErrorKind,
@@ -105,8 +102,6 @@ use std::path::{
 use std::process::Command;
 
 use bytes::Buf;
-use curl::easy::Easy;
-use zip::ZipArchive;
 
 use prost::Message;
 use prost_codegen::google::protobuf::FileDescriptorSet;
@@ -119,32 +114,32 @@ use prost_codegen::CodeGeneratorConfig;
 /// with the [include!][1] macro. See the [Cargo `build.rs` code generation][2] example for more
 /// info.
 ///
-/// This function should be called in a project's `build.rs`.
+/// Each `.rs` file is named after its full Protobuf package, e.g. `foo.v1.rs` for package
+/// `foo.v1`. The types of `.proto` files without a `package` declaration are written to `_.rs`.
+/// Generated code refers to the types of other packages with `super::` paths, so each file must be
+/// included in a module hierarchy mirroring the packages, which is typically rooted at the crate
+/// root. `CodeGeneratorConfig::include_file` configures an additional include file declaring this
+/// hierarchy.
 ///
-/// # Sourcing `protoc`
+/// This function should be called in a project's `build.rs`.
 ///
-/// `prost` uses the Protocol Buffers compiler, `protoc`, to parse `.proto` files into a
-/// representation that can be transformed into Rust. If present, `compile_protos` will use the
-/// `PROTOC` and `PROTOC_INCLUDE` environment variables for locating `protoc` and the protobuf
-/// built-in includes. For example, on a macOS system where protobuf is installed with Homebrew,
-/// set the environment to:
+/// # Parsing
 ///
-/// ```bash
-/// PROTOC=/usr/local/bin/protoc
-/// PROTOC_INCLUDE=/usr/local/include
-/// ```
+/// `.proto` files are parsed by `prost-codegen` itself, so the Protocol Buffers compiler, `protoc`,
+/// is not needed at build time. The well-known types in the `google/protobuf` include directory,
+/// such as `google/protobuf/timestamp.proto`, are bundled, and may be imported without adding an
+/// include path for them.
 ///
-/// and in a typical Linux installation:
+/// If the `PROTOC` environment variable is set, the `protoc` binary it names is used to parse the
+/// `.proto` files instead. This is a fallback for `.proto` files which `prost-codegen` fails to
+/// parse. The optional `PROTOC_INCLUDE` environment variable names the directory holding the
+/// well-known types for `protoc`, and is searched after the **`includes`** directories:
 ///
 /// ```bash
 /// PROTOC=/usr/bin/protoc
 /// PROTOC_INCLUDE=/usr/include
 /// ```
 ///
-/// If `PROTOC` and `PROTOC_INCLUDE` are not found in the environment, then a pre-compiled `protoc`
-/// binary will be downloaded and cached in the target directory. Pre-compiled `protoc` binaries
-/// exist for Linux, macOS, and Windows systems.
-///
 /// # Arguments
 ///
 /// **`protos`** - Paths to `.proto` files to compile. Any transitively [imported][3] `.proto`
@@ -158,9 +153,12 @@ use prost_codegen::CodeGeneratorConfig;
 ///
 /// This function can fail for a number of reasons:
 ///
-///   - Failure to locate or download `protoc`.
+///   - Failure to read the `.proto`s.
+///   - Failure to run `protoc`, if `PROTOC` is set.
 ///   - Failure to parse the `.proto`s.
 ///   - Failure to locate an imported `.proto`.
+///   - Failure to resolve a type or option name.
+///   - Invalid file descriptors, such as conflicting definitions.
 ///
 /// It's expected that this function call be `unwrap`ed in a `build.rs`; there is typically no
 /// reason to gracefully recover from errors during a build.
@@ -231,28 +229,35 @@ pub fn compile_protos_with_config<P>(config: &CodeGeneratorConfig,
     // don't want. Figure out how to do it in an additive way, perhaps gcc-rs
     // has this figured out.
 
-    // Find protoc.
-    let (protoc, protoc_include) = match find_protoc()? {
-        Some(paths) => paths,
-        None => {
-            // If the protoc directory doesn't already exist from a previous build,
-            // create it, and extract the protoc release into it.
-            let protoc_dir = target.join("protoc");
-            if !protoc_dir.exists() {
-                fs::create_dir(&protoc_dir)?;
-                download_protoc(&protoc_dir)?;
-            }
+    let descriptor_set = match env::var_os("PROTOC") {
+        Some(protoc) => run_protoc(&protoc, protos, includes, &target)?,
+        None => prost_codegen::parse_protos(protos, includes).map_err(|error| {
+            Error::new(ErrorKind::Other, error.to_string())
+        })?,
+    };
 
-            let mut protoc = protoc_dir.join("bin");
-            protoc.push("protoc");
-            protoc.set_extension(env::consts::EXE_EXTENSION);
+    let modules = prost_codegen::generate(config, descriptor_set.file).map_err(|error| {
+        Error::new(ErrorKind::Other, error.to_string())
+    })?;
+    for (module, content) in &modules {
+        let mut file = fs::File::create(target.join(prost_codegen::module_filename(module)))?;
+        file.write_all(content.as_bytes())?;
+        file.flush()?;
+    }
 
-            (protoc, protoc_dir.join("include"))
-        },
-    };
+    if let Some((filename, content)) = prost_codegen::generate_include_file(config, modules.keys()) {
+        let mut file = fs::File::create(target.join(filename))?;
+        file.write_all(content.as_bytes())?;
+        file.flush()?;
+    }
 
-    let tmp = tempdir::TempDir::new("prost-build")?;
-    let descriptor_set = tmp.path().join("prost-descriptor-set");
+    Ok(())
+}
+
+/// Parses `.proto` files with `protoc`, and returns the descriptors of the files and their imports.
+fn run_protoc<P>(protoc: &OsStr, protos: &[P], includes: &[P], target: &Path) -> Result<FileDescriptorSet>
+where P: AsRef<Path> {
+    let descriptor_set = target.join("prost-descriptor-set");
 
     let mut cmd = Command::new(protoc);
     cmd.arg("--include_imports")
@@ -265,7 +270,9 @@ pub fn compile_protos_with_config<P>(config: &CodeGeneratorConfig,
 
     // Set the protoc include after the user includes in case the user wants to
     // override one of the built-in .protos.
-    cmd.arg("-I").arg(protoc_include);
+    if let Some(protoc_include) = env::var_os("PROTOC_INCLUDE") {
+        cmd.arg("-I").arg(protoc_include);
+    }
 
     for proto in protos {
         cmd.arg(proto.as_ref());
@@ -281,123 +288,5 @@ pub fn compile_protos_with_config<P>(config: &CodeGeneratorConfig,
     let mut buf = Vec::new();
     fs::File::open(descriptor_set)?.read_to_end(&mut buf)?;
     let len = buf.len();
-    let descriptor_set = FileDescriptorSet::decode(&mut <Cursor<Vec<u8>> as Buf>::take(Cursor::new(buf), len))?;
-
-    let modules = prost_codegen::generate(config, descriptor_set.file);
-    for (module, content) in modules {
-        let mut filename = match module.last() {
-            Some(filename) => PathBuf::from(filename),
-            None => return Err(Error::new(ErrorKind::InvalidInput, ".proto must have a package")),
-        };
-        filename.set_extension("rs");
-        let mut file = fs::File::create(target.join(filename))?;
-        file.write_all(content.as_bytes())?;
-        file.flush()?;
-    }
-
-    Ok(())
-}
-
-/// Finds `protoc` and the protobuf include dir in the environment, if it is available.
-fn find_protoc() -> Result<Option<(PathBuf, PathBuf)>> {
-    let protoc = match env::var("PROTOC") {
-        Ok(val) => PathBuf::from(val),
-        Err(env::VarError::NotPresent) => return Ok(None),
-        Err(env::VarError::NotUnicode(..)) => {
-            return Err(Error::new(ErrorKind::InvalidData,
-                                  "PROTOC environment variable is not valid UTF-8"));
-        },
-    };
-
-    let protoc_include = match env::var("PROTOC_INCLUDE") {
-        Ok(val) => PathBuf::from(val),
-        Err(env::VarError::NotPresent) => {
-            // We could fall back to downloading protoc here, but if PROTOC is set without
-            // PROTOC_INCLUDE, it indicates a misconfiguration, so lets bubble that back to the
-            // user.
-            return Err(Error::new(ErrorKind::InvalidInput,
-                                  "PROTOC_INCLUDE environment variable not set (PROTOC is set)"));
-        }
-        Err(env::VarError::NotUnicode(..)) => {
-            return Err(Error::new(ErrorKind::InvalidData,
-                                  "PROTOC_INCLUDE environment variable is not valid UTF-8"));
-        },
-    };
-
-    Ok(Some((protoc, protoc_include)))
-}
-
-/// Downloads and unpacks the protoc package for the current architecture to the target path.
-/// Returns the paths to `protoc` and the include directory.
-fn download_protoc(target: &Path) -> Result<()> {
-    let url = protoc_url()?;
-    let mut data = Vec::new();
-    let mut handle = Easy::new();
-
-    handle.url(url)?;
-    handle.follow_location(true)?;
-    {
-        let mut transfer = handle.transfer();
-        transfer.write_function(|new_data| {
-            data.extend_from_slice(new_data);
-            Ok(new_data.len())
-        })?;
-        transfer.perform()?;
-    }
-
-    let mut archive = ZipArchive::new(Cursor::new(data))?;
-
-    for i in 0..archive.len()
-    {
-        let mut src = archive.by_index(i)?;
-
-        let mut path = target.to_owned();
-        path.push(src.name());
-
-        if src.name().ends_with('/') {
-            fs::create_dir(&path)?;
-        } else {
-            let mut dest = &mut fs::File::create(&path)?;
-            io::copy(&mut src, &mut dest)?;
-
-            #[cfg(unix)]
-            fn convert_permissions(mode: u32) -> Option<fs::Permissions> {
-                use std::os::unix::fs::PermissionsExt;
-                Some(fs::Permissions::from_mode(mode))
-            }
-            #[cfg(not(unix))]
-            fn convert_permissions(_mode: u32) -> Option<fs::Permissions> {
-                None
-            }
-            if let Some(permissions) = src.unix_mode().and_then(convert_permissions) {
-                fs::set_permissions(&path, permissions)?;
-            }
-        }
-    }
-
-    Ok(())
-}
-
-fn protoc_url() -> Result<&'static str> {
-    match (env::consts::OS, env::consts::ARCH) {
-        ("linux", "x86")    => Ok("https://github.com/google/protobuf/releases/download/v3.3.0/protoc-3.3.0-linux-x86_32.zip"),
-        ("linux", "x86_64") => Ok("https://github.com/google/protobuf/releases/download/v3.3.0/protoc-3.3.0-linux-x86_64.zip"),
-        ("macos", "x86")    => Ok("https://github.com/google/protobuf/releases/download/v3.3.0/protoc-3.3.0-osx-x86_32.zip"),
-        ("macos", "x86_64") => Ok("https://github.com/google/protobuf/releases/download/v3.3.0/protoc-3.3.0-osx-x86_64.zip"),
-        ("windows", _)      => Ok("https://github.com/google/protobuf/releases/download/v3.3.0/protoc-3.3.0-win32.zip"),
-        _ => Err(Error::new(ErrorKind::NotFound,
-                            format!("no precompiled protoc binary for current the platform: {}-{}",
-                                    env::consts::OS, env::consts::ARCH))),
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_download_protoc() {
-        let dir = tempdir::TempDir::new("protoc").unwrap();
-        download_protoc(dir.path()).unwrap();
-    }
+    Ok(FileDescriptorSet::decode(&mut <Cursor<Vec<u8>> as Buf>::take(Cursor::new(buf), len))?)
 }
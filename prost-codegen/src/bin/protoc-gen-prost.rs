@@ -34,7 +34,13 @@ fn main() {
     let request = CodeGeneratorRequest::decode(&mut Buf::take(Cursor::new(&mut bytes), len)).unwrap();
     let mut response = CodeGeneratorResponse::default();
 
-    let modules = prost_codegen::generate(&CodeGeneratorConfig::new(), request.proto_file);
+    let modules = match prost_codegen::generate(&CodeGeneratorConfig::new(), request.proto_file) {
+        Ok(modules) => modules,
+        Err(error) => {
+            response.error = Some(error.to_string());
+            HashMap::new()
+        },
+    };
 
     // For each module, build up a list of its child modules.
     let mut children: HashMap<prost_codegen::Module, Vec<String>> = HashMap::new();
@@ -9,6 +9,12 @@ use ast::{
     Method,
     Service,
 };
+use descriptor_pool::{
+    DescriptorError,
+    DescriptorPool,
+    MessageDescriptor,
+    unescape_c_bytes,
+};
 use google::protobuf::{
     DescriptorProto,
     EnumDescriptorProto,
@@ -24,7 +30,10 @@ use google::protobuf::source_code_info::Location;
 use ident::{
     camel_to_snake,
     match_field,
+    match_message,
     snake_to_upper_camel,
+    to_json_name,
+    upper_camel_to_snake,
 };
 use message_graph::MessageGraph;
 use CodeGeneratorConfig;
@@ -38,6 +47,139 @@ pub fn module(file: &FileDescriptorProto) -> Module {
         .collect()
 }
 
+/// Returns the name of the file holding the generated code of a module: the module path joined
+/// with `.`, or `_` for the root module, with an `.rs` extension.
+pub fn module_filename(module: &Module) -> String {
+    if module.is_empty() {
+        "_.rs".to_string()
+    } else {
+        format!("{}.rs", module.join("."))
+    }
+}
+
+/// Returns the source of a file which declares the nested module hierarchy of `modules`, and
+/// includes the generated code of each module from the Cargo `OUT_DIR` directory.
+pub fn include_file<'a, I>(modules: I) -> String where I: IntoIterator<Item = &'a Module> {
+    fn push_indent(buf: &mut String, depth: usize) {
+        for _ in 0..depth {
+            buf.push_str("    ");
+        }
+    }
+
+    // Sorting places each module after its parent module, and next to its sibling modules.
+    let mut modules = modules.into_iter().collect::<Vec<_>>();
+    modules.sort();
+
+    let mut buf = String::new();
+    let mut open: Vec<&str> = Vec::new();
+    for module in modules {
+        let common = open.iter().zip(module).take_while(|&(open, component)| open == component).count();
+        while open.len() > common {
+            open.pop();
+            push_indent(&mut buf, open.len());
+            buf.push_str("}\n");
+        }
+        for component in &module[common..] {
+            push_indent(&mut buf, open.len());
+            buf.push_str(&format!("pub mod {} {{\n", component));
+            open.push(component);
+        }
+        push_indent(&mut buf, open.len());
+        buf.push_str(&format!("include!(concat!(env!(\"OUT_DIR\"), \"/{}\"));\n", module_filename(module)));
+    }
+    while let Some(_) = open.pop() {
+        push_indent(&mut buf, open.len());
+        buf.push_str("}\n");
+    }
+    buf
+}
+
+/// The `.proto` files of the well-known types provided by the `prost-types` crate.
+const WELL_KNOWN_FILES: &'static [&'static str] = &[
+    "google/protobuf/duration.proto",
+    "google/protobuf/empty.proto",
+    "google/protobuf/field_mask.proto",
+    "google/protobuf/struct.proto",
+    "google/protobuf/timestamp.proto",
+    "google/protobuf/wrappers.proto",
+];
+
+/// The well-known types provided by the `prost-types` crate.
+const WELL_KNOWN_TYPES: &'static [&'static str] = &[
+    "BoolValue",
+    "BytesValue",
+    "DoubleValue",
+    "Duration",
+    "Empty",
+    "FieldMask",
+    "FloatValue",
+    "Int32Value",
+    "Int64Value",
+    "ListValue",
+    "NullValue",
+    "StringValue",
+    "Struct",
+    "Timestamp",
+    "UInt32Value",
+    "UInt64Value",
+    "Value",
+];
+
+/// Returns `true` if the `.proto` file defines well-known types provided by `prost-types`.
+pub fn is_well_known_file(name: &str) -> bool {
+    WELL_KNOWN_FILES.contains(&name)
+}
+
+/// Returns the `prost-types` path of a fully qualified well-known type, or `None` if the type is
+/// not provided by `prost-types`.
+fn well_known_type(pb_ident: &str) -> Option<String> {
+    const PACKAGE: &'static str = ".google.protobuf.";
+    if !pb_ident.starts_with(PACKAGE) {
+        return None;
+    }
+    let name = &pb_ident[PACKAGE.len()..];
+    if WELL_KNOWN_TYPES.contains(&name) {
+        Some(format!("::prost_types::{}", name))
+    } else {
+        None
+    }
+}
+
+/// Returns the Rust path of a fully qualified Protobuf package or type covered by one of the
+/// `extern_paths`, or `None` if it is not covered. The longest matching extern path is used.
+pub fn extern_path(pool: &DescriptorPool,
+                   extern_paths: &[(String, String)],
+                   pb_ident: &str) -> Option<String> {
+    extern_paths.iter()
+                .filter(|&&(ref proto_path, _)| {
+                    pb_ident.starts_with(proto_path.as_str())
+                        && (pb_ident.len() == proto_path.len()
+                            || pb_ident.as_bytes()[proto_path.len()] == b'.')
+                })
+                .max_by_key(|&&(ref proto_path, _)| proto_path.len())
+                .map(|&(ref proto_path, ref rust_path)| {
+                    let mut segments = pb_ident[proto_path.len()..].split('.')
+                                                                   .filter(|s| !s.is_empty())
+                                                                   .collect::<Vec<_>>();
+                    let ident_type = match segments.pop() {
+                        Some(ident_type) => ident_type,
+                        None => return rust_path.clone(),
+                    };
+                    // Types nested in an extern message are in the module named after the message.
+                    let rust_path = match (pool.get_message(proto_path), rust_path.rfind("::")) {
+                        (Some(_), Some(idx)) => {
+                            format!("{}::{}", &rust_path[..idx], camel_to_snake(&rust_path[idx + 2..]))
+                        },
+                        (Some(_), None) => camel_to_snake(rust_path),
+                        (None, _) => rust_path.clone(),
+                    };
+                    Some(rust_path).into_iter()
+                                   .chain(segments.into_iter().map(camel_to_snake))
+                                   .chain(Some(ident_type.to_string()).into_iter())
+                                   .join("::")
+                })
+}
+
 #[derive(PartialEq)]
 enum Syntax {
     Proto2,
@@ -46,6 +188,7 @@ enum Syntax {
 
 pub struct CodeGenerator<'a> {
     config: &'a CodeGeneratorConfig,
+    pool: &'a DescriptorPool,
     package: String,
     source_info: SourceCodeInfo,
     syntax: Syntax,
@@ -57,9 +200,11 @@ pub struct CodeGenerator<'a> {
 
 impl <'a> CodeGenerator<'a> {
     pub fn generate(config: &CodeGeneratorConfig,
+                    pool: &DescriptorPool,
                     message_graph: &MessageGraph,
                     file: FileDescriptorProto,
-                    buf: &mut String) {
+                    buf: &mut String)
+                    -> Result<(), DescriptorError> {
 
         let mut source_info = file.source_code_info.expect("no source code info in request");
         source_info.location.retain(|location| {
@@ -76,7 +221,8 @@ impl <'a> CodeGenerator<'a> {
 
         let mut code_gen = CodeGenerator {
             config: config,
-            package: file.package.unwrap(),
+            pool: pool,
+            package: file.package.unwrap_or_default(),
             source_info: source_info,
             syntax: syntax,
             message_graph: message_graph,
@@ -90,7 +236,7 @@ impl <'a> CodeGenerator<'a> {
         code_gen.path.push(4);
         for (idx, message) in file.message_type.into_iter().enumerate() {
             code_gen.path.push(idx as i32);
-            code_gen.append_message(message);
+            code_gen.append_message(message)?;
             code_gen.path.pop();
         }
         code_gen.path.pop();
@@ -103,6 +249,14 @@ impl <'a> CodeGenerator<'a> {
         }
         code_gen.path.pop();
 
+        code_gen.path.push(7);
+        for (idx, extension) in file.extension.iter().enumerate() {
+            code_gen.path.push(idx as i32);
+            code_gen.append_extension(extension);
+            code_gen.path.pop();
+        }
+        code_gen.path.pop();
+
         if let Some(ref service_generator) = code_gen.config.service_generator {
             code_gen.path.push(6);
             for (idx, service) in file.service.into_iter().enumerate() {
@@ -112,16 +266,21 @@ impl <'a> CodeGenerator<'a> {
             }
             code_gen.path.pop();
         }
+        Ok(())
     }
 
-    fn append_message(&mut self, message: DescriptorProto) {
+    fn append_message(&mut self, message: DescriptorProto) -> Result<(), DescriptorError> {
         debug!("\tmessage: {:?}", message.name());
 
         // Split the nested message types into a vector of normal nested message types, and a map
         // of the map field entry types. The path index of the nested message types is preserved so
         // that comments can be retrieved.
         let message_name = message.name.as_ref().expect("message name");
-        let fq_message_name = format!(".{}.{}", self.package, message_name);
+        let fq_message_name = self.fq_name(message_name);
+        if self.is_extern(&fq_message_name) {
+            return Ok(());
+        }
+
         let (nested_types, map_types): (Vec<(DescriptorProto, usize)>, HashMap<String, (FieldDescriptorProto, FieldDescriptorProto)>) =
             message.nested_type.into_iter().enumerate().partition_map(|(idx, nested_type)| {
                 if nested_type.options.as_ref().and_then(|options| options.map_entry).unwrap_or(false) {
@@ -152,81 +311,178 @@ impl <'a> CodeGenerator<'a> {
 
         assert_eq!(oneof_fields.len(), message.oneof_decl.len());
 
+        let borrowed = self.borrowed(&fq_message_name);
+        let extendable = !message.extension_range.is_empty();
+        self.append_struct(message_name, &fq_message_name, &fields, &map_types,
+                           &message.oneof_decl, &oneof_fields, extendable, false)?;
+        if borrowed {
+            self.append_struct(message_name, &fq_message_name, &fields, &map_types,
+                               &message.oneof_decl, &oneof_fields, false, true)?;
+        }
+
+        // Nested types provided by an extern path don't need a module.
+        let has_nested_types =
+            nested_types.iter().any(|&(ref nested_type, _)| {
+                !self.is_extern(&format!("{}.{}", fq_message_name, nested_type.name()))
+            }) || message.enum_type.iter().any(|nested_enum| {
+                !self.is_extern(&format!("{}.{}", fq_message_name, nested_enum.name()))
+            });
+        if has_nested_types || !oneof_fields.is_empty() || !message.extension.is_empty() {
+            self.push_mod(&message_name);
+            self.path.push(3);
+            for (nested_type, idx) in nested_types.into_iter() {
+                self.path.push(idx as i32);
+                self.append_message(nested_type)?;
+                self.path.pop();
+            }
+            self.path.pop();
+
+            self.path.push(4);
+            for (idx, nested_enum) in message.enum_type.into_iter().enumerate() {
+                self.path.push(idx as i32);
+                self.append_enum(nested_enum);
+                self.path.pop();
+            }
+            self.path.pop();
+
+            for (idx, oneof) in message.oneof_decl.into_iter().enumerate() {
+                let idx = idx as i32;
+                let fields = oneof_fields.remove(&idx).unwrap();
+                self.append_oneof(&oneof, idx, &fields, false)?;
+                if borrowed && fields.iter().any(|&(ref field, _)| borrows(field)) {
+                    self.append_oneof(&oneof, idx, &fields, true)?;
+                }
+            }
+
+            self.path.push(6);
+            for (idx, extension) in message.extension.iter().enumerate() {
+                self.path.push(idx as i32);
+                self.append_extension(extension);
+                self.path.pop();
+            }
+            self.path.pop();
+
+            self.pop_mod();
+        }
+        Ok(())
+    }
+
+    /// Appends the struct for a message. Borrowed structs are named with a `Ref` suffix, and
+    /// borrow their `string`, `bytes` and message fields from the buffer they are decoded from.
+    /// Extendable structs hold the message's extension fields.
+    fn append_struct(&mut self,
+                     message_name: &str,
+                     fq_message_name: &str,
+                     fields: &[(FieldDescriptorProto, usize)],
+                     map_types: &HashMap<String, (FieldDescriptorProto, FieldDescriptorProto)>,
+                     oneof_decl: &[OneofDescriptorProto],
+                     oneof_fields: &MultiMap<i32, (FieldDescriptorProto, usize)>,
+                     extendable: bool,
+                     borrowed: bool)
+                     -> Result<(), DescriptorError> {
         self.append_doc();
+        self.append_type_attributes(fq_message_name);
         self.push_indent();
         self.buf.push_str("#[derive(Clone, Debug, PartialEq, Message)]\n");
         self.push_indent();
         self.buf.push_str("pub struct ");
-        self.buf.push_str(&message_name);
+        self.buf.push_str(message_name);
+        if borrowed { self.buf.push_str("Ref<'a>"); }
         self.buf.push_str(" {\n");
 
         self.depth += 1;
         self.path.push(2);
-        for (field, idx) in fields.into_iter() {
+        for &(ref field, idx) in fields {
             self.path.push(idx as i32);
             match field.type_name.as_ref().and_then(|type_name| map_types.get(type_name)) {
-                Some(&(ref key, ref value)) => self.append_map_field(&fq_message_name, field, key, value),
-                None => self.append_field(&fq_message_name, field),
+                Some(&(ref key, ref value)) => self.append_map_field(fq_message_name, field, key, value, borrowed)?,
+                None => self.append_field(fq_message_name, field, borrowed)?,
             }
             self.path.pop();
         }
         self.path.pop();
 
         self.path.push(8);
-        for (idx, oneof) in message.oneof_decl.iter().enumerate() {
+        for (idx, oneof) in oneof_decl.iter().enumerate() {
             let idx = idx as i32;
             self.path.push(idx);
-            self.append_oneof_field(&message_name, oneof, &oneof_fields.get_vec(&idx).unwrap());
+            self.append_oneof_field(message_name, fq_message_name, oneof,
+                                    &oneof_fields.get_vec(&idx).unwrap(), borrowed);
             self.path.pop();
         }
         self.path.pop();
 
-        self.depth -= 1;
-        self.push_indent();
-        self.buf.push_str("}\n");
-
-        if !message.enum_type.is_empty() || !nested_types.is_empty() || !oneof_fields.is_empty() {
-            self.push_mod(&message_name);
-            self.path.push(3);
-            for (nested_type, idx) in nested_types.into_iter() {
-                self.path.push(idx as i32);
-                self.append_message(nested_type);
-                self.path.pop();
-            }
-            self.path.pop();
+        if extendable {
+            self.push_indent();
+            self.buf.push_str("#[prost(extension_set)]\n");
+            self.push_indent();
+            self.buf.push_str("pub _extensions: ::prost::ExtensionSet,\n");
+        }
 
-            self.path.push(4);
-            for (idx, nested_enum) in message.enum_type.into_iter().enumerate() {
-                self.path.push(idx as i32);
-                self.append_enum(nested_enum);
-                self.path.pop();
-            }
-            self.path.pop();
+        if self.config.retain_unknown_fields.iter().any(|matcher| match_message(matcher, fq_message_name)) {
+            self.push_indent();
+            self.buf.push_str("#[prost(unknown_fields)]\n");
+            self.push_indent();
+            self.buf.push_str("pub _unknown_fields: ::prost::UnknownFields,\n");
+        }
 
-            for (idx, oneof) in message.oneof_decl.into_iter().enumerate() {
-                let idx = idx as i32;
-                self.append_oneof(oneof, idx, oneof_fields.remove(&idx).unwrap());
+        // Borrowed messages without any borrowed fields still need to use their lifetime.
+        let borrows_input = fields.iter().any(|&(ref field, _)| {
+            match field.type_name.as_ref().and_then(|type_name| map_types.get(type_name)) {
+                Some(&(ref key, ref value)) => borrows(key) || borrows(value),
+                None => borrows(field),
             }
-
-            self.pop_mod();
+        }) || oneof_fields.iter_all().any(|(_, fields)| fields.iter().any(|&(ref field, _)| borrows(field)));
+        if borrowed && !borrows_input {
+            self.push_indent();
+            self.buf.push_str("#[prost(ignore)]\n");
+            self.push_indent();
+            self.buf.push_str("pub _marker: ::std::marker::PhantomData<&'a ()>,\n");
         }
+
+        self.depth -= 1;
+        self.push_indent();
+        self.buf.push_str("}\n");
+        Ok(())
     }
 
-    fn append_field(&mut self, msg_name: &str, field: FieldDescriptorProto) {
+    fn append_field(&mut self,
+                    msg_name: &str,
+                    field: &FieldDescriptorProto,
+                    borrowed: bool)
+                    -> Result<(), DescriptorError> {
         let repeated = field.label == Some(Label::LabelRepeated as i32);
-        let optional = self.optional(&field);
-        let ty = self.resolve_type(&field);
+        // Required fields are held in an `Option` so that missing fields can be detected.
+        let optional = self.optional(field) || field.label == Some(Label::LabelRequired as i32);
+        let bytes = !borrowed && self.bytes(msg_name, field);
+        let open_enum = self.open_enum(msg_name, field);
+        let ty = if bytes {
+            Cow::Borrowed("::bytes::Bytes")
+        } else if open_enum {
+            Cow::Owned(self.open_enum_type(field))
+        } else if borrowed {
+            self.resolve_borrowed_type(&format!("{}.{}", msg_name, field.name()), field)?
+        } else {
+            self.resolve_type(field)
+        };
 
+        let type_ = field.type_().unwrap();
         let boxed = !repeated
-                 && field.type_().unwrap() == Type::TypeMessage
-                 && self.message_graph.is_nested(field.type_name(), msg_name);
+                 && (type_ == Type::TypeMessage || type_ == Type::TypeGroup)
+                 && self.message_graph.is_nested(self.message(field.type_name()), self.message(msg_name));
 
         debug!("\t\tfield: {:?}, type: {:?}", field.name(), ty);
 
         self.append_doc();
         self.push_indent();
         self.buf.push_str("#[prost(");
-        let type_tag = self.field_type_tag(&field);
+        let type_tag = if bytes {
+            Cow::Borrowed("bytes=\"bytes\"")
+        } else if open_enum {
+            Cow::Owned(format!("open_enum={:?}", self.resolve_ident(field.type_name())))
+        } else {
+            self.field_type_tag(field)
+        };
         self.buf.push_str(&type_tag);
 
         match field.label().expect("unknown label") {
@@ -236,16 +492,21 @@ impl <'a> CodeGenerator<'a> {
             Label::LabelRequired => self.buf.push_str(", required"),
             Label::LabelRepeated => {
                 self.buf.push_str(", repeated");
-                if can_pack(&field) && !field.options.as_ref().map_or(false, |options| options.packed()) {
+                if can_pack(field) && !field.options.as_ref().map_or(false, |options| options.packed()) {
                     self.buf.push_str(", packed=\"false\"");
                 }
             },
         }
 
         if boxed { self.buf.push_str(", boxed"); }
+        if let Some(default) = default_attr(field) {
+            self.buf.push_str(&default);
+        }
+        self.buf.push_str(&name_attrs(field, &camel_to_snake(field.name())));
         self.buf.push_str(", tag=\"");
         self.buf.push_str(&field.number().to_string());
         self.buf.push_str("\")]\n");
+        self.append_field_attributes(msg_name, field.name());
         self.push_indent();
         self.buf.push_str("pub ");
         self.buf.push_str(&camel_to_snake(field.name()));
@@ -257,15 +518,31 @@ impl <'a> CodeGenerator<'a> {
         if boxed { self.buf.push_str(">"); }
         if repeated || optional { self.buf.push_str(">"); }
         self.buf.push_str(",\n");
+        Ok(())
     }
 
     fn append_map_field(&mut self,
                         msg_name: &str,
-                        field: FieldDescriptorProto,
+                        field: &FieldDescriptorProto,
                         key: &FieldDescriptorProto,
-                        value: &FieldDescriptorProto) {
-        let key_ty = self.resolve_type(key);
-        let value_ty = self.resolve_type(value);
+                        value: &FieldDescriptorProto,
+                        borrowed: bool)
+                        -> Result<(), DescriptorError> {
+        let (key_ty, mut value_ty) = if borrowed {
+            let referrer = format!("{}.{}", msg_name, field.name());
+            (self.resolve_borrowed_type(&referrer, key)?, self.resolve_borrowed_type(&referrer, value)?)
+        } else {
+            (self.resolve_type(key), self.resolve_type(value))
+        };
+        // Map fields with enumeration values are matched by the name of the map field.
+        let open_enum = value.type_() == Some(Type::TypeEnum) &&
+                        self.config
+                            .open_enums
+                            .iter()
+                            .any(|matcher| match_field(matcher, msg_name, field.name()));
+        if open_enum {
+            value_ty = Cow::Owned(self.open_enum_type(value));
+        }
 
         debug!("\t\tmap field: {:?}, key type: {:?}, value type: {:?}",
                field.name(), key_ty, value_ty);
@@ -284,70 +561,175 @@ impl <'a> CodeGenerator<'a> {
         };
 
         let key_tag = self.field_type_tag(key);
-        let value_tag = self.map_value_type_tag(value);
-        self.buf.push_str(&format!("#[prost({}=\"{}, {}\", tag=\"{}\")]\n",
+        let value_tag = if open_enum {
+            Cow::Owned(format!("open_enum({})", self.resolve_ident(value.type_name())))
+        } else {
+            self.map_value_type_tag(value)
+        };
+        self.buf.push_str(&format!("#[prost({}=\"{}, {}\"{}, tag=\"{}\")]\n",
                                    annotation_ty,
                                    key_tag,
                                    value_tag,
+                                   name_attrs(field, &camel_to_snake(field.name())),
                                    field.number()));
+        self.append_field_attributes(msg_name, field.name());
         self.push_indent();
         self.buf.push_str(&format!("pub {}: ::std::collections::{}<{}, {}>,\n",
                                    camel_to_snake(field.name()), rust_ty, key_ty, value_ty));
+        Ok(())
     }
 
     fn append_oneof_field(&mut self,
                           message_name: &str,
+                          fq_message_name: &str,
                           oneof: &OneofDescriptorProto,
-                          fields: &[(FieldDescriptorProto, usize)]) {
-        let name = format!("{}::{}",
+                          fields: &[(FieldDescriptorProto, usize)],
+                          borrowed: bool) {
+        // Borrowed variants are only generated for oneofs with borrowed fields.
+        let borrowed = borrowed && fields.iter().any(|&(ref field, _)| borrows(field));
+        let name = format!("{}::{}{}",
                            camel_to_snake(message_name),
-                           snake_to_upper_camel(oneof.name()));
+                           snake_to_upper_camel(oneof.name()),
+                           if borrowed { "Ref" } else { "" });
         self.append_doc();
         self.push_indent();
         self.buf.push_str(&format!("#[prost(oneof=\"{}\", tags=\"{}\")]\n",
                                    name,
                                    fields.iter().map(|&(ref field, _)| field.number()).join(", ")));
+        self.append_field_attributes(fq_message_name, oneof.name());
         self.push_indent();
-        self.buf.push_str(&format!("pub {}: Option<{}>,\n", camel_to_snake(oneof.name()), name));
+        self.buf.push_str(&format!("pub {}: Option<{}{}>,\n",
+                                   camel_to_snake(oneof.name()),
+                                   name,
+                                   if borrowed { "<'a>" } else { "" }));
     }
 
     fn append_oneof(&mut self,
-                    oneof: OneofDescriptorProto,
+                    oneof: &OneofDescriptorProto,
                     idx: i32,
-                    fields: Vec<(FieldDescriptorProto, usize)>) {
+                    fields: &[(FieldDescriptorProto, usize)],
+                    borrowed: bool)
+                    -> Result<(), DescriptorError> {
         self.path.push(8);
         self.path.push(idx);
         self.append_doc();
         self.path.pop();
         self.path.pop();
 
+        // Oneofs are generated in a module nested under the message, so the package is the fully
+        // qualified message name.
+        let msg_name = format!(".{}", self.package);
+        let oneof_name = format!("{}.{}", msg_name, oneof.name());
+
+        self.append_type_attributes(&oneof_name);
         self.push_indent();
         self.buf.push_str("#[derive(Clone, Debug, Oneof, PartialEq)]\n");
         self.push_indent();
         self.buf.push_str("pub enum ");
         self.buf.push_str(&snake_to_upper_camel(oneof.name()));
+        if borrowed { self.buf.push_str("Ref<'a>"); }
         self.buf.push_str(" {\n");
 
         self.path.push(2);
         self.depth += 1;
-        for (field, idx) in fields {
+        for &(ref field, idx) in fields {
             self.path.push(idx as i32);
             self.append_doc();
             self.path.pop();
 
+            let bytes = !borrowed && self.bytes(&msg_name, field);
+            let open_enum = self.open_enum(&msg_name, field);
+            let type_ = field.type_().unwrap();
+            let boxed = (type_ == Type::TypeMessage || type_ == Type::TypeGroup)
+                     && self.message_graph.is_nested(self.message(field.type_name()), self.message(&msg_name));
+
             self.push_indent();
-            let ty_tag = self.field_type_tag(&field);
-            self.buf.push_str(&format!("#[prost({}, tag=\"{}\")]\n", ty_tag, field.number()));
+            let ty_tag = if bytes {
+                Cow::Borrowed("bytes=\"bytes\"")
+            } else if open_enum {
+                Cow::Owned(format!("open_enum={:?}", self.resolve_ident(field.type_name())))
+            } else {
+                self.field_type_tag(field)
+            };
+            let variant = snake_to_upper_camel(field.name());
+            self.buf.push_str(&format!("#[prost({}{}{}, tag=\"{}\")]\n",
+                                       ty_tag,
+                                       if boxed { ", boxed" } else { "" },
+                                       name_attrs(field, &upper_camel_to_snake(&variant)),
+                                       field.number()));
+            self.append_field_attributes(&oneof_name, field.name());
 
             self.push_indent();
-            let ty = self.resolve_type(&field);
-            self.buf.push_str(&format!("{}({}),\n", snake_to_upper_camel(field.name()), ty));
+            let ty = if bytes {
+                Cow::Borrowed("::bytes::Bytes")
+            } else if open_enum {
+                Cow::Owned(self.open_enum_type(field))
+            } else if borrowed {
+                self.resolve_borrowed_type(&format!("{}.{}", msg_name, field.name()), field)?
+            } else {
+                self.resolve_type(field)
+            };
+            if boxed {
+                self.buf.push_str(&format!("{}(Box<{}>),\n", variant, ty));
+            } else {
+                self.buf.push_str(&format!("{}({}),\n", variant, ty));
+            }
         }
         self.depth -= 1;
         self.path.pop();
 
         self.push_indent();
         self.buf.push_str("}\n");
+        Ok(())
+    }
+
+    /// Appends the typed descriptor constant for an extension field.
+    fn append_extension(&mut self, field: &FieldDescriptorProto) {
+        debug!("\textension: {:?}, extendee: {:?}", field.name(), field.extendee());
+
+        let repeated = field.label == Some(Label::LabelRepeated as i32);
+        let packed = field.options.as_ref().map_or(false, |options| options.packed());
+        let ty = self.resolve_type(field);
+        let module = match field.type_().expect("unknown field type") {
+            Type::TypeEnum => Cow::Borrowed("int32"),
+            _ => self.field_type_tag(field),
+        };
+        let (encode_fn, merge_fn) = match (repeated, packed) {
+            (false, _) => ("encode", "merge"),
+            (true, false) => ("encode_repeated", "merge_repeated"),
+            (true, true) => ("encode_packed", "merge_repeated"),
+        };
+        // Groups are terminated by an end group key with the group's tag.
+        let merge_tag = if field.type_() == Some(Type::TypeGroup) {
+            format!("{}, ", field.number())
+        } else {
+            String::new()
+        };
+
+        self.append_doc();
+        self.push_indent();
+        self.buf.push_str(&format!("pub const {}: ::prost::Extension<{}, {}{}{}> = ::prost::Extension {{\n",
+                                   camel_to_snake(field.name()).trim_right_matches('_').to_uppercase(),
+                                   self.resolve_ident(field.extendee()),
+                                   if repeated { "Vec<" } else { "" },
+                                   ty,
+                                   if repeated { ">" } else { "" }));
+        self.depth += 1;
+        self.push_indent();
+        self.buf.push_str(&format!("name: \"{}\",\n", &self.fq_name(field.name())[1..]));
+        self.push_indent();
+        self.buf.push_str(&format!("tag: {},\n", field.number()));
+        self.push_indent();
+        self.buf.push_str(&format!("encode: |tag, value, buf| ::prost::encoding::{}::{}(tag, value, buf),\n",
+                                   module, encode_fn));
+        self.push_indent();
+        self.buf.push_str(&format!("merge: |wire_type, value, buf, ctx| ::prost::encoding::{}::{}({}wire_type, value, buf, ctx),\n",
+                                   module, merge_fn, merge_tag));
+        self.push_indent();
+        self.buf.push_str("extendee: ::std::marker::PhantomData,\n");
+        self.depth -= 1;
+        self.push_indent();
+        self.buf.push_str("};\n");
     }
 
     fn location(&self) -> &Location {
@@ -404,7 +786,13 @@ impl <'a> CodeGenerator<'a> {
     fn append_enum(&mut self, desc: EnumDescriptorProto) {
         debug!("\tenum: {:?}", desc.name());
 
+        let fq_enum_name = self.fq_name(desc.name());
+        if self.is_extern(&fq_enum_name) {
+            return;
+        }
+
         self.append_doc();
+        self.append_type_attributes(&fq_enum_name);
         self.push_indent();
         self.buf.push_str("#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]\n");
         self.push_indent();
@@ -436,8 +824,14 @@ impl <'a> CodeGenerator<'a> {
 
     fn append_enum_value(&mut self, value: EnumValueDescriptorProto) {
         self.append_doc();
+        let variant = snake_to_upper_camel(value.name());
+        // The name is only annotated when the derive can't recover it from the variant.
+        if upper_camel_to_snake(&variant).to_uppercase() != value.name() {
+            self.push_indent();
+            self.buf.push_str(&format!("#[prost(name=\"{}\")]\n", value.name()));
+        }
         self.push_indent();
-        self.buf.push_str(&snake_to_upper_camel(value.name()));
+        self.buf.push_str(&variant);
         self.buf.push_str(" = ");
         self.buf.push_str(&value.number().to_string());
         self.buf.push_str(",\n");
@@ -481,6 +875,36 @@ impl <'a> CodeGenerator<'a> {
         }
     }
 
+    /// Appends the configured attributes of the type with the fully qualified name `fq_name`.
+    fn append_type_attributes(&mut self, fq_name: &str) {
+        let config = self.config;
+        for &(ref matcher, ref attribute) in &config.type_attributes {
+            if match_message(matcher, fq_name) {
+                self.append_attribute(attribute);
+            }
+        }
+    }
+
+    /// Appends the configured attributes of the field `field_name` of the message or oneof with
+    /// the fully qualified name `fq_name`.
+    fn append_field_attributes(&mut self, fq_name: &str, field_name: &str) {
+        let config = self.config;
+        for &(ref matcher, ref attribute) in &config.field_attributes {
+            if match_field(matcher, fq_name, field_name) {
+                self.append_attribute(attribute);
+            }
+        }
+    }
+
+    /// Appends a configured attribute, which may span multiple lines.
+    fn append_attribute(&mut self, attribute: &str) {
+        for line in attribute.lines() {
+            self.push_indent();
+            self.buf.push_str(line);
+            self.buf.push_str("\n");
+        }
+    }
+
     fn push_indent(&mut self) {
         for _ in 0..self.depth {
             self.buf.push_str("    ");
@@ -493,7 +917,9 @@ impl <'a> CodeGenerator<'a> {
         self.buf.push_str(&camel_to_snake(module));
         self.buf.push_str(" {\n");
 
-        self.package.push_str(".");
+        if !self.package.is_empty() {
+            self.package.push_str(".");
+        }
         self.package.push_str(module);
 
         self.depth += 1;
@@ -502,7 +928,7 @@ impl <'a> CodeGenerator<'a> {
     fn pop_mod(&mut self) {
         self.depth -= 1;
 
-        let idx = self.package.rfind('.').unwrap();
+        let idx = self.package.rfind('.').unwrap_or(0);
         self.package.truncate(idx);
 
         self.push_indent();
@@ -525,15 +951,62 @@ impl <'a> CodeGenerator<'a> {
         }
     }
 
+    /// Resolves the Rust type of a field in a borrowed message. `referrer` is the fully-qualified
+    /// name of the field, or of the map field which the field is the key or value of.
+    fn resolve_borrowed_type<'b>(&self,
+                                 referrer: &str,
+                                 field: &'b FieldDescriptorProto)
+                                 -> Result<Cow<'b, str>, DescriptorError> {
+        Ok(match field.type_().expect("unknown field type") {
+            Type::TypeString => Cow::Borrowed("&'a str"),
+            Type::TypeBytes => Cow::Borrowed("&'a [u8]"),
+            Type::TypeGroup | Type::TypeMessage => {
+                let type_name = field.type_name();
+                // Only messages generated with a borrowed variant have a `Ref` type to refer to.
+                if extern_path(self.pool, &self.config.extern_paths, type_name).is_some()
+                    || (!self.config.compile_well_known_types && well_known_type(type_name).is_some())
+                    || !self.borrowed(type_name) {
+                    return Err(DescriptorError::NotBorrowed {
+                        referrer: referrer.to_string(),
+                        type_name: type_name.to_string(),
+                    });
+                }
+                Cow::Owned(format!("{}Ref<'a>", self.resolve_ident(type_name)))
+            },
+            _ => self.resolve_type(field),
+        })
+    }
+
     fn resolve_ident(&self, pb_ident: &str) -> String {
-        // protoc should always give fully qualified identifiers.
-        assert_eq!(".", &pb_ident[..1]);
+        if let Some(ident) = extern_path(self.pool, &self.config.extern_paths, pb_ident) {
+            return ident;
+        }
+        if !self.config.compile_well_known_types {
+            if let Some(ident) = well_known_type(pb_ident) {
+                return ident;
+            }
+        }
+
+        let (package, mut parent, ident_type) = match self.pool.get_message(pb_ident) {
+            Some(message) => (message.package_name(), message.parent_message(), message.name()),
+            None => {
+                let enum_type = self.pool.get_enum(pb_ident).expect("unknown type");
+                (enum_type.package_name(), enum_type.parent_message(), enum_type.name())
+            },
+        };
 
-        let mut local_path = self.package.split('.').peekable();
+        // The type's path is its package, followed by the messages it is nested in.
+        let mut parents = Vec::new();
+        while let Some(message) = parent {
+            parents.push(message.name());
+            parent = message.parent_message();
+        }
+        let mut ident_path = package.split('.')
+                                    .filter(|s| !s.is_empty())
+                                    .chain(parents.into_iter().rev())
+                                    .peekable();
 
-        let mut ident_path = pb_ident[1..].split('.');
-        let ident_type = ident_path.next_back().unwrap();
-        let mut ident_path = ident_path.peekable();
+        let mut local_path = self.package.split('.').filter(|s| !s.is_empty()).peekable();
 
         // Skip path elements in common.
         while local_path.peek().is_some() &&
@@ -548,6 +1021,26 @@ impl <'a> CodeGenerator<'a> {
                   .join("::")
     }
 
+    /// Returns the fully qualified name of the type `name` in the current package. Files without
+    /// a package declaration define their types in the root package.
+    fn fq_name(&self, name: &str) -> String {
+        if self.package.is_empty() {
+            format!(".{}", name)
+        } else {
+            format!(".{}.{}", self.package, name)
+        }
+    }
+
+    /// Returns `true` if the type `fq_name` is provided by an extern path.
+    fn is_extern(&self, fq_name: &str) -> bool {
+        extern_path(self.pool, &self.config.extern_paths, fq_name).is_some()
+    }
+
+    /// Returns the descriptor of a message type in the pool.
+    fn message(&self, fq_message_name: &str) -> MessageDescriptor<'a> {
+        self.pool.get_message(fq_message_name).expect("unknown message type")
+    }
+
     fn field_type_tag(&self, field: &FieldDescriptorProto) -> Cow<'static, str> {
         match field.type_().expect("unknown field type") {
             Type::TypeFloat => Cow::Borrowed("float"),
@@ -578,6 +1071,29 @@ impl <'a> CodeGenerator<'a> {
         }
     }
 
+    /// Returns `true` if the field is a `bytes` field which should be generated as a `Bytes`.
+    fn bytes(&self, msg_name: &str, field: &FieldDescriptorProto) -> bool {
+        field.type_() == Some(Type::TypeBytes) &&
+            self.config.bytes.iter().any(|matcher| match_field(matcher, msg_name, field.name()))
+    }
+
+    /// Returns `true` if the field is an enumeration field which should be generated as an
+    /// `OpenEnum`.
+    fn open_enum(&self, msg_name: &str, field: &FieldDescriptorProto) -> bool {
+        field.type_() == Some(Type::TypeEnum) &&
+            self.config.open_enums.iter().any(|matcher| match_field(matcher, msg_name, field.name()))
+    }
+
+    /// Returns the Rust `OpenEnum` type of an enumeration field.
+    fn open_enum_type(&self, field: &FieldDescriptorProto) -> String {
+        format!("::prost::OpenEnum<{}>", self.resolve_ident(field.type_name()))
+    }
+
+    /// Returns `true` if a borrowed variant should be generated for the message.
+    fn borrowed(&self, fq_message_name: &str) -> bool {
+        self.config.borrowed.iter().any(|matcher| match_message(matcher, fq_message_name))
+    }
+
     fn optional(&self, field: &FieldDescriptorProto) -> bool {
         if field.label().expect("unknown label") != Label::LabelOptional {
             return false;
@@ -590,6 +1106,60 @@ impl <'a> CodeGenerator<'a> {
     }
 }
 
+/// Returns `true` if the field borrows from the input buffer in a borrowed message.
+fn borrows(field: &FieldDescriptorProto) -> bool {
+    match field.type_().expect("unknown field type") {
+        Type::TypeString | Type::TypeBytes | Type::TypeGroup | Type::TypeMessage => true,
+        _ => false,
+    }
+}
+
+/// Returns the `name` and `json_name` attributes of a field, which are only annotated when they
+/// differ from the names the derive assumes for a field with the identifier `ident`.
+fn name_attrs(field: &FieldDescriptorProto, ident: &str) -> String {
+    let mut attrs = String::new();
+    if field.name() != ident {
+        attrs.push_str(&format!(", name=\"{}\"", field.name()));
+    }
+    if let Some(ref json_name) = field.json_name {
+        if *json_name != to_json_name(field.name()) {
+            attrs.push_str(&format!(", json_name=\"{}\"", json_name));
+        }
+    }
+    attrs
+}
+
+/// Returns the `default` attribute of a field with a proto2 default value. `protoc` writes
+/// enumeration defaults as the name of the value, which is converted to the name of the variant,
+/// and `bytes` defaults in C-escaped form, which is converted to a byte string literal. Other
+/// defaults are understood by the derive as `protoc` writes them.
+fn default_attr(field: &FieldDescriptorProto) -> Option<String> {
+    let default = match field.default_value {
+        Some(ref default) => default,
+        None => return None,
+    };
+    let value = match field.type_().expect("unknown field type") {
+        Type::TypeEnum => snake_to_upper_camel(default),
+        Type::TypeBytes => {
+            let mut literal = String::from("b\"");
+            for byte in unescape_c_bytes(default) {
+                match byte {
+                    b'"' | b'\\' => {
+                        literal.push('\\');
+                        literal.push(byte as char);
+                    },
+                    _ if byte >= 0x20 && byte < 0x7f => literal.push(byte as char),
+                    _ => literal.push_str(&format!("\\x{:02x}", byte)),
+                }
+            }
+            literal.push('"');
+            literal
+        },
+        _ => default.clone(),
+    };
+    Some(format!(", default={:?}", value))
+}
+
 fn can_pack(field: &FieldDescriptorProto) -> bool {
         match field.type_().expect("unknown field type") {
             Type::TypeFloat   | Type::TypeDouble  | Type::TypeInt32    | Type::TypeInt64    |
@@ -599,3 +1169,342 @@ fn can_pack(field: &FieldDescriptorProto) -> bool {
             _ => false,
         }
 }
+
+#[cfg(test)]
+mod tests {
+    use google::protobuf::FileDescriptorSet;
+    use parser;
+    use super::*;
+
+    /// Parses the `.proto` files `sources`, and returns the code generated for each module.
+    fn generate_modules(config: &CodeGeneratorConfig, sources: &[(&str, &str)]) -> HashMap<Module, String> {
+        let names = sources.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+        let set = parser::compile(&names, |name| {
+            Ok(sources.iter().find(|&&(n, _)| n == name).map(|&(_, source)| source.to_string()))
+        }).unwrap();
+        ::generate(config, set.file).unwrap()
+    }
+
+    /// Parses the `.proto` file `source`, and returns the generated code.
+    fn generate(config: &CodeGeneratorConfig, source: &str) -> String {
+        let modules = generate_modules(config, &[("test.proto", source)]);
+        assert_eq!(modules.len(), 1);
+        modules.into_iter().next().unwrap().1
+    }
+
+    #[test]
+    fn test_well_known_type() {
+        assert_eq!(Some("::prost_types::Timestamp".to_string()),
+                   well_known_type(".google.protobuf.Timestamp"));
+        assert_eq!(Some("::prost_types::Value".to_string()),
+                   well_known_type(".google.protobuf.Value"));
+        assert_eq!(None, well_known_type(".google.protobuf.FileDescriptorProto"));
+        assert_eq!(None, well_known_type(".foo.google.protobuf.Timestamp"));
+        assert_eq!(None, well_known_type(".Timestamp"));
+
+        assert!(is_well_known_file("google/protobuf/timestamp.proto"));
+        assert!(!is_well_known_file("google/protobuf/descriptor.proto"));
+    }
+
+    #[test]
+    fn test_include_file() {
+        fn module(path: &str) -> Module {
+            path.split('.').filter(|s| !s.is_empty()).map(str::to_string).collect()
+        }
+
+        assert_eq!(module_filename(&module("")), "_.rs");
+        assert_eq!(module_filename(&module("foo.v1")), "foo.v1.rs");
+
+        let modules = vec![module("foo.v1"), module("bar.v1"), module(""), module("foo"), module("foo.bar.baz")];
+        assert_eq!(include_file(&modules), "\
+include!(concat!(env!(\"OUT_DIR\"), \"/_.rs\"));
+pub mod bar {
+    pub mod v1 {
+        include!(concat!(env!(\"OUT_DIR\"), \"/bar.v1.rs\"));
+    }
+}
+pub mod foo {
+    include!(concat!(env!(\"OUT_DIR\"), \"/foo.rs\"));
+    pub mod bar {
+        pub mod baz {
+            include!(concat!(env!(\"OUT_DIR\"), \"/foo.bar.baz.rs\"));
+        }
+    }
+    pub mod v1 {
+        include!(concat!(env!(\"OUT_DIR\"), \"/foo.v1.rs\"));
+    }
+}
+");
+    }
+
+    #[test]
+    fn test_extern_path() {
+        let pool = DescriptorPool::new(FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("my/common.proto".to_string()),
+                package: Some("my.common".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Special".to_string()),
+                    nested_type: vec![DescriptorProto { name: Some("Inner".to_string()), ..DescriptorProto::default() }],
+                    ..DescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        }).unwrap();
+        let extern_paths = vec![
+            (".my.common".to_string(), "::common_protos::my::common".to_string()),
+            (".my.common.Special".to_string(), "::special::Special".to_string()),
+            (".my.messages.Uuid".to_string(), "::uuid::Uuid".to_string()),
+        ];
+
+        assert_eq!(Some("::common_protos::my::common".to_string()), extern_path(&pool, &extern_paths, ".my.common"));
+        assert_eq!(Some("::common_protos::my::common::Foo".to_string()),
+                   extern_path(&pool, &extern_paths, ".my.common.Foo"));
+        assert_eq!(Some("::common_protos::my::common::foo_bar::Baz".to_string()),
+                   extern_path(&pool, &extern_paths, ".my.common.FooBar.Baz"));
+        assert_eq!(Some("::special::Special".to_string()), extern_path(&pool, &extern_paths, ".my.common.Special"));
+        assert_eq!(Some("::special::special::Inner".to_string()),
+                   extern_path(&pool, &extern_paths, ".my.common.Special.Inner"));
+        assert_eq!(Some("::uuid::Uuid".to_string()), extern_path(&pool, &extern_paths, ".my.messages.Uuid"));
+        assert_eq!(None, extern_path(&pool, &extern_paths, ".my.messages.UuidList"));
+        assert_eq!(None, extern_path(&pool, &extern_paths, ".my.commonly.Foo"));
+        assert_eq!(None, extern_path(&pool, &extern_paths, ".my"));
+    }
+
+    #[test]
+    fn test_extern_type() {
+        let mut config = CodeGeneratorConfig::new();
+        config.extern_path(".app.Uuid", "::uuid::Uuid");
+        let buf = generate(&config, "\
+syntax = \"proto3\";
+package app;
+message Local {
+  Uuid uuid = 1;
+}
+message Uuid {}
+");
+
+        assert!(buf.contains("pub uuid: Option<::uuid::Uuid>,"), "{}", buf);
+        assert!(!buf.contains("pub struct Uuid"), "{}", buf);
+    }
+
+    #[test]
+    fn test_extern_nested_type() {
+        let mut config = CodeGeneratorConfig::new();
+        config.extern_path(".app.Outer.Inner", "::inner::Inner")
+              .extern_path(".app.Outer.Kind", "::kind::Kind");
+        let buf = generate(&config, "\
+syntax = \"proto3\";
+package app;
+message Outer {
+  message Inner {}
+  enum Kind {
+    DEFAULT = 0;
+  }
+  Inner inner = 1;
+  Kind kind = 2;
+}
+");
+
+        assert!(buf.contains("pub inner: Option<::inner::Inner>,"), "{}", buf);
+        assert!(buf.contains("#[prost(enumeration=\"::kind::Kind\", tag=\"2\")]"), "{}", buf);
+        assert!(!buf.contains("pub mod outer"), "{}", buf);
+        assert!(!buf.contains("pub struct Inner"), "{}", buf);
+        assert!(!buf.contains("pub enum Kind"), "{}", buf);
+    }
+
+    #[test]
+    fn test_root_package() {
+        let modules = generate_modules(&CodeGeneratorConfig::new(), &[
+            ("root.proto", "syntax = \"proto3\"; message Root { message Inner {} }"),
+            ("foo.proto", "syntax = \"proto3\"; package foo.bar; import \"root.proto\"; \
+                           message Foo { Root.Inner inner = 1; }"),
+        ]);
+
+        let root = &modules[&Vec::new()];
+        assert!(root.contains("pub struct Root {"), "{}", root);
+        assert!(root.contains("pub mod root {\n    #[derive(Clone, Debug, PartialEq, Message)]\n    pub struct Inner {"), "{}", root);
+
+        let foo = &modules[&vec!["foo".to_string(), "bar".to_string()]];
+        assert!(foo.contains("pub inner: Option<super::super::root::Inner>,"), "{}", foo);
+    }
+
+    #[test]
+    fn test_resolve_ident() {
+        fn message(name: &str, nested_type: Vec<DescriptorProto>, enum_type: Vec<EnumDescriptorProto>) -> DescriptorProto {
+            DescriptorProto {
+                name: Some(name.to_string()),
+                nested_type: nested_type,
+                enum_type: enum_type,
+                ..DescriptorProto::default()
+            }
+        }
+        fn enumeration(name: &str) -> EnumDescriptorProto {
+            EnumDescriptorProto { name: Some(name.to_string()), ..EnumDescriptorProto::default() }
+        }
+
+        let pool = DescriptorPool::new(FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("foo/bar.proto".to_string()),
+                    package: Some("foo.bar".to_string()),
+                    message_type: vec![message("Outer", vec![message("Inner", vec![], vec![])], vec![enumeration("Kind")])],
+                    enum_type: vec![enumeration("TopLevel")],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("foo/baz.proto".to_string()),
+                    package: Some("foo.baz".to_string()),
+                    message_type: vec![message("FooBaz", vec![message("Inner", vec![], vec![])], vec![])],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        }).unwrap();
+        let config = CodeGeneratorConfig::new();
+        let message_graph = MessageGraph::new(&pool);
+        let mut buf = String::new();
+        let mut code_gen = CodeGenerator {
+            config: &config,
+            pool: &pool,
+            package: "foo.bar".to_string(),
+            source_info: SourceCodeInfo::default(),
+            syntax: Syntax::Proto3,
+            message_graph: &message_graph,
+            depth: 0,
+            path: Vec::new(),
+            buf: &mut buf,
+        };
+
+        assert_eq!("Outer", code_gen.resolve_ident(".foo.bar.Outer"));
+        assert_eq!("outer::Inner", code_gen.resolve_ident(".foo.bar.Outer.Inner"));
+        assert_eq!("outer::Kind", code_gen.resolve_ident(".foo.bar.Outer.Kind"));
+        assert_eq!("super::baz::foo_baz::Inner", code_gen.resolve_ident(".foo.baz.FooBaz.Inner"));
+        assert_eq!("::prost_types::Timestamp", code_gen.resolve_ident(".google.protobuf.Timestamp"));
+
+        code_gen.package = "foo.bar.Outer".to_string();
+        assert_eq!("Inner", code_gen.resolve_ident(".foo.bar.Outer.Inner"));
+        assert_eq!("super::TopLevel", code_gen.resolve_ident(".foo.bar.TopLevel"));
+        assert_eq!("super::super::baz::FooBaz", code_gen.resolve_ident(".foo.baz.FooBaz"));
+    }
+
+    #[test]
+    fn test_boxed_oneof_field() {
+        let buf = generate(&CodeGeneratorConfig::new(), "\
+syntax = \"proto3\";
+package foo;
+message Node {
+  oneof kind {
+    Node child = 1;
+    string name = 2;
+  }
+  repeated Node children = 3;
+}
+");
+
+        // The recursive oneof variant is boxed, and the repeated field is not.
+        assert!(buf.contains("#[prost(message, boxed, tag=\"1\")]\n        Child(Box<super::Node>),"), "{}", buf);
+        assert!(buf.contains("pub children: Vec<Node>,"), "{}", buf);
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let mut config = CodeGeneratorConfig::new();
+        config.borrowed(&[".foo"]);
+        let buf = generate(&config, "\
+syntax = \"proto3\";
+package foo;
+message Outer {
+  Inner inner = 1;
+  map<int32, int64> counts = 2;
+}
+message Inner {
+  map<int32, int64> counts = 1;
+}
+");
+
+        assert!(buf.contains("pub struct OuterRef<'a> {"), "{}", buf);
+        assert!(buf.contains("pub inner: Option<InnerRef<'a>>,"), "{}", buf);
+        assert_eq!(buf.matches("pub _marker: ::std::marker::PhantomData<&'a ()>,").count(), 1, "{}", buf);
+        assert!(buf.contains("pub struct InnerRef<'a> {\n    #[prost(map=\"int32, int64\", tag=\"1\")]\n    \
+                              pub counts: ::std::collections::HashMap<i32, i64>,\n    #[prost(ignore)]\n    \
+                              pub _marker"), "{}", buf);
+    }
+
+    #[test]
+    fn test_borrowed_well_known_type() {
+        let mut config = CodeGeneratorConfig::new();
+        config.borrowed(&["."]);
+        let source = "\
+syntax = \"proto3\";
+package foo;
+import \"google/protobuf/timestamp.proto\";
+message Event {
+  google.protobuf.Timestamp time = 1;
+}
+";
+        let set = parser::compile(&["test.proto"], |name| {
+            Ok(if name == "test.proto" { Some(source.to_string()) } else { None })
+        }).unwrap();
+        match ::generate(&config, set.file) {
+            Err(DescriptorError::NotBorrowed { ref referrer, ref type_name }) => {
+                assert_eq!(referrer, ".foo.Event.time");
+                assert_eq!(type_name, ".google.protobuf.Timestamp");
+            },
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut config = CodeGeneratorConfig::new();
+        config.type_attribute(".", "#[derive(Eq)]")
+              .type_attribute("Bar.kind", "#[oneof]")
+              .type_attribute(".foo.Bar.Color", "#[enumeration]\n#[allow(dead_code)]")
+              .field_attribute(".foo.Bar.id", "#[id]")
+              .field_attribute("Bar.kind", "#[kind]")
+              .field_attribute("kind.name", "#[name]")
+              .field_attribute("other", "#[other]");
+        let buf = generate(&config, "\
+syntax = \"proto3\";
+package foo;
+message Bar {
+  int32 id = 1;
+  oneof kind {
+    string name = 2;
+  }
+  enum Color {
+    RED = 0;
+  }
+}
+");
+
+        assert!(buf.contains("#[derive(Eq)]\n#[derive(Clone, Debug, PartialEq, Message)]\npub struct Bar {"), "{}", buf);
+        assert!(buf.contains("#[prost(int32, tag=\"1\")]\n    #[id]\n    pub id: i32,"), "{}", buf);
+        assert!(buf.contains("\n    #[kind]\n    pub kind: Option<bar::Kind>,"), "{}", buf);
+        assert!(buf.contains("    #[derive(Eq)]\n    #[enumeration]\n    #[allow(dead_code)]\n    \
+                              #[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]\n    pub enum Color {"), "{}", buf);
+        assert!(buf.contains("    #[derive(Eq)]\n    #[oneof]\n    #[derive(Clone, Debug, Oneof, PartialEq)]\n    pub enum Kind {"), "{}", buf);
+        assert!(buf.contains("#[prost(string, tag=\"2\")]\n        #[name]\n        Name(String),"), "{}", buf);
+        assert!(!buf.contains("#[other]"), "{}", buf);
+    }
+
+    #[test]
+    fn test_default_attr() {
+        fn field(type_: Type, default: &str) -> FieldDescriptorProto {
+            FieldDescriptorProto {
+                type_: Some(type_ as i32),
+                default_value: Some(default.to_string()),
+                ..FieldDescriptorProto::default()
+            }
+        }
+
+        assert_eq!(default_attr(&FieldDescriptorProto::default()), None);
+        assert_eq!(default_attr(&field(Type::TypeInt32, "-42")).unwrap(), ", default=\"-42\"");
+        assert_eq!(default_attr(&field(Type::TypeFloat, "-inf")).unwrap(), ", default=\"-inf\"");
+        assert_eq!(default_attr(&field(Type::TypeEnum, "FOO_BAR")).unwrap(), ", default=\"FooBar\"");
+        assert_eq!(default_attr(&field(Type::TypeString, "a \"b\"")).unwrap(),
+                   r#", default="a \"b\"""#);
+        assert_eq!(default_attr(&field(Type::TypeBytes, r#"\001a\"\n\377\x7F"#)).unwrap(),
+                   r#", default="b\"\\x01a\\\"\\x0a\\xff\\x7f\"""#);
+    }
+}
@@ -0,0 +1,1369 @@
+//! An index of the types, fields and services defined by a set of `.proto` files.
+
+use std::char;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::ptr;
+
+use ast::Comments;
+use google::protobuf::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet,
+    MethodDescriptorProto,
+    OneofDescriptorProto,
+    ServiceDescriptorProto,
+};
+use google::protobuf::field_descriptor_proto::{Label, Type};
+
+/// The index of a type in a `DescriptorPool`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TypeIndex {
+    Message(usize),
+    Enum(usize),
+}
+
+/// A named definition in a `DescriptorPool`. Fields and oneofs are identified by the index of
+/// the containing message and their index within it, and methods by the index of the containing
+/// service and their index within it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Symbol {
+    Type(TypeIndex),
+    Field(usize, usize),
+    Oneof(usize, usize),
+    Service(usize),
+    Method(usize, usize),
+    /// Enumeration values are defined in the scope enclosing their enumeration.
+    EnumValue,
+    Package,
+}
+
+#[derive(Debug)]
+struct MessageEntry {
+    full_name: String,
+    file: usize,
+    /// The indices of the message, and of each message containing it, in the `message_type` list
+    /// of the file and the `nested_type` lists of the containing messages.
+    path: Vec<usize>,
+    parent: Option<usize>,
+    nested_messages: Vec<usize>,
+    nested_enums: Vec<usize>,
+    /// The message or enumeration type of each field, in field order.
+    field_types: Vec<Option<TypeIndex>>,
+    fields_by_number: HashMap<u32, usize>,
+    fields_by_name: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct EnumEntry {
+    full_name: String,
+    file: usize,
+    /// The containing message, if the enumeration is nested.
+    parent: Option<usize>,
+    /// The index of the enumeration in the `enum_type` list of the file or containing message.
+    index: usize,
+}
+
+#[derive(Debug)]
+struct ServiceEntry {
+    full_name: String,
+    file: usize,
+    /// The index of the service in the `service` list of the file.
+    index: usize,
+    /// The input and output message types of each method, in method order.
+    method_types: Vec<(usize, usize)>,
+}
+
+/// A set of `.proto` file descriptors, indexed by fully-qualified name.
+///
+/// Messages, enumerations, fields, oneofs, services and methods are named without a leading `.`,
+/// for example `google.protobuf.Timestamp` or `google.protobuf.Timestamp.seconds`. Lookups also
+/// accept the leading `.` used by the `type_name` of fields.
+#[derive(Debug)]
+pub struct DescriptorPool {
+    files: Vec<FileDescriptorProto>,
+    /// The index of each source location in the file's `source_code_info`, by path.
+    locations: Vec<HashMap<Vec<i32>, usize>>,
+    messages: Vec<MessageEntry>,
+    enums: Vec<EnumEntry>,
+    services: Vec<ServiceEntry>,
+    names: HashMap<String, Symbol>,
+}
+
+impl DescriptorPool {
+
+    /// Creates a descriptor pool from a set of file descriptors, such as the output of
+    /// `protoc --descriptor_set_out --include_imports`.
+    ///
+    /// Type references of fields, extensions and methods may be relative, in which case they are
+    /// resolved according to the Protobuf scoping rules. All type references in the file
+    /// descriptors held by the pool are replaced with fully-qualified names with a leading `.`,
+    /// and fields referring to a type without a field type are given the kind of the type.
+    ///
+    /// Fails if a name is defined more than once, or if a reference can not be resolved to a type
+    /// of the expected kind.
+    pub fn new(file_set: FileDescriptorSet) -> Result<DescriptorPool, DescriptorError> {
+        let mut pool = DescriptorPool {
+            locations: file_set.file.iter().map(|file| {
+                file.source_code_info.as_ref().map_or_else(HashMap::new, |info| {
+                    info.location.iter().enumerate().map(|(idx, location)| (location.path.clone(), idx)).collect()
+                })
+            }).collect(),
+            files: file_set.file,
+            messages: Vec::new(),
+            enums: Vec::new(),
+            services: Vec::new(),
+            names: HashMap::new(),
+        };
+
+        {
+            let DescriptorPool { ref files, ref mut messages, ref mut enums, ref mut services, ref mut names, .. } = pool;
+            for (file_idx, file) in files.iter().enumerate() {
+                let package = file.package.as_ref().map_or("", String::as_str);
+                let mut index = Index {
+                    file: file_idx,
+                    messages: messages,
+                    enums: enums,
+                    services: services,
+                    names: names,
+                };
+                index.add_package(package)?;
+                for (idx, message) in file.message_type.iter().enumerate() {
+                    index.add_message(package, None, vec![idx], message)?;
+                }
+                for (idx, enum_type) in file.enum_type.iter().enumerate() {
+                    index.add_enum(package, None, idx, enum_type)?;
+                }
+                for (idx, service) in file.service.iter().enumerate() {
+                    index.add_service(package, idx, service)?;
+                }
+            }
+        }
+
+        {
+            let DescriptorPool { ref mut files, ref mut messages, ref mut services, ref names, .. } = pool;
+            for entry in messages.iter_mut() {
+                let message = message_mut(files, entry.file, &entry.path);
+                entry.field_types = message.field.iter_mut().map(|field| {
+                    resolve_field(names, &entry.full_name, field)
+                }).collect::<Result<_, _>>()?;
+                for extension in &mut message.extension {
+                    resolve_extension(names, &entry.full_name, extension)?;
+                }
+            }
+            for entry in services.iter_mut() {
+                let file = &mut files[entry.file];
+                let package = file.package.as_ref().map_or("", String::as_str);
+                let service = &mut file.service[entry.index];
+                entry.method_types = service.method.iter_mut().map(|method| {
+                    resolve_method(names, package, &entry.full_name, method)
+                }).collect::<Result<_, _>>()?;
+            }
+            for file in files.iter_mut() {
+                let package = file.package.as_ref().map_or("", String::as_str);
+                for extension in &mut file.extension {
+                    resolve_extension(names, package, extension)?;
+                }
+            }
+        }
+
+        Ok(pool)
+    }
+
+    /// Returns the file descriptors in the pool.
+    pub fn files(&self) -> &[FileDescriptorProto] {
+        &self.files
+    }
+
+    /// Returns all message types in the pool, including nested and map entry messages.
+    pub fn messages(&self) -> Vec<MessageDescriptor> {
+        (0..self.messages.len()).map(|index| self.message(index)).collect()
+    }
+
+    /// Returns all enumeration types in the pool, including nested enumerations.
+    pub fn enums(&self) -> Vec<EnumDescriptor> {
+        (0..self.enums.len()).map(|index| self.enumeration(index)).collect()
+    }
+
+    /// Returns all services in the pool.
+    pub fn services(&self) -> Vec<ServiceDescriptor> {
+        (0..self.services.len()).map(|index| self.service(index)).collect()
+    }
+
+    /// Returns the message type with the fully-qualified name.
+    pub fn get_message(&self, name: &str) -> Option<MessageDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Type(TypeIndex::Message(index))) => Some(self.message(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the enumeration type with the fully-qualified name.
+    pub fn get_enum(&self, name: &str) -> Option<EnumDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Type(TypeIndex::Enum(index))) => Some(self.enumeration(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the message field with the fully-qualified name, for example
+    /// `google.protobuf.Timestamp.seconds`.
+    pub fn get_field(&self, name: &str) -> Option<FieldDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Field(message, index)) => Some(self.message(message).field(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the oneof with the fully-qualified name.
+    pub fn get_oneof(&self, name: &str) -> Option<OneofDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Oneof(message, index)) => Some(OneofDescriptor { message: self.message(message), index: index }),
+            _ => None,
+        }
+    }
+
+    /// Returns the service with the fully-qualified name.
+    pub fn get_service(&self, name: &str) -> Option<ServiceDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Service(index)) => Some(self.service(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the method with the fully-qualified name, for example `foo.Greeter.SayHello`.
+    pub fn get_method(&self, name: &str) -> Option<MethodDescriptor> {
+        match self.names.get(strip_dot(name)) {
+            Some(&Symbol::Method(service, index)) => Some(MethodDescriptor { service: self.service(service), index: index }),
+            _ => None,
+        }
+    }
+
+    fn message(&self, index: usize) -> MessageDescriptor {
+        MessageDescriptor { pool: self, index: index }
+    }
+
+    fn enumeration(&self, index: usize) -> EnumDescriptor {
+        EnumDescriptor { pool: self, index: index }
+    }
+
+    fn service(&self, index: usize) -> ServiceDescriptor {
+        ServiceDescriptor { pool: self, index: index }
+    }
+
+    /// Returns the comments of the definition at the source code path in a file, or empty
+    /// comments if the file has no source code info.
+    fn comments(&self, file: usize, path: &[i32]) -> Comments {
+        let location = self.locations[file].get(path).and_then(|&idx| {
+            self.files[file].source_code_info.as_ref().map(|info| &info.location[idx])
+        });
+        location.map_or_else(Comments::default, Comments::from_location)
+    }
+}
+
+/// Adds the definitions in a file to the pool indexes.
+struct Index<'a> {
+    file: usize,
+    messages: &'a mut Vec<MessageEntry>,
+    enums: &'a mut Vec<EnumEntry>,
+    services: &'a mut Vec<ServiceEntry>,
+    names: &'a mut HashMap<String, Symbol>,
+}
+
+impl <'a> Index<'a> {
+
+    fn add_name(&mut self, full_name: &str, symbol: Symbol) -> Result<(), DescriptorError> {
+        if self.names.insert(full_name.to_string(), symbol).is_some() {
+            return Err(DescriptorError::DuplicateName(full_name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Adds a package, and each of its enclosing packages. Packages may be declared by any number
+    /// of files.
+    fn add_package(&mut self, package: &str) -> Result<(), DescriptorError> {
+        if package.is_empty() {
+            return Ok(());
+        }
+        let packages = package.match_indices('.').map(|(idx, _)| &package[..idx]).chain(Some(package));
+        for package in packages {
+            match self.names.get(package) {
+                Some(&Symbol::Package) => continue,
+                Some(_) => return Err(DescriptorError::DuplicateName(package.to_string())),
+                None => (),
+            }
+            self.names.insert(package.to_string(), Symbol::Package);
+        }
+        Ok(())
+    }
+
+    fn add_message(&mut self,
+                   scope: &str,
+                   parent: Option<usize>,
+                   path: Vec<usize>,
+                   message: &DescriptorProto)
+                   -> Result<(), DescriptorError> {
+        let full_name = qualify(scope, message.name());
+        let message_idx = self.messages.len();
+        self.add_name(&full_name, Symbol::Type(TypeIndex::Message(message_idx)))?;
+
+        let mut fields_by_number = HashMap::new();
+        let mut fields_by_name = HashMap::new();
+        for (idx, field) in message.field.iter().enumerate() {
+            self.add_name(&qualify(&full_name, field.name()), Symbol::Field(message_idx, idx))?;
+            fields_by_number.insert(field.number() as u32, idx);
+            fields_by_name.insert(field.name().to_string(), idx);
+        }
+        for (idx, oneof) in message.oneof_decl.iter().enumerate() {
+            self.add_name(&qualify(&full_name, oneof.name()), Symbol::Oneof(message_idx, idx))?;
+        }
+
+        self.messages.push(MessageEntry {
+            full_name: full_name.clone(),
+            file: self.file,
+            path: path.clone(),
+            parent: parent,
+            nested_messages: Vec::new(),
+            nested_enums: Vec::new(),
+            field_types: Vec::new(),
+            fields_by_number: fields_by_number,
+            fields_by_name: fields_by_name,
+        });
+
+        for (idx, nested) in message.nested_type.iter().enumerate() {
+            let nested_idx = self.messages.len();
+            self.messages[message_idx].nested_messages.push(nested_idx);
+            let mut nested_path = path.clone();
+            nested_path.push(idx);
+            self.add_message(&full_name, Some(message_idx), nested_path, nested)?;
+        }
+        for (idx, enum_type) in message.enum_type.iter().enumerate() {
+            let enum_idx = self.enums.len();
+            self.messages[message_idx].nested_enums.push(enum_idx);
+            self.add_enum(&full_name, Some(message_idx), idx, enum_type)?;
+        }
+        Ok(())
+    }
+
+    fn add_enum(&mut self,
+                scope: &str,
+                parent: Option<usize>,
+                index: usize,
+                enum_type: &EnumDescriptorProto)
+                -> Result<(), DescriptorError> {
+        let full_name = qualify(scope, enum_type.name());
+        let enum_idx = self.enums.len();
+        self.add_name(&full_name, Symbol::Type(TypeIndex::Enum(enum_idx)))?;
+        for value in &enum_type.value {
+            self.add_name(&qualify(scope, value.name()), Symbol::EnumValue)?;
+        }
+        self.enums.push(EnumEntry {
+            full_name: full_name,
+            file: self.file,
+            parent: parent,
+            index: index,
+        });
+        Ok(())
+    }
+
+    fn add_service(&mut self,
+                   package: &str,
+                   index: usize,
+                   service: &ServiceDescriptorProto)
+                   -> Result<(), DescriptorError> {
+        let full_name = qualify(package, service.name());
+        let service_idx = self.services.len();
+        self.add_name(&full_name, Symbol::Service(service_idx))?;
+        for (idx, method) in service.method.iter().enumerate() {
+            self.add_name(&qualify(&full_name, method.name()), Symbol::Method(service_idx, idx))?;
+        }
+        self.services.push(ServiceEntry {
+            full_name: full_name,
+            file: self.file,
+            index: index,
+            method_types: Vec::new(),
+        });
+        Ok(())
+    }
+}
+
+/// Returns the message at the path in a file.
+fn message_mut<'a>(files: &'a mut [FileDescriptorProto], file: usize, path: &[usize]) -> &'a mut DescriptorProto {
+    let mut message = &mut files[file].message_type[path[0]];
+    for &idx in &path[1..] {
+        message = &mut message.nested_type[idx];
+    }
+    message
+}
+
+/// Resolves the type name of a field in a message or package scope, and returns the field type.
+fn resolve_field(names: &HashMap<String, Symbol>,
+                 scope: &str,
+                 field: &mut FieldDescriptorProto)
+                 -> Result<Option<TypeIndex>, DescriptorError> {
+    let referrer = qualify(scope, field.name());
+    let ty = match field.type_name {
+        Some(ref mut type_name) => resolve_type(names, scope, &referrer, type_name)?,
+        None => return Ok(None),
+    };
+    match (field.type_(), ty) {
+        (Some(Type::TypeMessage), TypeIndex::Message(_)) |
+        (Some(Type::TypeGroup), TypeIndex::Message(_)) |
+        (Some(Type::TypeEnum), TypeIndex::Enum(_)) => (),
+        // Fields parsed from `.proto` files do not know the kind of the type they refer to.
+        (None, TypeIndex::Message(_)) => field.type_ = Some(Type::TypeMessage as i32),
+        (None, TypeIndex::Enum(_)) => field.type_ = Some(Type::TypeEnum as i32),
+        _ => return Err(DescriptorError::InvalidType {
+            referrer: referrer,
+            type_name: field.type_name().to_string(),
+        }),
+    }
+    Ok(Some(ty))
+}
+
+/// Resolves the extended message type and the type name of an extension field.
+fn resolve_extension(names: &HashMap<String, Symbol>,
+                     scope: &str,
+                     field: &mut FieldDescriptorProto)
+                     -> Result<(), DescriptorError> {
+    let referrer = qualify(scope, field.name());
+    if let Some(ref mut extendee) = field.extendee {
+        resolve_message(names, scope, &referrer, extendee)?;
+    }
+    resolve_field(names, scope, field).map(|_| ())
+}
+
+/// Resolves the input and output message types of a method, and returns their indices.
+fn resolve_method(names: &HashMap<String, Symbol>,
+                  package: &str,
+                  service: &str,
+                  method: &mut MethodDescriptorProto)
+                  -> Result<(usize, usize), DescriptorError> {
+    let referrer = qualify(service, method.name());
+    let input_type = resolve_message(names, package, &referrer, method.input_type.get_or_insert_with(String::new))?;
+    let output_type = resolve_message(names, package, &referrer, method.output_type.get_or_insert_with(String::new))?;
+    Ok((input_type, output_type))
+}
+
+fn resolve_message(names: &HashMap<String, Symbol>,
+                   scope: &str,
+                   referrer: &str,
+                   type_name: &mut String)
+                   -> Result<usize, DescriptorError> {
+    match resolve_type(names, scope, referrer, type_name)? {
+        TypeIndex::Message(index) => Ok(index),
+        TypeIndex::Enum(_) => Err(DescriptorError::InvalidType {
+            referrer: referrer.to_string(),
+            type_name: type_name.clone(),
+        }),
+    }
+}
+
+/// Resolves a type name referred to from a message or package scope, and replaces it with the
+/// fully-qualified name of the type.
+///
+/// As in protoc, the first component of a relative name is looked up in the scope and then in
+/// each enclosing scope, so that the innermost message, enumeration or package with that name is
+/// found. The rest of the name must then be defined within it.
+fn resolve_type(names: &HashMap<String, Symbol>,
+                scope: &str,
+                referrer: &str,
+                type_name: &mut String)
+                -> Result<TypeIndex, DescriptorError> {
+    let lookup = |full_name: &str| match names.get(full_name) {
+        Some(&Symbol::Type(ty)) => Some(ty),
+        _ => None,
+    };
+
+    let full_name = if type_name.starts_with('.') {
+        type_name[1..].to_string()
+    } else {
+        let first = type_name.split('.').next().unwrap();
+        let mut scope = scope;
+        loop {
+            match names.get(&qualify(scope, first)) {
+                Some(&Symbol::Type(_)) | Some(&Symbol::Package) => break qualify(scope, type_name),
+                _ if scope.is_empty() => break type_name.clone(),
+                _ => scope = scope.rfind('.').map_or("", |idx| &scope[..idx]),
+            }
+        }
+    };
+
+    match lookup(&full_name) {
+        Some(ty) => {
+            *type_name = format!(".{}", full_name);
+            Ok(ty)
+        },
+        None => Err(DescriptorError::UnresolvedType {
+            referrer: referrer.to_string(),
+            type_name: type_name.clone(),
+        }),
+    }
+}
+
+/// Returns the fully-qualified name of a definition in a package, message or service.
+pub fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn strip_dot(name: &str) -> &str {
+    if name.starts_with('.') { &name[1..] } else { name }
+}
+
+/// A message type in a `DescriptorPool`.
+#[derive(Clone, Copy)]
+pub struct MessageDescriptor<'a> {
+    pool: &'a DescriptorPool,
+    index: usize,
+}
+
+impl <'a> MessageDescriptor<'a> {
+
+    fn entry(&self) -> &'a MessageEntry {
+        &self.pool.messages[self.index]
+    }
+
+    /// Returns the pool containing the message type.
+    pub fn pool(&self) -> &'a DescriptorPool {
+        self.pool
+    }
+
+    /// Returns the index of the message type in the list returned by `DescriptorPool::messages`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the fully-qualified name of the message type, for example
+    /// `google.protobuf.Timestamp`.
+    pub fn full_name(&self) -> &'a str {
+        &self.entry().full_name
+    }
+
+    /// Returns the name of the message type, for example `Timestamp`.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the package of the file which defines the message type.
+    pub fn package_name(&self) -> &'a str {
+        self.file_descriptor_proto().package.as_ref().map_or("", String::as_str)
+    }
+
+    /// Returns the file descriptor which defines the message type.
+    pub fn file_descriptor_proto(&self) -> &'a FileDescriptorProto {
+        &self.pool.files[self.entry().file]
+    }
+
+    /// Returns the descriptor of the message type.
+    pub fn descriptor_proto(&self) -> &'a DescriptorProto {
+        let entry = self.entry();
+        let mut message = &self.pool.files[entry.file].message_type[entry.path[0]];
+        for &idx in &entry.path[1..] {
+            message = &message.nested_type[idx];
+        }
+        message
+    }
+
+    /// Returns the message type containing the message type, if it is nested.
+    pub fn parent_message(&self) -> Option<MessageDescriptor<'a>> {
+        self.entry().parent.map(|index| self.pool.message(index))
+    }
+
+    /// Returns the message types nested in the message type, including map entry messages.
+    pub fn nested_messages(&self) -> Vec<MessageDescriptor<'a>> {
+        self.entry().nested_messages.iter().map(|&index| self.pool.message(index)).collect()
+    }
+
+    /// Returns the enumeration types nested in the message type.
+    pub fn nested_enums(&self) -> Vec<EnumDescriptor<'a>> {
+        self.entry().nested_enums.iter().map(|&index| self.pool.enumeration(index)).collect()
+    }
+
+    /// Returns the comments of the message type in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        self.pool.comments(self.entry().file, &self.source_path())
+    }
+
+    /// Returns `true` if the message type is defined in a `proto3` file.
+    pub fn is_proto3(&self) -> bool {
+        self.file_descriptor_proto().syntax.as_ref().map_or(false, |syntax| syntax == "proto3")
+    }
+
+    /// Returns `true` if the message type is the generated entry type of a map field.
+    pub fn is_map_entry(&self) -> bool {
+        self.descriptor_proto().options.as_ref().map_or(false, |options| options.map_entry())
+    }
+
+    /// Returns the fields of the message type, in the order they are defined.
+    pub fn fields(&self) -> Vec<FieldDescriptor<'a>> {
+        (0..self.descriptor_proto().field.len()).map(|index| self.field(index)).collect()
+    }
+
+    /// Returns the field with the number.
+    pub fn get_field(&self, number: u32) -> Option<FieldDescriptor<'a>> {
+        self.entry().fields_by_number.get(&number).map(|&index| self.field(index))
+    }
+
+    /// Returns the field with the name.
+    pub fn get_field_by_name(&self, name: &str) -> Option<FieldDescriptor<'a>> {
+        self.entry().fields_by_name.get(name).map(|&index| self.field(index))
+    }
+
+    /// Returns the oneofs of the message type, in the order they are defined.
+    pub fn oneofs(&self) -> Vec<OneofDescriptor<'a>> {
+        (0..self.descriptor_proto().oneof_decl.len()).map(|index| OneofDescriptor { message: *self, index: index }).collect()
+    }
+
+    fn field(&self, index: usize) -> FieldDescriptor<'a> {
+        FieldDescriptor { message: *self, index: index }
+    }
+
+    /// Returns the path of the message type in the source code info of its file.
+    fn source_path(&self) -> Vec<i32> {
+        let path = &self.entry().path;
+        let mut source_path = vec![4, path[0] as i32];
+        for &idx in &path[1..] {
+            source_path.push(3);
+            source_path.push(idx as i32);
+        }
+        source_path
+    }
+}
+
+impl <'a> PartialEq for MessageDescriptor<'a> {
+    fn eq(&self, other: &MessageDescriptor<'a>) -> bool {
+        ptr::eq(self.pool, other.pool) && self.index == other.index
+    }
+}
+
+impl <'a> fmt::Debug for MessageDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("MessageDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// A field of a message type in a `DescriptorPool`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FieldDescriptor<'a> {
+    message: MessageDescriptor<'a>,
+    index: usize,
+}
+
+impl <'a> FieldDescriptor<'a> {
+
+    /// Returns the message type containing the field.
+    pub fn containing_message(&self) -> MessageDescriptor<'a> {
+        self.message
+    }
+
+    /// Returns the descriptor of the field.
+    pub fn descriptor_proto(&self) -> &'a FieldDescriptorProto {
+        &self.message.descriptor_proto().field[self.index]
+    }
+
+    /// Returns the fully-qualified name of the field.
+    pub fn full_name(&self) -> String {
+        qualify(self.message.full_name(), self.name())
+    }
+
+    /// Returns the name of the field.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the field number.
+    pub fn number(&self) -> u32 {
+        self.descriptor_proto().number() as u32
+    }
+
+    /// Returns the type of the field.
+    pub fn ty(&self) -> Type {
+        self.descriptor_proto().type_().expect("unknown field type")
+    }
+
+    /// Returns the comments of the field in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        let mut path = self.message.source_path();
+        path.push(2);
+        path.push(self.index as i32);
+        self.message.pool.comments(self.message.entry().file, &path)
+    }
+
+    /// Returns `true` if the field is `repeated`, including map fields.
+    pub fn is_repeated(&self) -> bool {
+        self.descriptor_proto().label() == Some(Label::LabelRepeated)
+    }
+
+    /// Returns `true` if the field is a map field.
+    pub fn is_map(&self) -> bool {
+        self.is_repeated() && self.message_type().map_or(false, |message| message.is_map_entry())
+    }
+
+    /// Returns `true` if the field is a repeated scalar field which is encoded in packed format.
+    /// Fields in `proto3` files are packed unless the `packed` option is `false`.
+    pub fn is_packed(&self) -> bool {
+        if !self.is_repeated() {
+            return false;
+        }
+        match self.ty() {
+            Type::TypeString | Type::TypeBytes | Type::TypeMessage | Type::TypeGroup => return false,
+            _ => (),
+        }
+        match self.descriptor_proto().options.as_ref().and_then(|options| options.packed) {
+            Some(packed) => packed,
+            None => self.message.is_proto3(),
+        }
+    }
+
+    /// Returns `true` if the field tracks whether it is set, rather than only whether it holds a
+    /// non-default value: singular fields of `proto2` messages, message fields, and oneof fields.
+    pub fn has_presence(&self) -> bool {
+        !self.is_repeated() && (!self.message.is_proto3()
+                                || self.ty() == Type::TypeMessage
+                                || self.ty() == Type::TypeGroup
+                                || self.oneof_index().is_some())
+    }
+
+    /// Returns the index of the oneof containing the field in the `oneof_decl` list of the
+    /// message type, if the field is a member of a oneof.
+    pub fn oneof_index(&self) -> Option<usize> {
+        self.descriptor_proto().oneof_index.map(|index| index as usize)
+    }
+
+    /// Returns the oneof containing the field, if the field is a member of a oneof.
+    pub fn containing_oneof(&self) -> Option<OneofDescriptor<'a>> {
+        self.oneof_index().map(|index| OneofDescriptor { message: self.message, index: index })
+    }
+
+    /// Returns the message type of a message, group or map field.
+    pub fn message_type(&self) -> Option<MessageDescriptor<'a>> {
+        match self.message.entry().field_types[self.index] {
+            Some(TypeIndex::Message(index)) => Some(self.message.pool.message(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the enumeration type of an enumeration field.
+    pub fn enum_type(&self) -> Option<EnumDescriptor<'a>> {
+        match self.message.entry().field_types[self.index] {
+            Some(TypeIndex::Enum(index)) => Some(self.message.pool.enumeration(index)),
+            _ => None,
+        }
+    }
+}
+
+impl <'a> fmt::Debug for FieldDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("FieldDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// A oneof of a message type in a `DescriptorPool`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct OneofDescriptor<'a> {
+    message: MessageDescriptor<'a>,
+    index: usize,
+}
+
+impl <'a> OneofDescriptor<'a> {
+
+    /// Returns the message type containing the oneof.
+    pub fn containing_message(&self) -> MessageDescriptor<'a> {
+        self.message
+    }
+
+    /// Returns the descriptor of the oneof.
+    pub fn descriptor_proto(&self) -> &'a OneofDescriptorProto {
+        &self.message.descriptor_proto().oneof_decl[self.index]
+    }
+
+    /// Returns the fully-qualified name of the oneof.
+    pub fn full_name(&self) -> String {
+        qualify(self.message.full_name(), self.name())
+    }
+
+    /// Returns the name of the oneof.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the fields which are members of the oneof.
+    pub fn fields(&self) -> Vec<FieldDescriptor<'a>> {
+        self.message.fields().into_iter().filter(|field| field.oneof_index() == Some(self.index)).collect()
+    }
+
+    /// Returns the comments of the oneof in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        let mut path = self.message.source_path();
+        path.push(8);
+        path.push(self.index as i32);
+        self.message.pool.comments(self.message.entry().file, &path)
+    }
+}
+
+impl <'a> fmt::Debug for OneofDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OneofDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// An enumeration type in a `DescriptorPool`.
+#[derive(Clone, Copy)]
+pub struct EnumDescriptor<'a> {
+    pool: &'a DescriptorPool,
+    index: usize,
+}
+
+impl <'a> EnumDescriptor<'a> {
+
+    fn entry(&self) -> &'a EnumEntry {
+        &self.pool.enums[self.index]
+    }
+
+    /// Returns the fully-qualified name of the enumeration type.
+    pub fn full_name(&self) -> &'a str {
+        &self.entry().full_name
+    }
+
+    /// Returns the name of the enumeration type.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the package of the file which defines the enumeration type.
+    pub fn package_name(&self) -> &'a str {
+        self.file_descriptor_proto().package.as_ref().map_or("", String::as_str)
+    }
+
+    /// Returns the file descriptor which defines the enumeration type.
+    pub fn file_descriptor_proto(&self) -> &'a FileDescriptorProto {
+        &self.pool.files[self.entry().file]
+    }
+
+    /// Returns the descriptor of the enumeration type.
+    pub fn descriptor_proto(&self) -> &'a EnumDescriptorProto {
+        let entry = self.entry();
+        match entry.parent {
+            Some(parent) => &self.pool.message(parent).descriptor_proto().enum_type[entry.index],
+            None => &self.pool.files[entry.file].enum_type[entry.index],
+        }
+    }
+
+    /// Returns the message type containing the enumeration type, if it is nested.
+    pub fn parent_message(&self) -> Option<MessageDescriptor<'a>> {
+        self.entry().parent.map(|index| self.pool.message(index))
+    }
+
+    /// Returns the comments of the enumeration type in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        let entry = self.entry();
+        let path = match self.parent_message() {
+            Some(parent) => {
+                let mut path = parent.source_path();
+                path.push(4);
+                path.push(entry.index as i32);
+                path
+            },
+            None => vec![5, entry.index as i32],
+        };
+        self.pool.comments(entry.file, &path)
+    }
+
+    /// Returns the number of the value with the name.
+    pub fn value_number(&self, name: &str) -> Option<i32> {
+        self.descriptor_proto().value.iter().find(|value| value.name() == name).map(|value| value.number())
+    }
+
+    /// Returns the name of the first value with the number.
+    pub fn value_name(&self, number: i32) -> Option<&'a str> {
+        self.descriptor_proto().value.iter().find(|value| value.number() == number).map(|value| value.name())
+    }
+
+    /// Returns the default value of the enumeration type, which is its first value.
+    pub fn default_value(&self) -> i32 {
+        self.descriptor_proto().value.first().map_or(0, |value| value.number())
+    }
+}
+
+impl <'a> PartialEq for EnumDescriptor<'a> {
+    fn eq(&self, other: &EnumDescriptor<'a>) -> bool {
+        ptr::eq(self.pool, other.pool) && self.index == other.index
+    }
+}
+
+impl <'a> fmt::Debug for EnumDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("EnumDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// A service in a `DescriptorPool`.
+#[derive(Clone, Copy)]
+pub struct ServiceDescriptor<'a> {
+    pool: &'a DescriptorPool,
+    index: usize,
+}
+
+impl <'a> ServiceDescriptor<'a> {
+
+    fn entry(&self) -> &'a ServiceEntry {
+        &self.pool.services[self.index]
+    }
+
+    /// Returns the pool containing the service.
+    pub fn pool(&self) -> &'a DescriptorPool {
+        self.pool
+    }
+
+    /// Returns the fully-qualified name of the service.
+    pub fn full_name(&self) -> &'a str {
+        &self.entry().full_name
+    }
+
+    /// Returns the name of the service.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the file descriptor which defines the service.
+    pub fn file_descriptor_proto(&self) -> &'a FileDescriptorProto {
+        &self.pool.files[self.entry().file]
+    }
+
+    /// Returns the descriptor of the service.
+    pub fn descriptor_proto(&self) -> &'a ServiceDescriptorProto {
+        &self.file_descriptor_proto().service[self.entry().index]
+    }
+
+    /// Returns the methods of the service, in the order they are defined.
+    pub fn methods(&self) -> Vec<MethodDescriptor<'a>> {
+        (0..self.entry().method_types.len()).map(|index| MethodDescriptor { service: *self, index: index }).collect()
+    }
+
+    /// Returns the comments of the service in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        let entry = self.entry();
+        self.pool.comments(entry.file, &[6, entry.index as i32])
+    }
+}
+
+impl <'a> PartialEq for ServiceDescriptor<'a> {
+    fn eq(&self, other: &ServiceDescriptor<'a>) -> bool {
+        ptr::eq(self.pool, other.pool) && self.index == other.index
+    }
+}
+
+impl <'a> fmt::Debug for ServiceDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ServiceDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// A method of a service in a `DescriptorPool`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct MethodDescriptor<'a> {
+    service: ServiceDescriptor<'a>,
+    index: usize,
+}
+
+impl <'a> MethodDescriptor<'a> {
+
+    /// Returns the service containing the method.
+    pub fn parent_service(&self) -> ServiceDescriptor<'a> {
+        self.service
+    }
+
+    /// Returns the descriptor of the method.
+    pub fn descriptor_proto(&self) -> &'a MethodDescriptorProto {
+        &self.service.descriptor_proto().method[self.index]
+    }
+
+    /// Returns the fully-qualified name of the method.
+    pub fn full_name(&self) -> String {
+        qualify(self.service.full_name(), self.name())
+    }
+
+    /// Returns the name of the method.
+    pub fn name(&self) -> &'a str {
+        self.descriptor_proto().name()
+    }
+
+    /// Returns the input message type of the method.
+    pub fn input_type(&self) -> MessageDescriptor<'a> {
+        self.service.pool.message(self.service.entry().method_types[self.index].0)
+    }
+
+    /// Returns the output message type of the method.
+    pub fn output_type(&self) -> MessageDescriptor<'a> {
+        self.service.pool.message(self.service.entry().method_types[self.index].1)
+    }
+
+    /// Returns the comments of the method in its `.proto` file.
+    pub fn comments(&self) -> Comments {
+        let entry = self.service.entry();
+        self.service.pool.comments(entry.file, &[6, entry.index as i32, 2, self.index as i32])
+    }
+}
+
+impl <'a> fmt::Debug for MethodDescriptor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("MethodDescriptor").field(&self.full_name()).finish()
+    }
+}
+
+/// An error building a `DescriptorPool` from a set of file descriptors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// More than one definition has the fully-qualified name.
+    DuplicateName(String),
+    /// A field, extension or method refers to a type which is not defined in the pool.
+    UnresolvedType {
+        /// The fully-qualified name of the field, extension or method.
+        referrer: String,
+        /// The name of the type.
+        type_name: String,
+    },
+    /// A field, extension or method refers to a type of the wrong kind, for example a message
+    /// field referring to an enumeration type.
+    InvalidType {
+        /// The fully-qualified name of the field, extension or method.
+        referrer: String,
+        /// The name of the type.
+        type_name: String,
+    },
+    /// A field of a borrowed message refers to a message type which has no borrowed variant.
+    /// Extern and well-known types can not be borrowed, and other message types must match the
+    /// `borrowed` paths of the `CodeGeneratorConfig`.
+    NotBorrowed {
+        /// The fully-qualified name of the field.
+        referrer: String,
+        /// The name of the message type.
+        type_name: String,
+    },
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DescriptorError::DuplicateName(ref name) => write!(f, "duplicate name: {}", name),
+            DescriptorError::UnresolvedType { ref referrer, ref type_name } => {
+                write!(f, "{} refers to an undefined type: {}", referrer, type_name)
+            },
+            DescriptorError::InvalidType { ref referrer, ref type_name } => {
+                write!(f, "{} refers to a type of the wrong kind: {}", referrer, type_name)
+            },
+            DescriptorError::NotBorrowed { ref referrer, ref type_name } => {
+                write!(f, "{} refers to a message type without a borrowed variant: {}", referrer, type_name)
+            },
+        }
+    }
+}
+
+impl error::Error for DescriptorError {
+    fn description(&self) -> &str {
+        "invalid file descriptor set"
+    }
+}
+
+/// Decodes a string with C-style escape sequences, such as the `default_value` of a `bytes`
+/// field in a `FieldDescriptorProto`, or the contents of a string literal in a `.proto` file.
+///
+/// Octal escapes have up to three digits, and hexadecimal escapes up to two. Unicode escapes
+/// (`\u` and `\U`) are encoded as UTF-8, and a surrogate pair of `\u` escapes is combined into a
+/// single code point.
+pub fn unescape_c_bytes(escaped: &str) -> Vec<u8> {
+    let bytes = escaped.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        i += 1;
+        if c != b'\\' {
+            unescaped.push(c);
+            continue;
+        }
+        let c = match bytes.get(i) {
+            Some(&c) => c,
+            None => {
+                unescaped.push(b'\\');
+                break;
+            },
+        };
+        i += 1;
+        match c {
+            b'0'...b'7' => {
+                let (code, len) = parse_digits(&bytes[i..], 8, 2, (c - b'0') as u32);
+                i += len;
+                unescaped.push(code as u8);
+            },
+            b'x' => {
+                let (code, len) = parse_digits(&bytes[i..], 16, 2, 0);
+                i += len;
+                unescaped.push(code as u8);
+            },
+            b'u' | b'U' => {
+                let (mut code, len) = parse_digits(&bytes[i..], 16, if c == b'u' { 4 } else { 8 }, 0);
+                i += len;
+                // Combine a surrogate pair into a single code point.
+                if code >= 0xd800 && code < 0xdc00 && bytes[i..].starts_with(b"\\u") {
+                    let (low, len) = parse_digits(&bytes[i + 2..], 16, 4, 0);
+                    if len == 4 && low >= 0xdc00 && low < 0xe000 {
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        i += 6;
+                    }
+                }
+                let c = char::from_u32(code).unwrap_or('\u{fffd}');
+                let mut buf = [0; 4];
+                unescaped.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            },
+            b'a' => unescaped.push(b'\x07'),
+            b'b' => unescaped.push(b'\x08'),
+            b'f' => unescaped.push(b'\x0c'),
+            b'n' => unescaped.push(b'\n'),
+            b'r' => unescaped.push(b'\r'),
+            b't' => unescaped.push(b'\t'),
+            b'v' => unescaped.push(b'\x0b'),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Appends up to `max` digits in the radix to `code`. Returns the result, and the number of
+/// digits consumed.
+fn parse_digits(bytes: &[u8], radix: u32, max: usize, mut code: u32) -> (u32, usize) {
+    let mut len = 0;
+    while len < max {
+        match bytes.get(len).and_then(|&b| (b as char).to_digit(radix)) {
+            Some(digit) => code = code * radix + digit,
+            None => break,
+        }
+        len += 1;
+    }
+    (code, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use google::protobuf::{EnumValueDescriptorProto, SourceCodeInfo};
+    use google::protobuf::source_code_info::Location;
+    use super::*;
+
+    fn file(name: &str, package: Option<&str>, message_type: Vec<DescriptorProto>) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: package.map(str::to_string),
+            message_type: message_type,
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    fn message(name: &str, type_name: Option<&str>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: type_name.into_iter().map(|type_name| FieldDescriptorProto {
+                name: Some("field".to_string()),
+                number: Some(1),
+                label: Some(Label::LabelOptional as i32),
+                type_: Some(Type::TypeMessage as i32),
+                type_name: Some(type_name.to_string()),
+                ..FieldDescriptorProto::default()
+            }).collect(),
+            ..DescriptorProto::default()
+        }
+    }
+
+    #[test]
+    fn resolve_types() {
+        let pool = DescriptorPool::new(FileDescriptorSet {
+            file: vec![
+                file("a.proto", Some("foo.bar"), vec![message("A", Some(".B"))]),
+                file("b.proto", None, vec![message("B", None)]),
+            ],
+        }).unwrap();
+
+        let a = pool.get_message(".foo.bar.A").unwrap();
+        assert_eq!(a.full_name(), "foo.bar.A");
+        assert_eq!(a.name(), "A");
+        let b = a.get_field(1).unwrap().message_type().unwrap();
+        assert_eq!(b, pool.get_message("B").unwrap());
+        assert_eq!(b.file_descriptor_proto().name.as_ref().unwrap(), "b.proto");
+        assert!(pool.get_message("foo.bar.B").is_none());
+    }
+
+    #[test]
+    fn resolve_relative_types() {
+        let mut outer = message("Outer", Some("Inner"));
+        outer.nested_type.push(message("Inner", Some("bar.Top")));
+        outer.field[0].type_ = None;
+        let mut enum_field = outer.field[0].clone();
+        enum_field.name = Some("kind".to_string());
+        enum_field.number = Some(2);
+        enum_field.type_name = Some("Kind".to_string());
+        outer.field.push(enum_field);
+        outer.enum_type.push(EnumDescriptorProto { name: Some("Kind".to_string()), ..EnumDescriptorProto::default() });
+
+        let pool = DescriptorPool::new(FileDescriptorSet {
+            file: vec![file("a.proto", Some("foo.bar"), vec![outer, message("Top", None)])],
+        }).unwrap();
+
+        let outer = pool.get_message("foo.bar.Outer").unwrap();
+        let inner = pool.get_message("foo.bar.Outer.Inner").unwrap();
+        let field = outer.get_field(1).unwrap();
+        assert_eq!(field.message_type(), Some(inner));
+        assert_eq!(field.descriptor_proto().type_name(), ".foo.bar.Outer.Inner");
+        assert_eq!(field.ty(), Type::TypeMessage);
+        assert_eq!(outer.get_field(2).unwrap().enum_type(), pool.get_enum("foo.bar.Outer.Kind"));
+        assert_eq!(outer.get_field(2).unwrap().ty(), Type::TypeEnum);
+        assert_eq!(inner.get_field(1).unwrap().message_type(), pool.get_message("foo.bar.Top"));
+    }
+
+    #[test]
+    fn navigation() {
+        let mut outer = message("Outer", None);
+        outer.nested_type.push(message("Inner", None));
+        outer.enum_type.push(EnumDescriptorProto { name: Some("Kind".to_string()), ..EnumDescriptorProto::default() });
+        outer.field.push(FieldDescriptorProto {
+            name: Some("value".to_string()),
+            number: Some(1),
+            type_: Some(Type::TypeInt32 as i32),
+            oneof_index: Some(0),
+            ..FieldDescriptorProto::default()
+        });
+        outer.oneof_decl.push(OneofDescriptorProto { name: Some("choice".to_string()), ..OneofDescriptorProto::default() });
+
+        let mut file = file("a.proto", Some("foo"), vec![outer, message("Empty", None)]);
+        file.service.push(ServiceDescriptorProto {
+            name: Some("Service".to_string()),
+            method: vec![MethodDescriptorProto {
+                name: Some("Call".to_string()),
+                input_type: Some("Outer".to_string()),
+                output_type: Some(".foo.Empty".to_string()),
+                ..MethodDescriptorProto::default()
+            }],
+            ..ServiceDescriptorProto::default()
+        });
+        file.source_code_info = Some(SourceCodeInfo {
+            location: vec![
+                Location { path: vec![4, 0, 2, 0], leading_comments: Some(" A value.\n".to_string()), ..Location::default() },
+                Location { path: vec![6, 0, 2, 0], trailing_comments: Some(" Calls.\n".to_string()), ..Location::default() },
+            ],
+        });
+        let pool = DescriptorPool::new(FileDescriptorSet { file: vec![file] }).unwrap();
+
+        let outer = pool.get_message("foo.Outer").unwrap();
+        let inner = pool.get_message("foo.Outer.Inner").unwrap();
+        assert_eq!(outer.nested_messages(), vec![inner]);
+        assert_eq!(outer.nested_enums(), vec![pool.get_enum("foo.Outer.Kind").unwrap()]);
+        assert_eq!(inner.parent_message(), Some(outer));
+        assert_eq!(outer.parent_message(), None);
+        assert_eq!(pool.get_enum("foo.Outer.Kind").unwrap().parent_message(), Some(outer));
+
+        let field = pool.get_field("foo.Outer.value").unwrap();
+        let oneof = pool.get_oneof(".foo.Outer.choice").unwrap();
+        assert_eq!(field.containing_oneof(), Some(oneof));
+        assert_eq!(oneof.fields(), vec![field]);
+        assert_eq!(outer.oneofs(), vec![oneof]);
+        assert_eq!(field.comments().leading, vec![" A value.".to_string()]);
+        assert!(outer.comments().leading.is_empty());
+
+        let service = pool.get_service("foo.Service").unwrap();
+        let method = pool.get_method("foo.Service.Call").unwrap();
+        assert_eq!(pool.services(), vec![service]);
+        assert_eq!(service.methods(), vec![method]);
+        assert_eq!(method.parent_service(), service);
+        assert_eq!(method.input_type(), outer);
+        assert_eq!(method.output_type(), pool.get_message("foo.Empty").unwrap());
+        assert_eq!(method.descriptor_proto().input_type(), ".foo.Outer");
+        assert_eq!(method.comments().trailing, vec![" Calls.".to_string()]);
+
+        assert!(pool.get_message("foo.Outer.value").is_none());
+        assert!(pool.get_field("foo.Outer").is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let duplicate = DescriptorPool::new(FileDescriptorSet {
+            file: vec![
+                file("a.proto", Some("foo"), vec![message("A", None)]),
+                file("b.proto", Some("foo"), vec![message("A", None)]),
+            ],
+        });
+        assert_eq!(duplicate.err(), Some(DescriptorError::DuplicateName("foo.A".to_string())));
+
+        let unresolved = DescriptorPool::new(FileDescriptorSet {
+            file: vec![file("a.proto", Some("foo"), vec![message("A", Some(".foo.B"))])],
+        });
+        assert_eq!(unresolved.err(), Some(DescriptorError::UnresolvedType {
+            referrer: "foo.A.field".to_string(),
+            type_name: ".foo.B".to_string(),
+        }));
+
+        let mut a = message("A", Some("E"));
+        a.enum_type.push(EnumDescriptorProto { name: Some("E".to_string()), ..EnumDescriptorProto::default() });
+        let invalid = DescriptorPool::new(FileDescriptorSet {
+            file: vec![file("a.proto", Some("foo"), vec![a])],
+        });
+        assert_eq!(invalid.err(), Some(DescriptorError::InvalidType {
+            referrer: "foo.A.field".to_string(),
+            type_name: ".foo.A.E".to_string(),
+        }));
+
+        let mut a = message("A", Some(".foo.A"));
+        a.oneof_decl.push(OneofDescriptorProto { name: Some("field".to_string()), ..OneofDescriptorProto::default() });
+        let duplicate = DescriptorPool::new(FileDescriptorSet {
+            file: vec![file("a.proto", Some("foo"), vec![a])],
+        });
+        assert_eq!(duplicate.err(), Some(DescriptorError::DuplicateName("foo.A.field".to_string())));
+
+        // Enumeration values are defined in the scope enclosing their enumeration.
+        let enumeration = |name: &str| EnumDescriptorProto {
+            name: Some(name.to_string()),
+            value: vec![EnumValueDescriptorProto { name: Some("X".to_string()), ..Default::default() }],
+            ..EnumDescriptorProto::default()
+        };
+        let mut a = file("a.proto", Some("foo"), Vec::new());
+        a.enum_type = vec![enumeration("E1"), enumeration("E2")];
+        let duplicate = DescriptorPool::new(FileDescriptorSet { file: vec![a] });
+        assert_eq!(duplicate.err(), Some(DescriptorError::DuplicateName("foo.X".to_string())));
+
+        let duplicate = DescriptorPool::new(FileDescriptorSet {
+            file: vec![
+                file("a.proto", Some("foo.bar"), Vec::new()),
+                file("b.proto", None, vec![message("foo", None)]),
+            ],
+        });
+        assert_eq!(duplicate.err(), Some(DescriptorError::DuplicateName("foo".to_string())));
+
+        // Only the first component of a relative name is looked up in the enclosing scopes.
+        let mut a = message("A", Some("B.C"));
+        a.nested_type.push(message("B", None));
+        let mut b = message("B", None);
+        b.nested_type.push(message("C", None));
+        let unresolved = DescriptorPool::new(FileDescriptorSet {
+            file: vec![
+                file("a.proto", Some("foo"), vec![a]),
+                file("b.proto", None, vec![b]),
+            ],
+        });
+        assert_eq!(unresolved.err(), Some(DescriptorError::UnresolvedType {
+            referrer: "foo.A.field".to_string(),
+            type_name: "B.C".to_string(),
+        }));
+    }
+
+    #[test]
+    fn unescape() {
+        assert_eq!(unescape_c_bytes(r"a\tb\x41\101\0\u00e9"), b"a\tbAA\0\xc3\xa9".to_vec());
+        assert_eq!(unescape_c_bytes(r#"\'\"\\\377\x7"#), b"'\"\\\xff\x07".to_vec());
+        assert_eq!(unescape_c_bytes(r"\ud83d\ude00\U0001F600"), "\u{1f600}\u{1f600}".as_bytes().to_vec());
+        assert_eq!(unescape_c_bytes("caf\u{e9}\\"), b"caf\xc3\xa9\\".to_vec());
+    }
+}
@@ -0,0 +1,1014 @@
+//! Messages whose type is only known at runtime.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use bytes::{
+    Buf,
+    BufMut,
+    Take,
+};
+use prost::{
+    DecodeError,
+    EncodeError,
+    UnknownFields,
+};
+use prost::encoding::{
+    DecodeContext,
+    WireType,
+    check_end_group,
+    check_wire_type,
+    decode_key,
+    decode_varint,
+    encode_key,
+    encode_varint,
+    encoded_len_varint,
+    key_len,
+};
+
+use descriptor_pool::{
+    FieldDescriptor,
+    MessageDescriptor,
+    unescape_c_bytes,
+};
+use google::protobuf::field_descriptor_proto::Type;
+
+/// Matches a scalar field type and value, binding `$module` to the `prost::encoding` module of
+/// the field type and `$v` to the value.
+macro_rules! match_scalar {
+    ($ty:expr, $value:expr, [$($reference:tt)+] [$($binding:tt)+],
+     $module:ident, $v:ident => $body:expr, $other:ident => $fallback:expr) => (
+        match ($ty, $value) {
+            (Type::TypeDouble, $($reference)+ Value::F64($($binding)+ $v)) => { use prost::encoding::double as $module; $body },
+            (Type::TypeFloat, $($reference)+ Value::F32($($binding)+ $v)) => { use prost::encoding::float as $module; $body },
+            (Type::TypeInt64, $($reference)+ Value::I64($($binding)+ $v)) => { use prost::encoding::int64 as $module; $body },
+            (Type::TypeUint64, $($reference)+ Value::U64($($binding)+ $v)) => { use prost::encoding::uint64 as $module; $body },
+            (Type::TypeInt32, $($reference)+ Value::I32($($binding)+ $v)) => { use prost::encoding::int32 as $module; $body },
+            (Type::TypeFixed64, $($reference)+ Value::U64($($binding)+ $v)) => { use prost::encoding::fixed64 as $module; $body },
+            (Type::TypeFixed32, $($reference)+ Value::U32($($binding)+ $v)) => { use prost::encoding::fixed32 as $module; $body },
+            (Type::TypeBool, $($reference)+ Value::Bool($($binding)+ $v)) => { use prost::encoding::bool as $module; $body },
+            (Type::TypeString, $($reference)+ Value::String($($binding)+ $v)) => { use prost::encoding::string as $module; $body },
+            (Type::TypeBytes, $($reference)+ Value::Bytes($($binding)+ $v)) => { use prost::encoding::bytes as $module; $body },
+            (Type::TypeUint32, $($reference)+ Value::U32($($binding)+ $v)) => { use prost::encoding::uint32 as $module; $body },
+            (Type::TypeEnum, $($reference)+ Value::Enum($($binding)+ $v)) => { use prost::encoding::int32 as $module; $body },
+            (Type::TypeSfixed32, $($reference)+ Value::I32($($binding)+ $v)) => { use prost::encoding::sfixed32 as $module; $body },
+            (Type::TypeSfixed64, $($reference)+ Value::I64($($binding)+ $v)) => { use prost::encoding::sfixed64 as $module; $body },
+            (Type::TypeSint32, $($reference)+ Value::I32($($binding)+ $v)) => { use prost::encoding::sint32 as $module; $body },
+            (Type::TypeSint64, $($reference)+ Value::I64($($binding)+ $v)) => { use prost::encoding::sint64 as $module; $body },
+            (_, $other) => $fallback,
+        }
+    )
+}
+
+/// Matches a packable field type, binding `$module` to the `prost::encoding` module of the field
+/// type and `$typed` to the values of the list as a `Vec` of the native type.
+macro_rules! match_packed {
+    ($ty:expr, $values:expr, $module:ident, $typed:ident => $body:expr) => ({
+        macro_rules! typed {
+            ($variant:ident) => (
+                $values.iter().map(|value| match *value {
+                    Value::$variant(value) => value,
+                    _ => unreachable!("invalid list value"),
+                }).collect::<Vec<_>>()
+            )
+        }
+        match $ty {
+            Type::TypeDouble => { use prost::encoding::double as $module; let $typed = typed!(F64); $body },
+            Type::TypeFloat => { use prost::encoding::float as $module; let $typed = typed!(F32); $body },
+            Type::TypeInt64 => { use prost::encoding::int64 as $module; let $typed = typed!(I64); $body },
+            Type::TypeUint64 => { use prost::encoding::uint64 as $module; let $typed = typed!(U64); $body },
+            Type::TypeInt32 => { use prost::encoding::int32 as $module; let $typed = typed!(I32); $body },
+            Type::TypeFixed64 => { use prost::encoding::fixed64 as $module; let $typed = typed!(U64); $body },
+            Type::TypeFixed32 => { use prost::encoding::fixed32 as $module; let $typed = typed!(U32); $body },
+            Type::TypeBool => { use prost::encoding::bool as $module; let $typed = typed!(Bool); $body },
+            Type::TypeUint32 => { use prost::encoding::uint32 as $module; let $typed = typed!(U32); $body },
+            Type::TypeEnum => { use prost::encoding::int32 as $module; let $typed = typed!(Enum); $body },
+            Type::TypeSfixed32 => { use prost::encoding::sfixed32 as $module; let $typed = typed!(I32); $body },
+            Type::TypeSfixed64 => { use prost::encoding::sfixed64 as $module; let $typed = typed!(I64); $body },
+            Type::TypeSint32 => { use prost::encoding::sint32 as $module; let $typed = typed!(I32); $body },
+            Type::TypeSint64 => { use prost::encoding::sint64 as $module; let $typed = typed!(I64); $body },
+            ty => unreachable!("invalid packed field type: {:?}", ty),
+        }
+    })
+}
+
+/// The value of a `DynamicMessage` field.
+///
+/// Each Protobuf scalar type maps to the variant holding its Rust type, for example `sint64`,
+/// `sfixed64` and `int64` fields all hold `I64` values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value<'a> {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    /// An enumeration value number, which may not correspond to a value of the enumeration type.
+    Enum(i32),
+    Message(DynamicMessage<'a>),
+    /// The values of a repeated field.
+    List(Vec<Value<'a>>),
+    /// The entries of a map field.
+    Map(BTreeMap<MapKey, Value<'a>>),
+}
+
+impl <'a> Value<'a> {
+
+    /// Returns the default value of a field: an empty list or map for repeated fields, the
+    /// `default` option or zero value for scalar fields, and an empty message for message fields.
+    pub fn default_for(field: FieldDescriptor<'a>) -> Value<'a> {
+        if field.is_map() {
+            Value::Map(BTreeMap::new())
+        } else if field.is_repeated() {
+            Value::List(Vec::new())
+        } else {
+            default_singular(field)
+        }
+    }
+
+    /// Returns `true` if the value has the type of the field.
+    pub fn is_valid_for(&self, field: FieldDescriptor<'a>) -> bool {
+        if field.is_map() {
+            let entry = field.message_type().unwrap();
+            let key_field = entry.get_field(1).expect("map entry has no key field");
+            let value_field = entry.get_field(2).expect("map entry has no value field");
+            match *self {
+                Value::Map(ref map) => map.iter().all(|(key, value)| {
+                    Value::from(key.clone()).is_valid_singular(key_field) && value.is_valid_singular(value_field)
+                }),
+                _ => false,
+            }
+        } else if field.is_repeated() {
+            match *self {
+                Value::List(ref values) => values.iter().all(|value| value.is_valid_singular(field)),
+                _ => false,
+            }
+        } else {
+            self.is_valid_singular(field)
+        }
+    }
+
+    fn is_valid_singular(&self, field: FieldDescriptor<'a>) -> bool {
+        match (field.ty(), self) {
+            (Type::TypeDouble, &Value::F64(..)) |
+            (Type::TypeFloat, &Value::F32(..)) |
+            (Type::TypeInt64, &Value::I64(..)) |
+            (Type::TypeSint64, &Value::I64(..)) |
+            (Type::TypeSfixed64, &Value::I64(..)) |
+            (Type::TypeUint64, &Value::U64(..)) |
+            (Type::TypeFixed64, &Value::U64(..)) |
+            (Type::TypeInt32, &Value::I32(..)) |
+            (Type::TypeSint32, &Value::I32(..)) |
+            (Type::TypeSfixed32, &Value::I32(..)) |
+            (Type::TypeUint32, &Value::U32(..)) |
+            (Type::TypeFixed32, &Value::U32(..)) |
+            (Type::TypeBool, &Value::Bool(..)) |
+            (Type::TypeString, &Value::String(..)) |
+            (Type::TypeBytes, &Value::Bytes(..)) |
+            (Type::TypeEnum, &Value::Enum(..)) => true,
+            (Type::TypeMessage, &Value::Message(ref msg)) |
+            (Type::TypeGroup, &Value::Message(ref msg)) => Some(msg.descriptor()) == field.message_type(),
+            _ => false,
+        }
+    }
+}
+
+/// The key of a map field entry.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    String(String),
+}
+
+impl <'a> From<MapKey> for Value<'a> {
+    fn from(key: MapKey) -> Value<'a> {
+        match key {
+            MapKey::Bool(value) => Value::Bool(value),
+            MapKey::I32(value) => Value::I32(value),
+            MapKey::I64(value) => Value::I64(value),
+            MapKey::U32(value) => Value::U32(value),
+            MapKey::U64(value) => Value::U64(value),
+            MapKey::String(value) => Value::String(value),
+        }
+    }
+}
+
+/// Converts a map entry key value to a `MapKey`.
+fn map_key(value: Value) -> MapKey {
+    match value {
+        Value::Bool(value) => MapKey::Bool(value),
+        Value::I32(value) => MapKey::I32(value),
+        Value::I64(value) => MapKey::I64(value),
+        Value::U32(value) => MapKey::U32(value),
+        Value::U64(value) => MapKey::U64(value),
+        Value::String(value) => MapKey::String(value),
+        value => unreachable!("invalid map key: {:?}", value),
+    }
+}
+
+/// Returns the default value of a singular field, or of a single value of a repeated field.
+fn default_singular(field: FieldDescriptor) -> Value {
+    let default = field.descriptor_proto().default_value.as_ref().map(String::as_str);
+    match field.ty() {
+        Type::TypeDouble => Value::F64(default.map_or(0.0, parse_float)),
+        Type::TypeFloat => Value::F32(default.map_or(0.0, parse_float) as f32),
+        Type::TypeInt64 | Type::TypeSint64 | Type::TypeSfixed64 => {
+            Value::I64(default.and_then(|value| value.parse().ok()).unwrap_or(0))
+        },
+        Type::TypeUint64 | Type::TypeFixed64 => {
+            Value::U64(default.and_then(|value| value.parse().ok()).unwrap_or(0))
+        },
+        Type::TypeInt32 | Type::TypeSint32 | Type::TypeSfixed32 => {
+            Value::I32(default.and_then(|value| value.parse().ok()).unwrap_or(0))
+        },
+        Type::TypeUint32 | Type::TypeFixed32 => {
+            Value::U32(default.and_then(|value| value.parse().ok()).unwrap_or(0))
+        },
+        Type::TypeBool => Value::Bool(default == Some("true")),
+        Type::TypeString => Value::String(default.unwrap_or("").to_string()),
+        Type::TypeBytes => Value::Bytes(default.map_or(Vec::new(), unescape_c_bytes)),
+        Type::TypeEnum => {
+            let enum_type = field.enum_type().unwrap();
+            Value::Enum(default.and_then(|name| enum_type.value_number(name))
+                               .unwrap_or_else(|| enum_type.default_value()))
+        },
+        Type::TypeMessage | Type::TypeGroup => {
+            Value::Message(DynamicMessage::new(field.message_type().unwrap()))
+        },
+    }
+}
+
+/// Parses the `default` option of a floating point field.
+fn parse_float(value: &str) -> f64 {
+    match value {
+        "inf" => ::std::f64::INFINITY,
+        "-inf" => ::std::f64::NEG_INFINITY,
+        "nan" => ::std::f64::NAN,
+        _ => value.parse().unwrap_or(0.0),
+    }
+}
+
+/// Returns the wire type of a single value of the field type.
+fn value_wire_type(ty: Type) -> WireType {
+    match ty {
+        Type::TypeDouble | Type::TypeFixed64 | Type::TypeSfixed64 => WireType::SixtyFourBit,
+        Type::TypeFloat | Type::TypeFixed32 | Type::TypeSfixed32 => WireType::ThirtyTwoBit,
+        Type::TypeString | Type::TypeBytes | Type::TypeMessage => WireType::LengthDelimited,
+        Type::TypeGroup => WireType::StartGroup,
+        _ => WireType::Varint,
+    }
+}
+
+/// A Protocol Buffers message whose type is described by a `MessageDescriptor` at runtime,
+/// rather than by a Rust type.
+///
+/// Fields are held as `Value`s, and may be read and written by name or number. Fields which are
+/// not defined by the message type are retained as unknown fields, so that decoding and then
+/// encoding a message produces the same output as a generated message type: known fields are
+/// written in field number order, followed by the unknown fields. Map entries are written in key
+/// order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicMessage<'a> {
+    descriptor: MessageDescriptor<'a>,
+    fields: BTreeMap<u32, Value<'a>>,
+    unknown_fields: UnknownFields,
+}
+
+impl <'a> DynamicMessage<'a> {
+
+    /// Creates a new message of the type, with no fields set.
+    pub fn new(descriptor: MessageDescriptor<'a>) -> DynamicMessage<'a> {
+        DynamicMessage {
+            descriptor: descriptor,
+            fields: BTreeMap::new(),
+            unknown_fields: UnknownFields::new(),
+        }
+    }
+
+    /// Returns the type of the message.
+    pub fn descriptor(&self) -> MessageDescriptor<'a> {
+        self.descriptor
+    }
+
+    /// Returns `true` if the field with the name is set. Fields without presence, such as
+    /// `proto3` scalar fields, are set when they hold a non-default value.
+    pub fn has(&self, name: &str) -> bool {
+        self.descriptor.get_field_by_name(name).map_or(false, |field| self.has_field(field))
+    }
+
+    /// Returns `true` if the field is set.
+    pub fn has_field(&self, field: FieldDescriptor<'a>) -> bool {
+        self.fields.contains_key(&field.number())
+    }
+
+    /// Returns the value of the field with the name, or `None` if the message type has no such
+    /// field. Fields which are not set have their default value.
+    pub fn get(&self, name: &str) -> Option<Cow<Value<'a>>> {
+        self.descriptor.get_field_by_name(name).map(|field| self.get_field(field))
+    }
+
+    /// Returns the value of the field with the number, or `None` if the message type has no such
+    /// field. Fields which are not set have their default value.
+    pub fn get_by_number(&self, number: u32) -> Option<Cow<Value<'a>>> {
+        self.descriptor.get_field(number).map(|field| self.get_field(field))
+    }
+
+    /// Returns the value of the field. Fields which are not set have their default value.
+    pub fn get_field(&self, field: FieldDescriptor<'a>) -> Cow<Value<'a>> {
+        match self.fields.get(&field.number()) {
+            Some(value) => Cow::Borrowed(value),
+            None => Cow::Owned(Value::default_for(field)),
+        }
+    }
+
+    /// Sets the value of the field with the name. Setting a member of a oneof clears the other
+    /// members.
+    pub fn set(&mut self, name: &str, value: Value<'a>) -> Result<(), SetFieldError> {
+        let field = self.descriptor.get_field_by_name(name).ok_or(SetFieldError::NotFound)?;
+        self.set_field(field, value)
+    }
+
+    /// Sets the value of the field with the number. Setting a member of a oneof clears the other
+    /// members.
+    pub fn set_by_number(&mut self, number: u32, value: Value<'a>) -> Result<(), SetFieldError> {
+        let field = self.descriptor.get_field(number).ok_or(SetFieldError::NotFound)?;
+        self.set_field(field, value)
+    }
+
+    /// Sets the value of the field. Setting a member of a oneof clears the other members.
+    pub fn set_field(&mut self, field: FieldDescriptor<'a>, value: Value<'a>) -> Result<(), SetFieldError> {
+        if field.containing_message() != self.descriptor || !value.is_valid_for(field) {
+            return Err(SetFieldError::InvalidType);
+        }
+        self.insert(field, value);
+        Ok(())
+    }
+
+    /// Clears the field with the name, returning its value if it was set.
+    pub fn clear(&mut self, name: &str) -> Option<Value<'a>> {
+        let number = self.descriptor.get_field_by_name(name)?.number();
+        self.fields.remove(&number)
+    }
+
+    /// Clears the field with the number, returning its value if it was set.
+    pub fn clear_by_number(&mut self, number: u32) -> Option<Value<'a>> {
+        self.fields.remove(&number)
+    }
+
+    /// Returns the fields which are set, in field number order.
+    pub fn fields(&self) -> Vec<(FieldDescriptor<'a>, &Value<'a>)> {
+        self.fields.iter().map(|(&number, value)| (self.descriptor.get_field(number).unwrap(), value)).collect()
+    }
+
+    /// Returns the fields which were not recognized while decoding the message.
+    pub fn unknown_fields(&self) -> &UnknownFields {
+        &self.unknown_fields
+    }
+
+    /// Sets a field to a value of the field's type, clearing the other members of its oneof.
+    /// Fields without presence are cleared instead if the value is the default.
+    fn insert(&mut self, field: FieldDescriptor<'a>, value: Value<'a>) {
+        let number = field.number();
+        if let Some(oneof) = field.oneof_index() {
+            let members = self.descriptor.fields().into_iter().filter(|member| {
+                member.oneof_index() == Some(oneof) && member.number() != number
+            });
+            for member in members {
+                self.fields.remove(&member.number());
+            }
+        }
+
+        let is_default = match value {
+            Value::List(ref values) => values.is_empty(),
+            Value::Map(ref map) => map.is_empty(),
+            ref value => !field.has_presence() && *value == default_singular(field),
+        };
+        if is_default {
+            self.fields.remove(&number);
+        } else {
+            self.fields.insert(number, value);
+        }
+    }
+
+    /// Decodes a message of the type from the buffer. The entire buffer will be consumed.
+    pub fn decode<B>(descriptor: MessageDescriptor<'a>, buf: &mut Take<B>) -> Result<DynamicMessage<'a>, DecodeError>
+    where B: Buf {
+        DynamicMessage::decode_with_context(descriptor, buf, DecodeContext::default())
+    }
+
+    /// Decodes a message of the type from the buffer using the provided decode context. The
+    /// entire buffer will be consumed.
+    pub fn decode_with_context<B>(descriptor: MessageDescriptor<'a>,
+                                  buf: &mut Take<B>,
+                                  ctx: DecodeContext)
+                                  -> Result<DynamicMessage<'a>, DecodeError>
+    where B: Buf {
+        let mut message = DynamicMessage::new(descriptor);
+        message.merge_with_context(buf, ctx)?;
+        Ok(message)
+    }
+
+    /// Decodes a message from the buffer, and merges it into `self`. The entire buffer will be
+    /// consumed.
+    pub fn merge<B>(&mut self, buf: &mut Take<B>) -> Result<(), DecodeError> where B: Buf {
+        self.merge_with_context(buf, DecodeContext::default())
+    }
+
+    /// Decodes a message from the buffer using the provided decode context, and merges it into
+    /// `self`. The entire buffer will be consumed.
+    pub fn merge_with_context<B>(&mut self, buf: &mut Take<B>, ctx: DecodeContext) -> Result<(), DecodeError>
+    where B: Buf {
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(buf)?;
+            self.merge_field(tag, wire_type, buf, ctx)?;
+        }
+        Ok(())
+    }
+
+    fn merge_field<B>(&mut self,
+                      tag: u32,
+                      wire_type: WireType,
+                      buf: &mut Take<B>,
+                      ctx: DecodeContext)
+                      -> Result<(), DecodeError>
+    where B: Buf {
+        let field = match self.descriptor.get_field(tag) {
+            Some(field) => field,
+            None => return self.unknown_fields.merge_field(tag, wire_type, buf, ctx),
+        };
+
+        if field.is_map() {
+            let entry_type = field.message_type().unwrap();
+            let mut entry = DynamicMessage::new(entry_type);
+            merge_message(&mut entry, wire_type, buf, ctx)?;
+            let key = entry.fields.remove(&1).unwrap_or_else(|| default_singular(entry_type.get_field(1).unwrap()));
+            let value = entry.fields.remove(&2).unwrap_or_else(|| default_singular(entry_type.get_field(2).unwrap()));
+            match *self.fields.entry(tag).or_insert_with(|| Value::Map(BTreeMap::new())) {
+                Value::Map(ref mut map) => map.insert(map_key(key), value),
+                _ => unreachable!("invalid map value"),
+            };
+        } else if field.is_repeated() {
+            match *self.fields.entry(tag).or_insert_with(|| Value::List(Vec::new())) {
+                Value::List(ref mut values) => merge_repeated(field, wire_type, values, buf, ctx)?,
+                _ => unreachable!("invalid list value"),
+            }
+        } else {
+            // Embedded messages are merged into the existing value, and scalars replace it.
+            let mut value = self.fields.remove(&tag).unwrap_or_else(|| default_singular(field));
+            merge_singular(field, wire_type, &mut value, buf, ctx)?;
+            self.insert(field, value);
+        }
+        Ok(())
+    }
+
+    /// Encodes the message, and writes it to the buffer. An error will be returned if the buffer
+    /// does not have sufficient capacity.
+    pub fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError> where B: BufMut {
+        let required = self.encoded_len();
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
+        }
+        self.encode_raw(buf);
+        Ok(())
+    }
+
+    fn encode_raw<B>(&self, buf: &mut B) where B: BufMut {
+        for (field, value) in self.fields() {
+            encode_field(field, value, buf);
+        }
+        self.unknown_fields.encode(buf);
+    }
+
+    /// Returns the encoded length of the message.
+    pub fn encoded_len(&self) -> usize {
+        self.fields().into_iter().map(|(field, value)| encoded_len_field(field, value)).sum::<usize>()
+            + self.unknown_fields.encoded_len()
+    }
+}
+
+fn merge_message<B>(msg: &mut DynamicMessage,
+                    wire_type: WireType,
+                    buf: &mut Take<B>,
+                    ctx: DecodeContext)
+                    -> Result<(), DecodeError>
+where B: Buf {
+    check_wire_type(WireType::LengthDelimited, wire_type)?;
+    ctx.limit_reached()?;
+    let len = decode_varint(buf)?;
+    if len > buf.remaining() as u64 {
+        return Err(DecodeError::new(::prost::DecodeErrorKind::BufferUnderflow));
+    }
+    let len = len as usize;
+    let limit = buf.limit();
+    buf.set_limit(len);
+    let result = msg.merge_with_context(buf, ctx.enter_recursion());
+    let unconsumed = buf.limit();
+    buf.set_limit(limit - len + unconsumed);
+    result
+}
+
+fn merge_group<B>(tag: u32,
+                  msg: &mut DynamicMessage,
+                  wire_type: WireType,
+                  buf: &mut Take<B>,
+                  ctx: DecodeContext)
+                  -> Result<(), DecodeError>
+where B: Buf {
+    check_wire_type(WireType::StartGroup, wire_type)?;
+    ctx.limit_reached()?;
+    let ctx = ctx.enter_recursion();
+    loop {
+        let (field_tag, field_wire_type) = decode_key(buf)?;
+        if field_wire_type == WireType::EndGroup {
+            return check_end_group(tag, field_tag);
+        }
+        msg.merge_field(field_tag, field_wire_type, buf, ctx)?;
+    }
+}
+
+fn merge_singular<B>(field: FieldDescriptor,
+                     wire_type: WireType,
+                     value: &mut Value,
+                     buf: &mut Take<B>,
+                     ctx: DecodeContext)
+                     -> Result<(), DecodeError>
+where B: Buf {
+    match_scalar!(field.ty(), value, [&mut] [ref mut], module, value => module::merge(wire_type, value, buf, ctx),
+                  value => match (field.ty(), value) {
+        (Type::TypeMessage, &mut Value::Message(ref mut msg)) => merge_message(msg, wire_type, buf, ctx),
+        (Type::TypeGroup, &mut Value::Message(ref mut msg)) => merge_group(field.number(), msg, wire_type, buf, ctx),
+        (_, value) => unreachable!("invalid value for field {:?}: {:?}", field, value),
+    })
+}
+
+/// Merges a value of a repeated field, or a packed list of values, into `values`.
+fn merge_repeated<'a, B>(field: FieldDescriptor<'a>,
+                         wire_type: WireType,
+                         values: &mut Vec<Value<'a>>,
+                         buf: &mut Take<B>,
+                         ctx: DecodeContext)
+                         -> Result<(), DecodeError>
+where B: Buf {
+    let value_wire_type = value_wire_type(field.ty());
+    // Packable fields accept both the packed and unpacked encodings.
+    if wire_type == WireType::LengthDelimited && value_wire_type != WireType::LengthDelimited {
+        let len = decode_varint(buf)?;
+        if len > buf.remaining() as u64 {
+            return Err(DecodeError::new(::prost::DecodeErrorKind::BufferUnderflow));
+        }
+        let len = len as usize;
+        let limit = buf.limit();
+        buf.set_limit(len);
+        let result = (|| {
+            while buf.has_remaining() {
+                let mut value = default_singular(field);
+                merge_singular(field, value_wire_type, &mut value, buf, ctx)?;
+                values.push(value);
+            }
+            Ok(())
+        })();
+        let unconsumed = buf.limit();
+        buf.set_limit(limit - len + unconsumed);
+        result
+    } else {
+        let mut value = default_singular(field);
+        merge_singular(field, wire_type, &mut value, buf, ctx)?;
+        values.push(value);
+        Ok(())
+    }
+}
+
+fn encode_field<B>(field: FieldDescriptor, value: &Value, buf: &mut B) where B: BufMut {
+    let number = field.number();
+    match *value {
+        Value::Map(ref map) => {
+            let entry_type = field.message_type().unwrap();
+            let key_field = entry_type.get_field(1).unwrap();
+            let value_field = entry_type.get_field(2).unwrap();
+            for (key, value) in map {
+                let key = Value::from(key.clone());
+                let (key, value) = map_entry(key_field, &key, value_field, value);
+                let len = key.map_or(0, |key| encoded_len_singular(key_field, key))
+                        + value.map_or(0, |value| encoded_len_singular(value_field, value));
+                encode_key(number, WireType::LengthDelimited, buf);
+                encode_varint(len as u64, buf);
+                if let Some(key) = key {
+                    encode_singular(key_field, key, buf);
+                }
+                if let Some(value) = value {
+                    encode_singular(value_field, value, buf);
+                }
+            }
+        },
+        Value::List(ref values) if field.is_packed() => {
+            match_packed!(field.ty(), values, module, values => module::encode_packed(number, &values, buf))
+        },
+        Value::List(ref values) => {
+            for value in values {
+                encode_singular(field, value, buf);
+            }
+        },
+        ref value => encode_singular(field, value, buf),
+    }
+}
+
+fn encoded_len_field(field: FieldDescriptor, value: &Value) -> usize {
+    let number = field.number();
+    match *value {
+        Value::Map(ref map) => {
+            let entry_type = field.message_type().unwrap();
+            let key_field = entry_type.get_field(1).unwrap();
+            let value_field = entry_type.get_field(2).unwrap();
+            key_len(number) * map.len() + map.iter().map(|(key, value)| {
+                let key = Value::from(key.clone());
+                let (key, value) = map_entry(key_field, &key, value_field, value);
+                let len = key.map_or(0, |key| encoded_len_singular(key_field, key))
+                        + value.map_or(0, |value| encoded_len_singular(value_field, value));
+                encoded_len_varint(len as u64) + len
+            }).sum::<usize>()
+        },
+        Value::List(ref values) if field.is_packed() => {
+            match_packed!(field.ty(), values, module, values => module::encoded_len_packed(number, &values))
+        },
+        Value::List(ref values) => values.iter().map(|value| encoded_len_singular(field, value)).sum(),
+        ref value => encoded_len_singular(field, value),
+    }
+}
+
+/// Returns the key and value of a map entry which are written, omitting default values in the
+/// same way as generated map fields.
+fn map_entry<'a, 'b>(key_field: FieldDescriptor<'a>,
+                     key: &'b Value<'a>,
+                     value_field: FieldDescriptor<'a>,
+                     value: &'b Value<'a>)
+                     -> (Option<&'b Value<'a>>, Option<&'b Value<'a>>) {
+    let key = if *key == default_singular(key_field) { None } else { Some(key) };
+    let value = if *value == default_singular(value_field) { None } else { Some(value) };
+    (key, value)
+}
+
+fn encode_singular<B>(field: FieldDescriptor, value: &Value, buf: &mut B) where B: BufMut {
+    let number = field.number();
+    match_scalar!(field.ty(), value, [&] [ref], module, value => module::encode(number, value, buf),
+                  value => match (field.ty(), value) {
+        (Type::TypeMessage, &Value::Message(ref msg)) => {
+            encode_key(number, WireType::LengthDelimited, buf);
+            encode_varint(msg.encoded_len() as u64, buf);
+            msg.encode_raw(buf);
+        },
+        (Type::TypeGroup, &Value::Message(ref msg)) => {
+            encode_key(number, WireType::StartGroup, buf);
+            msg.encode_raw(buf);
+            encode_key(number, WireType::EndGroup, buf);
+        },
+        (_, value) => unreachable!("invalid value for field {:?}: {:?}", field, value),
+    })
+}
+
+fn encoded_len_singular(field: FieldDescriptor, value: &Value) -> usize {
+    let number = field.number();
+    match_scalar!(field.ty(), value, [&] [ref], module, value => module::encoded_len(number, value),
+                  value => match (field.ty(), value) {
+        (Type::TypeMessage, &Value::Message(ref msg)) => {
+            let len = msg.encoded_len();
+            key_len(number) + encoded_len_varint(len as u64) + len
+        },
+        (Type::TypeGroup, &Value::Message(ref msg)) => 2 * key_len(number) + msg.encoded_len(),
+        (_, value) => unreachable!("invalid value for field {:?}: {:?}", field, value),
+    })
+}
+
+/// The reason that a `DynamicMessage` field could not be set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetFieldError {
+    /// The message type has no field with the name or number.
+    NotFound,
+    /// The value does not have the type of the field.
+    InvalidType,
+}
+
+impl fmt::Display for SetFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SetFieldError::NotFound => f.write_str("field not found"),
+            SetFieldError::InvalidType => f.write_str("value does not match the field type"),
+        }
+    }
+}
+
+impl error::Error for SetFieldError {
+    fn description(&self) -> &str {
+        "failed to set field"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use bytes::Buf;
+    use prost::Message;
+
+    use google::protobuf::{
+        DescriptorProto,
+        FieldDescriptorProto,
+        FileDescriptorProto,
+        FileDescriptorSet,
+        MessageOptions,
+        OneofDescriptorProto,
+        EnumDescriptorProto,
+        EnumValueDescriptorProto,
+    };
+    use google::protobuf::field_descriptor_proto::Label;
+    use super::*;
+    use DescriptorPool;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Enumeration)]
+    pub enum Kind {
+        A = 0,
+        B = 1,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Message)]
+    pub struct Nested {
+        #[prost(int32, tag="1")]
+        pub value: i32,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Oneof)]
+    pub enum Choice {
+        #[prost(int32, tag="16")]
+        A(i32),
+        #[prost(string, tag="17")]
+        B(String),
+    }
+
+    #[derive(Clone, Debug, PartialEq, Message)]
+    pub struct Msg {
+        #[prost(int32, tag="1")]
+        pub int32: i32,
+        #[prost(sint64, tag="2")]
+        pub sint64: i64,
+        #[prost(string, tag="3")]
+        pub string: String,
+        #[prost(uint32, repeated, tag="4")]
+        pub packed: Vec<u32>,
+        #[prost(string, repeated, tag="5")]
+        pub strings: Vec<String>,
+        #[prost(message, optional, tag="6")]
+        pub nested: Option<Nested>,
+        #[prost(message, repeated, tag="7")]
+        pub nesteds: Vec<Nested>,
+        #[prost(btree_map="string, int32", tag="8")]
+        pub map: BTreeMap<String, i32>,
+        #[prost(enumeration="Kind", tag="9")]
+        pub kind: i32,
+        #[prost(fixed64, tag="12")]
+        pub fixed: u64,
+        #[prost(double, tag="13")]
+        pub double: f64,
+        #[prost(bytes, tag="14")]
+        pub bytes: Vec<u8>,
+        #[prost(bool, tag="15")]
+        pub bool: bool,
+        #[prost(oneof="Choice", tags="16, 17")]
+        pub choice: Option<Choice>,
+    }
+
+    fn field(name: &str, number: i32, label: Label, ty: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            type_: Some(ty as i32),
+            type_name: type_name.map(str::to_string),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    /// Returns the descriptors of the `Msg` message type.
+    fn file_descriptor_set() -> FileDescriptorSet {
+        let nested = DescriptorProto {
+            name: Some("Nested".to_string()),
+            field: vec![field("value", 1, Label::LabelOptional, Type::TypeInt32, None)],
+            ..DescriptorProto::default()
+        };
+        let map_entry = DescriptorProto {
+            name: Some("MapEntry".to_string()),
+            field: vec![
+                field("key", 1, Label::LabelOptional, Type::TypeString, None),
+                field("value", 2, Label::LabelOptional, Type::TypeInt32, None),
+            ],
+            options: Some(MessageOptions { map_entry: Some(true), ..MessageOptions::default() }),
+            ..DescriptorProto::default()
+        };
+        let kind = EnumDescriptorProto {
+            name: Some("Kind".to_string()),
+            value: vec![
+                EnumValueDescriptorProto { name: Some("A".to_string()), number: Some(0), ..Default::default() },
+                EnumValueDescriptorProto { name: Some("B".to_string()), number: Some(1), ..Default::default() },
+            ],
+            ..EnumDescriptorProto::default()
+        };
+        let mut a = field("a", 16, Label::LabelOptional, Type::TypeInt32, None);
+        a.oneof_index = Some(0);
+        let mut b = field("b", 17, Label::LabelOptional, Type::TypeString, None);
+        b.oneof_index = Some(0);
+        let msg = DescriptorProto {
+            name: Some("Msg".to_string()),
+            field: vec![
+                field("int32", 1, Label::LabelOptional, Type::TypeInt32, None),
+                field("sint64", 2, Label::LabelOptional, Type::TypeSint64, None),
+                field("string", 3, Label::LabelOptional, Type::TypeString, None),
+                field("packed", 4, Label::LabelRepeated, Type::TypeUint32, None),
+                field("strings", 5, Label::LabelRepeated, Type::TypeString, None),
+                field("nested", 6, Label::LabelOptional, Type::TypeMessage, Some(".test.Msg.Nested")),
+                field("nesteds", 7, Label::LabelRepeated, Type::TypeMessage, Some(".test.Msg.Nested")),
+                field("map", 8, Label::LabelRepeated, Type::TypeMessage, Some(".test.Msg.MapEntry")),
+                field("kind", 9, Label::LabelOptional, Type::TypeEnum, Some(".test.Msg.Kind")),
+                field("fixed", 12, Label::LabelOptional, Type::TypeFixed64, None),
+                field("double", 13, Label::LabelOptional, Type::TypeDouble, None),
+                field("bytes", 14, Label::LabelOptional, Type::TypeBytes, None),
+                field("bool", 15, Label::LabelOptional, Type::TypeBool, None),
+                a,
+                b,
+            ],
+            nested_type: vec![nested, map_entry],
+            enum_type: vec![kind],
+            oneof_decl: vec![OneofDescriptorProto { name: Some("choice".to_string()), ..Default::default() }],
+            ..DescriptorProto::default()
+        };
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("test.proto".to_string()),
+                package: Some("test".to_string()),
+                message_type: vec![msg],
+                syntax: Some("proto3".to_string()),
+                ..FileDescriptorProto::default()
+            }],
+        }
+    }
+
+    fn decode<'a>(descriptor: MessageDescriptor<'a>, buf: &[u8]) -> DynamicMessage<'a> {
+        DynamicMessage::decode(descriptor, &mut Cursor::new(buf).take(buf.len())).unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut msg = Msg::default();
+        msg.int32 = -5;
+        msg.sint64 = -6;
+        msg.string = "foo".to_string();
+        msg.packed = vec![1, 200, 3];
+        msg.strings = vec!["a".to_string(), String::new()];
+        msg.nested = Some(Nested { value: 0 });
+        msg.nesteds = vec![Nested { value: 1 }, Nested::default()];
+        msg.map.insert("x".to_string(), 0);
+        msg.map.insert(String::new(), 7);
+        msg.kind = Kind::B as i32;
+        msg.fixed = 9;
+        msg.double = 1.5;
+        msg.bytes = vec![0, 1];
+        msg.bool = true;
+        msg.choice = Some(Choice::B("bar".to_string()));
+
+        let mut buf = Vec::new();
+        msg.encode(&mut buf).unwrap();
+        // An unknown field.
+        buf.extend_from_slice(&[0xA0, 0x06, 0x2A]);
+
+        let pool = DescriptorPool::new(file_descriptor_set()).unwrap();
+        let descriptor = pool.get_message(".test.Msg").unwrap();
+        let dynamic = decode(descriptor, &buf);
+
+        assert_eq!(dynamic.get("int32").unwrap().into_owned(), Value::I32(-5));
+        assert_eq!(dynamic.get_by_number(2).unwrap().into_owned(), Value::I64(-6));
+        assert_eq!(dynamic.get("packed").unwrap().into_owned(),
+                   Value::List(vec![Value::U32(1), Value::U32(200), Value::U32(3)]));
+        assert_eq!(dynamic.get("kind").unwrap().into_owned(), Value::Enum(1));
+        assert_eq!(dynamic.get("b").unwrap().into_owned(), Value::String("bar".to_string()));
+        assert!(!dynamic.has("a"));
+        assert_eq!(dynamic.get("a").unwrap().into_owned(), Value::I32(0));
+        assert!(dynamic.get("missing").is_none());
+        match *dynamic.get("map").unwrap() {
+            Value::Map(ref map) => {
+                assert_eq!(map.get(&MapKey::String("x".to_string())), Some(&Value::I32(0)));
+                assert_eq!(map.get(&MapKey::String(String::new())), Some(&Value::I32(7)));
+            },
+            ref value => panic!("unexpected map value: {:?}", value),
+        }
+        match *dynamic.get("nested").unwrap() {
+            Value::Message(ref nested) => assert_eq!(nested.get("value").unwrap().into_owned(), Value::I32(0)),
+            ref value => panic!("unexpected message value: {:?}", value),
+        }
+        assert_eq!(dynamic.unknown_fields().len(), 1);
+
+        let mut reencoded = Vec::new();
+        dynamic.encode(&mut reencoded).unwrap();
+        assert_eq!(dynamic.encoded_len(), reencoded.len());
+        assert_eq!(buf, reencoded);
+    }
+
+    #[test]
+    fn set_fields() {
+        let pool = DescriptorPool::new(file_descriptor_set()).unwrap();
+        let descriptor = pool.get_message("test.Msg").unwrap();
+        let nested_descriptor = pool.get_message("test.Msg.Nested").unwrap();
+
+        let mut dynamic = DynamicMessage::new(descriptor);
+        dynamic.set("int32", Value::I32(42)).unwrap();
+        dynamic.set("strings", Value::List(vec![Value::String("s".to_string())])).unwrap();
+        dynamic.set("a", Value::I32(1)).unwrap();
+        let mut nested = DynamicMessage::new(nested_descriptor);
+        nested.set("value", Value::I32(3)).unwrap();
+        dynamic.set_by_number(6, Value::Message(nested)).unwrap();
+
+        // Setting a oneof member clears the other members.
+        dynamic.set("b", Value::String("b".to_string())).unwrap();
+        assert!(!dynamic.has("a"));
+
+        // Setting a field without presence to its default value clears it.
+        dynamic.set("double", Value::F64(0.0)).unwrap();
+        assert!(!dynamic.has("double"));
+
+        assert_eq!(dynamic.set("int32", Value::I64(1)), Err(SetFieldError::InvalidType));
+        assert_eq!(dynamic.set("strings", Value::String("s".to_string())), Err(SetFieldError::InvalidType));
+        assert_eq!(dynamic.set("nested", Value::Message(DynamicMessage::new(descriptor))),
+                   Err(SetFieldError::InvalidType));
+        assert_eq!(dynamic.set("missing", Value::I32(1)), Err(SetFieldError::NotFound));
+
+        let mut buf = Vec::new();
+        dynamic.encode(&mut buf).unwrap();
+        let msg = Msg::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+        assert_eq!(msg.int32, 42);
+        assert_eq!(msg.strings, vec!["s".to_string()]);
+        assert_eq!(msg.nested, Some(Nested { value: 3 }));
+        assert_eq!(msg.choice, Some(Choice::B("b".to_string())));
+
+        assert_eq!(dynamic.clear("int32"), Some(Value::I32(42)));
+        assert_eq!(dynamic.clear("int32"), None);
+        assert_eq!(dynamic.fields().len(), 3);
+    }
+
+    #[test]
+    fn merge_fields() {
+        let pool = DescriptorPool::new(file_descriptor_set()).unwrap();
+        let descriptor = pool.get_message("test.Msg").unwrap();
+
+        // Unpacked values of packed fields are accepted, and repeated fields are appended.
+        let mut dynamic = decode(descriptor, &[0x20, 0x01, 0x22, 0x02, 0x02, 0x03]);
+        assert_eq!(dynamic.get("packed").unwrap().into_owned(),
+                   Value::List(vec![Value::U32(1), Value::U32(2), Value::U32(3)]));
+
+        // Embedded messages are merged.
+        let mut nested = Msg::default();
+        nested.nested = Some(Nested { value: 4 });
+        let mut buf = Vec::new();
+        nested.encode(&mut buf).unwrap();
+        dynamic.merge(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+        dynamic.merge(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+        assert_eq!(dynamic.fields().len(), 2);
+
+        assert!(DynamicMessage::decode(descriptor, &mut Cursor::new(&[0x0A][..]).take(1)).is_err());
+    }
+
+    #[test]
+    fn default_values() {
+        let mut file_set = file_descriptor_set();
+        {
+            let file = &mut file_set.file[0];
+            file.syntax = None;
+            let fields = &mut file.message_type[0].field;
+            fields[0].default_value = Some("-7".to_string());
+            fields[8].default_value = Some("B".to_string());
+            fields[10].default_value = Some("-inf".to_string());
+            fields[11].default_value = Some("a\\000\\x41\\n".to_string());
+        }
+        let pool = DescriptorPool::new(file_set).unwrap();
+        let descriptor = pool.get_message("test.Msg").unwrap();
+        let mut dynamic = DynamicMessage::new(descriptor);
+
+        assert_eq!(dynamic.get("int32").unwrap().into_owned(), Value::I32(-7));
+        assert_eq!(dynamic.get("kind").unwrap().into_owned(), Value::Enum(1));
+        assert_eq!(dynamic.get("double").unwrap().into_owned(), Value::F64(::std::f64::NEG_INFINITY));
+        assert_eq!(dynamic.get("bytes").unwrap().into_owned(), Value::Bytes(b"a\0A\n".to_vec()));
+        assert_eq!(dynamic.get("nesteds").unwrap().into_owned(), Value::List(Vec::new()));
+
+        // proto2 fields are written when they are set, even to the default value.
+        dynamic.set("int32", Value::I32(-7)).unwrap();
+        assert!(dynamic.has("int32"));
+        assert_eq!(dynamic.encoded_len(), 11);
+    }
+}
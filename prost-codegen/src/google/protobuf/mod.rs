@@ -105,7 +105,7 @@ pub struct FieldDescriptorProto {
     pub label: Option<i32>,
     /// If type_name is set, this need not be set.  If both this and type_name
     /// are set, this must be one of TYPE_ENUM, TYPE_MESSAGE or TYPE_GROUP.
-    #[prost(enumeration="field_descriptor_proto::Type", optional, tag="5")]
+    #[prost(enumeration="field_descriptor_proto::Type", optional, name="type", tag="5")]
     pub type_: Option<i32>,
     /// For message and enum types, this is the name of the type.  If the name
     /// starts with a '.', it is fully-qualified.  Otherwise, C++-like scoping
@@ -361,6 +361,8 @@ pub struct FileOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 pub mod file_options {
     /// Generated classes can be optimized for speed or code size.
@@ -434,6 +436,8 @@ pub struct MessageOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct FieldOptions {
@@ -503,6 +507,8 @@ pub struct FieldOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 pub mod field_options {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
@@ -527,6 +533,8 @@ pub struct OneofOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct EnumOptions {
@@ -543,6 +551,8 @@ pub struct EnumOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct EnumValueOptions {
@@ -555,6 +565,8 @@ pub struct EnumValueOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct ServiceOptions {
@@ -572,6 +584,8 @@ pub struct ServiceOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct MethodOptions {
@@ -591,6 +605,8 @@ pub struct MethodOptions {
     /// The parser stores options it doesn't recognize here. See above.
     #[prost(message, repeated, tag="999")]
     pub uninterpreted_option: Vec<UninterpretedOption>,
+    #[prost(unknown_fields)]
+    pub _unknown_fields: ::prost::UnknownFields,
 }
 pub mod method_options {
     /// Is this method side-effect-free (or safe in HTTP parlance), or idempotent,
@@ -639,9 +655,9 @@ pub mod uninterpreted_option {
     #[derive(Clone, Debug, PartialEq, Message)]
     pub struct NamePart {
         #[prost(string, required, tag="1")]
-        pub name_part: String,
+        pub name_part: Option<String>,
         #[prost(bool, required, tag="2")]
-        pub is_extension: bool,
+        pub is_extension: Option<bool>,
     }
 }
 // ===================================================================
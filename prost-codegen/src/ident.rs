@@ -70,10 +70,61 @@ pub fn snake_to_upper_camel(snake: &str) -> String {
     ident
 }
 
+/// Converts an UpperCamel case Rust type identifier to a snake_case identifier. This is the
+/// name which `#[derive(Oneof)]` and `#[derive(Enumeration)]` assume for a variant which has no
+/// `name` attribute (upper-cased for enumeration variants).
+pub fn upper_camel_to_snake(camel: &str) -> String {
+    let mut snake = String::with_capacity(camel.len() + 4);
+    let mut break_on_cap = false;
+    for c in camel.chars() {
+        if c.is_uppercase() && break_on_cap {
+            snake.push('_');
+        }
+        break_on_cap = c.is_lowercase() || c.is_numeric();
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// Converts a field name to the default JSON name which `protoc` assigns to it, by removing
+/// underscores and capitalizing the letters which follow them.
+pub fn to_json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            json_name.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
 /// Matches a 'matcher' against a fully qualified field name.
 pub fn match_field(matcher: &str, msg: &str, field: &str) -> bool {
     assert_eq!(b'.', msg.as_bytes()[0]);
 
+    let field_paths = {
+        let mut paths = msg.split('.').collect::<Vec<_>>();
+        paths.push(field);
+        paths
+    };
+    match_paths(matcher, &field_paths)
+}
+
+/// Matches a 'matcher' against a fully qualified message name.
+pub fn match_message(matcher: &str, msg: &str) -> bool {
+    assert_eq!(b'.', msg.as_bytes()[0]);
+    match_paths(matcher, &msg.split('.').collect::<Vec<_>>())
+}
+
+/// Matches a 'matcher' against the components of a fully qualified name. The first component
+/// is always empty, since fully qualified names begin with a `.`.
+fn match_paths(matcher: &str, paths: &[&str]) -> bool {
     if matcher.is_empty() {
         return false;
     } else if matcher == "." {
@@ -81,25 +132,20 @@ pub fn match_field(matcher: &str, msg: &str, field: &str) -> bool {
     }
 
     let match_paths = matcher.split('.').collect::<Vec<_>>();
-    let field_paths = {
-        let mut paths = msg.split('.').collect::<Vec<_>>();
-        paths.push(field);
-        paths
-    };
 
     if &matcher[..1] == "." {
         // Prefix match.
-        if match_paths.len() > field_paths.len() {
+        if match_paths.len() > paths.len() {
             false
         } else {
-            &match_paths[..] == &field_paths[..match_paths.len()]
+            &match_paths[..] == &paths[..match_paths.len()]
         }
     } else {
         // Suffix match.
-        if match_paths.len() > field_paths.len() {
+        if match_paths.len() > paths.len() {
             false
         } else {
-            &match_paths[..] == &field_paths[field_paths.len() - match_paths.len()..]
+            &match_paths[..] == &paths[paths.len() - match_paths.len()..]
         }
     }
 }
@@ -173,6 +219,28 @@ mod tests {
         assert_eq!("Self_", &snake_to_upper_camel("self"));
     }
 
+    #[test]
+    fn test_upper_camel_to_snake() {
+        assert_eq!("", &upper_camel_to_snake(""));
+        assert_eq!("foo", &upper_camel_to_snake("Foo"));
+        assert_eq!("foo_bar", &upper_camel_to_snake("FooBar"));
+        assert_eq!("foo_bar1", &upper_camel_to_snake("FooBar1"));
+        assert_eq!("foo1_bar", &upper_camel_to_snake("Foo1Bar"));
+        assert_eq!("zero", &upper_camel_to_snake("ZERO"));
+        assert_eq!("self_", &upper_camel_to_snake("Self_"));
+    }
+
+    #[test]
+    fn test_to_json_name() {
+        assert_eq!("fooBar", &to_json_name("foo_bar"));
+        assert_eq!("fooBar", &to_json_name("fooBar"));
+        assert_eq!("FooBar", &to_json_name("_foo_bar"));
+        assert_eq!("fieldName4", &to_json_name("field__name4_"));
+        assert_eq!("field0name5", &to_json_name("field0name5"));
+        assert_eq!("field0Name6", &to_json_name("field_0_name6"));
+        assert_eq!("FIELDNAME11", &to_json_name("FIELD_NAME11"));
+    }
+
     #[test]
     fn test_match_field() {
         // Prefix matches
@@ -196,4 +264,22 @@ mod tests {
         assert!(!match_field("buz", ".foo.bar.Baz", "buzz"));
         assert!(!match_field("uz", ".foo.bar.Baz", "buzz"));
     }
+
+    #[test]
+    fn test_match_message() {
+        // Prefix matches
+        assert!(match_message(".", ".foo.bar.Baz"));
+        assert!(match_message(".foo", ".foo.bar.Baz"));
+        assert!(match_message(".foo.bar.Baz", ".foo.bar.Baz"));
+
+        assert!(!match_message(".foo.bar.Baz.buzz", ".foo.bar.Baz"));
+        assert!(!match_message(".bar", ".foo.bar.Baz"));
+
+        // Suffix matches
+        assert!(match_message("Baz", ".foo.bar.Baz"));
+        assert!(match_message("bar.Baz", ".foo.bar.Baz"));
+
+        assert!(!match_message("az", ".foo.bar.Baz"));
+        assert!(!match_message("bar", ".foo.bar.Baz"));
+    }
 }
@@ -15,8 +15,11 @@ extern crate prost;
 
 mod ast;
 mod code_generator;
+mod descriptor_pool;
+mod dynamic;
 mod ident;
 mod message_graph;
+mod parser;
 pub mod google;
 
 use std::collections::HashMap;
@@ -26,11 +29,38 @@ pub use ast::{
     Method,
     Service,
 };
+pub use descriptor_pool::{
+    DescriptorError,
+    DescriptorPool,
+    EnumDescriptor,
+    FieldDescriptor,
+    MessageDescriptor,
+    MethodDescriptor,
+    OneofDescriptor,
+    ServiceDescriptor,
+};
+pub use dynamic::{
+    DynamicMessage,
+    MapKey,
+    SetFieldError,
+    Value,
+};
+pub use parser::{
+    ParseError,
+    parse_protos,
+};
+pub use code_generator::module_filename;
 use code_generator::{
     CodeGenerator,
+    extern_path,
+    include_file,
+    is_well_known_file,
     module,
 };
-use google::protobuf::FileDescriptorProto;
+use google::protobuf::{
+    FileDescriptorProto,
+    FileDescriptorSet,
+};
 use message_graph::MessageGraph;
 
 pub type Module = Vec<String>;
@@ -44,6 +74,15 @@ pub trait ServiceGenerator {
 pub struct CodeGeneratorConfig {
     service_generator: Option<Box<ServiceGenerator>>,
     btree_map: Vec<String>,
+    bytes: Vec<String>,
+    open_enums: Vec<String>,
+    retain_unknown_fields: Vec<String>,
+    borrowed: Vec<String>,
+    type_attributes: Vec<(String, String)>,
+    field_attributes: Vec<(String, String)>,
+    extern_paths: Vec<(String, String)>,
+    include_file: Option<String>,
+    compile_well_known_types: bool,
 }
 
 impl CodeGeneratorConfig {
@@ -105,6 +144,298 @@ impl CodeGeneratorConfig {
         self
     }
 
+    /// Configure the code generator to generate Rust [`bytes::Bytes`][1] fields for Protobuf
+    /// `bytes` type fields.
+    ///
+    /// `Bytes` fields are reference counted, and share the memory of the input buffer when a
+    /// message is decoded with `Message::decode_bytes`, which avoids copying large field values.
+    /// The generated code refers to the type as `::bytes::Bytes`, so the crate including it must
+    /// depend on the `bytes` crate.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific fields, messages, or packages which should use a Rust
+    /// `Bytes` for Protobuf `bytes` fields. Paths are matched in the same way as the paths passed
+    /// to `btree_map`. If a Protobuf `bytes` field matches any of the paths, a Rust `Bytes` field
+    /// will be generated instead of the default `Vec<u8>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Match a specific field in a message type.
+    /// config.bytes(&[".my_messages.MyMessageType.my_bytes_field"]);
+    ///
+    /// // Match all bytes fields.
+    /// config.bytes(&["."]);
+    /// ```
+    ///
+    /// [1]: https://docs.rs/bytes/0.4/bytes/struct.Bytes.html
+    pub fn bytes<I, S>(&mut self, paths: I) -> &mut Self
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> {
+        self.bytes = paths.into_iter().map(|s| s.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Configure the code generator to generate [`prost::OpenEnum`][1] fields for Protobuf enum
+    /// type fields.
+    ///
+    /// By default, enum fields are generated as `i32` fields, with accessor methods which convert
+    /// the value to the Rust enum. An `OpenEnum<E>` field holds either a known variant of the
+    /// Rust enum `E`, or an unknown `i32` value, so that unknown values are still preserved.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific fields, messages, or packages which should use an
+    /// `OpenEnum` for Protobuf enum fields. Paths are matched in the same way as the paths passed
+    /// to `btree_map`. Map fields with enum values are matched by the name of the map field.
+    /// Extension fields are always generated as `i32` fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Match a specific field in a message type.
+    /// config.open_enums(&[".my_messages.MyMessageType.my_enum_field"]);
+    ///
+    /// // Match all enum fields.
+    /// config.open_enums(&["."]);
+    /// ```
+    ///
+    /// [1]: https://docs.rs/prost/*/prost/enum.OpenEnum.html
+    pub fn open_enums<I, S>(&mut self, paths: I) -> &mut Self
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> {
+        self.open_enums = paths.into_iter().map(|s| s.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Configure the code generator to generate a field which retains unknown fields in Protobuf
+    /// messages.
+    ///
+    /// By default, fields which are not recognized when decoding a message are skipped, and are
+    /// lost when the message is re-encoded. Messages matching any of the paths get an additional
+    /// `_unknown_fields` field of type [`prost::UnknownFields`][1], which preserves unrecognized
+    /// fields across a decode and encode round-trip.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages, or packages which should retain unknown fields.
+    /// Paths are matched in the same way as the paths passed to `btree_map`, except that they
+    /// refer to messages instead of fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Retain unknown fields in a specific message type.
+    /// config.retain_unknown_fields(&[".my_messages.MyMessageType"]);
+    ///
+    /// // Retain unknown fields in all messages in a package.
+    /// config.retain_unknown_fields(&[".my_messages"]);
+    ///
+    /// // Retain unknown fields in all messages.
+    /// config.retain_unknown_fields(&["."]);
+    /// ```
+    ///
+    /// [1]: https://docs.rs/prost/*/prost/struct.UnknownFields.html
+    pub fn retain_unknown_fields<I, S>(&mut self, paths: I) -> &mut Self
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> {
+        self.retain_unknown_fields = paths.into_iter().map(|s| s.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Configure the code generator to generate borrowed variants of Protobuf messages.
+    ///
+    /// The borrowed variant of a message is generated alongside the owned struct, with a `Ref`
+    /// suffix and a lifetime parameter (e.g. `FooRef<'a>` for message `Foo`). Borrowed messages
+    /// implement [`prost::Message`][1], and are decoded from a `Cursor<&'a [u8]>` without
+    /// copying: `string` fields become `&'a str`, `bytes` fields become `&'a [u8]`, and
+    /// message fields refer to the borrowed variant of the field's message type. As a result,
+    /// every message type used by a borrowed message must also match one of the paths, and
+    /// borrowed messages may not have fields of extern or well-known message types; `generate`
+    /// returns a `DescriptorError::NotBorrowed` error if they do.
+    ///
+    /// # Arguments
+    ///
+    /// **`paths`** - paths to specific messages, or packages which should have a borrowed
+    /// variant generated. Paths are matched in the same way as the paths passed to
+    /// `retain_unknown_fields`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Generate borrowed variants of all messages in a package.
+    /// config.borrowed(&[".my_messages"]);
+    ///
+    /// // Generate borrowed variants of all messages.
+    /// config.borrowed(&["."]);
+    /// ```
+    ///
+    /// [1]: https://docs.rs/prost/*/prost/trait.Message.html
+    pub fn borrowed<I, S>(&mut self, paths: I) -> &mut Self
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> {
+        self.borrowed = paths.into_iter().map(|s| s.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Add an attribute to the Rust types generated for matching Protobuf messages, enums and
+    /// oneofs.
+    ///
+    /// The attribute is added before the `#[derive]` attribute of each matching struct or enum,
+    /// including the borrowed variants generated by `borrowed`. This can be used to derive
+    /// additional traits, or to add `serde` container attributes.
+    ///
+    /// # Arguments
+    ///
+    /// **`path`** - a path matching any number of types. Paths are matched in the same way as the
+    /// paths passed to `btree_map`, except that they refer to types instead of fields. The path of
+    /// a oneof is the path of its message followed by the oneof name.
+    ///
+    /// **`attribute`** - an arbitrary string, which is added to the generated code verbatim. It
+    /// may contain more than one attribute.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Derive `Eq` and `Hash` for a specific message type.
+    /// config.type_attribute(".my_messages.MyMessageType", "#[derive(Eq, Hash)]");
+    ///
+    /// // Derive `Serialize` and `Deserialize` for all types.
+    /// config.type_attribute(".", "#[derive(Serialize, Deserialize)]");
+    ///
+    /// // Rename the variants of a oneof named 'my_oneof' in any message type.
+    /// config.type_attribute("my_oneof", "#[serde(rename_all = \"snake_case\")]");
+    /// ```
+    pub fn type_attribute<P, A>(&mut self, path: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str> {
+        self.type_attributes.push((path.as_ref().to_string(), attribute.as_ref().to_string()));
+        self
+    }
+
+    /// Add an attribute to the Rust fields generated for matching Protobuf fields.
+    ///
+    /// The attribute is added after the `#[prost]` attribute of each matching struct field or
+    /// oneof variant. Oneofs are held in a struct field with the name of the oneof, which can be
+    /// matched as well.
+    ///
+    /// # Arguments
+    ///
+    /// **`path`** - a path matching any number of fields. Paths are matched in the same way as the
+    /// paths passed to `btree_map`. The fields of a oneof are matched as fields of the oneof,
+    /// whose path is the path of its message followed by the oneof name.
+    ///
+    /// **`attribute`** - an arbitrary string, which is added to the generated code verbatim. It
+    /// may contain more than one attribute.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Skip a specific field when serializing.
+    /// config.field_attribute(".my_messages.MyMessageType.my_field", "#[serde(skip_serializing)]");
+    ///
+    /// // Add an attribute to all fields named 'id'.
+    /// config.field_attribute("id", "#[serde(rename = \"ID\")]");
+    /// ```
+    pub fn field_attribute<P, A>(&mut self, path: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str> {
+        self.field_attributes.push((path.as_ref().to_string(), attribute.as_ref().to_string()));
+        self
+    }
+
+    /// Declare an externally provided Protobuf package or type.
+    ///
+    /// Types in the package, or the type itself, are not generated. Instead, fields and other
+    /// references to them refer to the provided Rust path. This allows a crate to reuse the types
+    /// generated by another crate for a shared `.proto` file, rather than generating its own
+    /// incompatible copies.
+    ///
+    /// # Arguments
+    ///
+    /// **`proto_path`** - a fully qualified Protobuf package or type name, with a leading `.`.
+    /// When more than one extern path covers a type, the longest one is used.
+    ///
+    /// **`rust_path`** - the Rust path of the module holding the package's types, or of the type
+    /// itself. Types nested in the package or type are referred to by appending their module path
+    /// and name to the Rust path, in the same way as they would be generated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `proto_path` is not fully qualified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// // Use the types of the 'my.common' package from the 'common_protos' crate.
+    /// config.extern_path(".my.common", "::common_protos::my::common");
+    ///
+    /// // Use a Rust type for a specific message type.
+    /// config.extern_path(".my.messages.Uuid", "::uuid::Uuid");
+    /// ```
+    pub fn extern_path<P, R>(&mut self, proto_path: P, rust_path: R) -> &mut Self
+    where P: AsRef<str>,
+          R: AsRef<str> {
+        let proto_path = proto_path.as_ref();
+        assert!(proto_path.starts_with('.') && proto_path.len() > 1,
+                "extern path '{}' is not a fully qualified Protobuf name", proto_path);
+        self.extern_paths.push((proto_path.to_string(), rust_path.as_ref().to_string()));
+        self
+    }
+
+    /// Configure the code generator to generate Rust types for the Protobuf well-known types.
+    ///
+    /// By default, the well-known types defined in `google/protobuf/timestamp.proto`,
+    /// `duration.proto`, `struct.proto`, `wrappers.proto`, `empty.proto` and `field_mask.proto`
+    /// are not generated. Instead, fields of the well-known types refer to the types provided by
+    /// the [`prost-types`][1] crate, which must be a dependency of the crate including the
+    /// generated code. This option is only necessary when compiling the well-known types
+    /// themselves, or when `prost-types` can not be used.
+    ///
+    /// [1]: https://docs.rs/prost-types
+    pub fn compile_well_known_types(&mut self) -> &mut Self {
+        self.compile_well_known_types = true;
+        self
+    }
+
+    /// Configure `prost-build` to write an include file, which declares the nested module
+    /// hierarchy of the generated packages and includes the generated code of each of them.
+    ///
+    /// Including the file in a crate, typically at the crate root, declares every package module,
+    /// so that the `super::` paths generated for references between packages resolve. The
+    /// types of `.proto` files without a `package` declaration are included in the module which
+    /// includes the file.
+    ///
+    /// # Arguments
+    ///
+    /// **`filename`** - the name of the include file, which is written to the Cargo `OUT_DIR`
+    /// directory alongside the generated code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let mut config = prost_codegen::CodeGeneratorConfig::new();
+    /// config.include_file("protos.rs");
+    /// ```
+    ///
+    /// The generated modules can then be included in `lib.rs`:
+    ///
+    /// ```rust,ignore
+    /// include!(concat!(env!("OUT_DIR"), "/protos.rs"));
+    /// ```
+    pub fn include_file<S>(&mut self, filename: S) -> &mut Self where S: AsRef<str> {
+        self.include_file = Some(filename.as_ref().to_string());
+        self
+    }
+
     /// Configures the code generator to use the provided service generator.
     pub fn service_generator(&mut self, service_generator: Box<ServiceGenerator>) -> &mut Self {
         self.service_generator = Some(service_generator);
@@ -112,17 +443,40 @@ impl CodeGeneratorConfig {
     }
 }
 
+/// Generates the Rust code for the file descriptors, grouped by module.
+///
+/// An error is returned if the file descriptors are invalid, for example if a field refers to an
+/// undefined type.
 pub fn generate(config: &CodeGeneratorConfig,
                 files: Vec<FileDescriptorProto>)
-                -> HashMap<Module, String> {
+                -> Result<HashMap<Module, String>, DescriptorError> {
     let mut modules = HashMap::new();
 
-    let message_graph = MessageGraph::new(&files);
+    let pool = DescriptorPool::new(FileDescriptorSet { file: files })?;
+    let message_graph = MessageGraph::new(&pool);
 
-    for file in files {
-        let module = module(&file);
+    for file in pool.files() {
+        // The well-known types are provided by the prost-types crate.
+        if !config.compile_well_known_types && is_well_known_file(file.name()) {
+            continue;
+        }
+        // Files in extern packages are generated by another crate.
+        if extern_path(&pool, &config.extern_paths, &format!(".{}", file.package())).is_some() {
+            continue;
+        }
+        let module = module(file);
         let mut buf = modules.entry(module).or_insert(String::new());
-        CodeGenerator::generate(&config, &message_graph, file, &mut buf);
+        CodeGenerator::generate(&config, &pool, &message_graph, file.clone(), &mut buf)?;
     }
-    modules
+    Ok(modules)
+}
+
+/// Returns the name and the source of the include file configured with
+/// `CodeGeneratorConfig::include_file`, which includes the generated code of `modules` from files
+/// named by `module_filename`. Returns `None` if no include file is configured.
+pub fn generate_include_file<'a, I>(config: &CodeGeneratorConfig,
+                                    modules: I)
+                                    -> Option<(String, String)>
+where I: IntoIterator<Item = &'a Module> {
+    config.include_file.as_ref().map(|filename| (filename.clone(), include_file(modules)))
 }
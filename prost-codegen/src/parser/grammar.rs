@@ -0,0 +1,1631 @@
+//! A parser for the `.proto` language, producing the `FileDescriptorProto` of a single file.
+//!
+//! The parser follows the grammar and source location conventions of `protoc`: each definition
+//! is given a `SourceCodeInfo` location spanning its tokens, and comments are attached to
+//! locations as `protoc` attaches them. Type names and options are left as written; they are
+//! resolved once all imported files have been parsed.
+
+use std::mem;
+
+use google::protobuf::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    EnumValueDescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    MessageOptions,
+    MethodDescriptorProto,
+    OneofDescriptorProto,
+    ServiceDescriptorProto,
+    SourceCodeInfo,
+    UninterpretedOption,
+};
+use google::protobuf::descriptor_proto::{
+    ExtensionRange,
+    ReservedRange,
+};
+use google::protobuf::field_descriptor_proto::{
+    Label,
+    Type,
+};
+use google::protobuf::source_code_info::Location;
+use google::protobuf::uninterpreted_option::NamePart;
+use parser::options::Options;
+use parser::tokenizer::{
+    Comments,
+    SyntaxError,
+    Token,
+    TokenKind,
+    Tokenizer,
+    parse_float,
+    parse_integer,
+    unescape,
+};
+
+/// The field numbers of the descriptor messages, which make up source location paths.
+pub mod tag {
+    pub mod file {
+        pub const PACKAGE: i32 = 2;
+        pub const DEPENDENCY: i32 = 3;
+        pub const MESSAGE_TYPE: i32 = 4;
+        pub const ENUM_TYPE: i32 = 5;
+        pub const SERVICE: i32 = 6;
+        pub const EXTENSION: i32 = 7;
+        pub const OPTIONS: i32 = 8;
+        pub const PUBLIC_DEPENDENCY: i32 = 10;
+        pub const WEAK_DEPENDENCY: i32 = 11;
+        pub const SYNTAX: i32 = 12;
+    }
+    pub mod message {
+        pub const NAME: i32 = 1;
+        pub const FIELD: i32 = 2;
+        pub const NESTED_TYPE: i32 = 3;
+        pub const ENUM_TYPE: i32 = 4;
+        pub const EXTENSION_RANGE: i32 = 5;
+        pub const EXTENSION: i32 = 6;
+        pub const OPTIONS: i32 = 7;
+        pub const ONEOF_DECL: i32 = 8;
+        pub const RESERVED_RANGE: i32 = 9;
+        pub const RESERVED_NAME: i32 = 10;
+    }
+    pub mod range {
+        pub const START: i32 = 1;
+        pub const END: i32 = 2;
+    }
+    pub mod field {
+        pub const NAME: i32 = 1;
+        pub const EXTENDEE: i32 = 2;
+        pub const NUMBER: i32 = 3;
+        pub const LABEL: i32 = 4;
+        pub const TYPE: i32 = 5;
+        pub const TYPE_NAME: i32 = 6;
+        pub const DEFAULT_VALUE: i32 = 7;
+        pub const OPTIONS: i32 = 8;
+        pub const JSON_NAME: i32 = 10;
+    }
+    pub mod oneof {
+        pub const NAME: i32 = 1;
+        pub const OPTIONS: i32 = 2;
+    }
+    pub mod enumeration {
+        pub const NAME: i32 = 1;
+        pub const VALUE: i32 = 2;
+        pub const OPTIONS: i32 = 3;
+    }
+    pub mod enum_value {
+        pub const NAME: i32 = 1;
+        pub const NUMBER: i32 = 2;
+        pub const OPTIONS: i32 = 3;
+    }
+    pub mod service {
+        pub const NAME: i32 = 1;
+        pub const METHOD: i32 = 2;
+        pub const OPTIONS: i32 = 3;
+    }
+    pub mod method {
+        pub const NAME: i32 = 1;
+        pub const INPUT_TYPE: i32 = 2;
+        pub const OUTPUT_TYPE: i32 = 3;
+        pub const OPTIONS: i32 = 4;
+        pub const CLIENT_STREAMING: i32 = 5;
+        pub const SERVER_STREAMING: i32 = 6;
+    }
+    pub mod option {
+        pub const UNINTERPRETED_OPTION: i32 = 999;
+        pub const NAME: i32 = 2;
+        pub const NAME_PART: i32 = 1;
+        pub const IDENTIFIER_VALUE: i32 = 3;
+        pub const POSITIVE_INT_VALUE: i32 = 4;
+        pub const NEGATIVE_INT_VALUE: i32 = 5;
+        pub const DOUBLE_VALUE: i32 = 6;
+        pub const STRING_VALUE: i32 = 7;
+        pub const AGGREGATE_VALUE: i32 = 8;
+    }
+}
+
+/// The largest field number.
+pub const MAX_FIELD_NUMBER: i32 = 536_870_911;
+
+/// The end of an extension or reserved range ending with `max`, until the message's options are
+/// known.
+const MAX_RANGE_SENTINEL: i32 = -1;
+
+/// Parses the contents of a `.proto` file.
+pub fn parse(source: &str) -> Result<FileDescriptorProto, SyntaxError> {
+    let mut parser = Parser {
+        tokenizer: Tokenizer::new(source),
+        locations: Vec::new(),
+        upcoming_doc_comments: String::new(),
+        upcoming_detached_comments: Vec::new(),
+        syntax: "proto2".to_string(),
+    };
+    let mut file = FileDescriptorProto::default();
+    parser.parse_file(&mut file)?;
+    file.source_code_info = Some(SourceCodeInfo { location: parser.locations });
+    Ok(file)
+}
+
+/// The type of a field, either a scalar type or the name of a message or enumeration type.
+enum FieldType {
+    Scalar(Type),
+    Named(String),
+}
+
+struct Parser<'a> {
+    tokenizer: Tokenizer<'a>,
+    /// The source locations, in the order the definitions were started.
+    locations: Vec<Location>,
+    /// The leading comments of the next declaration.
+    upcoming_doc_comments: String,
+    /// The detached comments preceding the next declaration.
+    upcoming_detached_comments: Vec<String>,
+    syntax: String,
+}
+
+impl <'a> Parser<'a> {
+
+    fn parse_file(&mut self, file: &mut FileDescriptorProto) -> Result<(), SyntaxError> {
+        let (_, comments) = self.tokenizer.next_with_comments()?;
+        self.upcoming_detached_comments = comments.detached;
+        self.upcoming_doc_comments = comments.leading;
+
+        let root = self.start_location(Vec::new());
+        if self.looking_at("syntax") {
+            self.parse_syntax(file, root)?;
+        }
+        while !self.at_end() {
+            self.parse_top_level_statement(file, root)?;
+        }
+        self.end(root);
+        Ok(())
+    }
+
+    fn parse_syntax(&mut self, file: &mut FileDescriptorProto, root: usize) -> Result<(), SyntaxError> {
+        let location = self.child_location(root, &[tag::file::SYNTAX]);
+        self.consume("syntax")?;
+        self.consume("=")?;
+        let syntax_token = self.tokenizer.current().clone();
+        let syntax = self.consume_utf8_string("Expected syntax identifier.")?;
+        self.consume_end_of_declaration(";", Some(location))?;
+        self.end(location);
+
+        if syntax != "proto2" && syntax != "proto3" {
+            return error_at(&syntax_token,
+                            &format!("Unrecognized syntax identifier \"{}\".  This parser only recognizes \
+                                      \"proto2\" and \"proto3\".", syntax));
+        }
+        self.syntax = syntax.clone();
+        file.syntax = Some(syntax);
+        Ok(())
+    }
+
+    fn parse_top_level_statement(&mut self, file: &mut FileDescriptorProto, root: usize) -> Result<(), SyntaxError> {
+        if self.try_consume_end_of_declaration(";", None)? {
+            // An empty statement.
+        } else if self.looking_at("message") {
+            let location = self.child_location(root, &[tag::file::MESSAGE_TYPE, file.message_type.len() as i32]);
+            let mut message = DescriptorProto::default();
+            self.parse_message_definition(&mut message, location)?;
+            file.message_type.push(message);
+            self.end(location);
+        } else if self.looking_at("enum") {
+            let location = self.child_location(root, &[tag::file::ENUM_TYPE, file.enum_type.len() as i32]);
+            let mut enum_type = EnumDescriptorProto::default();
+            self.parse_enum_definition(&mut enum_type, location)?;
+            file.enum_type.push(enum_type);
+            self.end(location);
+        } else if self.looking_at("service") {
+            let location = self.child_location(root, &[tag::file::SERVICE, file.service.len() as i32]);
+            let mut service = ServiceDescriptorProto::default();
+            self.parse_service_definition(&mut service, location)?;
+            file.service.push(service);
+            self.end(location);
+        } else if self.looking_at("extend") {
+            let location = self.child_location(root, &[tag::file::EXTENSION]);
+            self.parse_extend(&mut file.extension, &mut file.message_type, root, tag::file::MESSAGE_TYPE, location)?;
+            self.end(location);
+        } else if self.looking_at("import") {
+            self.parse_import(file, root)?;
+        } else if self.looking_at("package") {
+            self.parse_package(file, root)?;
+        } else if self.looking_at("option") {
+            let location = self.child_location(root, &[tag::file::OPTIONS]);
+            self.parse_option(file.options.get_or_insert_with(Default::default), location, true)?;
+            self.end(location);
+        } else {
+            return self.error("Expected top-level statement (e.g. \"message\").");
+        }
+        Ok(())
+    }
+
+    fn parse_import(&mut self, file: &mut FileDescriptorProto, root: usize) -> Result<(), SyntaxError> {
+        let location = self.child_location(root, &[tag::file::DEPENDENCY, file.dependency.len() as i32]);
+        self.consume("import")?;
+        if self.looking_at("public") {
+            let public_location = self.child_location(root, &[tag::file::PUBLIC_DEPENDENCY,
+                                                             file.public_dependency.len() as i32]);
+            self.consume("public")?;
+            self.end(public_location);
+            file.public_dependency.push(file.dependency.len() as i32);
+        } else if self.looking_at("weak") {
+            let weak_location = self.child_location(root, &[tag::file::WEAK_DEPENDENCY,
+                                                           file.weak_dependency.len() as i32]);
+            self.consume("weak")?;
+            self.end(weak_location);
+            file.weak_dependency.push(file.dependency.len() as i32);
+        }
+        let dependency = self.consume_utf8_string("Expected a string naming the file to import.")?;
+        file.dependency.push(dependency);
+        self.consume_end_of_declaration(";", Some(location))?;
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_package(&mut self, file: &mut FileDescriptorProto, root: usize) -> Result<(), SyntaxError> {
+        if file.package.is_some() {
+            return self.error("Multiple package definitions.");
+        }
+        let location = self.child_location(root, &[tag::file::PACKAGE]);
+        self.consume("package")?;
+
+        let mut package = String::new();
+        loop {
+            package.push_str(&self.consume_identifier("Expected identifier.")?);
+            if !self.try_consume(".")? {
+                break;
+            }
+            package.push('.');
+        }
+        file.package = Some(package);
+        self.consume_end_of_declaration(";", Some(location))?;
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_message_definition(&mut self, message: &mut DescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        self.consume("message")?;
+        let name_location = self.child_location(location, &[tag::message::NAME]);
+        message.name = Some(self.consume_identifier("Expected message name.")?);
+        self.end(name_location);
+        self.parse_message_block(message, location)
+    }
+
+    fn parse_message_block(&mut self, message: &mut DescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        self.consume_end_of_declaration("{", Some(location))?;
+        while !self.try_consume_end_of_declaration("}", None)? {
+            if self.at_end() {
+                return self.error("Reached end of input in message definition (missing '}').");
+            }
+            self.parse_message_statement(message, location)?;
+        }
+
+        // The end of a range ending with `max` depends on whether the message uses the message
+        // set wire format, which is only known once its options have been parsed.
+        let max = if is_message_set(message) { i32::max_value() } else { MAX_FIELD_NUMBER + 1 };
+        for range in &mut message.extension_range {
+            if range.end == Some(MAX_RANGE_SENTINEL) {
+                range.end = Some(max);
+            }
+        }
+        for range in &mut message.reserved_range {
+            if range.end == Some(MAX_RANGE_SENTINEL) {
+                range.end = Some(max);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_message_statement(&mut self, message: &mut DescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        if self.try_consume_end_of_declaration(";", None)? {
+            // An empty statement.
+        } else if self.looking_at("message") {
+            let nested_location = self.child_location(location, &[tag::message::NESTED_TYPE,
+                                                                  message.nested_type.len() as i32]);
+            let mut nested = DescriptorProto::default();
+            self.parse_message_definition(&mut nested, nested_location)?;
+            message.nested_type.push(nested);
+            self.end(nested_location);
+        } else if self.looking_at("enum") {
+            let enum_location = self.child_location(location, &[tag::message::ENUM_TYPE,
+                                                                message.enum_type.len() as i32]);
+            let mut enum_type = EnumDescriptorProto::default();
+            self.parse_enum_definition(&mut enum_type, enum_location)?;
+            message.enum_type.push(enum_type);
+            self.end(enum_location);
+        } else if self.looking_at("extensions") {
+            let extensions_location = self.child_location(location, &[tag::message::EXTENSION_RANGE]);
+            self.parse_extensions(message, extensions_location)?;
+            self.end(extensions_location);
+        } else if self.looking_at("reserved") {
+            self.parse_reserved(message, location)?;
+        } else if self.looking_at("extend") {
+            let extend_location = self.child_location(location, &[tag::message::EXTENSION]);
+            self.parse_extend(&mut message.extension,
+                              &mut message.nested_type,
+                              location,
+                              tag::message::NESTED_TYPE,
+                              extend_location)?;
+            self.end(extend_location);
+        } else if self.looking_at("option") {
+            let options_location = self.child_location(location, &[tag::message::OPTIONS]);
+            self.parse_option(message.options.get_or_insert_with(Default::default), options_location, true)?;
+            self.end(options_location);
+        } else if self.looking_at("oneof") {
+            let oneof_index = message.oneof_decl.len() as i32;
+            let oneof_location = self.child_location(location, &[tag::message::ONEOF_DECL, oneof_index]);
+            let mut oneof = OneofDescriptorProto::default();
+            self.parse_oneof(&mut oneof, message, oneof_index, oneof_location, location)?;
+            message.oneof_decl.push(oneof);
+            self.end(oneof_location);
+        } else {
+            let field_location = self.child_location(location, &[tag::message::FIELD, message.field.len() as i32]);
+            let mut field = FieldDescriptorProto::default();
+            self.parse_message_field(&mut field,
+                                     &mut message.nested_type,
+                                     location,
+                                     tag::message::NESTED_TYPE,
+                                     field_location)?;
+            message.field.push(field);
+            self.end(field_location);
+        }
+        Ok(())
+    }
+
+    /// Parses a field, including its label.
+    ///
+    /// Groups and map fields define a message type as well as the field, which is added to
+    /// `messages`, the nested types of the containing message or the types of the file.
+    fn parse_message_field(&mut self,
+                           field: &mut FieldDescriptorProto,
+                           messages: &mut Vec<DescriptorProto>,
+                           parent_location: usize,
+                           nested_type_tag: i32,
+                           field_location: usize)
+                           -> Result<(), SyntaxError> {
+        let label = match &self.tokenizer.current().text[..] {
+            "optional" => Some(Label::LabelOptional),
+            "repeated" => Some(Label::LabelRepeated),
+            "required" => Some(Label::LabelRequired),
+            _ => None,
+        };
+        if let Some(label) = label {
+            let label_location = self.child_location(field_location, &[tag::field::LABEL]);
+            self.next()?;
+            self.end(label_location);
+            field.set_label(label);
+            if label == Label::LabelOptional && self.syntax == "proto3" {
+                return self.error("Explicit 'optional' labels are disallowed in the Proto3 syntax. To define \
+                                   'optional' fields in Proto3, simply remove the 'optional' label, as fields \
+                                   are 'optional' by default.");
+            }
+        }
+        self.parse_message_field_no_label(field, messages, parent_location, nested_type_tag, field_location)
+    }
+
+    fn parse_message_field_no_label(&mut self,
+                                    field: &mut FieldDescriptorProto,
+                                    messages: &mut Vec<DescriptorProto>,
+                                    parent_location: usize,
+                                    nested_type_tag: i32,
+                                    field_location: usize)
+                                    -> Result<(), SyntaxError> {
+        // The path of the type location depends on whether the type is a scalar type.
+        let type_location = self.child_location(field_location, &[]);
+
+        // A type named `map` is only a map field if it is followed by `<`.
+        let mut ty = None;
+        let mut map_types = None;
+        if self.try_consume("map")? {
+            if self.looking_at("<") {
+                if field.oneof_index.is_some() {
+                    return self.error("Map fields are not allowed in oneofs.");
+                }
+                if field.label.is_some() {
+                    return self.error("Field labels (required/optional/repeated) are not allowed on map fields.");
+                }
+                if field.extendee.is_some() {
+                    return self.error("Map fields are not allowed to be extensions.");
+                }
+                field.set_label(Label::LabelRepeated);
+                self.consume("<")?;
+                let key_type = self.parse_type()?;
+                self.consume(",")?;
+                let value_type = self.parse_type()?;
+                self.consume(">")?;
+                map_types = Some((key_type, value_type));
+                self.locations[type_location].path.push(tag::field::TYPE_NAME);
+            } else {
+                ty = Some(FieldType::Named("map".to_string()));
+            }
+        }
+
+        if map_types.is_none() {
+            if field.label.is_none() {
+                if self.syntax != "proto3" {
+                    return self.error("Expected \"required\", \"optional\", or \"repeated\".");
+                }
+                field.set_label(Label::LabelOptional);
+            }
+            let ty = match ty {
+                Some(ty) => ty,
+                None => self.parse_type()?,
+            };
+            match ty {
+                FieldType::Scalar(ty) => {
+                    self.locations[type_location].path.push(tag::field::TYPE);
+                    field.set_type_(ty);
+                },
+                FieldType::Named(type_name) => {
+                    self.locations[type_location].path.push(tag::field::TYPE_NAME);
+                    field.type_name = Some(type_name);
+                },
+            }
+        }
+        self.end(type_location);
+
+        let name_token = self.tokenizer.current().clone();
+        let name_location = self.child_location(field_location, &[tag::field::NAME]);
+        field.name = Some(self.consume_identifier("Expected field name.")?);
+        self.end(name_location);
+
+        self.consume_with("=", "Missing field number.")?;
+
+        let number_location = self.child_location(field_location, &[tag::field::NUMBER]);
+        field.number = Some(self.consume_integer("Expected field number.")?);
+        self.end(number_location);
+
+        self.parse_field_options(field, field_location)?;
+
+        if field.type_() == Some(Type::TypeGroup) {
+            // A group defines a message type as well as a field, so their locations overlap.
+            let group_location = self.child_location(parent_location, &[nested_type_tag, messages.len() as i32]);
+            let field_span = self.locations[field_location].span.clone();
+            self.locations[group_location].span = field_span;
+
+            let mut group = DescriptorProto::default();
+            group.name = field.name.clone();
+
+            // The name of the group, and the type name of the field, are both the field name.
+            let group_name_location = self.child_location(group_location, &[tag::message::NAME]);
+            self.start_at(group_name_location, &name_token);
+            self.end_at(group_name_location, &name_token);
+            let type_name_location = self.child_location(field_location, &[tag::field::TYPE_NAME]);
+            self.start_at(type_name_location, &name_token);
+            self.end_at(type_name_location, &name_token);
+
+            if !group.name().starts_with(|c: char| c.is_ascii_uppercase()) {
+                return error_at(&name_token, "Group names must start with a capital letter.");
+            }
+            field.name = Some(group.name().to_ascii_lowercase());
+            field.type_name = group.name.clone();
+
+            if !self.looking_at("{") {
+                return self.error("Missing group body.");
+            }
+            self.parse_message_block(&mut group, group_location)?;
+            messages.push(group);
+            self.end(group_location);
+        } else {
+            self.consume_end_of_declaration(";", Some(field_location))?;
+        }
+
+        if let Some((key_type, value_type)) = map_types {
+            let entry = map_entry(field, key_type, value_type);
+            field.type_name = entry.name.clone();
+            messages.push(entry);
+        }
+        Ok(())
+    }
+
+    fn parse_type(&mut self) -> Result<FieldType, SyntaxError> {
+        match scalar_type(&self.tokenizer.current().text) {
+            Some(ty) => {
+                self.next()?;
+                Ok(FieldType::Scalar(ty))
+            },
+            None => self.parse_user_defined_type().map(FieldType::Named),
+        }
+    }
+
+    fn parse_user_defined_type(&mut self) -> Result<String, SyntaxError> {
+        if scalar_type(&self.tokenizer.current().text).is_some() {
+            return self.error("Expected message type.");
+        }
+        let mut type_name = String::new();
+        if self.try_consume(".")? {
+            type_name.push('.');
+        }
+        type_name.push_str(&self.consume_identifier("Expected type name.")?);
+        while self.try_consume(".")? {
+            type_name.push('.');
+            type_name.push_str(&self.consume_identifier("Expected identifier.")?);
+        }
+        Ok(type_name)
+    }
+
+    fn parse_field_options(&mut self, field: &mut FieldDescriptorProto, field_location: usize) -> Result<(), SyntaxError> {
+        if !self.looking_at("[") {
+            return Ok(());
+        }
+        let location = self.child_location(field_location, &[tag::field::OPTIONS]);
+        self.consume("[")?;
+        loop {
+            // The default value and JSON name are not options, so their locations are children of
+            // the field location.
+            if self.looking_at("default") {
+                self.parse_default_assignment(field, field_location)?;
+            } else if self.looking_at("json_name") {
+                self.parse_json_name(field, field_location)?;
+            } else {
+                self.parse_option(field.options.get_or_insert_with(Default::default), location, false)?;
+            }
+            if !self.try_consume(",")? {
+                break;
+            }
+        }
+        self.consume("]")?;
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_default_assignment(&mut self, field: &mut FieldDescriptorProto, field_location: usize) -> Result<(), SyntaxError> {
+        if field.default_value.is_some() {
+            return self.error("Already set option \"default\".");
+        }
+        self.consume("default")?;
+        self.consume("=")?;
+
+        let location = self.child_location(field_location, &[tag::field::DEFAULT_VALUE]);
+        let ty = match field.type_() {
+            Some(ty) => ty,
+            None => {
+                // The field refers to a message or enumeration type, which is not known until
+                // the type name is resolved. The default value is checked once it is.
+                field.default_value = Some(self.tokenizer.current().text.clone());
+                self.next()?;
+                self.end(location);
+                return Ok(());
+            },
+        };
+
+        let default_value = match ty {
+            Type::TypeInt32 | Type::TypeInt64 |
+            Type::TypeSint32 | Type::TypeSint64 |
+            Type::TypeSfixed32 | Type::TypeSfixed64 => {
+                let mut max = match ty {
+                    Type::TypeInt32 | Type::TypeSint32 | Type::TypeSfixed32 => i32::max_value() as u64,
+                    _ => i64::max_value() as u64,
+                };
+                let negative = self.try_consume("-")?;
+                if negative {
+                    // There is one more negative value than positive value.
+                    max += 1;
+                }
+                let value = self.consume_integer64(max, "Expected integer for field default value.")?;
+                if negative && value != 0 {
+                    format!("-{}", value)
+                } else {
+                    value.to_string()
+                }
+            },
+            Type::TypeUint32 | Type::TypeUint64 |
+            Type::TypeFixed32 | Type::TypeFixed64 => {
+                let max = match ty {
+                    Type::TypeUint32 | Type::TypeFixed32 => u32::max_value() as u64,
+                    _ => u64::max_value(),
+                };
+                if self.looking_at("-") {
+                    return self.error("Unsigned field can't have negative default value.");
+                }
+                self.consume_integer64(max, "Expected integer for field default value.")?.to_string()
+            },
+            Type::TypeFloat | Type::TypeDouble => {
+                let negative = self.try_consume("-")?;
+                let mut value = self.consume_number("Expected number.")?;
+                if negative {
+                    value = -value;
+                }
+                if ty == Type::TypeFloat {
+                    format_float(value as f32)
+                } else {
+                    format_double(value)
+                }
+            },
+            Type::TypeBool => {
+                if self.try_consume("true")? {
+                    "true".to_string()
+                } else if self.try_consume("false")? {
+                    "false".to_string()
+                } else {
+                    return self.error("Expected \"true\" or \"false\".");
+                }
+            },
+            Type::TypeString => self.consume_utf8_string("Expected string for field default value.")?,
+            Type::TypeBytes => c_escape(&self.consume_string("Expected string.")?),
+            Type::TypeEnum => self.consume_identifier("Expected enum identifier for field default value.")?,
+            Type::TypeMessage | Type::TypeGroup => return self.error("Messages can't have default values."),
+        };
+        field.default_value = Some(default_value);
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_json_name(&mut self, field: &mut FieldDescriptorProto, field_location: usize) -> Result<(), SyntaxError> {
+        if field.json_name.is_some() {
+            return self.error("Already set option \"json_name\".");
+        }
+        let location = self.child_location(field_location, &[tag::field::JSON_NAME]);
+        self.consume("json_name")?;
+        self.consume("=")?;
+        let value_location = self.child_location(location, &[]);
+        field.json_name = Some(self.consume_utf8_string("Expected string for JSON name.")?);
+        self.end(value_location);
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_extensions(&mut self, message: &mut DescriptorProto, extensions_location: usize) -> Result<(), SyntaxError> {
+        self.consume("extensions")?;
+        loop {
+            let location = self.child_location(extensions_location, &[message.extension_range.len() as i32]);
+            let (start, end) = self.parse_range(location, "Expected field number range.")?;
+            message.extension_range.push(ExtensionRange { start: Some(start), end: Some(end) });
+            self.end(location);
+            if !self.try_consume(",")? {
+                break;
+            }
+        }
+        self.consume_end_of_declaration(";", Some(extensions_location))
+    }
+
+    fn parse_reserved(&mut self, message: &mut DescriptorProto, message_location: usize) -> Result<(), SyntaxError> {
+        let start_token = self.tokenizer.current().clone();
+        self.consume("reserved")?;
+        if self.looking_at_kind(TokenKind::String) {
+            let location = self.child_location(message_location, &[tag::message::RESERVED_NAME]);
+            self.start_at(location, &start_token);
+            loop {
+                let name_location = self.child_location(location, &[message.reserved_name.len() as i32]);
+                message.reserved_name.push(self.consume_utf8_string("Expected field name.")?);
+                self.end(name_location);
+                if !self.try_consume(",")? {
+                    break;
+                }
+            }
+            self.consume_end_of_declaration(";", Some(location))?;
+            self.end(location);
+        } else {
+            let location = self.child_location(message_location, &[tag::message::RESERVED_RANGE]);
+            self.start_at(location, &start_token);
+            let mut error = "Expected field name or number range.";
+            loop {
+                let range_location = self.child_location(location, &[message.reserved_range.len() as i32]);
+                let (start, end) = self.parse_range(range_location, error)?;
+                message.reserved_range.push(ReservedRange { start: Some(start), end: Some(end) });
+                self.end(range_location);
+                error = "Expected field number range.";
+                if !self.try_consume(",")? {
+                    break;
+                }
+            }
+            self.consume_end_of_declaration(";", Some(location))?;
+            self.end(location);
+        }
+        Ok(())
+    }
+
+    /// Parses an inclusive range of field numbers, such as `1`, `2 to 5` or `100 to max`, and
+    /// returns its start and exclusive end.
+    fn parse_range(&mut self, location: usize, error: &str) -> Result<(i32, i32), SyntaxError> {
+        let start_token = self.tokenizer.current().clone();
+        let start_location = self.child_location(location, &[tag::range::START]);
+        let start = self.consume_integer(error)?;
+        self.end(start_location);
+
+        let end = if self.try_consume("to")? {
+            let end_location = self.child_location(location, &[tag::range::END]);
+            let end = if self.try_consume("max")? {
+                MAX_RANGE_SENTINEL
+            } else {
+                self.consume_integer("Expected integer.")? + 1
+            };
+            self.end(end_location);
+            end
+        } else {
+            let end_location = self.child_location(location, &[tag::range::END]);
+            self.start_at(end_location, &start_token);
+            self.end_at(end_location, &start_token);
+            start + 1
+        };
+        Ok((start, end))
+    }
+
+    fn parse_extend(&mut self,
+                    extensions: &mut Vec<FieldDescriptorProto>,
+                    messages: &mut Vec<DescriptorProto>,
+                    parent_location: usize,
+                    nested_type_tag: i32,
+                    extend_location: usize)
+                    -> Result<(), SyntaxError> {
+        self.consume("extend")?;
+        let extendee_start = self.tokenizer.current().clone();
+        let extendee = self.parse_user_defined_type()?;
+        let extendee_end = self.tokenizer.previous().clone();
+        self.consume_end_of_declaration("{", Some(extend_location))?;
+
+        loop {
+            if self.at_end() {
+                return self.error("Reached end of input in extend definition (missing '}').");
+            }
+            let location = self.child_location(extend_location, &[extensions.len() as i32]);
+            let extendee_location = self.child_location(location, &[tag::field::EXTENDEE]);
+            self.start_at(extendee_location, &extendee_start);
+            self.end_at(extendee_location, &extendee_end);
+
+            let mut field = FieldDescriptorProto::default();
+            field.extendee = Some(extendee.clone());
+            self.parse_message_field(&mut field, messages, parent_location, nested_type_tag, location)?;
+            extensions.push(field);
+            self.end(location);
+
+            if self.try_consume_end_of_declaration("}", None)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_oneof(&mut self,
+                   oneof: &mut OneofDescriptorProto,
+                   message: &mut DescriptorProto,
+                   oneof_index: i32,
+                   oneof_location: usize,
+                   message_location: usize)
+                   -> Result<(), SyntaxError> {
+        self.consume("oneof")?;
+        let name_location = self.child_location(oneof_location, &[tag::oneof::NAME]);
+        oneof.name = Some(self.consume_identifier("Expected oneof name.")?);
+        self.end(name_location);
+        self.consume_end_of_declaration("{", Some(oneof_location))?;
+
+        loop {
+            if self.at_end() {
+                return self.error("Reached end of input in oneof definition (missing '}').");
+            }
+
+            if self.looking_at("option") {
+                let options_location = self.child_location(oneof_location, &[tag::oneof::OPTIONS]);
+                self.parse_option(oneof.options.get_or_insert_with(Default::default), options_location, true)?;
+                self.end(options_location);
+            } else {
+                if self.looking_at("required") || self.looking_at("optional") || self.looking_at("repeated") {
+                    return self.error("Fields in oneofs must not have labels (required / optional / repeated).");
+                }
+                let field_location = self.child_location(message_location, &[tag::message::FIELD,
+                                                                             message.field.len() as i32]);
+                let mut field = FieldDescriptorProto::default();
+                field.set_label(Label::LabelOptional);
+                field.oneof_index = Some(oneof_index);
+                self.parse_message_field_no_label(&mut field,
+                                                  &mut message.nested_type,
+                                                  message_location,
+                                                  tag::message::NESTED_TYPE,
+                                                  field_location)?;
+                message.field.push(field);
+                self.end(field_location);
+            }
+
+            if self.try_consume_end_of_declaration("}", None)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_enum_definition(&mut self, enum_type: &mut EnumDescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        self.consume("enum")?;
+        let name_location = self.child_location(location, &[tag::enumeration::NAME]);
+        enum_type.name = Some(self.consume_identifier("Expected enum name.")?);
+        self.end(name_location);
+
+        self.consume_end_of_declaration("{", Some(location))?;
+        while !self.try_consume_end_of_declaration("}", None)? {
+            if self.at_end() {
+                return self.error("Reached end of input in enum definition (missing '}').");
+            }
+            if self.try_consume_end_of_declaration(";", None)? {
+                // An empty statement.
+            } else if self.looking_at("option") {
+                let options_location = self.child_location(location, &[tag::enumeration::OPTIONS]);
+                self.parse_option(enum_type.options.get_or_insert_with(Default::default), options_location, true)?;
+                self.end(options_location);
+            } else {
+                let value_location = self.child_location(location, &[tag::enumeration::VALUE,
+                                                                     enum_type.value.len() as i32]);
+                let mut value = EnumValueDescriptorProto::default();
+                self.parse_enum_constant(&mut value, value_location)?;
+                enum_type.value.push(value);
+                self.end(value_location);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_enum_constant(&mut self, value: &mut EnumValueDescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        let name_location = self.child_location(location, &[tag::enum_value::NAME]);
+        value.name = Some(self.consume_identifier("Expected enum constant name.")?);
+        self.end(name_location);
+
+        self.consume_with("=", "Missing numeric value for enum constant.")?;
+
+        let number_location = self.child_location(location, &[tag::enum_value::NUMBER]);
+        let negative = self.try_consume("-")?;
+        let max = i32::max_value() as u64 + if negative { 1 } else { 0 };
+        let number = self.consume_integer64(max, "Expected integer.")? as i64;
+        value.number = Some(if negative { -number } else { number } as i32);
+        self.end(number_location);
+
+        if self.looking_at("[") {
+            let options_location = self.child_location(location, &[tag::enum_value::OPTIONS]);
+            self.consume("[")?;
+            loop {
+                self.parse_option(value.options.get_or_insert_with(Default::default), options_location, false)?;
+                if !self.try_consume(",")? {
+                    break;
+                }
+            }
+            self.consume("]")?;
+            self.end(options_location);
+        }
+
+        self.consume_end_of_declaration(";", Some(location))
+    }
+
+    fn parse_service_definition(&mut self, service: &mut ServiceDescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        self.consume("service")?;
+        let name_location = self.child_location(location, &[tag::service::NAME]);
+        service.name = Some(self.consume_identifier("Expected service name.")?);
+        self.end(name_location);
+
+        self.consume_end_of_declaration("{", Some(location))?;
+        while !self.try_consume_end_of_declaration("}", None)? {
+            if self.at_end() {
+                return self.error("Reached end of input in service definition (missing '}').");
+            }
+            if self.try_consume_end_of_declaration(";", None)? {
+                // An empty statement.
+            } else if self.looking_at("option") {
+                let options_location = self.child_location(location, &[tag::service::OPTIONS]);
+                self.parse_option(service.options.get_or_insert_with(Default::default), options_location, true)?;
+                self.end(options_location);
+            } else {
+                let method_location = self.child_location(location, &[tag::service::METHOD,
+                                                                      service.method.len() as i32]);
+                let mut method = MethodDescriptorProto::default();
+                self.parse_service_method(&mut method, method_location)?;
+                service.method.push(method);
+                self.end(method_location);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_service_method(&mut self, method: &mut MethodDescriptorProto, location: usize) -> Result<(), SyntaxError> {
+        self.consume("rpc")?;
+        let name_location = self.child_location(location, &[tag::method::NAME]);
+        method.name = Some(self.consume_identifier("Expected method name.")?);
+        self.end(name_location);
+
+        self.consume("(")?;
+        if self.looking_at("stream") {
+            let stream_location = self.child_location(location, &[tag::method::CLIENT_STREAMING]);
+            self.consume("stream")?;
+            self.end(stream_location);
+            method.client_streaming = Some(true);
+        }
+        let input_location = self.child_location(location, &[tag::method::INPUT_TYPE]);
+        method.input_type = Some(self.parse_user_defined_type()?);
+        self.end(input_location);
+        self.consume(")")?;
+
+        self.consume("returns")?;
+        self.consume("(")?;
+        if self.looking_at("stream") {
+            let stream_location = self.child_location(location, &[tag::method::SERVER_STREAMING]);
+            self.consume("stream")?;
+            self.end(stream_location);
+            method.server_streaming = Some(true);
+        }
+        let output_location = self.child_location(location, &[tag::method::OUTPUT_TYPE]);
+        method.output_type = Some(self.parse_user_defined_type()?);
+        self.end(output_location);
+        self.consume(")")?;
+
+        if !self.looking_at("{") {
+            return self.consume_end_of_declaration(";", Some(location));
+        }
+
+        self.consume_end_of_declaration("{", Some(location))?;
+        while !self.try_consume_end_of_declaration("}", None)? {
+            if self.at_end() {
+                return self.error("Reached end of input in method options (missing '}').");
+            }
+            if !self.try_consume_end_of_declaration(";", None)? {
+                let options_location = self.child_location(location, &[tag::method::OPTIONS]);
+                self.parse_option(method.options.get_or_insert_with(Default::default), options_location, true)?;
+                self.end(options_location);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an option, either an `option` statement or an assignment in a `[...]` list, and
+    /// appends it to the uninterpreted options.
+    fn parse_option<O>(&mut self, options: &mut O, options_location: usize, statement: bool) -> Result<(), SyntaxError>
+    where O: Options {
+        let location = self.child_location(options_location, &[tag::option::UNINTERPRETED_OPTION,
+                                                               options.uninterpreted_option().len() as i32]);
+        if statement {
+            self.consume("option")?;
+        }
+
+        let mut option = UninterpretedOption::default();
+        let name_location = self.child_location(location, &[tag::option::NAME]);
+        loop {
+            let part_location = self.child_location(name_location, &[tag::option::NAME, option.name.len() as i32]);
+            let part = self.parse_option_name_part(part_location)?;
+            option.name.push(part);
+            self.end(part_location);
+            if !self.try_consume(".")? {
+                break;
+            }
+        }
+        self.end(name_location);
+
+        self.consume("=")?;
+
+        // Every value is a single token, except for negative numbers and aggregate values.
+        let value_location = self.child_location(location, &[]);
+        let negative = self.try_consume("-")?;
+        let value_tag = match self.tokenizer.current().kind {
+            TokenKind::Start | TokenKind::End => {
+                return self.error("Unexpected end of stream while parsing option value.");
+            },
+            TokenKind::Identifier => {
+                if negative {
+                    return self.error("Invalid '-' symbol before identifier.");
+                }
+                option.identifier_value = Some(self.consume_identifier("Expected identifier.")?);
+                tag::option::IDENTIFIER_VALUE
+            },
+            TokenKind::Integer => {
+                let max = if negative { i64::max_value() as u64 + 1 } else { u64::max_value() };
+                let value = self.consume_integer64(max, "Expected integer.")?;
+                if negative {
+                    option.negative_int_value = Some((value as i64).wrapping_neg());
+                    tag::option::NEGATIVE_INT_VALUE
+                } else {
+                    option.positive_int_value = Some(value);
+                    tag::option::POSITIVE_INT_VALUE
+                }
+            },
+            TokenKind::Float => {
+                let value = self.consume_number("Expected number.")?;
+                option.double_value = Some(if negative { -value } else { value });
+                tag::option::DOUBLE_VALUE
+            },
+            TokenKind::String => {
+                if negative {
+                    return self.error("Invalid '-' symbol before string.");
+                }
+                option.string_value = Some(self.consume_string("Expected string.")?);
+                tag::option::STRING_VALUE
+            },
+            TokenKind::Symbol => {
+                if !self.looking_at("{") {
+                    return self.error("Expected option value.");
+                }
+                option.aggregate_value = Some(self.parse_uninterpreted_block()?);
+                tag::option::AGGREGATE_VALUE
+            },
+        };
+        self.locations[value_location].path.push(value_tag);
+        self.end(value_location);
+
+        options.uninterpreted_option().push(option);
+
+        if statement {
+            self.consume_end_of_declaration(";", Some(location))?;
+        }
+        self.end(location);
+        Ok(())
+    }
+
+    fn parse_option_name_part(&mut self, part_location: usize) -> Result<NamePart, SyntaxError> {
+        let mut name = String::new();
+        let is_extension = self.try_consume("(")?;
+        let location = self.child_location(part_location, &[tag::option::NAME_PART]);
+        if is_extension {
+            // An extension name is a dot-separated name, which may begin with a dot.
+            if self.looking_at_kind(TokenKind::Identifier) {
+                name.push_str(&self.consume_identifier("Expected identifier.")?);
+            }
+            while self.try_consume(".")? {
+                name.push('.');
+                name.push_str(&self.consume_identifier("Expected identifier.")?);
+            }
+            self.end(location);
+            self.consume(")")?;
+        } else {
+            name.push_str(&self.consume_identifier("Expected identifier.")?);
+            self.end(location);
+        }
+        Ok(NamePart { name_part: Some(name), is_extension: Some(is_extension) })
+    }
+
+    /// Parses an aggregate option value, and returns its tokens separated by spaces, without the
+    /// enclosing braces.
+    fn parse_uninterpreted_block(&mut self) -> Result<String, SyntaxError> {
+        self.consume("{")?;
+        let mut value = String::new();
+        let mut depth = 1;
+        while !self.at_end() {
+            if self.looking_at("{") {
+                depth += 1;
+            } else if self.looking_at("}") {
+                depth -= 1;
+                if depth == 0 {
+                    self.next()?;
+                    return Ok(value);
+                }
+            }
+            if !value.is_empty() {
+                value.push(' ');
+            }
+            value.push_str(&self.tokenizer.current().text);
+            self.next()?;
+        }
+        self.error("Unexpected end of stream while parsing aggregate value.")
+    }
+
+    // Source locations.
+
+    /// Starts a location at the current token.
+    fn start_location(&mut self, path: Vec<i32>) -> usize {
+        let span = {
+            let token = self.tokenizer.current();
+            vec![token.line, token.column]
+        };
+        self.locations.push(Location { path: path, span: span, ..Location::default() });
+        self.locations.len() - 1
+    }
+
+    /// Starts a location at the current token, with the path of the parent location extended by
+    /// the path components.
+    fn child_location(&mut self, parent: usize, components: &[i32]) -> usize {
+        let mut path = self.locations[parent].path.clone();
+        path.extend_from_slice(components);
+        self.start_location(path)
+    }
+
+    fn start_at(&mut self, location: usize, token: &Token) {
+        let span = &mut self.locations[location].span;
+        span[0] = token.line;
+        span[1] = token.column;
+    }
+
+    fn end_at(&mut self, location: usize, token: &Token) {
+        let span = &mut self.locations[location].span;
+        if token.line != span[0] {
+            span.push(token.line);
+        }
+        span.push(token.end_column);
+    }
+
+    /// Ends a location at the previous token, unless it has already been ended.
+    fn end(&mut self, location: usize) {
+        if self.locations[location].span.len() <= 2 {
+            let previous = self.tokenizer.previous().clone();
+            self.end_at(location, &previous);
+        }
+    }
+
+    fn attach_comments(&mut self, location: usize, leading: String, trailing: String, detached: Vec<String>) {
+        let location = &mut self.locations[location];
+        if !leading.is_empty() {
+            location.leading_comments = Some(leading);
+        }
+        if !trailing.is_empty() {
+            location.trailing_comments = Some(trailing);
+        }
+        location.leading_detached_comments.extend(detached);
+    }
+
+    // Tokens.
+
+    fn error<T>(&self, message: &str) -> Result<T, SyntaxError> {
+        error_at(self.tokenizer.current(), message)
+    }
+
+    fn next(&mut self) -> Result<(), SyntaxError> {
+        self.tokenizer.next().map(|_| ())
+    }
+
+    fn at_end(&self) -> bool {
+        self.looking_at_kind(TokenKind::End)
+    }
+
+    fn looking_at(&self, text: &str) -> bool {
+        self.tokenizer.current().text == text
+    }
+
+    fn looking_at_kind(&self, kind: TokenKind) -> bool {
+        self.tokenizer.current().kind == kind
+    }
+
+    fn try_consume(&mut self, text: &str) -> Result<bool, SyntaxError> {
+        if self.looking_at(text) {
+            self.next()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn consume(&mut self, text: &str) -> Result<(), SyntaxError> {
+        self.consume_with(text, &format!("Expected \"{}\".", text))
+    }
+
+    fn consume_with(&mut self, text: &str, error: &str) -> Result<(), SyntaxError> {
+        if self.try_consume(text)? {
+            Ok(())
+        } else {
+            self.error(error)
+        }
+    }
+
+    fn consume_identifier(&mut self, error: &str) -> Result<String, SyntaxError> {
+        if !self.looking_at_kind(TokenKind::Identifier) {
+            return self.error(error);
+        }
+        let identifier = self.tokenizer.current().text.clone();
+        self.next()?;
+        Ok(identifier)
+    }
+
+    fn consume_integer(&mut self, error: &str) -> Result<i32, SyntaxError> {
+        self.consume_integer64(i32::max_value() as u64, error).map(|value| value as i32)
+    }
+
+    fn consume_integer64(&mut self, max: u64, error: &str) -> Result<u64, SyntaxError> {
+        if !self.looking_at_kind(TokenKind::Integer) {
+            return self.error(error);
+        }
+        match parse_integer(&self.tokenizer.current().text, max) {
+            Some(value) => {
+                self.next()?;
+                Ok(value)
+            },
+            None => self.error("Integer out of range."),
+        }
+    }
+
+    /// Consumes a number, which may be a float, an integer, `inf` or `nan`.
+    fn consume_number(&mut self, error: &str) -> Result<f64, SyntaxError> {
+        let value = match self.tokenizer.current().kind {
+            TokenKind::Float => parse_float(&self.tokenizer.current().text),
+            TokenKind::Integer => match parse_integer(&self.tokenizer.current().text, u64::max_value()) {
+                Some(value) => value as f64,
+                None => return self.error("Integer out of range."),
+            },
+            _ if self.looking_at("inf") => ::std::f64::INFINITY,
+            _ if self.looking_at("nan") => ::std::f64::NAN,
+            _ => return self.error(error),
+        };
+        self.next()?;
+        Ok(value)
+    }
+
+    /// Consumes a string, and any strings immediately following it.
+    fn consume_string(&mut self, error: &str) -> Result<Vec<u8>, SyntaxError> {
+        if !self.looking_at_kind(TokenKind::String) {
+            return self.error(error);
+        }
+        let mut value = Vec::new();
+        while self.looking_at_kind(TokenKind::String) {
+            value.extend(unescape(&self.tokenizer.current().text));
+            self.next()?;
+        }
+        Ok(value)
+    }
+
+    fn consume_utf8_string(&mut self, error: &str) -> Result<String, SyntaxError> {
+        let token = self.tokenizer.current().clone();
+        String::from_utf8(self.consume_string(error)?).or_else(|_| {
+            error_at(&token, "String is not valid UTF-8.")
+        })
+    }
+
+    /// Consumes the token ending a declaration, such as `;` or `{`, and the comments following
+    /// it. Comments preceding the declaration and following the token are attached to the
+    /// declaration's location, if it has one.
+    fn try_consume_end_of_declaration(&mut self, text: &str, location: Option<usize>) -> Result<bool, SyntaxError> {
+        if !self.looking_at(text) {
+            return Ok(false);
+        }
+        let (_, Comments { trailing, detached, leading }) = self.tokenizer.next_with_comments()?;
+
+        // The leading comments of the next declaration become upcoming, and the upcoming
+        // comments belong to this declaration.
+        let leading = mem::replace(&mut self.upcoming_doc_comments, leading);
+        match location {
+            Some(location) => {
+                let detached = mem::replace(&mut self.upcoming_detached_comments, detached);
+                self.attach_comments(location, leading, trailing, detached);
+            },
+            // Detached comments at the end of a scope do not belong to any declaration.
+            None if text == "}" => self.upcoming_detached_comments = detached,
+            None => self.upcoming_detached_comments.extend(detached),
+        }
+        Ok(true)
+    }
+
+    fn consume_end_of_declaration(&mut self, text: &str, location: Option<usize>) -> Result<(), SyntaxError> {
+        if self.try_consume_end_of_declaration(text, location)? {
+            Ok(())
+        } else {
+            self.error(&format!("Expected \"{}\".", text))
+        }
+    }
+}
+
+fn error_at<T>(token: &Token, message: &str) -> Result<T, SyntaxError> {
+    Err(SyntaxError { line: token.line, column: token.column, message: message.to_string() })
+}
+
+fn scalar_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "double" => Type::TypeDouble,
+        "float" => Type::TypeFloat,
+        "int64" => Type::TypeInt64,
+        "uint64" => Type::TypeUint64,
+        "int32" => Type::TypeInt32,
+        "fixed64" => Type::TypeFixed64,
+        "fixed32" => Type::TypeFixed32,
+        "bool" => Type::TypeBool,
+        "string" => Type::TypeString,
+        "group" => Type::TypeGroup,
+        "bytes" => Type::TypeBytes,
+        "uint32" => Type::TypeUint32,
+        "sfixed32" => Type::TypeSfixed32,
+        "sfixed64" => Type::TypeSfixed64,
+        "sint32" => Type::TypeSint32,
+        "sint64" => Type::TypeSint64,
+        _ => return None,
+    })
+}
+
+/// Returns `true` if the message sets the `message_set_wire_format` option.
+fn is_message_set(message: &DescriptorProto) -> bool {
+    message.options.as_ref().map_or(false, |options| {
+        options.uninterpreted_option.iter().any(|option| {
+            option.name.len() == 1
+                && option.name[0].name_part() == "message_set_wire_format"
+                && option.identifier_value() == "true"
+        })
+    })
+}
+
+/// Returns the entry message type of a map field.
+fn map_entry(field: &FieldDescriptorProto, key_type: FieldType, value_type: FieldType) -> DescriptorProto {
+    let entry_field = |name: &str, number, ty| {
+        let mut field = FieldDescriptorProto::default();
+        field.name = Some(name.to_string());
+        field.number = Some(number);
+        field.set_label(Label::LabelOptional);
+        match ty {
+            FieldType::Scalar(ty) => field.set_type_(ty),
+            FieldType::Named(type_name) => field.type_name = Some(type_name),
+        }
+        field
+    };
+
+    let mut entry = DescriptorProto::default();
+    entry.name = Some(map_entry_name(field.name()));
+    entry.field.push(entry_field("key", 1, key_type));
+    entry.field.push(entry_field("value", 2, value_type));
+    entry.options = Some(MessageOptions { map_entry: Some(true), ..MessageOptions::default() });
+    entry
+}
+
+/// Returns the name of the entry message type of a map field, for example `FooBarEntry` for the
+/// field `foo_bar`.
+fn map_entry_name(field_name: &str) -> String {
+    let mut name = String::with_capacity(field_name.len() + 5);
+    let mut capitalize = true;
+    for c in field_name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            name.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name.push_str("Entry");
+    name
+}
+
+/// Formats a `float` default value with the fewest digits which parse back to the same value.
+fn format_float(value: f32) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    } else if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let short = format_g(value as f64, 6);
+    if short.parse::<f32>() == Ok(value) {
+        short
+    } else {
+        format_g(value as f64, 9)
+    }
+}
+
+/// Formats a `double` default value with the fewest digits which parse back to the same value.
+fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    } else if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let short = format_g(value, 15);
+    if short.parse::<f64>() == Ok(value) {
+        short
+    } else {
+        format_g(value, 17)
+    }
+}
+
+/// Formats a finite number like the C `%.<precision>g` format: in scientific notation if the
+/// exponent is less than -4 or at least the precision, and without trailing zeros.
+fn format_g(value: f64, precision: usize) -> String {
+    fn trim_zeros(digits: &str) -> &str {
+        if digits.contains('.') {
+            digits.trim_right_matches('0').trim_right_matches('.')
+        } else {
+            digits
+        }
+    }
+
+    let scientific = format!("{:.*e}", precision - 1, value);
+    let exponent_idx = scientific.find('e').unwrap();
+    let exponent: i32 = scientific[exponent_idx + 1..].parse().unwrap();
+    if exponent < -4 || exponent >= precision as i32 {
+        let mantissa = trim_zeros(&scientific[..exponent_idx]);
+        format!("{}e{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value);
+        trim_zeros(&fixed).to_string()
+    }
+}
+
+/// Escapes a `bytes` default value like the C++ `CEscape` function.
+fn c_escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '...b'~' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location<'a>(file: &'a FileDescriptorProto, path: &[i32]) -> &'a Location {
+        let locations = &file.source_code_info.as_ref().unwrap().location;
+        locations.iter()
+                 .find(|location| location.path == path)
+                 .unwrap_or_else(|| panic!("no location for path {:?}", path))
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let file = parse("\
+syntax = \"proto2\";
+package foo.bar;
+
+message Foo {
+  required int32 a = 1 [default = -0x10];
+  repeated string b = 2 [packed = false, json_name = \"bee\"];
+  optional group Baz = 3 {
+    optional Foo foo = 1;
+  }
+  map<string, .foo.bar.Foo> c = 4;
+  oneof d {
+    bytes e = 5 [default = \"\\001\\n\"];
+  }
+  extensions 100 to max;
+  reserved 6, 8 to 10;
+  reserved \"f\";
+}
+").unwrap();
+
+        assert_eq!(file.syntax(), "proto2");
+        assert_eq!(file.package(), "foo.bar");
+        let message = &file.message_type[0];
+        assert_eq!(message.name(), "Foo");
+
+        let fields = &message.field;
+        assert_eq!(fields.len(), 5);
+        assert_eq!(fields[0].label(), Some(Label::LabelRequired));
+        assert_eq!(fields[0].type_(), Some(Type::TypeInt32));
+        assert_eq!(fields[0].default_value(), "-16");
+        assert_eq!(fields[1].json_name(), "bee");
+        assert_eq!(fields[1].options.as_ref().unwrap().uninterpreted_option[0].identifier_value(), "false");
+        assert_eq!(fields[2].name(), "baz");
+        assert_eq!(fields[2].type_(), Some(Type::TypeGroup));
+        assert_eq!(fields[2].type_name(), "Baz");
+        assert_eq!(fields[3].label(), Some(Label::LabelRepeated));
+        assert_eq!(fields[3].type_(), None);
+        assert_eq!(fields[3].type_name(), "CEntry");
+        assert_eq!(fields[4].oneof_index, Some(0));
+        assert_eq!(fields[4].default_value(), "\\001\\n");
+
+        let nested = &message.nested_type;
+        assert_eq!(nested[0].name(), "Baz");
+        assert_eq!(nested[0].field[0].type_name(), "Foo");
+        assert_eq!(nested[1].name(), "CEntry");
+        assert_eq!(nested[1].options.as_ref().unwrap().map_entry, Some(true));
+        assert_eq!(nested[1].field[0].type_(), Some(Type::TypeString));
+        assert_eq!(nested[1].field[1].type_name(), ".foo.bar.Foo");
+
+        assert_eq!(message.oneof_decl[0].name(), "d");
+        assert_eq!(message.extension_range, vec![ExtensionRange { start: Some(100), end: Some(536870912) }]);
+        assert_eq!(message.reserved_range, vec![ReservedRange { start: Some(6), end: Some(7) },
+                                                ReservedRange { start: Some(8), end: Some(11) }]);
+        assert_eq!(message.reserved_name, vec!["f".to_string()]);
+
+        // Group locations overlap the field location.
+        assert_eq!(location(&file, &[4, 0, 2, 2]).span, vec![6, 2, 8, 3]);
+        assert_eq!(location(&file, &[4, 0, 3, 0]).span, vec![6, 2, 8, 3]);
+        assert_eq!(location(&file, &[4, 0, 3, 0, 1]).span, vec![6, 17, 20]);
+        assert_eq!(location(&file, &[4, 0, 2, 2, 6]).span, vec![6, 17, 20]);
+        assert_eq!(location(&file, &[4, 0, 2, 3, 6]).span, vec![9, 2, 27]);
+        assert_eq!(location(&file, &[4, 0, 2, 0, 7]).span, vec![4, 34, 39]);
+        assert_eq!(location(&file, &[4, 0, 5, 0, 2]).span, vec![13, 20, 23]);
+        assert_eq!(location(&file, &[4, 0, 9, 0, 2]).span, vec![14, 11, 12]);
+        assert_eq!(location(&file, &[2]).span, vec![1, 0, 16]);
+    }
+
+    #[test]
+    fn test_parse_service_and_options() {
+        let file = parse("\
+syntax = \"proto3\";
+
+import public \"other.proto\";
+
+option java_package = \"com.example\";
+option (my.opt).x = -5;
+
+enum E {
+  option allow_alias = true;
+  ZERO = 0;
+  NEG = -1 [(v) = { a: 1 b: \"c\" }];
+}
+
+service S {
+  rpc M (stream In) returns (Out) {
+    option deprecated = true;
+  }
+  rpc N (.pkg.In) returns (stream Out);
+}
+").unwrap();
+
+        assert_eq!(file.dependency, vec!["other.proto".to_string()]);
+        assert_eq!(file.public_dependency, vec![0]);
+
+        let options = &file.options.as_ref().unwrap().uninterpreted_option;
+        assert_eq!(options[0].name[0].name_part(), "java_package");
+        assert_eq!(options[0].string_value(), b"com.example");
+        assert_eq!(options[1].name[0], NamePart { name_part: Some("my.opt".to_string()), is_extension: Some(true) });
+        assert_eq!(options[1].name[1], NamePart { name_part: Some("x".to_string()), is_extension: Some(false) });
+        assert_eq!(options[1].negative_int_value(), -5);
+
+        let enum_type = &file.enum_type[0];
+        assert_eq!(enum_type.value[1].number(), -1);
+        let value_option = &enum_type.value[1].options.as_ref().unwrap().uninterpreted_option[0];
+        assert_eq!(value_option.aggregate_value(), "a : 1 b : \"c\"");
+
+        let service = &file.service[0];
+        assert_eq!(service.method[0].client_streaming, Some(true));
+        assert_eq!(service.method[0].server_streaming, None);
+        assert_eq!(service.method[0].input_type(), "In");
+        assert!(service.method[0].options.is_some());
+        assert_eq!(service.method[1].input_type(), ".pkg.In");
+        assert_eq!(service.method[1].server_streaming, Some(true));
+
+        assert_eq!(location(&file, &[3, 0]).span, vec![2, 0, 28]);
+        assert_eq!(location(&file, &[10, 0]).span, vec![2, 7, 13]);
+        assert_eq!(location(&file, &[8, 999, 1, 2, 2, 0]).span, vec![5, 7, 15]);
+        assert_eq!(location(&file, &[8, 999, 1, 5]).span, vec![5, 20, 22]);
+        assert_eq!(location(&file, &[6, 0, 2, 0, 4, 999, 0]).span, vec![15, 4, 29]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let file = parse("\
+// Detached.
+
+// Leading.
+syntax = \"proto3\"; // Trailing.
+
+/* Message
+ * comment. */
+message Foo {
+  // Field.
+  int32 a = 1;
+
+  // Dangling.
+}
+").unwrap();
+
+        let syntax = location(&file, &[12]);
+        assert_eq!(syntax.leading_detached_comments, vec![" Detached.\n".to_string()]);
+        assert_eq!(syntax.leading_comments(), " Leading.\n");
+        assert_eq!(syntax.trailing_comments(), " Trailing.\n");
+
+        let message = location(&file, &[4, 0]);
+        assert_eq!(message.leading_comments(), " Message\n comment. ");
+        assert_eq!(message.span, vec![7, 0, 12, 1]);
+
+        let field = location(&file, &[4, 0, 2, 0]);
+        assert_eq!(field.leading_comments(), " Field.\n");
+        assert_eq!(field.trailing_comments, None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| {
+            let error = parse(source).unwrap_err();
+            (error.line, error.column, error.message)
+        };
+        assert_eq!(error("message Foo { int32 a = 1; }"),
+                   (0, 14, "Expected \"required\", \"optional\", or \"repeated\".".to_string()));
+        assert_eq!(error("syntax = \"proto3\";\nmessage Foo {\n  optional int32 a = 1;\n}"),
+                   (2, 11, "Explicit 'optional' labels are disallowed in the Proto3 syntax. To define \
+                            'optional' fields in Proto3, simply remove the 'optional' label, as fields \
+                            are 'optional' by default.".to_string()));
+        assert_eq!(error("syntax = \"proto4\";"),
+                   (0, 9, "Unrecognized syntax identifier \"proto4\".  This parser only recognizes \
+                           \"proto2\" and \"proto3\".".to_string()));
+        assert_eq!(error("message Foo {"),
+                   (0, 13, "Reached end of input in message definition (missing '}').".to_string()));
+        assert_eq!(error("message Foo { optional uint32 a = 1 [default = -1]; }"),
+                   (0, 47, "Unsigned field can't have negative default value.".to_string()));
+        assert_eq!(error("foo"), (0, 0, "Expected top-level statement (e.g. \"message\").".to_string()));
+    }
+
+    #[test]
+    fn test_format_default() {
+        assert_eq!(format_double(1.0), "1");
+        assert_eq!(format_double(-0.0), "-0");
+        assert_eq!(format_double(0.1), "0.1");
+        assert_eq!(format_double(1e100), "1e+100");
+        assert_eq!(format_double(1.5e-7), "1.5e-07");
+        assert_eq!(format_double(123456789012345680.0), "1.2345678901234568e+17");
+        assert_eq!(format_float(0.1), "0.1");
+        assert_eq!(format_float(16777217.0), "16777216");
+        assert_eq!(format_float(3.4028235e38), "3.40282347e+38");
+        assert_eq!(c_escape(b"a\0\"\xff"), "a\\000\\\"\\377");
+        assert_eq!(map_entry_name("foo_bar_baz"), "FooBarBazEntry");
+    }
+}
@@ -0,0 +1,540 @@
+//! A parser for `.proto` files, which produces the same file descriptors as `protoc`.
+//!
+//! Files are parsed by `grammar`, which records type names and options as written. Once a file
+//! and its imports are parsed, type names are resolved by the `DescriptorPool`, the definitions
+//! are checked, and the options are interpreted by `options`.
+
+mod grammar;
+mod options;
+mod tokenizer;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::iter;
+use std::path::{Component, Path, PathBuf};
+
+use itertools::Itertools;
+
+use descriptor_pool::{
+    DescriptorError,
+    DescriptorPool,
+    qualify,
+};
+use google::protobuf::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    FieldDescriptorProto,
+    FileDescriptorProto,
+    FileDescriptorSet,
+};
+use google::protobuf::field_descriptor_proto::{Label, Type};
+use self::grammar::{MAX_FIELD_NUMBER, tag};
+
+/// Parses `.proto` files, and returns the descriptors of the files and of the files they import,
+/// in the form written by `protoc --include_imports --include_source_info --descriptor_set_out`.
+///
+/// Each file in `protos` must be within one of the `includes` directories, or be a path relative
+/// to one of them. Imports are looked up in the `includes` directories in order, and then in the
+/// well-known types bundled with `prost-codegen`, such as `google/protobuf/timestamp.proto`.
+///
+/// Files are listed after the files they import.
+pub fn parse_protos<P>(protos: &[P], includes: &[P]) -> Result<FileDescriptorSet, ParseError>
+where P: AsRef<Path> {
+    let names = protos.iter()
+                      .map(|proto| input_name(proto.as_ref(), includes))
+                      .collect::<Result<Vec<_>, _>>()?;
+    compile(&names, |name| read_file(name, includes))
+}
+
+/// An error parsing `.proto` files.
+#[derive(Debug)]
+pub enum ParseError {
+    /// A file could not be read.
+    Io(PathBuf, io::Error),
+    /// A file was not found in the include directories.
+    NotFound(String),
+    /// A file is not a valid `.proto` file. The line and column are one-based.
+    Invalid {
+        file: String,
+        line: i32,
+        column: i32,
+        message: String,
+    },
+    /// A type name could not be resolved.
+    Descriptor(DescriptorError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Io(ref path, ref error) => write!(f, "{}: {}", path.display(), error),
+            ParseError::NotFound(ref name) => write!(f, "{}: File not found.", name),
+            ParseError::Invalid { ref file, line, column, ref message } => {
+                write!(f, "{}:{}:{}: {}", file, line, column, message)
+            },
+            ParseError::Descriptor(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::Io(..) => "failed to read .proto file",
+            ParseError::NotFound(..) => ".proto file not found",
+            ParseError::Invalid { .. } => "invalid .proto file",
+            ParseError::Descriptor(..) => "failed to resolve types",
+        }
+    }
+}
+
+impl From<DescriptorError> for ParseError {
+    fn from(error: DescriptorError) -> ParseError {
+        ParseError::Descriptor(error)
+    }
+}
+
+/// Returns the `.proto` source of a well-known type file bundled with `prost-codegen`.
+fn bundled_file(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "google/protobuf/any.proto" => include_str!("../../protobuf/google/protobuf/any.proto"),
+        "google/protobuf/api.proto" => include_str!("../../protobuf/google/protobuf/api.proto"),
+        "google/protobuf/descriptor.proto" => include_str!("../../protobuf/google/protobuf/descriptor.proto"),
+        "google/protobuf/duration.proto" => include_str!("../../protobuf/google/protobuf/duration.proto"),
+        "google/protobuf/empty.proto" => include_str!("../../protobuf/google/protobuf/empty.proto"),
+        "google/protobuf/field_mask.proto" => include_str!("../../protobuf/google/protobuf/field_mask.proto"),
+        "google/protobuf/source_context.proto" => include_str!("../../protobuf/google/protobuf/source_context.proto"),
+        "google/protobuf/struct.proto" => include_str!("../../protobuf/google/protobuf/struct.proto"),
+        "google/protobuf/timestamp.proto" => include_str!("../../protobuf/google/protobuf/timestamp.proto"),
+        "google/protobuf/type.proto" => include_str!("../../protobuf/google/protobuf/type.proto"),
+        "google/protobuf/wrappers.proto" => include_str!("../../protobuf/google/protobuf/wrappers.proto"),
+        _ => return None,
+    })
+}
+
+/// Returns the name of an input file relative to the include directory containing it, which is
+/// the name it is imported by.
+fn input_name<P>(proto: &Path, includes: &[P]) -> Result<String, ParseError> where P: AsRef<Path> {
+    fn normalize(path: &Path) -> PathBuf {
+        path.components().filter(|component| *component != Component::CurDir).collect()
+    }
+
+    let proto = normalize(proto);
+    let relative = includes.iter().filter_map(|include| {
+        proto.strip_prefix(&normalize(include.as_ref())).ok().map(Path::to_path_buf)
+    }).next();
+    match relative.as_ref().unwrap_or(&proto).to_str() {
+        Some(name) => Ok(name.replace('\\', "/")),
+        None => Err(ParseError::NotFound(proto.display().to_string())),
+    }
+}
+
+/// Reads an imported file from the first include directory containing it.
+fn read_file<P>(name: &str, includes: &[P]) -> Result<Option<String>, ParseError> where P: AsRef<Path> {
+    for include in includes {
+        let path = include.as_ref().join(name);
+        if path.is_file() {
+            let mut source = String::new();
+            return match File::open(&path).and_then(|mut file| file.read_to_string(&mut source)) {
+                Ok(_) => Ok(Some(source)),
+                Err(error) => Err(ParseError::Io(path, error)),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// Parses the named files and their imports, reading sources with `read`.
+pub fn compile<S, F>(names: &[S], read: F) -> Result<FileDescriptorSet, ParseError>
+where S: AsRef<str>,
+      F: FnMut(&str) -> Result<Option<String>, ParseError> {
+    let mut loader = Loader { read: read, files: Vec::new(), importing: Vec::new() };
+    for name in names {
+        if !loader.load(name.as_ref())? {
+            return Err(ParseError::NotFound(name.as_ref().to_string()));
+        }
+    }
+
+    let pool = DescriptorPool::new(FileDescriptorSet { file: loader.files })?;
+    for file in pool.files() {
+        validate_file(&pool, file)?;
+    }
+
+    let mut files = pool.files().to_vec();
+    for file in &mut files {
+        set_json_names(&mut file.extension);
+        for message in &mut file.message_type {
+            set_message_json_names(message);
+        }
+    }
+    options::interpret(&mut files)?;
+
+    // `protoc` only records the syntax of proto3 files.
+    for file in &mut files {
+        if file.syntax() != "proto3" {
+            file.syntax = None;
+        }
+    }
+    Ok(FileDescriptorSet { file: files })
+}
+
+/// Loads files and their imports, in import order.
+struct Loader<F> {
+    read: F,
+    files: Vec<FileDescriptorProto>,
+    /// The names of the files whose imports are being loaded.
+    importing: Vec<String>,
+}
+
+impl <F> Loader<F> where F: FnMut(&str) -> Result<Option<String>, ParseError> {
+
+    /// Loads a file, after the files it imports. Returns `false` if the file is not found.
+    fn load(&mut self, name: &str) -> Result<bool, ParseError> {
+        if self.files.iter().any(|file| file.name() == name) {
+            return Ok(true);
+        }
+        let source = match (self.read)(name)? {
+            Some(source) => source,
+            None => match bundled_file(name) {
+                Some(source) => source.to_string(),
+                None => return Ok(false),
+            },
+        };
+
+        let mut file = grammar::parse(&source).map_err(|error| ParseError::Invalid {
+            file: name.to_string(),
+            line: error.line + 1,
+            column: error.column + 1,
+            message: error.message,
+        })?;
+        file.name = Some(name.to_string());
+
+        self.importing.push(name.to_string());
+        for (idx, dependency) in file.dependency.iter().enumerate() {
+            let path = [tag::file::DEPENDENCY, idx as i32];
+            if let Some(position) = self.importing.iter().position(|importing| importing == dependency) {
+                let cycle = self.importing[position..].iter().chain(iter::once(dependency)).join(" -> ");
+                return Err(invalid(&file, &path, format!("File recursively imports itself: {}", cycle)));
+            }
+            if !self.load(dependency)? {
+                return Err(invalid(&file, &path, format!("Import \"{}\" was not found or had errors.", dependency)));
+            }
+        }
+        self.importing.pop();
+
+        self.files.push(file);
+        Ok(true)
+    }
+}
+
+/// Returns an error located at the definition with the source location path in a file, or at
+/// the innermost definition containing it.
+fn invalid(file: &FileDescriptorProto, path: &[i32], message: String) -> ParseError {
+    let span = file.source_code_info.as_ref().and_then(|info| {
+        (0..path.len() + 1).rev().filter_map(|len| {
+            info.location.iter().find(|location| location.path[..] == path[..len])
+        }).next()
+    }).map(|location| &location.span[..]);
+    let (line, column) = match span {
+        Some(span) if span.len() >= 2 => (span[0], span[1]),
+        _ => (0, 0),
+    };
+    ParseError::Invalid {
+        file: file.name().to_string(),
+        line: line + 1,
+        column: column + 1,
+        message: message,
+    }
+}
+
+/// Checks the rules for definitions which the grammar does not enforce.
+fn validate_file(pool: &DescriptorPool, file: &FileDescriptorProto) -> Result<(), ParseError> {
+    let validator = Validator {
+        pool: pool,
+        file: file,
+        is_proto3: file.syntax() == "proto3",
+    };
+    let package = file.package();
+    for (idx, message) in file.message_type.iter().enumerate() {
+        validator.validate_message(message, vec![tag::file::MESSAGE_TYPE, idx as i32], package)?;
+    }
+    for (idx, enum_type) in file.enum_type.iter().enumerate() {
+        validator.validate_enum(enum_type, vec![tag::file::ENUM_TYPE, idx as i32])?;
+    }
+    for (idx, extension) in file.extension.iter().enumerate() {
+        validator.validate_field(extension, vec![tag::file::EXTENSION, idx as i32])?;
+    }
+    Ok(())
+}
+
+struct Validator<'a> {
+    pool: &'a DescriptorPool,
+    file: &'a FileDescriptorProto,
+    is_proto3: bool,
+}
+
+impl <'a> Validator<'a> {
+
+    fn error(&self, path: &[i32], message: &str) -> Result<(), ParseError> {
+        Err(invalid(self.file, path, message.to_string()))
+    }
+
+    fn validate_message(&self, message: &DescriptorProto, path: Vec<i32>, scope: &str) -> Result<(), ParseError> {
+        let full_name = qualify(scope, message.name());
+        let child = |components: &[i32]| {
+            let mut child = path.clone();
+            child.extend_from_slice(components);
+            child
+        };
+
+        if self.is_proto3 && !message.extension_range.is_empty() {
+            self.error(&child(&[tag::message::EXTENSION_RANGE, 0]), "Extension ranges are not allowed in proto3.")?;
+        }
+
+        for (idx, field) in message.field.iter().enumerate() {
+            let field_path = child(&[tag::message::FIELD, idx as i32]);
+            self.validate_field(field, field_path.clone())?;
+
+            let number_path = extend(&field_path, tag::field::NUMBER);
+            if let Some(other) = message.field[..idx].iter().find(|other| other.number() == field.number()) {
+                self.error(&number_path, &format!("Field number {} has already been used in \"{}\" by field \"{}\".",
+                                                  field.number(), full_name, other.name()))?;
+            }
+            if message.reserved_range.iter().any(|range| range.start() <= field.number() && field.number() < range.end()) {
+                self.error(&number_path, &format!("Field \"{}\" uses reserved number {}.", field.name(), field.number()))?;
+            }
+            if message.reserved_name.iter().any(|name| name == field.name()) {
+                self.error(&extend(&field_path, tag::field::NAME),
+                           &format!("Field name \"{}\" is reserved.", field.name()))?;
+            }
+        }
+        for (idx, extension) in message.extension.iter().enumerate() {
+            self.validate_field(extension, child(&[tag::message::EXTENSION, idx as i32]))?;
+        }
+        for (idx, nested) in message.nested_type.iter().enumerate() {
+            self.validate_message(nested, child(&[tag::message::NESTED_TYPE, idx as i32]), &full_name)?;
+        }
+        for (idx, enum_type) in message.enum_type.iter().enumerate() {
+            self.validate_enum(enum_type, child(&[tag::message::ENUM_TYPE, idx as i32]))?;
+        }
+        Ok(())
+    }
+
+    fn validate_field(&self, field: &FieldDescriptorProto, path: Vec<i32>) -> Result<(), ParseError> {
+        let number_path = extend(&path, tag::field::NUMBER);
+        if field.number() <= 0 {
+            self.error(&number_path, "Field numbers must be positive integers.")?;
+        } else if field.number() > MAX_FIELD_NUMBER {
+            self.error(&number_path, &format!("Field numbers cannot be greater than {}.", MAX_FIELD_NUMBER))?;
+        } else if 19000 <= field.number() && field.number() <= 19999 {
+            self.error(&number_path, "Field numbers 19000 through 19999 are reserved for the protocol buffer \
+                                      library implementation.")?;
+        }
+
+        let ty = field.type_();
+        if self.is_proto3 {
+            if field.label() == Some(Label::LabelRequired) {
+                self.error(&extend(&path, tag::field::LABEL), "Required fields are not allowed in proto3.")?;
+            }
+            if field.default_value.is_some() {
+                self.error(&extend(&path, tag::field::DEFAULT_VALUE), "Explicit default values are not allowed in proto3.")?;
+            }
+            if ty == Some(Type::TypeGroup) {
+                self.error(&extend(&path, tag::field::TYPE), "Groups are not supported in proto3 syntax.")?;
+            }
+        }
+
+        if let Some(ref default_value) = field.default_value {
+            let default_path = extend(&path, tag::field::DEFAULT_VALUE);
+            if field.label() == Some(Label::LabelRepeated) {
+                self.error(&default_path, "Repeated fields can't have default values.")?;
+            }
+            match ty {
+                Some(Type::TypeMessage) | Some(Type::TypeGroup) => {
+                    self.error(&default_path, "Messages can't have default values.")?;
+                },
+                Some(Type::TypeEnum) => {
+                    let enum_type = self.pool.get_enum(field.type_name()).expect("enum type not found");
+                    if enum_type.value_number(default_value).is_none() {
+                        self.error(&default_path, &format!("Enum type \"{}\" has no value named \"{}\".",
+                                                           enum_type.full_name(), default_value))?;
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        let entry = self.pool.get_message(field.type_name()).filter(|message| message.is_map_entry());
+        if let Some(entry) = entry {
+            let key_type = entry.get_field(1).expect("map entry has no key field").ty();
+            match key_type {
+                Type::TypeEnum => {
+                    self.error(&extend(&path, tag::field::TYPE_NAME), "Key in map fields cannot be enum types.")?;
+                },
+                Type::TypeFloat | Type::TypeDouble | Type::TypeBytes | Type::TypeMessage | Type::TypeGroup => {
+                    self.error(&extend(&path, tag::field::TYPE_NAME),
+                               "Key in map fields cannot be float/double, bytes or message types.")?;
+                },
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_enum(&self, enum_type: &EnumDescriptorProto, path: Vec<i32>) -> Result<(), ParseError> {
+        if enum_type.value.is_empty() {
+            return self.error(&path, "Enums must contain at least one value.");
+        }
+        if self.is_proto3 && enum_type.value[0].number() != 0 {
+            let mut number_path = path.clone();
+            number_path.extend_from_slice(&[tag::enumeration::VALUE, 0, tag::enum_value::NUMBER]);
+            self.error(&number_path, "The first enum value must be zero in proto3.")?;
+        }
+        Ok(())
+    }
+}
+
+fn extend(path: &[i32], component: i32) -> Vec<i32> {
+    let mut path = path.to_vec();
+    path.push(component);
+    path
+}
+
+fn set_message_json_names(message: &mut DescriptorProto) {
+    set_json_names(&mut message.field);
+    set_json_names(&mut message.extension);
+    for nested in &mut message.nested_type {
+        set_message_json_names(nested);
+    }
+}
+
+/// Sets the JSON name of fields without an explicit `json_name` option.
+fn set_json_names(fields: &mut [FieldDescriptorProto]) {
+    for field in fields {
+        if field.json_name.is_none() {
+            field.json_name = Some(json_name(field.name()));
+        }
+    }
+}
+
+/// Returns the default JSON name of a field, for example `fooBar` for `foo_bar`.
+fn json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            json_name.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use bytes::Buf;
+    use prost::Message;
+
+    use super::*;
+
+    fn compile_sources(sources: &[(&str, &str)]) -> Result<FileDescriptorSet, ParseError> {
+        let sources = sources.iter().cloned().collect::<HashMap<_, _>>();
+        compile(&[sources.keys().min().unwrap()], |name| Ok(sources.get(name).map(|source| source.to_string())))
+    }
+
+    fn error_message(sources: &[(&str, &str)]) -> String {
+        compile_sources(sources).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_imports() {
+        let set = compile_sources(&[
+            ("a.proto", "syntax = \"proto3\"; package a; import \"b.proto\"; import \"google/protobuf/empty.proto\"; \
+                         message A { b.B b = 1; google.protobuf.Empty e = 2; }"),
+            ("b.proto", "package b; message B { optional int32 some_value = 1; }"),
+        ]).unwrap();
+
+        let names = set.file.iter().map(|file| file.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["b.proto", "google/protobuf/empty.proto", "a.proto"]);
+
+        let a = &set.file[2];
+        assert_eq!(a.syntax(), "proto3");
+        assert_eq!(a.message_type[0].field[0].type_name(), ".b.B");
+        assert_eq!(a.message_type[0].field[0].type_(), Some(Type::TypeMessage));
+        assert_eq!(a.message_type[0].field[1].type_name(), ".google.protobuf.Empty");
+
+        let b = &set.file[0];
+        assert_eq!(b.syntax, None);
+        assert_eq!(b.message_type[0].field[0].json_name(), "someValue");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error_message(&[("a.proto", "import \"b.proto\";")]),
+                   "a.proto:1:1: Import \"b.proto\" was not found or had errors.");
+        assert_eq!(error_message(&[("a.proto", "import \"b.proto\";"), ("b.proto", "import \"a.proto\";")]),
+                   "b.proto:1:1: File recursively imports itself: a.proto -> b.proto -> a.proto");
+        assert_eq!(error_message(&[("a.proto", "message A {\n  optional int32 a = 0;\n}")]),
+                   "a.proto:2:22: Field numbers must be positive integers.");
+        assert_eq!(error_message(&[("a.proto", "message A {\n  optional int32 a = 1;\n  optional int32 b = 1;\n}")]),
+                   "a.proto:3:22: Field number 1 has already been used in \"A\" by field \"a\".");
+        assert_eq!(error_message(&[("a.proto", "syntax = \"proto3\";\nenum E {\n  A = 1;\n}")]),
+                   "a.proto:3:7: The first enum value must be zero in proto3.");
+        assert_eq!(error_message(&[("a.proto", "syntax = \"proto3\";\nmessage A {\n  map<float, int32> m = 1;\n}")]),
+                   "a.proto:3:3: Key in map fields cannot be float/double, bytes or message types.");
+        assert_eq!(error_message(&[("a.proto", "enum E { Z = 0; }\nmessage A {\n  optional E e = 1 [default = B];\n}")]),
+                   "a.proto:3:31: Enum type \"E\" has no value named \"B\".");
+        assert_eq!(error_message(&[("a.proto", "message A {\n  optional int32 a = 1 [default = ]\n}")]),
+                   "a.proto:2:35: Expected integer for field default value.");
+    }
+
+    /// Checks that the descriptors of `.proto` files are the same as the descriptor set produced by
+    /// `protoc`. The descriptor sets in `tests/descriptors` are generated from the repository root
+    /// with:
+    ///
+    /// ```bash
+    /// protoc --include_imports --include_source_info -I prost-codegen/protobuf \
+    ///     -o prost-codegen/tests/descriptors/well_known_types.pb \
+    ///     google/protobuf/descriptor.proto google/protobuf/api.proto \
+    ///     google/protobuf/struct.proto google/protobuf/type.proto
+    /// protoc --include_imports --include_source_info -I test-all-types/src -I prost-codegen/protobuf \
+    ///     -o prost-codegen/tests/descriptors/test_messages_proto3.pb test_messages_proto3.proto
+    /// protoc --include_imports --include_source_info -I tests/packages -I prost-codegen/protobuf \
+    ///     -o prost-codegen/tests/descriptors/root.pb root.proto
+    /// ```
+    fn check_descriptors(names: &[&str], descriptor_set: &[u8]) {
+        let sources = vec![
+            ("test_messages_proto3.proto", include_str!("../../../test-all-types/src/test_messages_proto3.proto")),
+            ("root.proto", include_str!("../../../tests/packages/root.proto")),
+        ].into_iter().collect::<HashMap<_, _>>();
+        let actual = compile(names, |name| Ok(sources.get(name).map(|source| source.to_string()))).unwrap();
+
+        let len = descriptor_set.len();
+        let expected = FileDescriptorSet::decode(&mut <Cursor<&[u8]> as Buf>::take(Cursor::new(descriptor_set), len)).unwrap();
+        assert_eq!(actual.file.len(), expected.file.len());
+        for (actual, expected) in actual.file.iter().zip(&expected.file) {
+            assert_eq!(actual, expected, "descriptors of {} differ", expected.name());
+        }
+    }
+
+    #[test]
+    fn test_matches_protoc() {
+        check_descriptors(&["google/protobuf/descriptor.proto",
+                            "google/protobuf/api.proto",
+                            "google/protobuf/struct.proto",
+                            "google/protobuf/type.proto"],
+                          include_bytes!("../../tests/descriptors/well_known_types.pb"));
+        check_descriptors(&["test_messages_proto3.proto"],
+                          include_bytes!("../../tests/descriptors/test_messages_proto3.pb"));
+        check_descriptors(&["root.proto"], include_bytes!("../../tests/descriptors/root.pb"));
+    }
+}
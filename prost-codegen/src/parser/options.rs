@@ -0,0 +1,862 @@
+//! Interpretation of the options of definitions parsed from `.proto` files.
+//!
+//! The parser records each option as an `UninterpretedOption`, holding the option name and value
+//! as written. Once all files are parsed and their types are resolved, the options are
+//! interpreted as `protoc` interprets them: each name is resolved to a field or extension of the
+//! options message, the value is encoded as that field, and the encoded options are merged into
+//! the options message. Options which are extensions are retained as unknown fields.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::mem;
+
+use bytes::Buf;
+use prost::{
+    Merge,
+    Message,
+};
+use prost::encoding::{
+    self,
+    WireType,
+    encode_key,
+    encode_varint,
+};
+use prost::text;
+
+use descriptor_pool::{
+    DescriptorPool,
+    FieldDescriptor,
+    MessageDescriptor,
+    qualify,
+};
+use dynamic::{
+    DynamicMessage,
+    MapKey,
+    Value,
+};
+use google::protobuf::{
+    DescriptorProto,
+    EnumDescriptorProto,
+    EnumOptions,
+    EnumValueOptions,
+    FieldDescriptorProto,
+    FieldOptions,
+    FileDescriptorProto,
+    FileDescriptorSet,
+    FileOptions,
+    MessageOptions,
+    MethodOptions,
+    OneofOptions,
+    ServiceOptions,
+    UninterpretedOption,
+};
+use google::protobuf::field_descriptor_proto::{Label, Type};
+use google::protobuf::source_code_info::Location;
+use parser::grammar::{self, tag};
+use parser::tokenizer::parse_integer;
+use parser::{ParseError, bundled_file, invalid};
+
+const DESCRIPTOR_FILE: &'static str = "google/protobuf/descriptor.proto";
+
+/// An options message, which holds the options of a definition.
+pub trait Options: Message + for<'a> Merge<Cursor<&'a [u8]>> + Default {
+    /// Returns the fully-qualified name of the options message type.
+    fn message_name() -> &'static str;
+
+    /// Returns the options which have not yet been interpreted.
+    fn uninterpreted_option(&mut self) -> &mut Vec<UninterpretedOption>;
+}
+
+macro_rules! options {
+    ($ty:ident) => (
+        impl Options for $ty {
+            fn message_name() -> &'static str {
+                concat!("google.protobuf.", stringify!($ty))
+            }
+
+            fn uninterpreted_option(&mut self) -> &mut Vec<UninterpretedOption> {
+                &mut self.uninterpreted_option
+            }
+        }
+    )
+}
+
+options!(FileOptions);
+options!(MessageOptions);
+options!(FieldOptions);
+options!(OneofOptions);
+options!(EnumOptions);
+options!(EnumValueOptions);
+options!(ServiceOptions);
+options!(MethodOptions);
+
+/// Interprets the options of the files, which must have resolved type names.
+pub fn interpret(files: &mut [FileDescriptorProto]) -> Result<(), ParseError> {
+    // The options message types are needed even if no file imports `descriptor.proto`.
+    let mut pool_files = files.to_vec();
+    if !pool_files.iter().any(|file| file.name() == DESCRIPTOR_FILE) {
+        let source = bundled_file(DESCRIPTOR_FILE).expect("descriptor.proto is not bundled");
+        let mut descriptor_file = grammar::parse(source).expect("bundled descriptor.proto is invalid");
+        descriptor_file.name = Some(DESCRIPTOR_FILE.to_string());
+        pool_files.push(descriptor_file);
+    }
+    let pool = DescriptorPool::new(FileDescriptorSet { file: pool_files }).map_err(ParseError::Descriptor)?;
+    let symbols = Symbols::new(pool.files());
+
+    for file in files {
+        let mut interpreter = Interpreter {
+            pool: &pool,
+            symbols: &symbols,
+            interpreted_paths: HashMap::new(),
+            repeated_option_counts: HashMap::new(),
+        };
+        if let Err((path, message)) = interpreter.interpret_file(file) {
+            return Err(invalid(file, &path, message));
+        }
+        if let Some(ref mut info) = file.source_code_info {
+            update_locations(&mut info.location, &interpreter.interpreted_paths);
+        }
+    }
+    Ok(())
+}
+
+/// The names defined by a set of files, used to resolve the names of extension options.
+struct Symbols {
+    /// Whether each name is a package, message, enumeration or service, within which other names
+    /// are defined.
+    names: HashMap<String, bool>,
+    extensions: HashMap<String, FieldDescriptorProto>,
+}
+
+impl Symbols {
+    fn new(files: &[FileDescriptorProto]) -> Symbols {
+        let mut symbols = Symbols { names: HashMap::new(), extensions: HashMap::new() };
+        for file in files {
+            let package = file.package();
+            let mut prefix = package;
+            while !prefix.is_empty() {
+                symbols.names.insert(prefix.to_string(), true);
+                prefix = prefix.rfind('.').map_or("", |idx| &prefix[..idx]);
+            }
+            for message in &file.message_type {
+                symbols.add_message(package, message);
+            }
+            for enum_type in &file.enum_type {
+                symbols.add_enum(package, enum_type);
+            }
+            for extension in &file.extension {
+                symbols.add_extension(package, extension);
+            }
+            for service in &file.service {
+                let full_name = qualify(package, service.name());
+                for method in &service.method {
+                    symbols.names.insert(qualify(&full_name, method.name()), false);
+                }
+                symbols.names.insert(full_name, true);
+            }
+        }
+        symbols
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let full_name = qualify(scope, message.name());
+        for field in &message.field {
+            self.names.insert(qualify(&full_name, field.name()), false);
+        }
+        for oneof in &message.oneof_decl {
+            self.names.insert(qualify(&full_name, oneof.name()), false);
+        }
+        for extension in &message.extension {
+            self.add_extension(&full_name, extension);
+        }
+        for nested in &message.nested_type {
+            self.add_message(&full_name, nested);
+        }
+        for enum_type in &message.enum_type {
+            self.add_enum(&full_name, enum_type);
+        }
+        self.names.insert(full_name, true);
+    }
+
+    fn add_enum(&mut self, scope: &str, enum_type: &EnumDescriptorProto) {
+        // Enumeration values are defined in the scope of the enumeration type, not within it.
+        for value in &enum_type.value {
+            self.names.insert(qualify(scope, value.name()), false);
+        }
+        self.names.insert(qualify(scope, enum_type.name()), true);
+    }
+
+    fn add_extension(&mut self, scope: &str, extension: &FieldDescriptorProto) {
+        let full_name = qualify(scope, extension.name());
+        self.names.insert(full_name.clone(), false);
+        self.extensions.insert(full_name, extension.clone());
+    }
+
+    /// Resolves a name relative to the fully-qualified name of a definition, and returns the
+    /// fully-qualified name it refers to.
+    ///
+    /// As in `protoc`, the first component of a compound name is looked up in each enclosing
+    /// scope, and the rest of the name is then looked up within the innermost match.
+    fn lookup(&self, name: &str, relative_to: &str) -> Option<String> {
+        if name.starts_with('.') {
+            let name = &name[1..];
+            return if self.names.contains_key(name) { Some(name.to_string()) } else { None };
+        }
+
+        let first_part = name.split('.').next().unwrap();
+        let mut scope = relative_to;
+        while let Some(idx) = scope.rfind('.') {
+            scope = &scope[..idx];
+            let candidate = qualify(scope, first_part);
+            match self.names.get(&candidate) {
+                Some(&is_aggregate) if first_part.len() < name.len() => {
+                    if is_aggregate {
+                        let full_name = qualify(scope, name);
+                        return if self.names.contains_key(&full_name) { Some(full_name) } else { None };
+                    }
+                },
+                Some(_) => return Some(candidate),
+                None => (),
+            }
+        }
+        if self.names.contains_key(name) { Some(name.to_string()) } else { None }
+    }
+}
+
+/// A field or extension of an options message, or of a message within it.
+struct OptionField {
+    full_name: String,
+    name: String,
+    number: u32,
+    ty: Type,
+    type_name: String,
+    /// The fully-qualified name of the containing or extended message, without a leading `.`.
+    containing_message: String,
+    is_repeated: bool,
+}
+
+impl OptionField {
+    fn from_field(field: FieldDescriptor) -> OptionField {
+        OptionField {
+            full_name: field.full_name(),
+            name: field.name().to_string(),
+            number: field.number(),
+            ty: field.ty(),
+            type_name: field.descriptor_proto().type_name().to_string(),
+            containing_message: field.containing_message().full_name().to_string(),
+            is_repeated: field.is_repeated(),
+        }
+    }
+
+    fn from_extension(full_name: String, extension: &FieldDescriptorProto) -> OptionField {
+        OptionField {
+            full_name: full_name,
+            name: extension.name().to_string(),
+            number: extension.number() as u32,
+            ty: extension.type_().unwrap_or(Type::TypeMessage),
+            type_name: extension.type_name().to_string(),
+            containing_message: extension.extendee().trim_left_matches('.').to_string(),
+            is_repeated: extension.label() == Some(Label::LabelRepeated),
+        }
+    }
+}
+
+/// An error interpreting an option, with the source location path of the option.
+type OptionError = (Vec<i32>, String);
+
+/// Interprets the options of a file.
+struct Interpreter<'a> {
+    pool: &'a DescriptorPool,
+    symbols: &'a Symbols,
+    /// The source location path of each interpreted option, mapped to the path of the option
+    /// field.
+    interpreted_paths: HashMap<Vec<i32>, Vec<i32>>,
+    /// The number of values set for each repeated option field, by path.
+    repeated_option_counts: HashMap<Vec<i32>, i32>,
+}
+
+impl <'a> Interpreter<'a> {
+
+    fn interpret_file(&mut self, file: &mut FileDescriptorProto) -> Result<(), OptionError> {
+        let package = file.package().to_string();
+
+        // Names in file options are resolved relative to the package.
+        self.interpret_options(&mut file.options, vec![tag::file::OPTIONS], &qualify(&package, "dummy"))?;
+
+        for (idx, message) in file.message_type.iter_mut().enumerate() {
+            self.interpret_message(message, vec![tag::file::MESSAGE_TYPE, idx as i32], &package)?;
+        }
+        for (idx, enum_type) in file.enum_type.iter_mut().enumerate() {
+            self.interpret_enum(enum_type, vec![tag::file::ENUM_TYPE, idx as i32], &package)?;
+        }
+        for (idx, extension) in file.extension.iter_mut().enumerate() {
+            let path = vec![tag::file::EXTENSION, idx as i32, tag::field::OPTIONS];
+            let relative_to = qualify(&package, extension.name());
+            self.interpret_options(&mut extension.options, path, &relative_to)?;
+        }
+        for (idx, service) in file.service.iter_mut().enumerate() {
+            let full_name = qualify(&package, service.name());
+            let path = vec![tag::file::SERVICE, idx as i32];
+            self.interpret_options(&mut service.options, extend(&path, &[tag::service::OPTIONS]), &full_name)?;
+            for (idx, method) in service.method.iter_mut().enumerate() {
+                let method_path = extend(&path, &[tag::service::METHOD, idx as i32, tag::method::OPTIONS]);
+                let relative_to = qualify(&full_name, method.name());
+                self.interpret_options(&mut method.options, method_path, &relative_to)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn interpret_message(&mut self, message: &mut DescriptorProto, path: Vec<i32>, scope: &str) -> Result<(), OptionError> {
+        let full_name = qualify(scope, message.name());
+        self.interpret_options(&mut message.options, extend(&path, &[tag::message::OPTIONS]), &full_name)?;
+
+        for (idx, field) in message.field.iter_mut().enumerate() {
+            let field_path = extend(&path, &[tag::message::FIELD, idx as i32, tag::field::OPTIONS]);
+            let relative_to = qualify(&full_name, field.name());
+            self.interpret_options(&mut field.options, field_path, &relative_to)?;
+        }
+        for (idx, extension) in message.extension.iter_mut().enumerate() {
+            let extension_path = extend(&path, &[tag::message::EXTENSION, idx as i32, tag::field::OPTIONS]);
+            let relative_to = qualify(&full_name, extension.name());
+            self.interpret_options(&mut extension.options, extension_path, &relative_to)?;
+        }
+        for (idx, oneof) in message.oneof_decl.iter_mut().enumerate() {
+            let oneof_path = extend(&path, &[tag::message::ONEOF_DECL, idx as i32, tag::oneof::OPTIONS]);
+            let relative_to = qualify(&full_name, oneof.name());
+            self.interpret_options(&mut oneof.options, oneof_path, &relative_to)?;
+        }
+        for (idx, nested) in message.nested_type.iter_mut().enumerate() {
+            self.interpret_message(nested, extend(&path, &[tag::message::NESTED_TYPE, idx as i32]), &full_name)?;
+        }
+        for (idx, enum_type) in message.enum_type.iter_mut().enumerate() {
+            self.interpret_enum(enum_type, extend(&path, &[tag::message::ENUM_TYPE, idx as i32]), &full_name)?;
+        }
+        Ok(())
+    }
+
+    fn interpret_enum(&mut self, enum_type: &mut EnumDescriptorProto, path: Vec<i32>, scope: &str) -> Result<(), OptionError> {
+        let full_name = qualify(scope, enum_type.name());
+        self.interpret_options(&mut enum_type.options, extend(&path, &[tag::enumeration::OPTIONS]), &full_name)?;
+        for (idx, value) in enum_type.value.iter_mut().enumerate() {
+            let value_path = extend(&path, &[tag::enumeration::VALUE, idx as i32, tag::enum_value::OPTIONS]);
+            let relative_to = qualify(scope, value.name());
+            self.interpret_options(&mut value.options, value_path, &relative_to)?;
+        }
+        Ok(())
+    }
+
+    /// Interprets the uninterpreted options of a definition, and merges them into its options.
+    ///
+    /// `path` is the source location path of the options, and `relative_to` is the
+    /// fully-qualified name of the definition, relative to which extension names are resolved.
+    fn interpret_options<O>(&mut self, options: &mut Option<O>, path: Vec<i32>, relative_to: &str) -> Result<(), OptionError>
+    where O: Options {
+        let options = match *options {
+            Some(ref mut options) => options,
+            None => return Ok(()),
+        };
+        let uninterpreted = mem::replace(options.uninterpreted_option(), Vec::new());
+        if uninterpreted.is_empty() {
+            return Ok(());
+        }
+
+        let mut set_options = Vec::new();
+        let mut buf = Vec::new();
+        for (idx, option) in uninterpreted.iter().enumerate() {
+            let src_path = extend(&path, &[tag::option::UNINTERPRETED_OPTION, idx as i32]);
+            let (numbers, is_repeated) = self.interpret_option(O::message_name(), option, relative_to,
+                                                               &mut set_options, &mut buf)
+                                             .map_err(|message| (src_path.clone(), message))?;
+
+            // Repeated option values are located by their index.
+            let mut dest_path = path.clone();
+            dest_path.extend(numbers.iter().map(|&number| number as i32));
+            if is_repeated {
+                let count = self.repeated_option_counts.entry(dest_path.clone()).or_insert(0);
+                dest_path.push(*count);
+                *count += 1;
+            }
+            self.interpreted_paths.insert(src_path, dest_path);
+        }
+
+        let len = buf.len();
+        options.merge(&mut Cursor::new(&buf[..]).take(len)).expect("failed to decode interpreted options");
+        Ok(())
+    }
+
+    /// Interprets an option, and appends its encoded value to the buffer. Returns the field
+    /// numbers of the option name, and whether the option field is repeated.
+    ///
+    /// `set_options` holds the field numbers of each non-repeated option set so far.
+    fn interpret_option(&self,
+                        options_name: &str,
+                        option: &UninterpretedOption,
+                        relative_to: &str,
+                        set_options: &mut Vec<Vec<u32>>,
+                        buf: &mut Vec<u8>)
+                        -> Result<(Vec<u32>, bool), String> {
+        if option.name.first().map(|part| part.name_part()) == Some("uninterpreted_option") {
+            return Err("Option must not use reserved name \"uninterpreted_option\".".to_string());
+        }
+
+        let mut message = self.pool.get_message(options_name).expect("options message type not found");
+        let mut fields = Vec::new();
+        let mut debug_name = String::new();
+        for (idx, part) in option.name.iter().enumerate() {
+            if !debug_name.is_empty() {
+                debug_name.push('.');
+            }
+            let field = if part.is_extension() {
+                debug_name.push_str(&format!("({})", part.name_part()));
+                self.symbols.lookup(part.name_part(), relative_to).and_then(|full_name| {
+                    if let Some(field) = self.pool.get_field(&full_name) {
+                        Some(OptionField::from_field(field))
+                    } else {
+                        self.symbols.extensions.get(&full_name).map(|extension| {
+                            OptionField::from_extension(full_name.clone(), extension)
+                        })
+                    }
+                })
+            } else {
+                debug_name.push_str(part.name_part());
+                message.get_field_by_name(part.name_part()).map(OptionField::from_field)
+            };
+
+            let field = match field {
+                Some(field) => field,
+                None => return Err(format!("Option \"{}\" unknown.", debug_name)),
+            };
+            if field.containing_message != message.full_name() {
+                return Err(format!("Option field \"{}\" is not a field or extension of message \"{}\".",
+                                   debug_name, message.name()));
+            }
+            if idx < option.name.len() - 1 {
+                if field.ty != Type::TypeMessage && field.ty != Type::TypeGroup {
+                    return Err(format!("Option \"{}\" is an atomic type, not a message.", debug_name));
+                }
+                if field.is_repeated {
+                    return Err(format!("Option field \"{}\" is a repeated message. Repeated message options \
+                                        must be initialized using an aggregate value.", debug_name));
+                }
+                message = self.pool.get_message(&field.type_name).expect("option message type not found");
+            }
+            fields.push(field);
+        }
+
+        let numbers = fields.iter().map(|field| field.number).collect::<Vec<_>>();
+        let leaf = fields.pop().unwrap();
+        if !leaf.is_repeated {
+            // A non-repeated option may only be set once, including by setting an enclosing
+            // message option with an aggregate value.
+            if set_options.iter().any(|set| set.starts_with(&numbers) || numbers.starts_with(set)) {
+                return Err(format!("Option \"{}\" was already set.", debug_name));
+            }
+            set_options.push(numbers.clone());
+        }
+
+        let mut value = Vec::new();
+        self.encode_value(&leaf, option, &mut value)?;
+        for field in fields.iter().rev() {
+            let mut wrapped = Vec::with_capacity(value.len() + 10);
+            if field.ty == Type::TypeGroup {
+                encode_key(field.number, WireType::StartGroup, &mut wrapped);
+                wrapped.extend_from_slice(&value);
+                encode_key(field.number, WireType::EndGroup, &mut wrapped);
+            } else {
+                encode_key(field.number, WireType::LengthDelimited, &mut wrapped);
+                encode_varint(value.len() as u64, &mut wrapped);
+                wrapped.extend_from_slice(&value);
+            }
+            value = wrapped;
+        }
+        buf.extend_from_slice(&value);
+
+        Ok((numbers, leaf.is_repeated))
+    }
+
+    /// Encodes the value of an option as the option field.
+    fn encode_value(&self, field: &OptionField, option: &UninterpretedOption, buf: &mut Vec<u8>) -> Result<(), String> {
+        let tag = field.number;
+        match field.ty {
+            Type::TypeInt32 => encoding::int32::encode(tag, &(signed_value(field, option, "int32", i32::min_value() as i64, i32::max_value() as i64)? as i32), buf),
+            Type::TypeSint32 => encoding::sint32::encode(tag, &(signed_value(field, option, "int32", i32::min_value() as i64, i32::max_value() as i64)? as i32), buf),
+            Type::TypeSfixed32 => encoding::sfixed32::encode(tag, &(signed_value(field, option, "int32", i32::min_value() as i64, i32::max_value() as i64)? as i32), buf),
+            Type::TypeInt64 => encoding::int64::encode(tag, &signed_value(field, option, "int64", i64::min_value(), i64::max_value())?, buf),
+            Type::TypeSint64 => encoding::sint64::encode(tag, &signed_value(field, option, "int64", i64::min_value(), i64::max_value())?, buf),
+            Type::TypeSfixed64 => encoding::sfixed64::encode(tag, &signed_value(field, option, "int64", i64::min_value(), i64::max_value())?, buf),
+            Type::TypeUint32 => encoding::uint32::encode(tag, &(unsigned_value(field, option, "uint32", u32::max_value() as u64)? as u32), buf),
+            Type::TypeFixed32 => encoding::fixed32::encode(tag, &(unsigned_value(field, option, "uint32", u32::max_value() as u64)? as u32), buf),
+            Type::TypeUint64 => encoding::uint64::encode(tag, &unsigned_value(field, option, "uint64", u64::max_value())?, buf),
+            Type::TypeFixed64 => encoding::fixed64::encode(tag, &unsigned_value(field, option, "uint64", u64::max_value())?, buf),
+            Type::TypeFloat => encoding::float::encode(tag, &(float_value(field, option, "float")? as f32), buf),
+            Type::TypeDouble => encoding::double::encode(tag, &float_value(field, option, "double")?, buf),
+            Type::TypeBool => {
+                let value = match option.identifier_value {
+                    Some(ref value) if value == "true" => true,
+                    Some(ref value) if value == "false" => false,
+                    Some(_) => return Err(format!("Value must be \"true\" or \"false\" for boolean option \"{}\".",
+                                                  field.full_name)),
+                    None => return Err(format!("Value must be identifier for boolean option \"{}\".",
+                                               field.full_name)),
+                };
+                encoding::bool::encode(tag, &value, buf);
+            },
+            Type::TypeEnum => {
+                let name = match option.identifier_value {
+                    Some(ref name) => name,
+                    None => return Err(format!("Value must be identifier for enum-valued option \"{}\".",
+                                               field.full_name)),
+                };
+                let enum_type = self.pool.get_enum(&field.type_name).expect("option enum type not found");
+                match enum_type.value_number(name) {
+                    Some(number) => encoding::int32::encode(tag, &number, buf),
+                    None => return Err(format!("Enum type \"{}\" has no value named \"{}\" for option \"{}\".",
+                                               enum_type.full_name(), name, field.full_name)),
+                }
+            },
+            Type::TypeString | Type::TypeBytes => {
+                match option.string_value {
+                    Some(ref value) => encoding::bytes::encode(tag, value, buf),
+                    None => return Err(format!("Value must be quoted string for string option \"{}\".",
+                                               field.full_name)),
+                }
+            },
+            Type::TypeMessage | Type::TypeGroup => {
+                let aggregate = match option.aggregate_value {
+                    Some(ref aggregate) => aggregate,
+                    None => return Err(format!("Option \"{}\" is a message. To set the entire message, use syntax \
+                                                like \"{1} = {{ <proto text format> }}\". To set fields within it, \
+                                                use syntax like \"{1}.foo = value\".", field.full_name, field.name)),
+                };
+                let descriptor = self.pool.get_message(&field.type_name).expect("option message type not found");
+                let message = text::Value::parse(aggregate).map_err(|error| error.to_string())
+                                                          .and_then(|fields| message_from_text(descriptor, fields))
+                                                          .map_err(|error| {
+                    format!("Error while parsing option value for \"{}\": {}", field.name, error)
+                })?;
+
+                let mut value = Vec::new();
+                message.encode(&mut value).expect("failed to encode option message");
+                if field.ty == Type::TypeGroup {
+                    encode_key(tag, WireType::StartGroup, buf);
+                    buf.extend_from_slice(&value);
+                    encode_key(tag, WireType::EndGroup, buf);
+                } else {
+                    encoding::bytes::encode(tag, &value, buf);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn signed_value(field: &OptionField, option: &UninterpretedOption, type_name: &str, min: i64, max: i64) -> Result<i64, String> {
+    let out_of_range = || format!("Value out of range for {} option \"{}\".", type_name, field.full_name);
+    if let Some(value) = option.positive_int_value {
+        if value > max as u64 { Err(out_of_range()) } else { Ok(value as i64) }
+    } else if let Some(value) = option.negative_int_value {
+        if value < min { Err(out_of_range()) } else { Ok(value) }
+    } else {
+        Err(format!("Value must be integer for {} option \"{}\".", type_name, field.full_name))
+    }
+}
+
+fn unsigned_value(field: &OptionField, option: &UninterpretedOption, type_name: &str, max: u64) -> Result<u64, String> {
+    match option.positive_int_value {
+        Some(value) if value > max => {
+            Err(format!("Value out of range for {} option \"{}\".", type_name, field.full_name))
+        },
+        Some(value) => Ok(value),
+        None => Err(format!("Value must be non-negative integer for {} option \"{}\".", type_name, field.full_name)),
+    }
+}
+
+fn float_value(field: &OptionField, option: &UninterpretedOption, type_name: &str) -> Result<f64, String> {
+    if let Some(value) = option.double_value {
+        Ok(value)
+    } else if let Some(value) = option.positive_int_value {
+        Ok(value as f64)
+    } else if let Some(value) = option.negative_int_value {
+        Ok(value as f64)
+    } else if option.identifier_value() == "inf" {
+        Ok(::std::f64::INFINITY)
+    } else if option.identifier_value() == "nan" {
+        Ok(::std::f64::NAN)
+    } else {
+        Err(format!("Value must be number for {} option \"{}\".", type_name, field.full_name))
+    }
+}
+
+/// Converts the fields of an aggregate option value, parsed from text format, to a message.
+fn message_from_text<'a>(descriptor: MessageDescriptor<'a>,
+                         fields: Vec<(String, text::Value)>)
+                         -> Result<DynamicMessage<'a>, String> {
+    let mut message = DynamicMessage::new(descriptor);
+    for (name, value) in fields {
+        // Groups are named by their message type in text format.
+        let field = descriptor.get_field_by_name(&name).or_else(|| {
+            descriptor.fields().into_iter().find(|field| {
+                field.ty() == Type::TypeGroup && field.message_type().map(|group| group.name()) == Some(&name[..])
+            })
+        });
+        let field = match field {
+            Some(field) => field,
+            None => return Err(format!("Message type \"{}\" has no field named \"{}\".", descriptor.full_name(), name)),
+        };
+
+        let value = if field.is_map() {
+            let entry = match value {
+                text::Value::Message(entry) => message_from_text(field.message_type().unwrap(), entry)?,
+                _ => return Err(format!("Expected a message value for field \"{}\".", name)),
+            };
+            let key = match entry.get_by_number(1).unwrap().into_owned() {
+                Value::Bool(key) => MapKey::Bool(key),
+                Value::I32(key) => MapKey::I32(key),
+                Value::I64(key) => MapKey::I64(key),
+                Value::U32(key) => MapKey::U32(key),
+                Value::U64(key) => MapKey::U64(key),
+                Value::String(key) => MapKey::String(key),
+                key => unreachable!("invalid map key: {:?}", key),
+            };
+            let mut map = match message.get_field(field).into_owned() {
+                Value::Map(map) => map,
+                _ => unreachable!(),
+            };
+            map.insert(key, entry.get_by_number(2).unwrap().into_owned());
+            Value::Map(map)
+        } else if field.is_repeated() {
+            let mut list = match message.get_field(field).into_owned() {
+                Value::List(list) => list,
+                _ => unreachable!(),
+            };
+            list.push(value_from_text(field, value)?);
+            Value::List(list)
+        } else {
+            if message.has_field(field) {
+                return Err(format!("Non-repeated field \"{}\" is specified multiple times.", name));
+            }
+            value_from_text(field, value)?
+        };
+        message.set_field(field, value).expect("invalid option field value");
+    }
+    Ok(message)
+}
+
+/// Converts a single value of a field, parsed from text format.
+fn value_from_text<'a>(field: FieldDescriptor<'a>, value: text::Value) -> Result<Value<'a>, String> {
+    let invalid_value = |value: &text::Value| format!("Invalid value for field \"{}\": {:?}", field.name(), value);
+
+    let value = match (field.ty(), value) {
+        (Type::TypeMessage, text::Value::Message(fields)) |
+        (Type::TypeGroup, text::Value::Message(fields)) => {
+            return message_from_text(field.message_type().unwrap(), fields).map(Value::Message);
+        },
+        (Type::TypeString, text::Value::String(value)) => {
+            return String::from_utf8(value).map(Value::String)
+                                           .map_err(|_| format!("Invalid UTF-8 in field \"{}\".", field.name()));
+        },
+        (Type::TypeBytes, text::Value::String(value)) => return Ok(Value::Bytes(value)),
+        (ty, value) => (ty, value),
+    };
+
+    let parsed = match value {
+        (Type::TypeBool, text::Value::Identifier(ref value)) => match &value[..] {
+            "true" | "True" | "t" => Some(Value::Bool(true)),
+            "false" | "False" | "f" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (Type::TypeBool, text::Value::Number(ref value)) => match &value[..] {
+            "1" => Some(Value::Bool(true)),
+            "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (Type::TypeEnum, text::Value::Identifier(ref value)) => {
+            field.enum_type().unwrap().value_number(value).map(Value::Enum)
+        },
+        (Type::TypeEnum, text::Value::Number(ref value)) => {
+            parse_signed(value, i32::min_value() as i64, i32::max_value() as i64).map(|v| Value::Enum(v as i32))
+        },
+        (Type::TypeFloat, text::Value::Identifier(ref value)) => parse_infinity(value).map(|v| Value::F32(v as f32)),
+        (Type::TypeDouble, text::Value::Identifier(ref value)) => parse_infinity(value).map(Value::F64),
+        (Type::TypeFloat, text::Value::Number(ref value)) => parse_number(value).map(|v| Value::F32(v as f32)),
+        (Type::TypeDouble, text::Value::Number(ref value)) => parse_number(value).map(Value::F64),
+        (Type::TypeInt32, text::Value::Number(ref value)) |
+        (Type::TypeSint32, text::Value::Number(ref value)) |
+        (Type::TypeSfixed32, text::Value::Number(ref value)) => {
+            parse_signed(value, i32::min_value() as i64, i32::max_value() as i64).map(|v| Value::I32(v as i32))
+        },
+        (Type::TypeInt64, text::Value::Number(ref value)) |
+        (Type::TypeSint64, text::Value::Number(ref value)) |
+        (Type::TypeSfixed64, text::Value::Number(ref value)) => {
+            parse_signed(value, i64::min_value(), i64::max_value()).map(Value::I64)
+        },
+        (Type::TypeUint32, text::Value::Number(ref value)) |
+        (Type::TypeFixed32, text::Value::Number(ref value)) => {
+            parse_integer(value, u32::max_value() as u64).map(|v| Value::U32(v as u32))
+        },
+        (Type::TypeUint64, text::Value::Number(ref value)) |
+        (Type::TypeFixed64, text::Value::Number(ref value)) => {
+            parse_integer(value, u64::max_value()).map(Value::U64)
+        },
+        _ => None,
+    };
+    parsed.ok_or_else(|| invalid_value(&value.1))
+}
+
+fn parse_signed(value: &str, min: i64, max: i64) -> Option<i64> {
+    if value.starts_with('-') {
+        parse_integer(&value[1..], (min as u64).wrapping_neg()).map(|v| (v as i64).wrapping_neg())
+    } else {
+        parse_integer(value, max as u64).map(|v| v as i64)
+    }
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    let (negative, magnitude) = if value.starts_with('-') { (true, &value[1..]) } else { (false, value) };
+    let magnitude = magnitude.trim_right_matches(|c| c == 'f' || c == 'F');
+    let parsed = parse_integer(magnitude, u64::max_value()).map(|v| v as f64)
+                                                            .or_else(|| magnitude.parse().ok());
+    parsed.map(|v: f64| if negative { -v } else { v })
+}
+
+fn parse_infinity(value: &str) -> Option<f64> {
+    match &value.to_lowercase()[..] {
+        "inf" | "infinity" => Some(::std::f64::INFINITY),
+        "-inf" | "-infinity" => Some(::std::f64::NEG_INFINITY),
+        "nan" | "-nan" => Some(::std::f64::NAN),
+        _ => None,
+    }
+}
+
+fn extend(path: &[i32], components: &[i32]) -> Vec<i32> {
+    let mut path = path.to_vec();
+    path.extend_from_slice(components);
+    path
+}
+
+/// Replaces the source location paths of interpreted options with the paths of the option
+/// fields, and removes the locations of the parts of the interpreted options.
+fn update_locations(locations: &mut Vec<Location>, interpreted_paths: &HashMap<Vec<i32>, Vec<i32>>) {
+    if interpreted_paths.is_empty() {
+        return;
+    }
+    let mut removed: HashSet<usize> = HashSet::new();
+    let mut interpreted: Option<Vec<i32>> = None;
+    for (idx, location) in locations.iter_mut().enumerate() {
+        if let Some(ref path) = interpreted {
+            if location.path.starts_with(path) {
+                removed.insert(idx);
+                continue;
+            }
+        }
+        interpreted = None;
+        if let Some(dest_path) = interpreted_paths.get(&location.path) {
+            interpreted = Some(mem::replace(&mut location.path, dest_path.clone()));
+        }
+    }
+    let mut idx = 0;
+    locations.retain(|_| {
+        idx += 1;
+        !removed.contains(&(idx - 1))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::compile;
+
+    fn compile_file(source: &str) -> Result<FileDescriptorProto, ParseError> {
+        compile(&["test.proto"], |name| Ok(if name == "test.proto" { Some(source.to_string()) } else { None }))
+            .map(|set| set.file.into_iter().find(|file| file.name() == "test.proto").unwrap())
+    }
+
+    #[test]
+    fn test_builtin_options() {
+        let file = compile_file("\
+syntax = \"proto2\";
+option java_package = \"com.example\";
+option optimize_for = CODE_SIZE;
+message Foo {
+  option deprecated = true;
+  repeated int32 a = 1 [packed = true];
+  optional float b = 2 [ctype = CORD, deprecated = false];
+}
+").unwrap();
+
+        let options = file.options.as_ref().unwrap();
+        assert_eq!(options.java_package(), "com.example");
+        assert_eq!(options.optimize_for(), Some(::google::protobuf::file_options::OptimizeMode::CodeSize));
+        assert!(options.uninterpreted_option.is_empty());
+        assert_eq!(file.message_type[0].options.as_ref().unwrap().deprecated, Some(true));
+        assert_eq!(file.message_type[0].field[0].options.as_ref().unwrap().packed, Some(true));
+        assert_eq!(file.message_type[0].field[1].options.as_ref().unwrap().deprecated, Some(false));
+
+        let paths = file.source_code_info.as_ref().unwrap().location.iter().map(|location| {
+            location.path.clone()
+        }).collect::<Vec<_>>();
+        assert!(paths.contains(&vec![8, 1]));
+        assert!(paths.contains(&vec![8, 9]));
+        assert!(paths.contains(&vec![4, 0, 7, 3]));
+        assert!(paths.contains(&vec![4, 0, 2, 1, 8, 1]));
+        assert!(!paths.iter().any(|path| path.contains(&999)));
+    }
+
+    #[test]
+    fn test_custom_options() {
+        let file = compile_file("\
+syntax = \"proto3\";
+package foo;
+import \"google/protobuf/descriptor.proto\";
+message Bar {
+  int32 x = 1;
+  repeated string y = 2;
+}
+extend google.protobuf.MessageOptions {
+  Bar bar = 50000;
+  sint32 num = 50001;
+  repeated foo.Bar bars = 50002;
+}
+message Baz {
+  option (bar).x = 5;
+  option (num) = -2;
+  option (.foo.bars) = { x: 1 y: [\"a\", \"b\"] };
+}
+").unwrap();
+
+        let options = file.message_type[1].options.as_ref().unwrap();
+        let mut encoded = Vec::new();
+        options.encode(&mut encoded).unwrap();
+        assert_eq!(encoded, vec![0x82, 0xb5, 0x18, 0x02, 0x08, 0x05,
+                                 0x88, 0xb5, 0x18, 0x03,
+                                 0x92, 0xb5, 0x18, 0x08, 0x08, 0x01, 0x12, 0x01, b'a', 0x12, 0x01, b'b']);
+
+        let paths = file.source_code_info.as_ref().unwrap().location.iter().map(|location| {
+            location.path.clone()
+        }).collect::<Vec<_>>();
+        assert!(paths.contains(&vec![4, 1, 7, 50000, 1]));
+        assert!(paths.contains(&vec![4, 1, 7, 50002, 0]));
+    }
+
+    #[test]
+    fn test_option_errors() {
+        let error = |source: &str| match compile_file(source).unwrap_err() {
+            ParseError::Invalid { message, .. } => message,
+            error => panic!("unexpected error: {}", error),
+        };
+        assert_eq!(error("option foo = 1;"), "Option \"foo\" unknown.");
+        assert_eq!(error("option java_package = 1;"),
+                   "Value must be quoted string for string option \"google.protobuf.FileOptions.java_package\".");
+        assert_eq!(error("option optimize_for = FAST;"),
+                   "Enum type \"google.protobuf.FileOptions.OptimizeMode\" has no value named \"FAST\" for option \
+                    \"google.protobuf.FileOptions.optimize_for\".");
+        assert_eq!(error("option java_package = \"a\"; option java_package = \"b\";"),
+                   "Option \"java_package\" was already set.");
+        assert_eq!(error("option java_package.x = \"a\";"),
+                   "Option \"java_package\" is an atomic type, not a message.");
+        assert_eq!(error("message Foo { option message_set_wire_format = 1; }"),
+                   "Value must be identifier for boolean option \
+                    \"google.protobuf.MessageOptions.message_set_wire_format\".");
+    }
+}
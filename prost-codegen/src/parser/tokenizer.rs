@@ -0,0 +1,672 @@
+//! A tokenizer for `.proto` files.
+//!
+//! Tokens and comments are recognized as `protoc` recognizes them, so that source locations and
+//! the comments attached to them match the output of `protoc --include_source_info`.
+
+use std::char;
+use std::mem;
+
+use descriptor_pool::unescape_c_bytes;
+
+/// An error at a position in a `.proto` file. Lines and columns are zero-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: i32,
+    pub column: i32,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// The tokenizer has not yet read the first token.
+    Start,
+    /// The end of the input.
+    End,
+    Identifier,
+    Integer,
+    Float,
+    /// A quoted string, including the quotes and any escape sequences.
+    String,
+    /// Any other single character.
+    Symbol,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    /// The zero-based line of the token.
+    pub line: i32,
+    /// The zero-based column of the first byte of the token. Tabs advance the column to the next
+    /// multiple of 8.
+    pub column: i32,
+    /// The column following the last byte of the token.
+    pub end_column: i32,
+}
+
+/// The comments surrounding a token, as collected by `Tokenizer::next_with_comments`.
+#[derive(Debug, Default)]
+pub struct Comments {
+    /// Comments following the previous token, on its line or on the lines immediately after it.
+    pub trailing: String,
+    /// Comments separated from both the previous and the next token by blank lines.
+    pub detached: Vec<String>,
+    /// Comments immediately preceding the next token.
+    pub leading: String,
+}
+
+enum CommentStart {
+    Line,
+    Block,
+    /// A `/` which does not start a comment, which has been read as a symbol token.
+    Slash,
+    None,
+}
+
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    line: i32,
+    column: i32,
+    current: Token,
+    previous: Token,
+}
+
+impl <'a> Tokenizer<'a> {
+
+    pub fn new(input: &'a str) -> Tokenizer<'a> {
+        let start = Token { kind: TokenKind::Start, text: String::new(), line: 0, column: 0, end_column: 0 };
+        Tokenizer {
+            input: input,
+            pos: 0,
+            line: 0,
+            column: 0,
+            current: start.clone(),
+            previous: start,
+        }
+    }
+
+    /// Returns the current token.
+    pub fn current(&self) -> &Token {
+        &self.current
+    }
+
+    /// Returns the token before the current token.
+    pub fn previous(&self) -> &Token {
+        &self.previous
+    }
+
+    /// Advances to the next token, skipping whitespace and comments. Returns `false` when the end
+    /// of the input is reached.
+    pub fn next(&mut self) -> Result<bool, SyntaxError> {
+        self.previous = mem::replace(&mut self.current, Token {
+            kind: TokenKind::End,
+            text: String::new(),
+            line: 0,
+            column: 0,
+            end_column: 0,
+        });
+
+        loop {
+            while is_whitespace(self.peek()) {
+                self.next_char();
+            }
+            match self.try_consume_comment_start() {
+                CommentStart::Line => {
+                    self.consume_line_comment(None);
+                    continue;
+                },
+                CommentStart::Block => {
+                    self.consume_block_comment(None)?;
+                    continue;
+                },
+                CommentStart::Slash => return Ok(true),
+                CommentStart::None => (),
+            }
+
+            if self.at_end() {
+                break;
+            }
+            self.read_token()?;
+            return Ok(true);
+        }
+
+        self.current = Token {
+            kind: TokenKind::End,
+            text: String::new(),
+            line: self.line,
+            column: self.column,
+            end_column: self.column,
+        };
+        Ok(false)
+    }
+
+    /// Advances to the next token like `next`, and collects the comments between the current
+    /// token and the next token.
+    ///
+    /// A comment on the same line as the current token, or on the lines immediately following it,
+    /// is a trailing comment of the current token, unless it runs into the next token. Comments
+    /// immediately preceding the next token are its leading comments, and any others are
+    /// detached.
+    pub fn next_with_comments(&mut self) -> Result<(bool, Comments), SyntaxError> {
+        let mut collector = CommentCollector::new();
+
+        if self.current.kind == TokenKind::Start {
+            // Skip a UTF-8 byte order mark.
+            if self.input.starts_with('\u{feff}') {
+                self.pos += '\u{feff}'.len_utf8();
+            }
+            collector.detach_from_prev();
+        } else {
+            // A comment on the same line as the previous token is attached to it.
+            while is_whitespace_no_newline(self.peek()) {
+                self.next_char();
+            }
+            match self.try_consume_comment_start() {
+                CommentStart::Line => {
+                    self.consume_line_comment(Some(collector.buffer_for_line_comment()));
+                    // Comments on the following lines can not be attached to a trailing comment.
+                    collector.flush();
+                },
+                CommentStart::Block => {
+                    self.consume_block_comment(Some(collector.buffer_for_block_comment()))?;
+                    while is_whitespace_no_newline(self.peek()) {
+                        self.next_char();
+                    }
+                    if !self.try_consume(b'\n') {
+                        // The next token is on the same line, so the comment can not be
+                        // attributed to either token.
+                        collector.clear_buffer();
+                        let more = self.next()?;
+                        return Ok((more, collector.finish()));
+                    }
+                    collector.flush();
+                },
+                CommentStart::Slash => return Ok((true, collector.finish())),
+                CommentStart::None => {
+                    if !self.try_consume(b'\n') {
+                        // The next token is on the same line, so there are no comments.
+                        let more = self.next()?;
+                        return Ok((more, collector.finish()));
+                    }
+                },
+            }
+        }
+
+        // The tokenizer is now on a line after the previous token.
+        loop {
+            while is_whitespace_no_newline(self.peek()) {
+                self.next_char();
+            }
+            match self.try_consume_comment_start() {
+                CommentStart::Line => {
+                    self.consume_line_comment(Some(collector.buffer_for_line_comment()));
+                },
+                CommentStart::Block => {
+                    self.consume_block_comment(Some(collector.buffer_for_block_comment()))?;
+                    // Consume the rest of the line, so that it is not taken for a blank line.
+                    while is_whitespace_no_newline(self.peek()) {
+                        self.next_char();
+                    }
+                    self.try_consume(b'\n');
+                },
+                CommentStart::Slash => return Ok((true, collector.finish())),
+                CommentStart::None => {
+                    if self.try_consume(b'\n') {
+                        // A blank line.
+                        collector.flush();
+                        collector.detach_from_prev();
+                    } else {
+                        let more = self.next()?;
+                        if !more || self.current.text == "}" || self.current.text == "]" || self.current.text == ")" {
+                            // At the end of a scope there is no following declaration to attach
+                            // a comment to.
+                            collector.flush();
+                        }
+                        return Ok((more, collector.finish()));
+                    }
+                },
+            }
+        }
+    }
+
+    fn peek(&self) -> u8 {
+        self.input.as_bytes().get(self.pos).cloned().unwrap_or(0)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn next_char(&mut self) {
+        match self.peek() {
+            b'\n' => {
+                self.line += 1;
+                self.column = 0;
+            },
+            b'\t' => self.column += 8 - self.column % 8,
+            _ => self.column += 1,
+        }
+        self.pos += 1;
+    }
+
+    fn try_consume(&mut self, c: u8) -> bool {
+        if !self.at_end() && self.peek() == c {
+            self.next_char();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, SyntaxError> {
+        Err(SyntaxError { line: self.line, column: self.column, message: message.to_string() })
+    }
+
+    fn try_consume_comment_start(&mut self) -> CommentStart {
+        if !self.try_consume(b'/') {
+            return CommentStart::None;
+        }
+        if self.try_consume(b'/') {
+            CommentStart::Line
+        } else if self.try_consume(b'*') {
+            CommentStart::Block
+        } else {
+            self.current = Token {
+                kind: TokenKind::Symbol,
+                text: "/".to_string(),
+                line: self.line,
+                column: self.column - 1,
+                end_column: self.column,
+            };
+            CommentStart::Slash
+        }
+    }
+
+    /// Consumes the rest of a line comment, including the newline, and appends the comment text
+    /// following the `//` to the buffer.
+    fn consume_line_comment(&mut self, buf: Option<&mut String>) {
+        let start = self.pos;
+        while !self.at_end() && self.peek() != b'\n' {
+            self.next_char();
+        }
+        self.try_consume(b'\n');
+        if let Some(buf) = buf {
+            buf.push_str(&self.input[start..self.pos]);
+        }
+    }
+
+    /// Consumes the rest of a block comment, and appends the comment text to the buffer. Leading
+    /// whitespace and a `*` at the start of each line are not part of the text.
+    fn consume_block_comment(&mut self, mut buf: Option<&mut String>) -> Result<(), SyntaxError> {
+        let mut start = self.pos;
+        loop {
+            while !self.at_end() && !b"*/\n".contains(&self.peek()) {
+                self.next_char();
+            }
+
+            if self.try_consume(b'\n') {
+                if let Some(ref mut buf) = buf {
+                    buf.push_str(&self.input[start..self.pos]);
+                }
+                while is_whitespace_no_newline(self.peek()) {
+                    self.next_char();
+                }
+                if self.try_consume(b'*') && self.try_consume(b'/') {
+                    return Ok(());
+                }
+                start = self.pos;
+            } else if self.try_consume(b'*') && self.try_consume(b'/') {
+                if let Some(ref mut buf) = buf {
+                    buf.push_str(&self.input[start..self.pos - 2]);
+                }
+                return Ok(());
+            } else if self.try_consume(b'/') && self.peek() == b'*' {
+                return self.error("\"/*\" inside block comment.  Block comments cannot be nested.");
+            } else if self.at_end() {
+                return self.error("End-of-file inside block comment.");
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> Result<(), SyntaxError> {
+        let start = self.pos;
+        let line = self.line;
+        let column = self.column;
+        let c = self.peek();
+
+        let kind = if c < b' ' {
+            return self.error("Invalid control characters encountered in text.");
+        } else if c >= 0x80 {
+            return self.error(&format!("Interpreting non ascii codepoint {}.", c));
+        } else if is_letter(c) {
+            while is_letter(self.peek()) || is_digit(self.peek()) {
+                self.next_char();
+            }
+            TokenKind::Identifier
+        } else if self.try_consume(b'0') {
+            self.consume_number(true, false)?
+        } else if self.try_consume(b'.') {
+            if is_digit(self.peek()) {
+                if self.previous.kind == TokenKind::Identifier
+                    && self.previous.line == line
+                    && self.previous.end_column == column {
+                    return self.error("Need space between identifier and decimal point.");
+                }
+                self.consume_number(false, true)?
+            } else {
+                TokenKind::Symbol
+            }
+        } else if is_digit(c) {
+            self.consume_number(false, false)?
+        } else if c == b'"' || c == b'\'' {
+            self.next_char();
+            self.consume_string(c)?;
+            TokenKind::String
+        } else {
+            self.next_char();
+            TokenKind::Symbol
+        };
+
+        self.current = Token {
+            kind: kind,
+            text: self.input[start..self.pos].to_string(),
+            line: line,
+            column: column,
+            end_column: self.column,
+        };
+        Ok(())
+    }
+
+    fn consume_number(&mut self, started_with_zero: bool, started_with_dot: bool) -> Result<TokenKind, SyntaxError> {
+        let mut is_float = false;
+
+        if started_with_zero && (self.try_consume(b'x') || self.try_consume(b'X')) {
+            if !is_hex_digit(self.peek()) {
+                return self.error("\"0x\" must be followed by hex digits.");
+            }
+            while is_hex_digit(self.peek()) {
+                self.next_char();
+            }
+        } else if started_with_zero && is_digit(self.peek()) {
+            while is_digit(self.peek()) {
+                if self.peek() > b'7' {
+                    return self.error("Numbers starting with leading zero must be in octal.");
+                }
+                self.next_char();
+            }
+        } else {
+            if started_with_dot {
+                is_float = true;
+            } else {
+                while is_digit(self.peek()) {
+                    self.next_char();
+                }
+                if self.try_consume(b'.') {
+                    is_float = true;
+                }
+            }
+            while is_digit(self.peek()) {
+                self.next_char();
+            }
+
+            if self.try_consume(b'e') || self.try_consume(b'E') {
+                is_float = true;
+                let _ = self.try_consume(b'-') || self.try_consume(b'+');
+                if !is_digit(self.peek()) {
+                    return self.error("\"e\" must be followed by exponent.");
+                }
+                while is_digit(self.peek()) {
+                    self.next_char();
+                }
+            }
+        }
+
+        if is_letter(self.peek()) {
+            return self.error("Need space between number and identifier.");
+        } else if self.peek() == b'.' {
+            if is_float {
+                return self.error("Already saw decimal point or exponent; can't have another one.");
+            } else {
+                return self.error("Hex and octal numbers must be integers.");
+            }
+        }
+
+        Ok(if is_float { TokenKind::Float } else { TokenKind::Integer })
+    }
+
+    fn consume_string(&mut self, delimiter: u8) -> Result<(), SyntaxError> {
+        loop {
+            match self.peek() {
+                _ if self.at_end() => return self.error("Unexpected end of string."),
+                b'\n' => return self.error("String literals cannot cross line boundaries."),
+                b'\\' => {
+                    self.next_char();
+                    let c = self.peek();
+                    if b"abfnrtv\\?'\"".contains(&c) || is_octal_digit(c) {
+                        self.next_char();
+                    } else if c == b'x' {
+                        self.next_char();
+                        if !is_hex_digit(self.peek()) {
+                            return self.error("Expected hex digits for escape sequence.");
+                        }
+                    } else if c == b'u' || c == b'U' {
+                        self.next_char();
+                        let digits = if c == b'u' { 4 } else { 8 };
+                        for _ in 0..digits {
+                            if !is_hex_digit(self.peek()) {
+                                return self.error("Expected hex digits for escape sequence.");
+                            }
+                            self.next_char();
+                        }
+                    } else {
+                        return self.error("Invalid escape sequence in string literal.");
+                    }
+                },
+                c if c == delimiter => {
+                    self.next_char();
+                    return Ok(());
+                },
+                _ => self.next_char(),
+            }
+        }
+    }
+}
+
+/// Collects the comments between two tokens, deciding which are trailing comments of the
+/// previous token, which are detached, and which are leading comments of the next token.
+struct CommentCollector {
+    comments: Comments,
+    buffer: String,
+    has_comment: bool,
+    is_line_comment: bool,
+    can_attach_to_prev: bool,
+}
+
+impl CommentCollector {
+
+    fn new() -> CommentCollector {
+        CommentCollector {
+            comments: Comments::default(),
+            buffer: String::new(),
+            has_comment: false,
+            is_line_comment: false,
+            can_attach_to_prev: true,
+        }
+    }
+
+    /// Returns the buffer to read a line comment into. Consecutive line comments are combined.
+    fn buffer_for_line_comment(&mut self) -> &mut String {
+        if self.has_comment && !self.is_line_comment {
+            self.flush();
+        }
+        self.has_comment = true;
+        self.is_line_comment = true;
+        &mut self.buffer
+    }
+
+    /// Returns the buffer to read a block comment into.
+    fn buffer_for_block_comment(&mut self) -> &mut String {
+        if self.has_comment {
+            self.flush();
+        }
+        self.has_comment = true;
+        self.is_line_comment = false;
+        &mut self.buffer
+    }
+
+    fn clear_buffer(&mut self) {
+        self.buffer.clear();
+        self.has_comment = false;
+    }
+
+    /// Completes the buffered comment, which is known not to precede the next token.
+    fn flush(&mut self) {
+        if self.has_comment {
+            if self.can_attach_to_prev {
+                self.comments.trailing.push_str(&self.buffer);
+                self.can_attach_to_prev = false;
+            } else {
+                self.comments.detached.push(self.buffer.clone());
+            }
+            self.clear_buffer();
+        }
+    }
+
+    fn detach_from_prev(&mut self) {
+        self.can_attach_to_prev = false;
+    }
+
+    /// Returns the collected comments. A comment still in the buffer precedes the next token.
+    fn finish(mut self) -> Comments {
+        if self.has_comment {
+            self.comments.leading = mem::replace(&mut self.buffer, String::new());
+        }
+        self.comments
+    }
+}
+
+fn is_letter(c: u8) -> bool {
+    c == b'_' || (c as char).is_ascii_alphabetic()
+}
+
+fn is_digit(c: u8) -> bool {
+    (c as char).is_ascii_digit()
+}
+
+fn is_octal_digit(c: u8) -> bool {
+    c >= b'0' && c <= b'7'
+}
+
+fn is_hex_digit(c: u8) -> bool {
+    (c as char).is_ascii_hexdigit()
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b'\n' || is_whitespace_no_newline(c)
+}
+
+fn is_whitespace_no_newline(c: u8) -> bool {
+    b" \t\r\x0b\x0c".contains(&c)
+}
+
+/// Parses the text of an integer token, which may be decimal, hexadecimal or octal. Returns
+/// `None` if the value is greater than `max`.
+pub fn parse_integer(text: &str, max: u64) -> Option<u64> {
+    let (digits, radix) = if text.starts_with("0x") || text.starts_with("0X") {
+        (&text[2..], 16)
+    } else if text.starts_with('0') {
+        (text, 8)
+    } else {
+        (text, 10)
+    };
+    u64::from_str_radix(digits, radix).ok().and_then(|value| if value <= max { Some(value) } else { None })
+}
+
+/// Parses the text of a float token.
+pub fn parse_float(text: &str) -> f64 {
+    // Trailing exponent and decimal point forms such as `1.` and `1e5` are accepted by Rust.
+    text.parse().expect("invalid float token")
+}
+
+/// Returns the bytes of a string token, with the quotes removed and escape sequences replaced.
+pub fn unescape(text: &str) -> Vec<u8> {
+    unescape_c_bytes(&text[1..text.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<(TokenKind, String, i32, i32, i32)> {
+        let mut tokenizer = Tokenizer::new(input);
+        let mut tokens = Vec::new();
+        while tokenizer.next().unwrap() {
+            let token = tokenizer.current();
+            tokens.push((token.kind, token.text.clone(), token.line, token.column, token.end_column));
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(tokens("foo = 0x1F;\n\t\"a\\\"b\" 1.5e3 .5 // comment\n/* block */ 017 -"), vec![
+            (TokenKind::Identifier, "foo".to_string(), 0, 0, 3),
+            (TokenKind::Symbol, "=".to_string(), 0, 4, 5),
+            (TokenKind::Integer, "0x1F".to_string(), 0, 6, 10),
+            (TokenKind::Symbol, ";".to_string(), 0, 10, 11),
+            (TokenKind::String, "\"a\\\"b\"".to_string(), 1, 8, 14),
+            (TokenKind::Float, "1.5e3".to_string(), 1, 15, 20),
+            (TokenKind::Float, ".5".to_string(), 1, 21, 23),
+            (TokenKind::Integer, "017".to_string(), 2, 12, 15),
+            (TokenKind::Symbol, "-".to_string(), 2, 16, 17),
+        ]);
+
+        let mut tokenizer = Tokenizer::new("1.2.3");
+        assert_eq!(tokenizer.next().unwrap_err().message,
+                   "Already saw decimal point or exponent; can't have another one.");
+        let mut tokenizer = Tokenizer::new("\"abc");
+        assert_eq!(tokenizer.next().unwrap_err().message, "Unexpected end of string.");
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "\
+            foo;  // trailing\n\
+            // detached\n\
+            \n\
+            /* leading\n  * second line */\n\
+            bar; // bar trailing\n\
+            \n\
+            baz;";
+        let mut tokenizer = Tokenizer::new(input);
+        let (_, comments) = tokenizer.next_with_comments().unwrap();
+        assert_eq!(tokenizer.current().text, "foo");
+        assert_eq!(comments.leading, "");
+        tokenizer.next().unwrap();
+        assert_eq!(tokenizer.current().text, ";");
+
+        let (_, comments) = tokenizer.next_with_comments().unwrap();
+        assert_eq!(tokenizer.current().text, "bar");
+        assert_eq!(comments.trailing, " trailing\n");
+        assert_eq!(comments.detached, vec![" detached\n".to_string()]);
+        assert_eq!(comments.leading, " leading\n second line ");
+
+        tokenizer.next().unwrap();
+        let (_, comments) = tokenizer.next_with_comments().unwrap();
+        assert_eq!(tokenizer.current().text, "baz");
+        assert_eq!(comments.trailing, " bar trailing\n");
+        assert!(comments.detached.is_empty());
+        assert_eq!(comments.leading, "");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r#""a\tb\x41\101\0\u00e9""#), b"a\tbAA\0\xc3\xa9".to_vec());
+        assert_eq!(unescape("'\\'\"'"), b"'\"".to_vec());
+        assert_eq!(parse_integer("0x10", 100), Some(16));
+        assert_eq!(parse_integer("010", 100), Some(8));
+        assert_eq!(parse_integer("0", 100), Some(0));
+        assert_eq!(parse_integer("101", 100), None);
+    }
+}
@@ -41,11 +41,14 @@ pub struct Field {
     pub key_ty: scalar::Ty,
     pub value_ty: ValueTy,
     pub tag: u32,
+    /// Whether the field belongs to a borrowed message, in which case `string`, `bytes` and
+    /// message keys and values borrow from the buffer being decoded.
+    pub borrowed: bool,
 }
 
 impl Field {
 
-    pub fn new(attrs: &[MetaItem]) -> Result<Option<Field>> {
+    pub fn new(attrs: &[MetaItem], borrowed: bool) -> Result<Option<Field>> {
         let mut types = None;
         let mut tag = None;
 
@@ -96,15 +99,16 @@ impl Field {
                     map_ty: map_ty,
                     key_ty: key_ty,
                     value_ty: val_ty,
-                    tag: tag
+                    tag: tag,
+                    borrowed: borrowed,
                 })
             },
             _ => None
         })
     }
 
-    pub fn new_oneof(attrs: &[MetaItem]) -> Result<Option<Field>> {
-        Field::new(attrs)
+    pub fn new_oneof(attrs: &[MetaItem], borrowed: bool) -> Result<Option<Field>> {
+        Field::new(attrs, borrowed)
     }
 
     /// Returns a statement which encodes the map field.
@@ -133,10 +137,14 @@ impl Field {
                 }
             },
             ValueTy::Message => {
+                // The length of each message value is needed for the length of its entry, so it
+                // is peeked before the value is encoded.
                 quote! {
                     _prost::encoding::#module::encode(#ke, #kl,
-                                                      _prost::encoding::message::encode,
-                                                      _prost::encoding::message::encoded_len,
+                                                      |tag, msg, buf| {
+                                                          _prost::encoding::message::encode_cached(tag, msg, buf, cache)
+                                                      },
+                                                      |tag, _| _prost::encoding::message::peek_encoded_len(tag, cache),
                                                       #tag, &#ident, buf);
                 }
             },
@@ -146,23 +154,25 @@ impl Field {
     /// Returns an expression which evaluates to the result of merging a decoded key value pair
     /// into the map.
     pub fn merge(&self, ident: &Ident) -> Tokens {
-        let km = Ident::new(format!("_prost::encoding::{}::merge", self.key_ty.encode_as()));
+        let km = Ident::new(format!("_prost::encoding::{}::{}",
+                                    self.key_ty.encode_as(), self.key_ty.merge_fn(self.borrowed)));
         let module = self.map_ty.module();
         match self.value_ty {
             ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) => {
                 let default = Ident::new(format!("{}::default() as i32", ty));
                 quote! {
                     _prost::encoding::#module::merge_with_default(#km, _prost::encoding::int32::merge,
-                                                                  #default, &mut #ident, buf)
+                                                                  #default, &mut #ident, buf, ctx)
                 }
             },
             ValueTy::Scalar(ref value_ty) => {
-                let vm = Ident::new(format!("_prost::encoding::{}::merge", value_ty.encode_as()));
-                quote!(_prost::encoding::#module::merge(#km, #vm, &mut #ident, buf))
+                let vm = Ident::new(format!("_prost::encoding::{}::{}",
+                                            value_ty.encode_as(), value_ty.merge_fn(self.borrowed)));
+                quote!(_prost::encoding::#module::merge(#km, #vm, &mut #ident, buf, ctx))
             },
             ValueTy::Message => {
                 quote!(_prost::encoding::#module::merge(#km, _prost::encoding::message::merge,
-                                                        &mut #ident, buf))
+                                                        &mut #ident, buf, ctx))
             },
         }
     }
@@ -192,6 +202,72 @@ impl Field {
         }
     }
 
+    /// Returns an expression which evaluates to the encoded length of the map, and records the
+    /// lengths of message values in `cache`.
+    pub fn encoded_len_cached(&self, ident: &Ident) -> Tokens {
+        if let ValueTy::Message = self.value_ty {
+            let tag = self.tag;
+            let kl = Ident::new(format!("_prost::encoding::{}::encoded_len", self.key_ty.encode_as()));
+            let module = self.map_ty.module();
+            quote! {
+                _prost::encoding::#module::encoded_len(#kl,
+                                                       |tag, msg| {
+                                                           _prost::encoding::message::encoded_len_cached(tag, msg, cache)
+                                                       },
+                                                       #tag, &#ident)
+            }
+        } else {
+            self.encoded_len(ident)
+        }
+    }
+
+    /// Returns a statement which merges the map of another message, `other`, into the map. Entries
+    /// of `other` replace entries with the same key.
+    pub fn merge_from(&self, ident: &Ident, other: &Ident) -> Tokens {
+        match self.value_ty {
+            ValueTy::Scalar(..) => quote! {
+                for (key, value) in &#other {
+                    #ident.insert(key.clone(), value.clone());
+                }
+            },
+            ValueTy::Message => quote! {
+                for (key, msg) in &#other {
+                    let mut value = ::std::default::Default::default();
+                    _prost::Message::merge_from(&mut value, msg);
+                    #ident.insert(key.clone(), value);
+                }
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the message values of the map have all
+    /// of their required fields set, or `None` if the map has scalar values.
+    pub fn is_initialized(&self, ident: &Ident) -> Option<Tokens> {
+        if let ValueTy::Message = self.value_ty {
+            Some(quote!(#ident.values().all(|msg| _prost::Message::is_initialized(msg))))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a statement which appends the paths of the missing required fields of the
+    /// message values of the map to `missing`, or `None` if the map has scalar values. Values are
+    /// identified by their key.
+    pub fn missing_fields(&self, ident: &Ident, name: &str) -> Option<Tokens> {
+        if let ValueTy::Message = self.value_ty {
+            Some(quote! {
+                for (key, msg) in &#ident {
+                    if !_prost::Message::is_initialized(msg) {
+                        _prost::Message::missing_fields(msg, &format!("{}{}[{:?}].", prefix, #name, key),
+                                                       missing);
+                    }
+                }
+            })
+        } else {
+            None
+        }
+    }
+
     /// Returns methods to embed in the message.
     pub fn methods(&self, ident: &Ident) -> Option<Tokens> {
         if let ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) = self.value_ty {
@@ -214,6 +290,78 @@ impl Field {
             None
         }
     }
+
+    /// Returns a statement which appends the map to the members of the message's JSON object.
+    pub fn json_encode(&self, ident: &Ident, json_name: &str) -> Tokens {
+        let module = self.map_ty.module();
+        let to_json = match self.value_ty {
+            ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) => {
+                let to_json = Ident::new(format!("_prost::json::enumeration::to_json::<{}>", ty));
+                quote!(|value| Ok(#to_json(value)))
+            },
+            ValueTy::Scalar(scalar::Ty::OpenEnum(ref ty)) => {
+                let to_json = Ident::new(format!("_prost::json::open_enum::to_json::<{}>", ty));
+                quote!(|value| Ok(#to_json(value)))
+            },
+            ValueTy::Scalar(..) => quote!(|value| Ok(_prost::json::scalar::to_json(value))),
+            ValueTy::Message => quote!(_prost::json::message::to_json),
+        };
+        quote! {
+            if !#ident.is_empty() {
+                fields.push((#json_name.to_string(), _prost::json::#module::to_json(&#ident, #to_json)?));
+            }
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the JSON value `value`
+    /// into the map.
+    pub fn json_merge(&self, ident: &Ident) -> Tokens {
+        let module = self.map_ty.module();
+        let from_json = match self.value_ty {
+            ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) => {
+                Ident::new(format!("_prost::json::enumeration::from_json::<{}>", ty))
+            },
+            ValueTy::Scalar(scalar::Ty::OpenEnum(ref ty)) => {
+                Ident::new(format!("_prost::json::open_enum::from_json::<{}>", ty))
+            },
+            ValueTy::Scalar(..) => Ident::new("_prost::json::scalar::from_json"),
+            ValueTy::Message => Ident::new("_prost::json::message::from_json"),
+        };
+        quote!(_prost::json::#module::merge(&mut #ident, value, #from_json))
+    }
+
+    /// Returns a statement which writes the map to `printer` in text format.
+    pub fn text_print(&self, ident: &Ident, name: &str) -> Tokens {
+        let module = self.map_ty.module();
+        let print = match self.value_ty {
+            ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) => {
+                Ident::new(format!("_prost::text::enumeration::print::<{}>", ty))
+            },
+            ValueTy::Scalar(scalar::Ty::OpenEnum(ref ty)) => {
+                Ident::new(format!("_prost::text::open_enum::print::<{}>", ty))
+            },
+            ValueTy::Scalar(..) => Ident::new("_prost::text::scalar::print"),
+            ValueTy::Message => Ident::new("_prost::text::message::print"),
+        };
+        quote!(_prost::text::#module::print(printer, #name, &#ident, #print);)
+    }
+
+    /// Returns an expression which evaluates to the result of merging the map entry in the text
+    /// format value `value` into the map.
+    pub fn text_merge(&self, ident: &Ident) -> Tokens {
+        let module = self.map_ty.module();
+        let from_text = match self.value_ty {
+            ValueTy::Scalar(scalar::Ty::Enumeration(ref ty)) => {
+                Ident::new(format!("_prost::text::enumeration::from_text::<{}>", ty))
+            },
+            ValueTy::Scalar(scalar::Ty::OpenEnum(ref ty)) => {
+                Ident::new(format!("_prost::text::open_enum::from_text::<{}>", ty))
+            },
+            ValueTy::Scalar(..) => Ident::new("_prost::text::scalar::from_text"),
+            ValueTy::Message => Ident::new("_prost::text::message::from_text"),
+        };
+        quote!(_prost::text::#module::merge(&mut #ident, value, #from_text))
+    }
 }
 
 fn key_ty_from_str(s: &str) -> Result<scalar::Ty> {
@@ -16,11 +16,18 @@ use field::{
 pub struct Field {
     pub label: Label,
     pub tag: u32,
+    /// Whether the field is a proto2 group, which is delimited by start and end group keys
+    /// instead of a length prefix.
+    pub group: bool,
+    /// Whether the field is a proto2 required field. Required fields are held in an `Option` so
+    /// that their presence is tracked, and are otherwise handled as optional fields.
+    pub required: bool,
 }
 
 impl Field {
     pub fn new(attrs: &[MetaItem]) -> Result<Option<Field>> {
         let mut message = false;
+        let mut group = false;
         let mut label = None;
         let mut tag = None;
         let mut boxed = false;
@@ -30,6 +37,8 @@ impl Field {
         for attr in attrs {
             if word_attr("message", attr) {
                 set_bool(&mut message, "duplicate message attribute")?;
+            } else if word_attr("group", attr) {
+                set_bool(&mut group, "duplicate group attribute")?;
             } else if word_attr("boxed", attr) {
                 set_bool(&mut boxed, "duplicate boxed attribute")?;
             } else if let Some(t) = tag_attr(attr)? {
@@ -41,8 +50,10 @@ impl Field {
             }
         }
 
-        if !message {
-            return Ok(None);
+        match (message, group) {
+            (false, false) => return Ok(None),
+            (true, true) => bail!("message and group attributes may not be combined"),
+            _ => (),
         }
 
         match unknown_attrs.len() {
@@ -56,9 +67,16 @@ impl Field {
             None => bail!("message field is missing a tag attribute"),
         };
 
+        let required = label == Some(Label::Required);
+        if required {
+            label = Some(Label::Optional);
+        }
+
         Ok(Some(Field {
             label: label.unwrap_or(Label::Optional),
             tag: tag,
+            group: group,
+            required: required,
         }))
     }
 
@@ -74,61 +92,233 @@ impl Field {
         }
     }
 
+    /// Returns the encoding module for the field's message type.
+    fn module(&self) -> Ident {
+        Ident::new(if self.group { "_prost::encoding::group" } else { "_prost::encoding::message" })
+    }
+
+    /// Returns a statement which encodes the message field, using the message lengths recorded
+    /// in `cache` by the sizing pass.
     pub fn encode(&self, ident: &Ident) -> Tokens {
+        let module = self.module();
         let tag = self.tag;
         match self.label {
             Label::Optional => quote! {
                 if let Some(ref msg) = #ident {
-                    _prost::encoding::message::encode(#tag, msg, buf);
+                    #module::encode_cached(#tag, msg, buf, cache);
                 }
             },
             Label::Required => quote! {
-                _prost::encoding::message::encode(#tag, &#ident, buf);
+                #module::encode_cached(#tag, &#ident, buf, cache);
             },
             Label::Repeated => quote! {
-                for msg in &#ident {
-                    _prost::encoding::message::encode(#tag, msg, buf);
-                }
+                #module::encode_repeated_cached(#tag, &#ident, buf, cache);
             },
         }
     }
 
     pub fn merge(&self, ident: &Ident) -> Tokens {
+        let module = self.module();
+        // Groups are terminated by an end group key with the field's tag.
+        let tag = if self.group {
+            let tag = self.tag;
+            quote!(#tag,)
+        } else {
+            quote!()
+        };
         match self.label {
             // TODO(rustlang/rust#39288): Use Option::get_or_insert_with when available:
-            // _prost::encoding::message::merge(#ident.get_or_insert_with(Default::default), buf)
+            // _prost::encoding::message::merge(wire_type, #ident.get_or_insert_with(Default::default), buf, ctx)
             Label::Optional => quote! {
                 {
                     if #ident.is_none() {
                         #ident = Some(Default::default());
                     }
                     match #ident {
-                        Some(ref mut msg) => _prost::encoding::message::merge(wire_type, msg, buf),
+                        Some(ref mut msg) => #module::merge(#tag wire_type, msg, buf, ctx),
                         _ => unreachable!(),
                     }
                 }
             },
             Label::Required => quote! {
-                _prost::encoding::message::merge(wire_type, &mut #ident, buf)
+                #module::merge(#tag wire_type, &mut #ident, buf, ctx)
             },
             Label::Repeated => quote! {
-                _prost::encoding::message::merge_repeated(wire_type, &mut #ident, buf)
+                #module::merge_repeated(#tag wire_type, &mut #ident, buf, ctx)
             },
         }
     }
 
     pub fn encoded_len(&self, ident: &Ident) -> Tokens {
+        let module = self.module();
+        let tag = self.tag;
+        match self.label {
+            Label::Optional => quote! {
+                #ident.as_ref().map_or(0, |msg| #module::encoded_len(#tag, msg))
+            },
+            Label::Required => quote! {
+                #module::encoded_len(#tag, &#ident)
+            },
+            Label::Repeated => quote! {
+                #module::encoded_len_repeated(#tag, &#ident)
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the encoded length of the field, and records the
+    /// lengths of its messages in `cache`.
+    pub fn encoded_len_cached(&self, ident: &Ident) -> Tokens {
+        let module = self.module();
         let tag = self.tag;
         match self.label {
             Label::Optional => quote! {
-                #ident.as_ref().map_or(0, |msg| _prost::encoding::message::encoded_len(#tag, msg))
+                #ident.as_ref().map_or(0, |msg| #module::encoded_len_cached(#tag, msg, cache))
             },
             Label::Required => quote! {
-                _prost::encoding::message::encoded_len(#tag, &#ident)
+                #module::encoded_len_cached(#tag, &#ident, cache)
+            },
+            Label::Repeated => quote! {
+                #module::encoded_len_repeated_cached(#tag, &#ident, cache)
+            },
+        }
+    }
+
+    /// Returns a statement which merges the field of another message, `other`, into the field.
+    /// Messages are merged recursively.
+    pub fn merge_from(&self, ident: &Ident, other: &Ident) -> Tokens {
+        match self.label {
+            Label::Optional => quote! {
+                if let ::std::option::Option::Some(ref msg) = #other {
+                    if let ::std::option::Option::Some(ref mut value) = #ident {
+                        _prost::Message::merge_from(value, msg);
+                    } else {
+                        let mut value = ::std::default::Default::default();
+                        _prost::Message::merge_from(&mut value, msg);
+                        #ident = ::std::option::Option::Some(value);
+                    }
+                }
             },
+            Label::Required => quote!(_prost::Message::merge_from(&mut #ident, &#other);),
             Label::Repeated => quote! {
-                _prost::encoding::message::encoded_len_repeated(#tag, &#ident)
+                for msg in &#other {
+                    let mut value = ::std::default::Default::default();
+                    _prost::Message::merge_from(&mut value, msg);
+                    #ident.push(value);
+                }
+            },
+        }
+    }
+
+    /// Returns a statement which resets the field to its default value.
+    pub fn clear(&self, ident: &Ident) -> Tokens {
+        match self.label {
+            Label::Optional => quote!(#ident = ::std::option::Option::None;),
+            Label::Required => quote!(_prost::Message::clear(&mut #ident);),
+            Label::Repeated => quote!(#ident.clear();),
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the field's messages have all of their
+    /// required fields set, and the field itself is set if it is required.
+    pub fn is_initialized(&self, ident: &Ident) -> Tokens {
+        // An unset field is initialized unless it is required.
+        let unset = !self.required;
+        match self.label {
+            Label::Optional => quote! {
+                #ident.as_ref().map_or(#unset, |msg| _prost::Message::is_initialized(msg))
             },
+            Label::Required => quote!(_prost::Message::is_initialized(&#ident)),
+            Label::Repeated => quote!(#ident.iter().all(|msg| _prost::Message::is_initialized(msg))),
+        }
+    }
+
+    /// Returns a statement which appends the paths of the missing required fields of the field,
+    /// and of its messages, to `missing`.
+    pub fn missing_fields(&self, ident: &Ident, name: &str) -> Tokens {
+        let missing_in = quote! {
+            if !_prost::Message::is_initialized(msg) {
+                _prost::Message::missing_fields(msg, &format!("{}{}.", prefix, #name), missing);
+            }
+        };
+        match self.label {
+            Label::Optional if self.required => quote! {
+                match #ident {
+                    ::std::option::Option::Some(ref msg) => { #missing_in },
+                    ::std::option::Option::None => missing.push(format!("{}{}", prefix, #name)),
+                }
+            },
+            Label::Optional => quote! {
+                if let ::std::option::Option::Some(ref msg) = #ident {
+                    #missing_in
+                }
+            },
+            Label::Required => quote! {
+                {
+                    let msg = &#ident;
+                    #missing_in
+                }
+            },
+            Label::Repeated => quote! {
+                for (index, msg) in #ident.iter().enumerate() {
+                    if !_prost::Message::is_initialized(msg) {
+                        _prost::Message::missing_fields(msg, &format!("{}{}[{}].", prefix, #name, index),
+                                                       missing);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Returns a statement which appends the field to the members of the message's JSON object.
+    pub fn json_encode(&self, ident: &Ident, json_name: &str) -> Tokens {
+        match self.label {
+            Label::Optional => quote! {
+                if let Some(ref msg) = #ident {
+                    fields.push((#json_name.to_string(), _prost::json::message::to_json(msg)?));
+                }
+            },
+            Label::Required => quote! {
+                fields.push((#json_name.to_string(), _prost::json::message::to_json(&#ident)?));
+            },
+            Label::Repeated => quote! {
+                if !#ident.is_empty() {
+                    fields.push((#json_name.to_string(),
+                                 _prost::json::message::to_json_repeated(&#ident)?));
+                }
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the JSON value `value`
+    /// into the field.
+    pub fn json_merge(&self, ident: &Ident) -> Tokens {
+        match self.label {
+            Label::Optional => quote!(_prost::json::message::merge_optional(&mut #ident, value)),
+            Label::Required => quote!(_prost::json::message::merge(&mut #ident, value)),
+            Label::Repeated => quote!(_prost::json::message::merge_repeated(&mut #ident, value)),
+        }
+    }
+
+    /// Returns a statement which writes the field to `printer` in text format.
+    pub fn text_print(&self, ident: &Ident, name: &str) -> Tokens {
+        match self.label {
+            Label::Optional => quote! {
+                if let Some(ref msg) = #ident {
+                    _prost::text::message::print(printer, #name, msg);
+                }
+            },
+            Label::Required => quote!(_prost::text::message::print(printer, #name, &#ident);),
+            Label::Repeated => quote!(_prost::text::message::print_repeated(printer, #name, &#ident);),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the text format value
+    /// `value` into the field.
+    pub fn text_merge(&self, ident: &Ident) -> Tokens {
+        match self.label {
+            Label::Optional => quote!(_prost::text::message::merge_optional(&mut #ident, value)),
+            Label::Required => quote!(_prost::text::message::merge(&mut #ident, value)),
+            Label::Repeated => quote!(_prost::text::message::merge_repeated(&mut #ident, value)),
         }
     }
 }
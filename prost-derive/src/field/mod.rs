@@ -30,20 +30,27 @@ pub enum Field {
 
 impl Field {
 
-    /// Creates a new `Field` from an iterator of field attributes.
+    /// Creates a new `Field` from an iterator of field attributes. Fields of borrowed messages
+    /// (messages with a lifetime parameter) borrow `string`, `bytes` and message values from the
+    /// buffer being decoded.
     ///
     /// If the meta items are invalid, an error will be returned.
     /// If the field should be ignored, `None` is returned.
-    pub fn new(attrs: Vec<Attribute>) -> Result<Option<Field>> {
-        let attrs = prost_attrs(attrs)?;
+    pub fn new(attrs: Vec<Attribute>, borrowed: bool) -> Result<Option<Field>> {
+        let attrs = prost_attrs(attrs)?.into_iter().filter(|attr| !is_name_attr(attr)).collect::<Vec<_>>();
 
-        // TODO: check for ignore attribute.
+        if attrs.iter().any(|attr| word_attr("ignore", attr)) {
+            if attrs.len() > 1 {
+                bail!("ignore attribute may not be combined with other attributes");
+            }
+            return Ok(None);
+        }
 
-        let field = if let Some(field) = scalar::Field::new(&attrs)? {
+        let field = if let Some(field) = scalar::Field::new(&attrs, borrowed)? {
             Field::Scalar(field)
         } else if let Some(field) = message::Field::new(&attrs)? {
             Field::Message(field)
-        } else if let Some(field) = map::Field::new(&attrs)? {
+        } else if let Some(field) = map::Field::new(&attrs, borrowed)? {
             Field::Map(field)
         } else if let Some(field) = oneof::Field::new(&attrs)? {
             Field::Oneof(field)
@@ -58,17 +65,22 @@ impl Field {
     ///
     /// If the meta items are invalid, an error will be returned.
     /// If the field should be ignored, `None` is returned.
-    pub fn new_oneof(attrs: Vec<Attribute>) -> Result<Option<Field>> {
-        let attrs = prost_attrs(attrs)?;
+    pub fn new_oneof(attrs: Vec<Attribute>, borrowed: bool) -> Result<Option<Field>> {
+        let attrs = prost_attrs(attrs)?.into_iter().filter(|attr| !is_name_attr(attr)).collect::<Vec<_>>();
 
-        // TODO: check for ignore attribute.
+        if attrs.iter().any(|attr| word_attr("ignore", attr)) {
+            if attrs.len() > 1 {
+                bail!("ignore attribute may not be combined with other attributes");
+            }
+            return Ok(None);
+        }
 
-        let field = if let Some(field) = scalar::Field::new_oneof(&attrs)? {
+        let field = if let Some(field) = scalar::Field::new_oneof(&attrs, borrowed)? {
             Field::Scalar(field)
         } else if let Some(field) = message::Field::new_oneof(&attrs)? {
             Field::Message(field)
-        } else if let Some(field) = map::Field::new_oneof(&attrs)? {
-            Field::Map(field)
+        } else if map::Field::new_oneof(&attrs, borrowed)?.is_some() {
+            bail!("oneof fields may not be maps");
         } else {
             bail!("no type attribute for oneof field");
         };
@@ -116,6 +128,61 @@ impl Field {
         }
     }
 
+    /// Returns an expression which evaluates to the encoded length of the field, and records the
+    /// lengths of nested messages in `cache` for use by `encode`.
+    pub fn encoded_len_cached(&self, ident: &Ident) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.encoded_len(ident),
+            Field::Map(ref map) => map.encoded_len_cached(ident),
+            Field::Message(ref msg) => msg.encoded_len_cached(ident),
+            Field::Oneof(ref oneof) => oneof.encoded_len_cached(ident),
+        }
+    }
+
+    /// Returns a statement which merges the field of another message, `other`, into the field.
+    pub fn merge_from(&self, ident: &Ident, other: &Ident) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.merge_from(ident, other),
+            Field::Message(ref message) => message.merge_from(ident, other),
+            Field::Map(ref map) => map.merge_from(ident, other),
+            Field::Oneof(ref oneof) => oneof.merge_from(ident, other),
+        }
+    }
+
+    /// Returns a statement which resets the field to its default value, keeping allocated
+    /// capacity where possible.
+    pub fn clear(&self, ident: &Ident) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.clear(ident),
+            Field::Message(ref message) => message.clear(ident),
+            Field::Map(..) => quote!(#ident.clear();),
+            Field::Oneof(..) => quote!(#ident = ::std::option::Option::None;),
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the required fields of the field, and
+    /// of its nested messages, are set, or `None` if the field can not be uninitialized.
+    pub fn is_initialized(&self, ident: &Ident) -> Option<Tokens> {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.is_initialized(ident),
+            Field::Message(ref message) => Some(message.is_initialized(ident)),
+            Field::Map(ref map) => map.is_initialized(ident),
+            Field::Oneof(ref oneof) => Some(oneof.is_initialized(ident)),
+        }
+    }
+
+    /// Returns a statement which appends the paths of the missing required fields of the field,
+    /// and of its nested messages, to `missing`, or `None` if the field can not be uninitialized.
+    /// Paths are prefixed by `prefix`.
+    pub fn missing_fields(&self, ident: &Ident, name: &str) -> Option<Tokens> {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.missing_fields(ident, name),
+            Field::Message(ref message) => Some(message.missing_fields(ident, name)),
+            Field::Map(ref map) => map.missing_fields(ident, name),
+            Field::Oneof(ref oneof) => Some(oneof.missing_fields(ident)),
+        }
+    }
+
     pub fn default(&self) -> Tokens {
         match *self {
             Field::Scalar(ref scalar) => scalar.default(),
@@ -130,6 +197,193 @@ impl Field {
             _ => None,
         }
     }
+
+    /// Returns a statement which appends the field to `fields`, the members of the message's
+    /// JSON object, unless the field is unset or holds its default value.
+    pub fn json_encode(&self, ident: &Ident, json_name: &str) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.json_encode(ident, json_name),
+            Field::Message(ref message) => message.json_encode(ident, json_name),
+            Field::Map(ref map) => map.json_encode(ident, json_name),
+            Field::Oneof(..) => quote! {
+                if let Some(ref oneof) = #ident {
+                    oneof.to_json(&mut fields)?;
+                }
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the JSON value `value`
+    /// into the field. Oneof fields are merged by the `merge_json` function of the oneof type.
+    pub fn json_merge(&self, ident: &Ident) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.json_merge(ident),
+            Field::Message(ref message) => message.json_merge(ident),
+            Field::Map(ref map) => map.json_merge(ident),
+            Field::Oneof(..) => unreachable!("oneof fields are merged by the oneof type"),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of converting the JSON value `value`
+    /// to the value of a oneof variant, or to `None` if the value is `null`.
+    pub fn json_oneof(&self) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.json_oneof(),
+            Field::Message(..) => quote!(_prost::json::message::from_json_oneof(value)),
+            Field::Map(..) | Field::Oneof(..) => unreachable!("invalid oneof variant"),
+        }
+    }
+
+    /// Returns `true` if the field may appear more than once in the text or JSON representation
+    /// of a message.
+    pub fn is_repeated(&self) -> bool {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.is_repeated(),
+            Field::Message(ref message) => message.label == Label::Repeated,
+            Field::Map(..) => true,
+            Field::Oneof(..) => false,
+        }
+    }
+
+    /// Returns a statement which writes the field to `printer` in text format, unless the field
+    /// is unset or holds its default value.
+    pub fn text_print(&self, ident: &Ident, name: &str) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.text_print(ident, name),
+            Field::Message(ref message) => message.text_print(ident, name),
+            Field::Map(ref map) => map.text_print(ident, name),
+            Field::Oneof(..) => quote! {
+                if let Some(ref oneof) = #ident {
+                    oneof.print_text(printer);
+                }
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the text format value
+    /// `value` into the field. Oneof fields are merged by the `merge_text` function of the
+    /// oneof type.
+    pub fn text_merge(&self, ident: &Ident) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.text_merge(ident),
+            Field::Message(ref message) => message.text_merge(ident),
+            Field::Map(ref map) => map.text_merge(ident),
+            Field::Oneof(..) => unreachable!("oneof fields are merged by the oneof type"),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of converting the text format value
+    /// `value` to the value of a oneof variant.
+    pub fn text_oneof(&self) -> Tokens {
+        match *self {
+            Field::Scalar(ref scalar) => scalar.text_oneof(),
+            Field::Message(..) => quote!(_prost::text::message::from_text(value)),
+            Field::Map(..) | Field::Oneof(..) => unreachable!("invalid oneof variant"),
+        }
+    }
+}
+
+/// The names of a field in the `.proto` file and in the JSON mapping.
+pub struct Names {
+    /// The name of the field as it is written in the `.proto` file.
+    pub name: String,
+    /// The name of the field in the JSON mapping.
+    pub json_name: String,
+}
+
+impl Names {
+
+    /// Reads the `name` and `json_name` attributes of a field. The name defaults to `default`,
+    /// and the JSON name defaults to the lowerCamelCase form of the name, as `protoc` computes it.
+    pub fn new(attrs: &[Attribute], default: &str) -> Result<Names> {
+        let mut name = None;
+        let mut json_name = None;
+        for attr in prost_attrs(attrs.to_vec())? {
+            if let Some(n) = str_attr("name", &attr)? {
+                set_option(&mut name, n, "duplicate name attributes")?;
+            } else if let Some(n) = str_attr("json_name", &attr)? {
+                set_option(&mut json_name, n, "duplicate json_name attributes")?;
+            }
+        }
+        let name = name.unwrap_or_else(|| default.to_string());
+        let json_name = json_name.unwrap_or_else(|| to_json_name(&name));
+        Ok(Names {
+            name: name,
+            json_name: json_name,
+        })
+    }
+
+    /// Returns the JSON object keys which are accepted for the field.
+    pub fn keys(&self) -> Vec<&str> {
+        if self.name == self.json_name {
+            vec![&self.name[..]]
+        } else {
+            vec![&self.name[..], &self.json_name[..]]
+        }
+    }
+}
+
+/// Returns the `name` attribute of an enumeration variant, if it has one.
+pub fn variant_name(attrs: &[Attribute]) -> Result<Option<String>> {
+    let mut name = None;
+    for attr in prost_attrs(attrs.to_vec())? {
+        if let Some(n) = str_attr("name", &attr)? {
+            set_option(&mut name, n, "duplicate name attributes")?;
+        } else {
+            bail!("unknown attribute for enumeration variant: {:?}", attr);
+        }
+    }
+    Ok(name)
+}
+
+/// Converts a field name to its default JSON name by removing underscores and capitalizing the
+/// letters which follow them.
+fn to_json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            json_name.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+/// Returns `true` if the attribute is a `name` or `json_name` attribute, which are read
+/// separately from the type attributes of a field.
+fn is_name_attr(attr: &MetaItem) -> bool {
+    attr.name() == "name" || attr.name() == "json_name"
+}
+
+/// Returns `true` if the field attributes mark the field as the container for the message's
+/// unknown fields (e.g. `#[prost(unknown_fields)]`).
+pub fn is_unknown_fields(attrs: &[Attribute]) -> Result<bool> {
+    let attrs = prost_attrs(attrs.to_vec())?;
+    if !attrs.iter().any(|attr| word_attr("unknown_fields", attr)) {
+        return Ok(false);
+    }
+    if attrs.len() > 1 {
+        bail!("unknown_fields attribute may not be combined with other attributes");
+    }
+    Ok(true)
+}
+
+/// Returns `true` if the field attributes mark the field as the container for the message's
+/// extension fields (e.g. `#[prost(extension_set)]`).
+pub fn is_extension_set(attrs: &[Attribute]) -> Result<bool> {
+    let attrs = prost_attrs(attrs.to_vec())?;
+    if !attrs.iter().any(|attr| word_attr("extension_set", attr)) {
+        return Ok(false);
+    }
+    if attrs.len() > 1 {
+        bail!("extension_set attribute may not be combined with other attributes");
+    }
+    Ok(true)
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -188,7 +442,7 @@ impl fmt::Display for Label {
 
 /// Get the items belonging to the 'prost' list attribute
 /// (e.g. #[prost(foo, bar="baz")]).
-fn prost_attrs(attrs: Vec<Attribute>) -> Result<Vec<MetaItem>> {
+pub fn prost_attrs(attrs: Vec<Attribute>) -> Result<Vec<MetaItem>> {
     Ok(attrs.into_iter().flat_map(|attr| match attr.value {
         MetaItem::List(ident, items) => if ident == "prost" { items } else { Vec::new() },
         _ => Vec::new(),
@@ -244,8 +498,20 @@ fn bool_attr(key: &str, attr: &MetaItem) -> Result<Option<bool>> {
     }
 }
 
+/// Unpacks an attribute into a (key, string) pair, returning the string value.
+/// If the key doesn't match the attribute, `None` is returned.
+fn str_attr(key: &str, attr: &MetaItem) -> Result<Option<String>> {
+    if attr.name() != key {
+        return Ok(None);
+    }
+    match *attr {
+        MetaItem::NameValue(_, Lit::Str(ref s, _)) => Ok(Some(s.clone())),
+        _ => bail!("invalid {} attribute: {:?}", key, attr),
+    }
+}
+
 /// Checks if an attribute matches a word.
-fn word_attr(key: &str, attr: &MetaItem) -> bool {
+pub fn word_attr(key: &str, attr: &MetaItem) -> bool {
     if let MetaItem::Word(ref ident) = *attr {
         ident == key
     } else {
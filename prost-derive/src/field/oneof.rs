@@ -73,7 +73,7 @@ impl Field {
     pub fn encode(&self, ident: &Ident) -> Tokens {
         quote! {
             if let Some(ref oneof) = #ident {
-                oneof.encode(buf)
+                oneof.encode(buf, cache)
             }
         }
     }
@@ -82,7 +82,36 @@ impl Field {
     pub fn merge(&self, ident: &Ident) -> Tokens {
         let ty = &self.ty;
         quote! {
-            #ty::merge(&mut #ident, tag, wire_type, buf)
+            #ty::merge(&mut #ident, tag, wire_type, buf, ctx)
+        }
+    }
+
+    /// Returns a statement which merges the oneof field of another message, `other`, into the
+    /// oneof field.
+    pub fn merge_from(&self, ident: &Ident, other: &Ident) -> Tokens {
+        let ty = &self.ty;
+        quote! {
+            if let ::std::option::Option::Some(ref oneof) = #other {
+                #ty::merge_from(&mut #ident, oneof);
+            }
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the message held by the oneof field, if
+    /// any, has all of its required fields set.
+    pub fn is_initialized(&self, ident: &Ident) -> Tokens {
+        quote! {
+            #ident.as_ref().map_or(true, |oneof| oneof.is_initialized())
+        }
+    }
+
+    /// Returns a statement which appends the paths of the missing required fields of the message
+    /// held by the oneof field, if any, to `missing`.
+    pub fn missing_fields(&self, ident: &Ident) -> Tokens {
+        quote! {
+            if let ::std::option::Option::Some(ref oneof) = #ident {
+                oneof.missing_fields(prefix, missing);
+            }
         }
     }
 
@@ -93,4 +122,12 @@ impl Field {
             #ident.as_ref().map_or(0, #ty::encoded_len)
         }
     }
+
+    /// Returns an expression which evaluates to the encoded length of the oneof field, and
+    /// records the lengths of its messages in `cache`.
+    pub fn encoded_len_cached(&self, ident: &Ident) -> Tokens {
+        quote! {
+            #ident.as_ref().map_or(0, |oneof| oneof.encoded_len_cached(cache))
+        }
+    }
 }
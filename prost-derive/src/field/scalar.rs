@@ -25,11 +25,17 @@ pub struct Field {
     pub ty: Ty,
     pub kind: Kind,
     pub tag: u32,
+    /// Whether the field is a proto2 required field. Required fields are held in an `Option` so
+    /// that their presence is tracked, and are otherwise handled as optional fields.
+    pub required: bool,
+    /// Whether the field belongs to a borrowed message, in which case `string` and `bytes`
+    /// values borrow from the buffer being decoded.
+    pub borrowed: bool,
 }
 
 impl Field {
 
-    pub fn new(attrs: &[MetaItem]) -> Result<Option<Field>> {
+    pub fn new(attrs: &[MetaItem], borrowed: bool) -> Result<Option<Field>> {
         let mut ty = None;
         let mut label = None;
         let mut packed = None;
@@ -88,7 +94,7 @@ impl Field {
 
             (None, _, _) => Kind::Plain(default),
             (Some(Label::Optional), _, _) => Kind::Optional(default),
-            (Some(Label::Required), _, _) => Kind::Required(default),
+            (Some(Label::Required), _, _) => Kind::Optional(default),
             (Some(Label::Repeated), packed, false) if packed.unwrap_or(ty.is_numeric()) => Kind::Packed,
             (Some(Label::Repeated), _, false) => Kind::Repeated,
         };
@@ -97,11 +103,16 @@ impl Field {
             ty: ty,
             kind: kind,
             tag: tag,
+            required: label == Some(Label::Required),
+            borrowed: borrowed,
         }))
     }
 
-    pub fn new_oneof(attrs: &[MetaItem]) -> Result<Option<Field>> {
-        if let Some(mut field) = Field::new(attrs)? {
+    pub fn new_oneof(attrs: &[MetaItem], borrowed: bool) -> Result<Option<Field>> {
+        if let Some(mut field) = Field::new(attrs, borrowed)? {
+            if field.required {
+                bail!("invalid required attribute on oneof field");
+            }
             match field.kind {
                 Kind::Plain(default) => {
                     field.kind = Kind::Required(default);
@@ -150,12 +161,12 @@ impl Field {
             Kind::Plain(..) | Kind::Optional(..) | Kind::Required(..) => "",
             Kind::Repeated | Kind::Packed => "_repeated",
         };
-        let merge_fn = Ident::new(format!("_prost::encoding::{}::merge{}",
-                                          self.ty.encode_as(), kind));
+        let merge_fn = Ident::new(format!("_prost::encoding::{}::{}{}",
+                                          self.ty.encode_as(), self.ty.merge_fn(self.borrowed), kind));
 
         match self.kind {
             Kind::Plain(..) | Kind::Required(..) | Kind::Repeated | Kind::Packed => quote! {
-                #merge_fn(wire_type, &mut #ident, buf)
+                #merge_fn(wire_type, &mut #ident, buf, ctx)
             },
             Kind::Optional(..) => quote! {
                 {
@@ -163,7 +174,7 @@ impl Field {
                         #ident = Some(Default::default());
                     }
                     match #ident {
-                        Some(ref mut value) => #merge_fn(wire_type, value, buf),
+                        Some(ref mut value) => #merge_fn(wire_type, value, buf, ctx),
                         _ => unreachable!(),
                     }
                 }
@@ -199,9 +210,75 @@ impl Field {
         }
     }
 
+    /// Returns a statement which merges the field of another message, `other`, into the field.
+    pub fn merge_from(&self, ident: &Ident, other: &Ident) -> Tokens {
+        match self.kind {
+            Kind::Plain(ref default) => quote! {
+                if #other != #default {
+                    #ident.clone_from(&#other);
+                }
+            },
+            Kind::Optional(..) => quote! {
+                if let ::std::option::Option::Some(ref value) = #other {
+                    #ident = ::std::option::Option::Some(value.clone());
+                }
+            },
+            Kind::Required(..) => quote!(#ident.clone_from(&#other);),
+            Kind::Repeated | Kind::Packed => quote!(#ident.extend(#other.iter().cloned());),
+        }
+    }
+
+    /// Returns a statement which resets the field to its default value. `string` and `bytes`
+    /// values keep their allocated capacity.
+    pub fn clear(&self, ident: &Ident) -> Tokens {
+        match self.kind {
+            Kind::Plain(ref default) | Kind::Required(ref default) => match self.ty {
+                Ty::String => quote! {
+                    #ident.clear();
+                    #ident.push_str(#default);
+                },
+                Ty::Bytes(BytesTy::Vec) => quote! {
+                    #ident.clear();
+                    #ident.extend_from_slice(#default);
+                },
+                _ => {
+                    let default = self.default();
+                    quote!(#ident = #default;)
+                },
+            },
+            Kind::Optional(..) => quote!(#ident = ::std::option::Option::None;),
+            Kind::Repeated | Kind::Packed => quote!(#ident.clear();),
+        }
+    }
+
+    /// Returns an expression which evaluates to `true` if the field is set, or `None` if the
+    /// field is not required.
+    pub fn is_initialized(&self, ident: &Ident) -> Option<Tokens> {
+        if self.required {
+            Some(quote!(#ident.is_some()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a statement which appends the path of the field to `missing` if the field is not
+    /// set, or `None` if the field is not required.
+    pub fn missing_fields(&self, ident: &Ident, name: &str) -> Option<Tokens> {
+        if self.required {
+            Some(quote! {
+                if #ident.is_none() {
+                    missing.push(format!("{}{}", prefix, #name));
+                }
+            })
+        } else {
+            None
+        }
+    }
+
     /// Returns an expression which evaluates to the default value of the field.
     pub fn default(&self) -> Tokens {
         match self.kind {
+            Kind::Plain(ref value) | Kind::Required(ref value) if self.borrowed => quote!(#value),
             Kind::Plain(ref value) | Kind::Required(ref value) => value.owned(),
             Kind::Optional(_) => quote!(::std::option::Option::None),
             Kind::Repeated | Kind::Packed => quote!(::std::vec::Vec::new()),
@@ -248,6 +325,12 @@ impl Field {
                     }
                 },
             })
+        } else if let (&Ty::OpenEnum(ref ty), &Kind::Optional(ref default)) = (&self.ty, &self.kind) {
+            Some(quote! {
+                pub fn #ident(&self) -> _prost::OpenEnum<#ty> {
+                    self.#ident.unwrap_or(#default)
+                }
+            })
         } else if let Kind::Optional(ref default) = self.kind {
             let ty = Ident::new(self.ty.rust_ref_type());
 
@@ -269,6 +352,117 @@ impl Field {
             None
         }
     }
+
+    /// Returns the path of a function in the JSON conversion module for the field's type.
+    fn json_fn(&self, name: &str) -> Ident {
+        match self.ty {
+            Ty::Enumeration(ref ty) => Ident::new(format!("_prost::json::enumeration::{}::<{}>", name, ty)),
+            Ty::OpenEnum(ref ty) => Ident::new(format!("_prost::json::open_enum::{}::<{}>", name, ty)),
+            _ => Ident::new(format!("_prost::json::scalar::{}", name)),
+        }
+    }
+
+    /// Returns a statement which appends the field to the members of the message's JSON object.
+    pub fn json_encode(&self, ident: &Ident, json_name: &str) -> Tokens {
+        let to_json = self.json_fn("to_json");
+        match self.kind {
+            Kind::Plain(ref default) => quote! {
+                if #ident != #default {
+                    fields.push((#json_name.to_string(), #to_json(&#ident)));
+                }
+            },
+            Kind::Optional(..) => quote! {
+                if let ::std::option::Option::Some(ref value) = #ident {
+                    fields.push((#json_name.to_string(), #to_json(value)));
+                }
+            },
+            Kind::Required(..) => quote! {
+                fields.push((#json_name.to_string(), #to_json(&#ident)));
+            },
+            Kind::Repeated | Kind::Packed => {
+                let to_json_repeated = self.json_fn("to_json_repeated");
+                quote! {
+                    if !#ident.is_empty() {
+                        fields.push((#json_name.to_string(), #to_json_repeated(&#ident)));
+                    }
+                }
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the JSON value `value`
+    /// into the field.
+    pub fn json_merge(&self, ident: &Ident) -> Tokens {
+        let merge_fn = self.json_fn(match self.kind {
+            Kind::Plain(..) | Kind::Required(..) => "merge",
+            Kind::Optional(..) => "merge_optional",
+            Kind::Repeated | Kind::Packed => "merge_repeated",
+        });
+        quote!(#merge_fn(&mut #ident, value))
+    }
+
+    /// Returns an expression which evaluates to the result of converting the JSON value `value`
+    /// to the value of a oneof variant.
+    pub fn json_oneof(&self) -> Tokens {
+        let from_json_oneof = self.json_fn("from_json_oneof");
+        quote!(#from_json_oneof(value))
+    }
+
+    pub fn is_repeated(&self) -> bool {
+        match self.kind {
+            Kind::Repeated | Kind::Packed => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the path of a function in the text format conversion module for the field's type.
+    fn text_fn(&self, name: &str) -> Ident {
+        match self.ty {
+            Ty::Enumeration(ref ty) => Ident::new(format!("_prost::text::enumeration::{}::<{}>", name, ty)),
+            Ty::OpenEnum(ref ty) => Ident::new(format!("_prost::text::open_enum::{}::<{}>", name, ty)),
+            _ => Ident::new(format!("_prost::text::scalar::{}", name)),
+        }
+    }
+
+    /// Returns a statement which writes the field to `printer` in text format.
+    pub fn text_print(&self, ident: &Ident, name: &str) -> Tokens {
+        let print = self.text_fn("print");
+        match self.kind {
+            Kind::Plain(ref default) => quote! {
+                if #ident != #default {
+                    #print(printer, #name, &#ident);
+                }
+            },
+            Kind::Optional(..) => quote! {
+                if let ::std::option::Option::Some(ref value) = #ident {
+                    #print(printer, #name, value);
+                }
+            },
+            Kind::Required(..) => quote!(#print(printer, #name, &#ident);),
+            Kind::Repeated | Kind::Packed => {
+                let print_repeated = self.text_fn("print_repeated");
+                quote!(#print_repeated(printer, #name, &#ident);)
+            },
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging the text format value
+    /// `value` into the field.
+    pub fn text_merge(&self, ident: &Ident) -> Tokens {
+        let merge_fn = self.text_fn(match self.kind {
+            Kind::Plain(..) | Kind::Required(..) => "merge",
+            Kind::Optional(..) => "merge_optional",
+            Kind::Repeated | Kind::Packed => "merge_repeated",
+        });
+        quote!(#merge_fn(&mut #ident, value))
+    }
+
+    /// Returns an expression which evaluates to the result of converting the text format value
+    /// `value` to the value of a oneof variant.
+    pub fn text_oneof(&self) -> Tokens {
+        let from_text = self.text_fn("from_text");
+        quote!(#from_text(value))
+    }
 }
 
 /// A scalar protobuf field type.
@@ -288,8 +482,34 @@ pub enum Ty {
     Sfixed64,
     Bool,
     String,
-    Bytes,
+    Bytes(BytesTy),
     Enumeration(Ident),
+    /// An enumeration held as an `OpenEnum` rather than an `i32`.
+    OpenEnum(Ident),
+}
+
+/// The Rust type of a protobuf `bytes` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BytesTy {
+    Vec,
+    Bytes,
+}
+
+impl BytesTy {
+    fn try_from_str(s: &str) -> Option<BytesTy> {
+        match s {
+            "vec" => Some(BytesTy::Vec),
+            "bytes" => Some(BytesTy::Bytes),
+            _ => None,
+        }
+    }
+
+    fn rust_type(&self) -> &'static str {
+        match *self {
+            BytesTy::Vec => "::std::vec::Vec<u8>",
+            BytesTy::Bytes => "_bytes::Bytes",
+        }
+    }
 }
 
 impl Ty {
@@ -310,20 +530,34 @@ impl Ty {
             MetaItem::Word(ref name) if name == "sfixed64" => Ty::Sfixed64,
             MetaItem::Word(ref name) if name == "bool" => Ty::Bool,
             MetaItem::Word(ref name) if name == "string" => Ty::String,
-            MetaItem::Word(ref name) if name == "bytes" => Ty::Bytes,
+            MetaItem::Word(ref name) if name == "bytes" => Ty::Bytes(BytesTy::Vec),
+            MetaItem::NameValue(ref name, Lit::Str(ref ty, _)) if name == "bytes" => {
+                match BytesTy::try_from_str(ty) {
+                    Some(ty) => Ty::Bytes(ty),
+                    None => bail!("invalid bytes attribute: {:?}", ty),
+                }
+            },
             MetaItem::NameValue(ref name, Lit::Str(ref ident, _)) if name == "enumeration" => {
                 Ty::Enumeration(Ident::new(ident.as_ref()))
             },
-            MetaItem::List(ref name, ref items) if name == "enumeration" => {
+            MetaItem::NameValue(ref name, Lit::Str(ref ident, _)) if name == "open_enum" => {
+                Ty::OpenEnum(Ident::new(ident.as_ref()))
+            },
+            MetaItem::List(ref name, ref items) if name == "enumeration" || name == "open_enum" => {
                 // TODO(rustlang/rust#23121): slice pattern matching would make this much nicer.
-                if items.len() == 1 {
+                let ident = if items.len() == 1 {
                     if let NestedMetaItem::MetaItem(MetaItem::Word(ref ident)) = items[0] {
-                        Ty::Enumeration(ident.clone())
+                        ident.clone()
                     } else {
-                        bail!("invalid enumeration attribute: item must be an identifier");
+                        bail!("invalid {} attribute: item must be an identifier", name);
                     }
                 } else {
-                    bail!("invalid enumeration attribute: only a single identifier is supported");
+                    bail!("invalid {} attribute: only a single identifier is supported", name);
+                };
+                if name == "enumeration" {
+                    Ty::Enumeration(ident)
+                } else {
+                    Ty::OpenEnum(ident)
                 }
             },
             _ => return Ok(None),
@@ -333,6 +567,7 @@ impl Ty {
 
     pub fn from_str(s: &str) -> Result<Ty> {
         let enumeration_len = "enumeration".len();
+        let open_enum_len = "open_enum".len();
         let error = Err(From::from(format!("invalid type: {}", s)));
         let ty = match s.trim() {
             "float" => Ty::Float,
@@ -349,7 +584,7 @@ impl Ty {
             "sfixed64" => Ty::Sfixed64,
             "bool" => Ty::Bool,
             "string" => Ty::String,
-            "bytes" => Ty::Bytes,
+            "bytes" => Ty::Bytes(BytesTy::Vec),
             s if s.len() > enumeration_len && &s[..enumeration_len] == "enumeration" => {
                 let s = &s[enumeration_len..].trim();
                 match s.chars().next() {
@@ -362,6 +597,18 @@ impl Ty {
                 }
                 Ty::Enumeration(Ident::new(s[1..s.len() - 1].trim()))
             },
+            s if s.len() > open_enum_len && &s[..open_enum_len] == "open_enum" => {
+                let s = &s[open_enum_len..].trim();
+                match s.chars().next() {
+                    Some('<') | Some('(') => (),
+                    _ => return error,
+                }
+                match s.chars().next_back() {
+                    Some('>') | Some(')') => (),
+                    _ => return error,
+                }
+                Ty::OpenEnum(Ident::new(s[1..s.len() - 1].trim()))
+            },
             _ => return error,
         };
         Ok(ty)
@@ -384,15 +631,15 @@ impl Ty {
             Ty::Sfixed64 => "sfixed64",
             Ty::Bool => "bool",
             Ty::String => "string",
-            Ty::Bytes => "bytes",
-            Ty::Enumeration(..) => "enum",
+            Ty::Bytes(..) => "bytes",
+            Ty::Enumeration(..) | Ty::OpenEnum(..) => "enum",
         }
     }
 
     pub fn rust_type(&self) -> &'static str {
         match *self {
             Ty::String => "::std::string::String",
-            Ty::Bytes => "::std::vec::Vec<u8>",
+            Ty::Bytes(ref ty) => ty.rust_type(),
             _ => self.rust_ref_type(),
         }
     }
@@ -413,21 +660,34 @@ impl Ty {
             Ty::Sfixed64 => "i64",
             Ty::Bool => "bool",
             Ty::String => "&str",
-            Ty::Bytes => "&[u8]",
-            Ty::Enumeration(..) => "i32",
+            Ty::Bytes(..) => "&[u8]",
+            Ty::Enumeration(..) | Ty::OpenEnum(..) => "i32",
         }
     }
 
     pub fn encode_as(&self) -> &'static str {
         match *self {
             Ty::Enumeration(..) => "int32",
+            Ty::OpenEnum(..) => "open_enum",
             _ => self.as_str(),
         }
     }
 
+    /// Returns the name of the encoding function which merges a value of the type. `string` and
+    /// `bytes` values in borrowed messages are merged without copying.
+    pub fn merge_fn(&self, borrowed: bool) -> &'static str {
+        match *self {
+            Ty::String | Ty::Bytes(..) if borrowed => "merge_borrowed",
+            _ => "merge",
+        }
+    }
+
     /// Returns true if the scalar type is length delimited (i.e., `string` or `bytes`).
     pub fn is_numeric(&self) -> bool {
-        *self != Ty::String && *self != Ty::Bytes
+        match *self {
+            Ty::String | Ty::Bytes(..) => false,
+            _ => true,
+        }
     }
 }
 
@@ -449,7 +709,7 @@ pub enum Kind {
     Plain(DefaultValue),
     /// An optional scalar field.
     Optional(DefaultValue),
-    /// A required proto2 scalar field.
+    /// A scalar field which is always present, such as the value of a oneof variant.
     Required(DefaultValue),
     /// A repeated scalar field.
     Repeated,
@@ -491,10 +751,10 @@ impl DefaultValue {
             Lit::Int(value, IntTy::Unsuffixed) if is_i64 => Lit::Int(value, IntTy::I64),
 
             Lit::Int(value, IntTy::U32) if is_u32 => Lit::Int(value, IntTy::U32),
-            Lit::Int(value, IntTy::Unsuffixed) if is_u64  => Lit::Int(value, IntTy::U32),
+            Lit::Int(value, IntTy::Unsuffixed) if is_u32 => Lit::Int(value, IntTy::U32),
 
-            Lit::Int(value, IntTy::U64) if ty.rust_type() == "u64" => Lit::Int(value, IntTy::U64),
-            Lit::Int(value, IntTy::Unsuffixed) if ty.rust_type() == "u64" => Lit::Int(value, IntTy::U64),
+            Lit::Int(value, IntTy::U64) if is_u64 => Lit::Int(value, IntTy::U64),
+            Lit::Int(value, IntTy::Unsuffixed) if is_u64 => Lit::Int(value, IntTy::U64),
 
             Lit::Float(ref value, FloatTy::F32) if *ty == Ty::Float => Lit::Float(value.clone(), FloatTy::F32),
             Lit::Float(ref value, FloatTy::Unsuffixed) if *ty == Ty::Float => Lit::Float(value.clone(), FloatTy::F32),
@@ -509,6 +769,12 @@ impl DefaultValue {
                 if let Ty::Enumeration(ref ty) = *ty {
                     return Ok(DefaultValue::Ident(Ident::new(format!("{}::{} as i32", ty, s))));
                 }
+                if let Ty::OpenEnum(ref ty) = *ty {
+                    return Ok(DefaultValue::Ident(Ident::new(format!("_prost::OpenEnum::Known({}::{})", ty, s))));
+                }
+                if let Some(value) = numeric_default(ty, &s) {
+                    return Ok(DefaultValue::Ident(Ident::new(value)));
+                }
                 match syn::parse::lit(&s) {
                     syn::parse::IResult::Done(rest, _) if !rest.is_empty() => (),
                     syn::parse::IResult::Done(_, Lit::Str(..)) => (),
@@ -539,8 +805,9 @@ impl DefaultValue {
             Ty::Sfixed64 => Lit::from(0i64),
             Ty::Bool => Lit::from(false),
             Ty::String => Lit::from(""),
-            Ty::Bytes => Lit::from(&b""[..]),
+            Ty::Bytes(..) => Lit::from(&b""[..]),
             Ty::Enumeration(ref ty) => return DefaultValue::Ident(Ident::new(format!("{}::default() as i32", ty))),
+            Ty::OpenEnum(ref ty) => return DefaultValue::Ident(Ident::new(format!("_prost::OpenEnum::Known({}::default())", ty))),
         };
         DefaultValue::Lit(lit)
     }
@@ -549,17 +816,42 @@ impl DefaultValue {
         match *self {
             DefaultValue::Lit(Lit::Str(ref value, ..)) if value.is_empty() => quote!(::std::string::String::new()),
             DefaultValue::Lit(ref lit@Lit::Str(..)) => quote!(#lit.to_owned()),
-            DefaultValue::Lit(Lit::ByteStr(ref value, ..)) if value.is_empty() => quote!(::std::vec::Vec::new()),
-            DefaultValue::Lit(ref lit@Lit::ByteStr(..)) => quote!(#lit.to_owned()),
+            DefaultValue::Lit(Lit::ByteStr(ref value, ..)) if value.is_empty() => quote!(::std::default::Default::default()),
+            DefaultValue::Lit(ref lit@Lit::ByteStr(..)) => quote!(::std::convert::From::from(&#lit[..])),
             DefaultValue::Lit(ref lit) => quote!(#lit),
             DefaultValue::Ident(ref ident) => quote!(#ident),
         }
     }
 }
 
+/// Converts the text form of a numeric or `bool` default value, as `protoc` writes it to
+/// `FieldDescriptorProto.default_value`, to a Rust expression. Negative values and the `inf`,
+/// `-inf` and `nan` floating point values can't be written as Rust literals.
+fn numeric_default(ty: &Ty, value: &str) -> Option<String> {
+    let float = |suffix: &str| match value {
+        "inf" => Some(format!("::std::{}::INFINITY", suffix)),
+        "-inf" => Some(format!("::std::{}::NEG_INFINITY", suffix)),
+        "nan" => Some(format!("::std::{}::NAN", suffix)),
+        _ => None,
+    };
+    match *ty {
+        Ty::Float => float("f32").or_else(|| value.parse::<f32>().ok().map(|v| format!("{:?}f32", v))),
+        Ty::Double => float("f64").or_else(|| value.parse::<f64>().ok().map(|v| format!("{:?}f64", v))),
+        Ty::Int32 | Ty::Sint32 | Ty::Sfixed32 => value.parse::<i32>().ok().map(|v| format!("{}i32", v)),
+        Ty::Int64 | Ty::Sint64 | Ty::Sfixed64 => value.parse::<i64>().ok().map(|v| format!("{}i64", v)),
+        Ty::Uint32 | Ty::Fixed32 => value.parse::<u32>().ok().map(|v| format!("{}u32", v)),
+        Ty::Uint64 | Ty::Fixed64 => value.parse::<u64>().ok().map(|v| format!("{}u64", v)),
+        Ty::Bool => value.parse::<bool>().ok().map(|v| v.to_string()),
+        _ => None,
+    }
+}
+
 impl quote::ToTokens for DefaultValue {
     fn to_tokens(&self, tokens: &mut Tokens) {
         match *self {
+            // Byte string literals are converted to slices, so that they can be compared to both
+            // `Vec<u8>` and `Bytes` values.
+            DefaultValue::Lit(ref lit@Lit::ByteStr(..)) => quote!(&#lit[..]).to_tokens(tokens),
             DefaultValue::Lit(ref lit) => lit.to_tokens(tokens),
             DefaultValue::Ident(ref ident) => ident.to_tokens(tokens),
         }
@@ -24,16 +24,34 @@ mod error {
 use error::*;
 
 mod field;
-use field::Field;
+use field::{
+    Field,
+    Names,
+};
 
-fn try_message(input: TokenStream) -> Result<TokenStream> {
-    let syn::DeriveInput { ident, generics, body, .. } = syn::parse_derive_input(&input.to_string())?;
-
-    if !generics.lifetimes.is_empty() ||
+/// Returns the lifetime parameter of a borrowed message or oneof type, or `None` if the type is
+/// not generic. Borrowed types may have a single lifetime parameter, and no other generics.
+fn borrowed_lifetime(kind: &str, generics: &syn::Generics) -> Result<Option<syn::Lifetime>> {
+    if generics.lifetimes.len() > 1 ||
        !generics.ty_params.is_empty() ||
        !generics.where_clause.predicates.is_empty() {
-        bail!("Message may not be derived for generic type");
+        bail!("{} may not be derived for generic type", kind);
+    }
+    match generics.lifetimes.first() {
+        Some(def) if !def.bounds.is_empty() => bail!("{} lifetime parameter may not have bounds", kind),
+        Some(def) => Ok(Some(def.lifetime.clone())),
+        None => Ok(None),
     }
+}
+
+fn try_message(input: TokenStream) -> Result<TokenStream> {
+    let syn::DeriveInput { ident, generics, body, attrs, .. } =
+        syn::parse_derive_input(&input.to_string())?;
+
+    // Messages with a lifetime parameter borrow their string and bytes fields from the buffer
+    // they are decoded from, so they implement `Merge` only for that buffer type.
+    let lifetime = borrowed_lifetime("Message", &generics)?;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
 
     let fields = match body {
         syn::Body::Struct(syn::VariantData::Struct(fields)) => fields,
@@ -42,30 +60,87 @@ fn try_message(input: TokenStream) -> Result<TokenStream> {
         syn::Body::Enum(..) => bail!("Message can not be derived for an enum"),
     };
 
+    // Messages annotated with `#[prost(custom_json)]` implement `JsonMessage` by hand.
+    let mut custom_json = false;
+    for attr in field::prost_attrs(attrs)? {
+        if field::word_attr("custom_json", &attr) {
+            field::set_bool(&mut custom_json, "duplicate custom_json attribute")?;
+        } else {
+            bail!("unknown attribute for message {}: {:?}", ident, attr);
+        }
+    }
+
+    // The field which retains unknown fields, if the message has one.
+    let mut unknown_fields = None;
+    // The field which holds extension fields, if the message has one.
+    let mut extension_set = None;
+    // Fields annotated with `#[prost(ignore)]`, which are not encoded or decoded.
+    let mut ignored_fields = Vec::new();
+
     let mut fields = fields.into_iter()
                            .enumerate()
                            .flat_map(|(idx, field)| {
                                let field_ident = field.ident
                                                        .unwrap_or_else(|| Ident::new(idx.to_string()));
-                               match Field::new(field.attrs) {
-                                   Ok(Some(field)) => Some(Ok((field_ident, field))),
-                                   Ok(None) => None,
+                               match field::is_unknown_fields(&field.attrs) {
+                                   Ok(true) => {
+                                       if unknown_fields.is_some() {
+                                           return Some(Err(Error::from(format!(
+                                               "message {} has multiple unknown_fields fields", ident))));
+                                       }
+                                       unknown_fields = Some(field_ident);
+                                       return None;
+                                   },
+                                   Ok(false) => (),
+                                   Err(err) => return Some(Err(err).chain_err(|| {
+                                       format!("invalid message field {}.{}",
+                                               ident, field_ident)
+                                   })),
+                               }
+                               match field::is_extension_set(&field.attrs) {
+                                   Ok(true) => {
+                                       if extension_set.is_some() {
+                                           return Some(Err(Error::from(format!(
+                                               "message {} has multiple extension_set fields", ident))));
+                                       }
+                                       extension_set = Some(field_ident);
+                                       return None;
+                                   },
+                                   Ok(false) => (),
+                                   Err(err) => return Some(Err(err).chain_err(|| {
+                                       format!("invalid message field {}.{}",
+                                               ident, field_ident)
+                                   })),
+                               }
+                               let names = match Names::new(&field.attrs, field_ident.as_ref()) {
+                                   Ok(names) => names,
+                                   Err(err) => return Some(Err(err).chain_err(|| {
+                                       format!("invalid message field {}.{}",
+                                               ident, field_ident)
+                                   })),
+                               };
+                               match Field::new(field.attrs, lifetime.is_some()) {
+                                   Ok(Some(field)) => Some(Ok((field_ident, field, names))),
+                                   Ok(None) => {
+                                       ignored_fields.push(field_ident);
+                                       None
+                                   },
                                    Err(err) => Some(Err(err).chain_err(|| {
                                        format!("invalid message field {}.{}",
                                                ident, field_ident)
                                    })),
                                }
                            })
-                           .collect::<Result<Vec<(Ident, Field)>>>()?;
+                           .collect::<Result<Vec<(Ident, Field, Names)>>>()?;
 
     // Sort the fields by tag number so that fields will be encoded in tag order.
     // TODO: This encodes oneof fields in the position of their lowest tag,
     // regardless of the currently occupied variant, is that consequential?
     // See: https://developers.google.com/protocol-buffers/docs/encoding#order
-    fields.sort_by_key(|&(_, ref field)| field.tags().into_iter().min().unwrap());
+    fields.sort_by_key(|&(_, ref field, _)| field.tags().into_iter().min().unwrap());
     let fields = fields;
 
-    let mut tags = fields.iter().flat_map(|&(_, ref field)| field.tags()).collect::<Vec<_>>();
+    let mut tags = fields.iter().flat_map(|&(_, ref field, _)| field.tags()).collect::<Vec<_>>();
     let num_tags = tags.len();
     tags.sort();
     tags.dedup();
@@ -75,46 +150,202 @@ fn try_message(input: TokenStream) -> Result<TokenStream> {
 
     let dummy_const = Ident::new(format!("_IMPL_MESSAGE_FOR_{}", ident));
 
-    let encoded_len = fields.iter()
-                            .map(|&(ref field_ident, ref field)| {
-                                field.encoded_len(&Ident::new(format!("self.{}", field_ident)))
-                            });
+    let mut encoded_len = fields.iter()
+                                .map(|&(ref field_ident, ref field, _)| {
+                                    field.encoded_len(&Ident::new(format!("self.{}", field_ident)))
+                                })
+                                .collect::<Vec<_>>();
+
+    let mut encoded_len_cached = fields.iter()
+                                       .map(|&(ref field_ident, ref field, _)| {
+                                           field.encoded_len_cached(&Ident::new(format!("self.{}", field_ident)))
+                                       })
+                                       .collect::<Vec<_>>();
+
+    let mut encode = fields.iter()
+                           .map(|&(ref field_ident, ref field, _)| {
+                               field.encode(&Ident::new(format!("self.{}", field_ident)))
+                           })
+                           .collect::<Vec<_>>();
+
+    // Extension fields are encoded after all known fields.
+    if let Some(ref field_ident) = extension_set {
+        encoded_len.push(quote!(self.#field_ident.encoded_len()));
+        encoded_len_cached.push(quote!(self.#field_ident.encoded_len()));
+        encode.push(quote!(self.#field_ident.encode(buf);));
+    }
 
-    let encode = fields.iter()
-                       .map(|&(ref field_ident, ref field)| {
-                           field.encode(&Ident::new(format!("self.{}", field_ident)))
-                       });
+    // Unknown fields are encoded after all known fields, and unrecognized tags are retained
+    // rather than skipped.
+    let merge_unknown = if let Some(ref field_ident) = unknown_fields {
+        encoded_len.push(quote!(self.#field_ident.encoded_len()));
+        encoded_len_cached.push(quote!(self.#field_ident.encoded_len()));
+        encode.push(quote!(self.#field_ident.encode(buf);));
+        quote!(self.#field_ident.merge_field(tag, wire_type, buf, ctx)?)
+    } else {
+        quote!(_prost::encoding::skip_field(wire_type, tag, buf, ctx)?)
+    };
 
-    let merge = fields.iter().map(|&(ref field_ident, ref field)| {
+    // Tags of extensions which are registered with the decode context are retained as extension
+    // fields, and all other unrecognized tags are treated as unknown fields.
+    let extendable = if let Some(ref field_ident) = extension_set {
+        if lifetime.is_some() {
+            bail!("borrowed message {} may not have an extension_set field", ident);
+        }
+        quote! {
+            #[automatically_derived]
+            impl _prost::Extendable for #ident {
+                fn extension_set(&self) -> &_prost::ExtensionSet {
+                    &self.#field_ident
+                }
+                fn extension_set_mut(&mut self) -> &mut _prost::ExtensionSet {
+                    &mut self.#field_ident
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+    let merge_unknown = if let Some(ref field_ident) = extension_set {
+        quote! {
+            if !self.#field_ident.merge_field::<Self, _>(tag, wire_type, buf, ctx)? {
+                #merge_unknown;
+            }
+        }
+    } else {
+        merge_unknown
+    };
+
+    let merge = fields.iter().map(|&(ref field_ident, ref field, _)| {
         let merge = field.merge(&Ident::new(format!("self.{}", field_ident)));
         let tags = field.tags().into_iter().map(|tag| quote!(#tag)).intersperse(quote!(|));
-        quote!(#(#tags)* => #merge.map_err(|error| map_err(stringify!(#field_ident), error))?,)
+        quote! {
+            #(#tags)* => #merge.map_err(|mut error| {
+                error.push(STRUCT_NAME, stringify!(#field_ident));
+                error
+            })?,
+        }
     });
 
     let default = fields.iter()
-                        .map(|&(ref field_ident, ref field)| {
+                        .map(|&(ref field_ident, ref field, _)| {
                             let value = field.default();
                             quote!(#field_ident: #value,)
-                        });
+                        })
+                        .chain(unknown_fields.iter()
+                                             .chain(&extension_set)
+                                             .chain(&ignored_fields)
+                                             .map(|field_ident| {
+                            quote!(#field_ident: ::std::default::Default::default(),)
+                        }));
+
+    // Borrowed messages can't be merged with `merge_from`, since their fields borrow from the
+    // buffer they were decoded from.
+    let merge_from = if lifetime.is_some() {
+        quote!()
+    } else {
+        let merge_from = fields.iter()
+                               .map(|&(ref field_ident, ref field, _)| {
+                                   field.merge_from(&Ident::new(format!("self.{}", field_ident)),
+                                                    &Ident::new(format!("other.{}", field_ident)))
+                               })
+                               .chain(unknown_fields.iter().chain(&extension_set).map(|field_ident| {
+                                   quote!(self.#field_ident.merge_from(&other.#field_ident);)
+                               }));
+        let clear = fields.iter()
+                          .map(|&(ref field_ident, ref field, _)| {
+                              field.clear(&Ident::new(format!("self.{}", field_ident)))
+                          })
+                          .chain(unknown_fields.iter().chain(&extension_set).map(|field_ident| {
+                              quote!(self.#field_ident.clear();)
+                          }))
+                          .chain(ignored_fields.iter().map(|field_ident| {
+                              quote!(self.#field_ident = ::std::default::Default::default();)
+                          }));
+        quote! {
+            fn merge_from(&mut self, other: &Self) {
+                #(#merge_from)*
+            }
+
+            fn clear(&mut self) {
+                #(#clear)*
+            }
+        }
+    };
+
+    // Messages without required fields or nested messages are always initialized, and use the
+    // default implementations of `is_initialized` and `missing_fields`.
+    let is_initialized = fields.iter()
+                               .filter_map(|&(ref field_ident, ref field, _)| {
+                                   field.is_initialized(&Ident::new(format!("self.{}", field_ident)))
+                               })
+                               .collect::<Vec<_>>();
+    let is_initialized = if is_initialized.is_empty() {
+        quote!()
+    } else {
+        let missing_fields = fields.iter().filter_map(|&(ref field_ident, ref field, ref names)| {
+            field.missing_fields(&Ident::new(format!("self.{}", field_ident)), &names.name)
+        });
+        quote! {
+            #[inline]
+            fn is_initialized(&self) -> bool {
+                true #(&& #is_initialized)*
+            }
+
+            fn missing_fields(&self,
+                              prefix: &str,
+                              missing: &mut ::std::vec::Vec<::std::string::String>) {
+                #(#missing_fields)*
+            }
+        }
+    };
 
     let methods = fields.iter()
-                        .flat_map(|&(ref field_ident, ref field)| field.methods(field_ident))
+                        .flat_map(|&(ref field_ident, ref field, _)| field.methods(field_ident))
                         .collect::<Vec<_>>();
     let methods = if methods.is_empty() {
         quote!()
     } else {
         quote! {
-            impl #ident {
+            impl #impl_generics #ident #ty_generics {
                 #(#methods)*
             }
         }
     };
 
+    // Borrowed messages have no JSON mapping.
+    let json = if lifetime.is_some() || custom_json {
+        quote!()
+    } else {
+        json_message(&ident, &fields)?
+    };
+
+    // Borrowed messages have no text format mapping.
+    let text = if lifetime.is_some() {
+        quote!()
+    } else {
+        text_message(&ident, &fields)
+    };
+
+    let merge_impl = match lifetime {
+        Some(ref lifetime) => quote! {
+            impl #impl_generics _prost::Merge<::std::io::Cursor<&#lifetime [u8]>> for #ident #ty_generics
+        },
+        None => quote! {
+            impl <B> _prost::Merge<B> for #ident where B: _bytes::Buf
+        },
+    };
+    let buf_ty = match lifetime {
+        Some(ref lifetime) => quote!(::std::io::Cursor<&#lifetime [u8]>),
+        None => quote!(B),
+    };
+
     let expanded = quote! {
         #[allow(
             non_upper_case_globals,
             unused_attributes,
             unused_imports,
+            unused_mut,
             unused_qualifications,
             unused_variables
         )]
@@ -124,54 +355,260 @@ fn try_message(input: TokenStream) -> Result<TokenStream> {
             extern crate bytes as _bytes;
 
             #[automatically_derived]
-            impl _prost::Message for #ident {
+            impl #impl_generics _prost::Message for #ident #ty_generics {
                 #[inline]
                 fn encode_raw<B>(&self, buf: &mut B) where B: _bytes::BufMut {
-                    #(#encode)*
+                    let cache = _prost::encoding::SizeCache::new();
+                    <Self as _prost::Message>::encoded_len_cached(self, &cache);
+                    <Self as _prost::Message>::encode_cached(self, buf, &cache);
                 }
 
                 #[inline]
-                fn merge<B>(&mut self, buf: &mut _bytes::Take<B>) -> ::std::io::Result<()> where B: _bytes::Buf {
-                    fn map_err(field: &str, cause: ::std::io::Error) -> ::std::io::Error {
-                        ::std::io::Error::new(cause.kind(),
-                                              format!(concat!("failed to decode field ",
-                                                              stringify!(#ident),
-                                                              ".{}: {}"),
-                                                      field, cause))
-                    }
-
-                    while _bytes::Buf::has_remaining(buf) {
-                        let (tag, wire_type) = _prost::encoding::decode_key(buf)?;
-                        match tag {
-                            #(#merge)*
-                            _ => _prost::encoding::skip_field(wire_type, buf)?,
-                        }
-                    }
-                    Ok(())
+                fn encode_cached<B>(&self, buf: &mut B, cache: &_prost::encoding::SizeCache)
+                where B: _bytes::BufMut {
+                    #(#encode)*
                 }
 
                 #[inline]
                 fn encoded_len(&self) -> usize {
                     0 #(+ #encoded_len)*
                 }
+
+                #[inline]
+                fn encoded_len_cached(&self, cache: &_prost::encoding::SizeCache) -> usize {
+                    0 #(+ #encoded_len_cached)*
+                }
+
+                #merge_from
+
+                #is_initialized
             }
 
             #[automatically_derived]
-            impl Default for #ident {
-                fn default() -> #ident {
+            #merge_impl {
+                #[inline]
+                fn merge_field(&mut self,
+                               tag: u32,
+                               wire_type: _prost::encoding::WireType,
+                               buf: &mut _bytes::Take<#buf_ty>,
+                               ctx: _prost::encoding::DecodeContext)
+                               -> ::std::result::Result<(), _prost::DecodeError> {
+                    const STRUCT_NAME: &'static str = stringify!(#ident);
+                    match tag {
+                        #(#merge)*
+                        _ => #merge_unknown,
+                    }
+                    Ok(())
+                }
+            }
+
+            #[automatically_derived]
+            impl #impl_generics Default for #ident #ty_generics {
+                fn default() -> #ident #ty_generics {
                     #ident {
                         #(#default)*
                     }
                 }
             }
-        };
 
-        #methods
+            #extendable
+
+            #json
+
+            #text
+
+            #methods
+        };
     };
 
     expanded.parse::<TokenStream>().map_err(|err| Error::from(format!("{:?}", err)))
 }
 
+/// Returns the implementation of `JsonMessage` for a message.
+/// Returns the implementation of `TextMessage` for a message.
+fn text_message(ident: &Ident, fields: &[(Ident, Field, Names)]) -> quote::Tokens {
+    let print_text = fields.iter().map(|&(ref field_ident, ref field, ref names)| {
+        field.text_print(&Ident::new(format!("self.{}", field_ident)), &names.name)
+    });
+
+    let merge_text = fields.iter()
+                           .enumerate()
+                           .filter(|&(_, &(_, ref field, _))| !is_oneof(field))
+                           .map(|(idx, &(ref field_ident, ref field, ref names))| {
+        let name = &names.name;
+        // Repeated fields may be split across any number of entries.
+        let check_duplicate = if field.is_repeated() {
+            quote!()
+        } else {
+            quote!(_prost::text::check_duplicate(&mut seen[#idx], &name)?;)
+        };
+        let merge = field.text_merge(&Ident::new(format!("self.{}", field_ident)));
+        quote! {
+            #name => {
+                #check_duplicate
+                #merge.map_err(|mut error| {
+                    error.push(STRUCT_NAME, stringify!(#field_ident));
+                    error
+                })?;
+            },
+        }
+    });
+
+    // Names which don't belong to a regular field are offered to each oneof field in turn.
+    let merge_oneofs = fields.iter()
+                             .enumerate()
+                             .filter_map(|(idx, &(ref field_ident, ref field, _))| match *field {
+        Field::Oneof(ref oneof) => {
+            let ty = &oneof.ty;
+            Some(quote! {
+                let value = match #ty::merge_text(&mut self.#field_ident, &name, value, &mut seen[#idx])
+                                      .map_err(|mut error| {
+                                          error.push(STRUCT_NAME, stringify!(#field_ident));
+                                          error
+                                      })? {
+                    ::std::option::Option::Some(value) => value,
+                    ::std::option::Option::None => continue,
+                };
+            })
+        },
+        _ => None,
+    });
+
+    let num_fields = fields.len();
+
+    quote! {
+        #[automatically_derived]
+        impl _prost::TextMessage for #ident {
+            fn print_text(&self, printer: &mut _prost::text::Printer) {
+                #(#print_text)*
+            }
+
+            fn merge_text_fields(&mut self, fields: ::std::vec::Vec<(::std::string::String, _prost::text::Value)>)
+                                 -> ::std::result::Result<(), _prost::text::TextError> {
+                const STRUCT_NAME: &'static str = stringify!(#ident);
+                let mut seen = [false; #num_fields];
+                for (name, value) in fields {
+                    match &name[..] {
+                        #(#merge_text)*
+                        _ => {
+                            #(#merge_oneofs)*
+                            return Err(_prost::text::unknown_field(&name));
+                        },
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn json_message(ident: &Ident, fields: &[(Ident, Field, Names)]) -> Result<quote::Tokens> {
+    let mut keys = fields.iter()
+                         .filter(|&&(_, ref field, _)| !is_oneof(field))
+                         .flat_map(|&(_, _, ref names)| names.keys())
+                         .collect::<Vec<_>>();
+    let num_keys = keys.len();
+    keys.sort();
+    keys.dedup();
+    if keys.len() != num_keys {
+        bail!("message {} has fields with duplicate JSON names", ident);
+    }
+
+    let to_json = fields.iter().map(|&(ref field_ident, ref field, ref names)| {
+        field.json_encode(&Ident::new(format!("self.{}", field_ident)), &names.json_name)
+    });
+
+    let merge_json = fields.iter()
+                           .enumerate()
+                           .filter(|&(_, &(_, ref field, _))| !is_oneof(field))
+                           .map(|(idx, &(ref field_ident, ref field, ref names))| {
+        let keys = names.keys().into_iter().map(|key| quote!(#key)).intersperse(quote!(|));
+        let merge = field.json_merge(&Ident::new(format!("self.{}", field_ident)));
+        quote! {
+            #(#keys)* => {
+                _prost::json::check_duplicate(&mut seen[#idx], &key)?;
+                #merge.map_err(|mut error| {
+                    error.push(STRUCT_NAME, stringify!(#field_ident));
+                    error
+                })?;
+            },
+        }
+    });
+
+    // Keys which don't belong to a regular field are offered to each oneof field in turn.
+    let merge_oneofs = fields.iter()
+                             .enumerate()
+                             .filter_map(|(idx, &(ref field_ident, ref field, _))| match *field {
+        Field::Oneof(ref oneof) => {
+            let ty = &oneof.ty;
+            Some(quote! {
+                let value = match #ty::merge_json(&mut self.#field_ident, &key, value, &mut seen[#idx])
+                                      .map_err(|mut error| {
+                                          error.push(STRUCT_NAME, stringify!(#field_ident));
+                                          error
+                                      })? {
+                    ::std::option::Option::Some(value) => value,
+                    ::std::option::Option::None => continue,
+                };
+            })
+        },
+        _ => None,
+    });
+
+    let num_fields = fields.len();
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl _prost::JsonMessage for #ident {
+            fn to_json(&self) -> ::std::result::Result<_prost::json::Value, _prost::json::JsonError> {
+                let mut fields = ::std::vec::Vec::new();
+                #(#to_json)*
+                Ok(_prost::json::Value::Object(fields))
+            }
+
+            fn merge_json(&mut self, value: _prost::json::Value)
+                          -> ::std::result::Result<(), _prost::json::JsonError> {
+                const STRUCT_NAME: &'static str = stringify!(#ident);
+                let mut seen = [false; #num_fields];
+                for (key, value) in _prost::json::object(value)? {
+                    match &key[..] {
+                        #(#merge_json)*
+                        _ => {
+                            #(#merge_oneofs)*
+                            return Err(_prost::json::unknown_field(&key));
+                        },
+                    }
+                }
+                Ok(())
+            }
+        }
+    })
+}
+
+fn is_oneof(field: &Field) -> bool {
+    if let Field::Oneof(..) = *field { true } else { false }
+}
+
+/// Converts an `UpperCamelCase` identifier to `snake_case`, the default name of a oneof variant.
+fn upper_camel_to_snake(ident: &str) -> String {
+    let mut snake = String::with_capacity(ident.len() + 4);
+    let mut break_on_cap = false;
+    for c in ident.chars() {
+        if c.is_uppercase() && break_on_cap {
+            snake.push('_');
+        }
+        break_on_cap = c.is_lowercase() || c.is_numeric();
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// Converts an `UpperCamelCase` identifier to `UPPER_SNAKE_CASE`, the default name of an
+/// enumeration variant.
+fn upper_camel_to_upper_snake(ident: &str) -> String {
+    upper_camel_to_snake(ident).to_uppercase()
+}
+
 #[proc_macro_derive(Message, attributes(prost))]
 pub fn message(input: TokenStream) -> TokenStream {
     try_message(input).unwrap()
@@ -193,10 +630,14 @@ pub fn enumeration(input: TokenStream) -> TokenStream {
         syn::Body::Enum(variants) => variants,
     };
 
-    let variants = variants.into_iter().map(|syn::Variant { ident: variant, data, discriminant, .. }| {
+    let variants = variants.into_iter().map(|syn::Variant { ident: variant, attrs, data, discriminant }| {
         if let syn::VariantData::Unit = data {
             if let Some(discriminant) = discriminant {
-                (variant, discriminant)
+                let name = match field::variant_name(&attrs) {
+                    Ok(name) => name.unwrap_or_else(|| upper_camel_to_upper_snake(variant.as_ref())),
+                    Err(err) => panic!("invalid enumeration variant {}::{}: {}", ident, variant, err),
+                };
+                (variant, discriminant, name)
             } else {
                 panic!("Enumeration variants must have a discriminant value: {}::{}", ident, variant);
             }
@@ -212,11 +653,36 @@ pub fn enumeration(input: TokenStream) -> TokenStream {
     let default = variants[0].0.clone();
 
     let dummy_const = Ident::new(format!("_IMPL_ENUMERATION_FOR_{}", ident));
-    let is_valid = variants.iter().map(|&(_, ref value)| quote!(#value => true));
-    let from = variants.iter().map(|&(ref variant, ref value)| quote!(#value => ::std::option::Option::Some(#ident::#variant)));
+    let is_valid = variants.iter().map(|&(_, ref value, _)| quote!(#value => true));
+    let from = variants.iter().map(|&(ref variant, ref value, _)| quote!(#value => ::std::option::Option::Some(#ident::#variant)));
+    let as_str_name = variants.iter().map(|&(ref variant, _, ref name)| quote!(#ident::#variant => #name));
+    let from_str_name = variants.iter().map(|&(ref variant, _, ref name)| quote!(#name => ::std::option::Option::Some(#ident::#variant)));
+    let value_name = variants.iter().map(|&(_, ref value, ref name)| quote!(#value => ::std::option::Option::Some(#name)));
+    let from_value_name = variants.iter().map(|&(_, ref value, ref name)| quote!(#name => ::std::option::Option::Some(#value)));
+    let values = variants.iter().map(|&(ref variant, _, _)| quote!(#ident::#variant));
 
     let is_valid_doc = format!("Returns `true` if `value` is a variant of `{}`.", ident);
     let from_i32_doc = format!("Converts an `i32` to a `{}`, or `None` if `value` is not a valid variant.", ident);
+    let as_str_name_doc = "Returns the Protobuf name of the variant, as it is written in the `.proto` file.";
+    let from_str_name_doc = format!("Converts a Protobuf variant name to a `{}`, or `None` if `name` is not the name of a variant.", ident);
+    let values_doc = format!("Returns an iterator over the variants of `{}`, in declaration order.", ident);
+
+    // `TryFrom` was stabilized in Rust 1.34, so the implementation is only derived with the
+    // `try-from` feature.
+    let try_from = if cfg!(feature = "try-from") {
+        quote! {
+            #[automatically_derived]
+            impl ::std::convert::TryFrom<i32> for #ident {
+                type Error = _prost::UnknownEnumValue;
+
+                fn try_from(value: i32) -> ::std::result::Result<#ident, _prost::UnknownEnumValue> {
+                    #ident::from_i32(value).ok_or(_prost::UnknownEnumValue::Value(value))
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
 
     let expanded = quote! {
         #[allow(non_upper_case_globals, unused_attributes, unused_qualifications)]
@@ -242,8 +708,47 @@ pub fn enumeration(input: TokenStream) -> TokenStream {
                         _ => ::std::option::Option::None,
                     }
                 }
+
+                #[doc=#as_str_name_doc]
+                pub fn as_str_name(&self) -> &'static str {
+                    match *self {
+                        #(#as_str_name,)*
+                    }
+                }
+
+                #[doc=#from_str_name_doc]
+                pub fn from_str_name(name: &str) -> ::std::option::Option<#ident> {
+                    match name {
+                        #(#from_str_name,)*
+                        _ => ::std::option::Option::None,
+                    }
+                }
+
+                #[doc=#values_doc]
+                pub fn values() -> ::std::iter::Cloned<::std::slice::Iter<'static, #ident>> {
+                    const VALUES: &'static [#ident] = &[#(#values),*];
+                    VALUES.iter().cloned()
+                }
             }
 
+            #[automatically_derived]
+            impl ::std::fmt::Display for #ident {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    f.write_str(self.as_str_name())
+                }
+            }
+
+            #[automatically_derived]
+            impl ::std::str::FromStr for #ident {
+                type Err = _prost::UnknownEnumValue;
+
+                fn from_str(name: &str) -> ::std::result::Result<#ident, _prost::UnknownEnumValue> {
+                    #ident::from_str_name(name).ok_or_else(|| _prost::UnknownEnumValue::Name(name.to_string()))
+                }
+            }
+
+            #try_from
+
             #[automatically_derived]
             impl ::std::default::Default for #ident {
                 fn default() -> #ident {
@@ -257,6 +762,27 @@ pub fn enumeration(input: TokenStream) -> TokenStream {
                     value as i32
                 }
             }
+
+            #[automatically_derived]
+            impl _prost::Enumeration for #ident {
+                fn from_i32(value: i32) -> ::std::option::Option<#ident> {
+                    #ident::from_i32(value)
+                }
+
+                fn value_name(value: i32) -> ::std::option::Option<&'static str> {
+                    match value {
+                        #(#value_name,)*
+                        _ => ::std::option::Option::None,
+                    }
+                }
+
+                fn from_value_name(name: &str) -> ::std::option::Option<i32> {
+                    match name {
+                        #(#from_value_name,)*
+                        _ => ::std::option::Option::None,
+                    }
+                }
+            }
         };
     };
 
@@ -266,11 +792,9 @@ pub fn enumeration(input: TokenStream) -> TokenStream {
 fn try_oneof(input: TokenStream) -> Result<TokenStream> {
     let syn::DeriveInput { ident, generics, body, .. } = syn::parse_derive_input(&input.to_string())?;
 
-    if !generics.lifetimes.is_empty() ||
-       !generics.ty_params.is_empty() ||
-       !generics.where_clause.predicates.is_empty() {
-        panic!("Oneof may not be derived for generic type");
-    }
+    // Oneofs with a lifetime parameter belong to borrowed messages.
+    let lifetime = borrowed_lifetime("Oneof", &generics)?;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
 
     let variants = match body {
         syn::Body::Enum(variants) => variants,
@@ -286,8 +810,12 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream> {
                 bail!("invalid oneof variant {}::{}: oneof variants must have a single field",
                       ident, variant_ident);
             }
-            match Field::new_oneof(attrs) {
-                Ok(Some(field)) => Ok((variant_ident, field)),
+            let names = match Names::new(&attrs, &upper_camel_to_snake(variant_ident.as_ref())) {
+                Ok(names) => names,
+                Err(err) => bail!("invalid oneof variant {}::{}: {}", ident, variant_ident, err),
+            };
+            match Field::new_oneof(attrs, lifetime.is_some()) {
+                Ok(Some(field)) => Ok((variant_ident, field, names)),
                 Ok(None) => bail!("invalid oneof variant {}::{}: oneof variants may not be ignored",
                                   ident, variant_ident),
                 Err(err) => bail!("invalid oneof variant {}::{}: {}", ident, variant_ident, err),
@@ -296,9 +824,9 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream> {
             bail!("invalid oneof variant {}::{}: oneof variants must have a single field",
                   ident, variant_ident);
         }
-    }).collect::<Result<Vec<(Ident, Field)>>>()?;
+    }).collect::<Result<Vec<(Ident, Field, Names)>>>()?;
 
-    let mut tags = fields.iter().flat_map(|&(ref variant_ident, ref field)| -> Result<u32> {
+    let mut tags = fields.iter().flat_map(|&(ref variant_ident, ref field, _)| -> Result<u32> {
         if field.tags().len() > 1 {
             bail!("invalid oneof variant {}::{}: oneof variants may only have a single tag",
                   ident, variant_ident);
@@ -313,27 +841,115 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream> {
 
     let dummy_const = Ident::new(format!("_IMPL_ONEOF_FOR_{}", ident));
 
-    let encode = fields.iter().map(|&(ref variant_ident, ref field)| {
+    let encode = fields.iter().map(|&(ref variant_ident, ref field, _)| {
         let encode = field.encode(&Ident::new("*value"));
         quote!(#ident::#variant_ident(ref value) => { #encode })
     });
 
-    let merge = fields.iter().map(|&(ref variant_ident, ref field)| {
+    // A value is merged into the existing value if the oneof is already set to the same variant,
+    // so that repeated occurrences of a message variant are merged.
+    let merge = fields.iter().map(|&(ref variant_ident, ref field, _)| {
         let tag = field.tags()[0];
+        let merge_existing = field.merge(&Ident::new("*value"));
         let merge = field.merge(&Ident::new("value"));
         quote! {
-            #tag => {
-                let mut value = ::std::default::Default::default();
-                #merge.map(|_| *field = ::std::option::Option::Some(#ident::#variant_ident(value)))
+            #tag => match *field {
+                ::std::option::Option::Some(#ident::#variant_ident(ref mut value)) => #merge_existing,
+                _ => {
+                    let mut value = ::std::default::Default::default();
+                    #merge.map(|_| *field = ::std::option::Option::Some(#ident::#variant_ident(value)))
+                },
             }
         }
     });
 
-    let encoded_len = fields.iter().map(|&(ref variant_ident, ref field)| {
+    let encoded_len = fields.iter().map(|&(ref variant_ident, ref field, _)| {
         let encoded_len = field.encoded_len(&Ident::new("*value"));
         quote!(#ident::#variant_ident(ref value) => #encoded_len)
     });
 
+    let encoded_len_cached = fields.iter().map(|&(ref variant_ident, ref field, _)| {
+        let encoded_len = field.encoded_len_cached(&Ident::new("*value"));
+        quote!(#ident::#variant_ident(ref value) => #encoded_len)
+    });
+
+    // Message variants are merged into a message variant of the same kind, and other variants
+    // replace the existing value.
+    let merge_from = if lifetime.is_some() {
+        quote!()
+    } else {
+        let merge_from = fields.iter().map(|&(ref variant_ident, ref field, _)| {
+            match *field {
+                Field::Message(..) => quote! {
+                    #ident::#variant_ident(ref msg) => {
+                        if let ::std::option::Option::Some(#ident::#variant_ident(ref mut value)) = *field {
+                            _prost::Message::merge_from(value, msg);
+                            return;
+                        }
+                        let mut value = ::std::default::Default::default();
+                        _prost::Message::merge_from(&mut value, msg);
+                        *field = ::std::option::Option::Some(#ident::#variant_ident(value));
+                    }
+                },
+                _ => quote! {
+                    #ident::#variant_ident(ref value) => {
+                        *field = ::std::option::Option::Some(#ident::#variant_ident(value.clone()));
+                    }
+                },
+            }
+        });
+        quote! {
+            pub fn merge_from(field: &mut ::std::option::Option<#ident>, other: &#ident) {
+                match *other {
+                    #(#merge_from,)*
+                }
+            }
+        }
+    };
+
+    let is_initialized = fields.iter().map(|&(ref variant_ident, ref field, _)| {
+        let is_initialized = field.is_initialized(&Ident::new("*value")).unwrap_or_else(|| quote!(true));
+        quote!(#ident::#variant_ident(ref value) => #is_initialized)
+    });
+
+    let missing_fields = fields.iter().map(|&(ref variant_ident, ref field, ref names)| {
+        let missing_fields = field.missing_fields(&Ident::new("*value"), &names.name);
+        quote!(#ident::#variant_ident(ref value) => { #missing_fields })
+    });
+
+    // Borrowed messages have no JSON mapping.
+    let json = if lifetime.is_some() {
+        quote!()
+    } else {
+        json_oneof(&ident, &fields)
+    };
+
+    let text = if lifetime.is_some() {
+        quote!()
+    } else {
+        text_oneof(&ident, &fields)
+    };
+
+    let merge_signature = match lifetime {
+        Some(ref lifetime) => quote! {
+            pub fn merge(field: &mut ::std::option::Option<#ident #ty_generics>,
+                         tag: u32,
+                         wire_type: _prost::encoding::WireType,
+                         buf: &mut _bytes::Take<::std::io::Cursor<&#lifetime [u8]>>,
+                         ctx: _prost::encoding::DecodeContext)
+                         -> ::std::result::Result<(), _prost::DecodeError>
+        },
+        None => quote! {
+            pub fn merge<B>(field: &mut ::std::option::Option<#ident>,
+                            tag: u32,
+                            wire_type: _prost::encoding::WireType,
+                            buf: &mut _bytes::Take<B>,
+                            ctx: _prost::encoding::DecodeContext)
+                            -> ::std::result::Result<(), _prost::DecodeError>
+            where B: _bytes::Buf
+        },
+    };
+
     let expanded = quote! {
         #[allow(
             non_upper_case_globals,
@@ -346,19 +962,15 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream> {
             extern crate bytes as _bytes;
             extern crate prost as _prost;
 
-            impl #ident {
-                pub fn encode<B>(&self, buf: &mut B) where B: _bytes::BufMut {
+            impl #impl_generics #ident #ty_generics {
+                pub fn encode<B>(&self, buf: &mut B, cache: &_prost::encoding::SizeCache)
+                where B: _bytes::BufMut {
                     match *self {
                         #(#encode,)*
                     }
                 }
 
-                pub fn merge<B>(field: &mut ::std::option::Option<#ident>,
-                                tag: u32,
-                                wire_type: _prost::encoding::WireType,
-                                buf: &mut _bytes::Take<B>)
-                                -> ::std::io::Result<()>
-                where B: _bytes::Buf {
+                #merge_signature {
                     match tag {
                         #(#merge,)*
                         _ => unreachable!(concat!("invalid ", stringify!(#ident), " tag: {}"), tag),
@@ -370,13 +982,130 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream> {
                         #(#encoded_len,)*
                     }
                 }
+
+                pub fn encoded_len_cached(&self, cache: &_prost::encoding::SizeCache) -> usize {
+                    match *self {
+                        #(#encoded_len_cached,)*
+                    }
+                }
+
+                pub fn is_initialized(&self) -> bool {
+                    match *self {
+                        #(#is_initialized,)*
+                    }
+                }
+
+                pub fn missing_fields(&self,
+                                      prefix: &str,
+                                      missing: &mut ::std::vec::Vec<::std::string::String>) {
+                    match *self {
+                        #(#missing_fields,)*
+                    }
+                }
+
+                #merge_from
             }
+
+            #json
+
+            #text
         };
     };
 
     expanded.parse::<TokenStream>().map_err(|err| Error::from(format!("{:?}", err)))
 }
 
+/// Returns the JSON conversion functions of a oneof, which are called by the `JsonMessage`
+/// implementation of the containing message.
+fn json_oneof(ident: &Ident, fields: &[(Ident, Field, Names)]) -> quote::Tokens {
+    let to_json = fields.iter().map(|&(ref variant_ident, ref field, ref names)| {
+        let encode = field.json_encode(&Ident::new("*value"), &names.json_name);
+        quote!(#ident::#variant_ident(ref value) => { #encode })
+    });
+
+    let merge_json = fields.iter().map(|&(ref variant_ident, ref field, ref names)| {
+        let keys = names.keys().into_iter().map(|key| quote!(#key)).intersperse(quote!(|));
+        let from_json = field.json_oneof();
+        quote! {
+            #(#keys)* => {
+                // A `null` value leaves the oneof unset, and doesn't conflict with other fields.
+                if let ::std::option::Option::Some(value) = #from_json? {
+                    _prost::json::check_duplicate(seen, key)?;
+                    *field = ::std::option::Option::Some(#ident::#variant_ident(value));
+                }
+                Ok(::std::option::Option::None)
+            }
+        }
+    });
+
+    quote! {
+        impl #ident {
+            pub fn to_json(&self, fields: &mut ::std::vec::Vec<(::std::string::String, _prost::json::Value)>)
+                           -> ::std::result::Result<(), _prost::json::JsonError> {
+                match *self {
+                    #(#to_json,)*
+                }
+                Ok(())
+            }
+
+            pub fn merge_json(field: &mut ::std::option::Option<#ident>,
+                              key: &str,
+                              value: _prost::json::Value,
+                              seen: &mut bool)
+                              -> ::std::result::Result<::std::option::Option<_prost::json::Value>,
+                                                       _prost::json::JsonError> {
+                match key {
+                    #(#merge_json,)*
+                    _ => Ok(::std::option::Option::Some(value)),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the text format conversion functions of a oneof, which are called by the
+/// `TextMessage` implementation of the containing message.
+fn text_oneof(ident: &Ident, fields: &[(Ident, Field, Names)]) -> quote::Tokens {
+    let print_text = fields.iter().map(|&(ref variant_ident, ref field, ref names)| {
+        let print = field.text_print(&Ident::new("*value"), &names.name);
+        quote!(#ident::#variant_ident(ref value) => { #print })
+    });
+
+    let merge_text = fields.iter().map(|&(ref variant_ident, ref field, ref names)| {
+        let name = &names.name;
+        let from_text = field.text_oneof();
+        quote! {
+            #name => {
+                _prost::text::check_duplicate(seen, name)?;
+                *field = ::std::option::Option::Some(#ident::#variant_ident(#from_text?));
+                Ok(::std::option::Option::None)
+            }
+        }
+    });
+
+    quote! {
+        impl #ident {
+            pub fn print_text(&self, printer: &mut _prost::text::Printer) {
+                match *self {
+                    #(#print_text,)*
+                }
+            }
+
+            pub fn merge_text(field: &mut ::std::option::Option<#ident>,
+                              name: &str,
+                              value: _prost::text::Value,
+                              seen: &mut bool)
+                              -> ::std::result::Result<::std::option::Option<_prost::text::Value>,
+                                                       _prost::text::TextError> {
+                match name {
+                    #(#merge_text,)*
+                    _ => Ok(::std::option::Option::Some(value)),
+                }
+            }
+        }
+    }
+}
+
 #[proc_macro_derive(Oneof, attributes(prost))]
 pub fn oneof(input: TokenStream) -> TokenStream {
     try_oneof(input).unwrap()
@@ -0,0 +1,482 @@
+//! The Protobuf JSON mapping of the well-known types which have a special JSON representation.
+
+use prost::JsonMessage;
+use prost::json::{
+    self,
+    JsonError,
+    JsonScalar,
+};
+
+use super::*;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Formats a number of nanoseconds as a fraction of a second with 0, 3, 6 or 9 digits.
+fn format_nanos(nanos: i32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos % 1_000_000 == 0 {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+/// Parses a fixed-width run of ASCII digits.
+fn parse_digits(bytes: &[u8]) -> Option<i64> {
+    let mut value = 0;
+    for &b in bytes {
+        if b < b'0' || b > b'9' {
+            return None;
+        }
+        value = value * 10 + (b - b'0') as i64;
+    }
+    Some(value)
+}
+
+/// Parses the fraction of a second which follows the decimal point, returning the number of
+/// nanoseconds and the number of bytes consumed. The fraction must have between 1 and 9 digits.
+fn parse_nanos(bytes: &[u8]) -> Option<(i32, usize)> {
+    let len = bytes.iter().take_while(|&&b| b >= b'0' && b <= b'9').count();
+    if len == 0 || len > 9 {
+        return None;
+    }
+    let mut nanos = parse_digits(&bytes[..len])? as i32;
+    for _ in len..9 {
+        nanos *= 10;
+    }
+    Some((nanos, len))
+}
+
+/// Returns the number of days from the Unix epoch to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_of_year = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_of_year + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date of the proleptic Gregorian calendar which is a number of days from the Unix
+/// epoch, as a (year, month, day) tuple.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+                       - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_of_year = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_of_year + 2) / 5 + 1;
+    let month = if month_of_year < 10 { month_of_year + 3 } else { month_of_year - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses an RFC 3339 date-time, such as `1972-01-01T10:00:20.021-05:00`.
+fn parse_timestamp(s: &str) -> Option<Timestamp> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' ||
+       bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    let year = parse_digits(&bytes[0..4])?;
+    let month = parse_digits(&bytes[5..7])?;
+    let day = parse_digits(&bytes[8..10])?;
+    let hour = parse_digits(&bytes[11..13])?;
+    let minute = parse_digits(&bytes[14..16])?;
+    let second = parse_digits(&bytes[17..19])?;
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) ||
+       hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let mut rest = &bytes[19..];
+    let mut nanos = 0;
+    if rest[0] == b'.' {
+        let (n, len) = parse_nanos(&rest[1..])?;
+        nanos = n;
+        rest = &rest[1 + len..];
+    }
+
+    let offset = match rest {
+        b"Z" => 0,
+        _ if rest.len() == 6 && rest[3] == b':' => {
+            let offset = parse_digits(&rest[1..3])? * 3600 + parse_digits(&rest[4..6])? * 60;
+            match rest[0] {
+                b'+' => offset,
+                b'-' => -offset,
+                _ => return None,
+            }
+        },
+        _ => return None,
+    };
+
+    let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY
+                + hour * 3600 + minute * 60 + second - offset;
+    if seconds < MIN_TIMESTAMP_SECONDS || seconds > MAX_TIMESTAMP_SECONDS {
+        return None;
+    }
+    Some(Timestamp {
+        seconds: seconds,
+        nanos: nanos,
+    })
+}
+
+/// Timestamps are encoded as RFC 3339 strings in UTC, such as `1972-01-01T15:00:20.021Z`.
+impl JsonMessage for Timestamp {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        if self.seconds < MIN_TIMESTAMP_SECONDS || self.seconds > MAX_TIMESTAMP_SECONDS ||
+           self.nanos < 0 || self.nanos >= NANOS_PER_SECOND {
+            return Err(JsonError::new("timestamp is out of range"));
+        }
+        let days = if self.seconds >= 0 {
+            self.seconds / SECONDS_PER_DAY
+        } else {
+            (self.seconds - SECONDS_PER_DAY + 1) / SECONDS_PER_DAY
+        };
+        let seconds_of_day = self.seconds - days * SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+        Ok(json::Value::String(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+                                       year, month, day,
+                                       seconds_of_day / 3600,
+                                       seconds_of_day / 60 % 60,
+                                       seconds_of_day % 60,
+                                       format_nanos(self.nanos))))
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        let s = String::from_json(value)?;
+        *self = parse_timestamp(&s).ok_or_else(|| {
+            JsonError::new(format!("invalid timestamp: {}", s))
+        })?;
+        Ok(())
+    }
+}
+
+/// Parses a duration in seconds with an `s` suffix, such as `-1.5s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let bytes = s.as_bytes();
+    if bytes.last() != Some(&b's') {
+        return None;
+    }
+    let (negative, bytes) = match bytes[0] {
+        b'-' => (true, &bytes[1..bytes.len() - 1]),
+        _ => (false, &bytes[..bytes.len() - 1]),
+    };
+
+    let len = bytes.iter().take_while(|&&b| b >= b'0' && b <= b'9').count();
+    if len == 0 || len > 12 {
+        return None;
+    }
+    let seconds = parse_digits(&bytes[..len])?;
+    let nanos = match &bytes[len..] {
+        b"" => 0,
+        rest if rest[0] == b'.' => {
+            match parse_nanos(&rest[1..]) {
+                Some((nanos, len)) if len + 1 == rest.len() => nanos,
+                _ => return None,
+            }
+        },
+        _ => return None,
+    };
+    if seconds > MAX_DURATION_SECONDS {
+        return None;
+    }
+
+    Some(if negative {
+        Duration { seconds: -seconds, nanos: -nanos }
+    } else {
+        Duration { seconds: seconds, nanos: nanos }
+    })
+}
+
+/// Durations are encoded as strings of seconds with an `s` suffix, such as `1.000340012s`.
+impl JsonMessage for Duration {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        if self.seconds < -MAX_DURATION_SECONDS || self.seconds > MAX_DURATION_SECONDS ||
+           self.nanos <= -NANOS_PER_SECOND || self.nanos >= NANOS_PER_SECOND ||
+           (self.seconds < 0 && self.nanos > 0) || (self.seconds > 0 && self.nanos < 0) {
+            return Err(JsonError::new("duration is out of range"));
+        }
+        let sign = if self.seconds < 0 || self.nanos < 0 { "-" } else { "" };
+        Ok(json::Value::String(format!("{}{}{}s",
+                                       sign,
+                                       self.seconds.abs(),
+                                       format_nanos(self.nanos.abs()))))
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        let s = String::from_json(value)?;
+        *self = parse_duration(&s).ok_or_else(|| {
+            JsonError::new(format!("invalid duration: {}", s))
+        })?;
+        Ok(())
+    }
+}
+
+/// Converts a `snake_case` field mask path to `lowerCamelCase`, or returns `None` if the path
+/// would not survive the conversion back to `snake_case`.
+fn path_to_json(path: &str) -> Option<String> {
+    let mut json = String::with_capacity(path.len());
+    let mut capitalize_next = false;
+    for c in path.chars() {
+        if c.is_uppercase() {
+            return None;
+        } else if capitalize_next {
+            if !c.is_lowercase() {
+                return None;
+            }
+            json.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else if c == '_' {
+            capitalize_next = true;
+        } else {
+            json.push(c);
+        }
+    }
+    if capitalize_next {
+        None
+    } else {
+        Some(json)
+    }
+}
+
+/// Converts a `lowerCamelCase` field mask path to `snake_case`.
+fn path_from_json(json: &str) -> Option<String> {
+    let mut path = String::with_capacity(json.len() + 4);
+    for c in json.chars() {
+        if c == '_' {
+            return None;
+        } else if c.is_uppercase() {
+            path.push('_');
+            path.extend(c.to_lowercase());
+        } else {
+            path.push(c);
+        }
+    }
+    Some(path)
+}
+
+/// Field masks are encoded as a comma separated list of `lowerCamelCase` paths, such as
+/// `"f.fooBar,h"`.
+impl JsonMessage for FieldMask {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        let mut paths = Vec::with_capacity(self.paths.len());
+        for path in &self.paths {
+            match path_to_json(path) {
+                Some(path) => paths.push(path),
+                None => return Err(JsonError::new(format!("invalid field mask path: {}", path))),
+            }
+        }
+        Ok(json::Value::String(paths.join(",")))
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        let s = String::from_json(value)?;
+        if s.is_empty() {
+            return Ok(());
+        }
+        for path in s.split(',') {
+            match path_from_json(path) {
+                Some(path) => self.paths.push(path),
+                None => return Err(JsonError::new(format!("invalid field mask path: {}", path))),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Structs are encoded as JSON objects.
+impl JsonMessage for Struct {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        json::btree_map::to_json(&self.fields, json::message::to_json)
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        json::btree_map::merge(&mut self.fields, value, json::message::from_json)
+    }
+}
+
+/// Values are encoded as the JSON value of their kind.
+impl JsonMessage for Value {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        match self.kind {
+            None => Err(JsonError::new("value has no kind")),
+            Some(value::Kind::NullValue(_)) => Ok(json::Value::Null),
+            Some(value::Kind::NumberValue(value)) if !value.is_finite() => {
+                Err(JsonError::new(format!("value is not a finite number: {}", value)))
+            },
+            Some(value::Kind::NumberValue(ref value)) => Ok(value.to_json()),
+            Some(value::Kind::StringValue(ref value)) => Ok(value.to_json()),
+            Some(value::Kind::BoolValue(ref value)) => Ok(value.to_json()),
+            Some(value::Kind::StructValue(ref value)) => value.to_json(),
+            Some(value::Kind::ListValue(ref value)) => value.to_json(),
+        }
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        let kind = match value {
+            json::Value::Null => value::Kind::NullValue(NullValue::NullValue as i32),
+            json::Value::Bool(value) => value::Kind::BoolValue(value),
+            value @ json::Value::Number(..) => value::Kind::NumberValue(f64::from_json(value)?),
+            json::Value::String(value) => value::Kind::StringValue(value),
+            value @ json::Value::Array(..) => value::Kind::ListValue(json::message::from_json(value)?),
+            value @ json::Value::Object(..) => value::Kind::StructValue(json::message::from_json(value)?),
+        };
+        self.kind = Some(kind);
+        Ok(())
+    }
+
+    fn null_json() -> Option<Value> {
+        Some(Value {
+            kind: Some(value::Kind::NullValue(NullValue::NullValue as i32)),
+        })
+    }
+}
+
+/// List values are encoded as JSON arrays.
+impl JsonMessage for ListValue {
+    fn to_json(&self) -> Result<json::Value, JsonError> {
+        json::message::to_json_repeated(&self.values)
+    }
+
+    fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+        json::message::merge_repeated(&mut self.values, value)
+    }
+}
+
+/// Wrapper messages are encoded as the JSON value of the wrapped value.
+macro_rules! wrapper {
+    ($ty:ident) => (
+        impl JsonMessage for $ty {
+            fn to_json(&self) -> Result<json::Value, JsonError> {
+                Ok(JsonScalar::to_json(&self.value))
+            }
+
+            fn merge_json(&mut self, value: json::Value) -> Result<(), JsonError> {
+                self.value = JsonScalar::from_json(value)?;
+                Ok(())
+            }
+        }
+    )
+}
+
+wrapper!(DoubleValue);
+wrapper!(FloatValue);
+wrapper!(Int64Value);
+wrapper!(UInt64Value);
+wrapper!(Int32Value);
+wrapper!(UInt32Value);
+wrapper!(BoolValue);
+wrapper!(StringValue);
+wrapper!(BytesValue);
+
+#[cfg(test)]
+mod test {
+    use prost::JsonMessage;
+
+    use super::*;
+
+    #[test]
+    fn timestamp_json() {
+        let cases = [
+            ("1970-01-01T00:00:00Z", 0, 0),
+            ("1970-01-01T00:00:00.010Z", 0, 10_000_000),
+            ("1970-01-01T00:00:00.000010Z", 0, 10_000),
+            ("1970-01-01T00:00:00.000000010Z", 0, 10),
+            ("1969-12-31T23:59:59Z", -1, 0),
+            ("2000-02-29T12:34:56Z", 951_827_696, 0),
+            ("0001-01-01T00:00:00Z", MIN_TIMESTAMP_SECONDS, 0),
+            ("9999-12-31T23:59:59.999999999Z", MAX_TIMESTAMP_SECONDS, 999_999_999),
+        ];
+        for &(json, seconds, nanos) in &cases {
+            let timestamp = Timestamp { seconds: seconds, nanos: nanos };
+            assert_eq!(format!("\"{}\"", json), timestamp.encode_json().unwrap());
+            assert_eq!(timestamp, Timestamp::decode_json(&format!("\"{}\"", json)).unwrap());
+        }
+
+        assert_eq!(Timestamp { seconds: 1, nanos: 0 },
+                   Timestamp::decode_json("\"1970-01-01T08:00:01+08:00\"").unwrap());
+        assert_eq!(Timestamp { seconds: 0, nanos: 100_000_000 },
+                   Timestamp::decode_json("\"1969-12-31T23:00:00.1-01:00\"").unwrap());
+
+        for json in &["\"1970-01-01t00:00:00Z\"", "\"1970-01-01T00:00:00z\"",
+                      "\"1970-01-01T00:00:00\"", "\"1970-01-01 00:00:00Z\"",
+                      "\"0000-01-01T00:00:00Z\"", "\"10000-01-01T00:00:00Z\"",
+                      "\"1970-02-30T00:00:00Z\"", "\"1970-01-01T00:00:00.Z\"",
+                      "\"1970-01-01T00:00:00.0000000001Z\"", "0"] {
+            assert!(Timestamp::decode_json(json).is_err(), "{}", json);
+        }
+        assert!(Timestamp { seconds: MIN_TIMESTAMP_SECONDS - 1, nanos: 0 }.encode_json().is_err());
+    }
+
+    #[test]
+    fn duration_json() {
+        let cases = [
+            ("0s", 0, 0),
+            ("1.500s", 1, 500_000_000),
+            ("-1.500s", -1, -500_000_000),
+            ("-0.000001s", 0, -1_000),
+            ("315576000000.999999999s", MAX_DURATION_SECONDS, 999_999_999),
+        ];
+        for &(json, seconds, nanos) in &cases {
+            let duration = Duration { seconds: seconds, nanos: nanos };
+            assert_eq!(format!("\"{}\"", json), duration.encode_json().unwrap());
+            assert_eq!(duration, Duration::decode_json(&format!("\"{}\"", json)).unwrap());
+        }
+
+        assert_eq!(Duration { seconds: 1, nanos: 500_000_000 }, Duration::decode_json("\"1.5s\"").unwrap());
+
+        for json in &["\"1\"", "\"s\"", "\"+1s\"", "\"1.s\"", "\"315576000001s\"", "\"1.0000000001s\""] {
+            assert!(Duration::decode_json(json).is_err(), "{}", json);
+        }
+        assert!(Duration { seconds: 1, nanos: -1 }.encode_json().is_err());
+    }
+
+    #[test]
+    fn field_mask_json() {
+        let mask = FieldMask { paths: vec!["foo_bar.baz".to_string(), "qux".to_string()] };
+        assert_eq!("\"fooBar.baz,qux\"", mask.encode_json().unwrap());
+        assert_eq!(mask, FieldMask::decode_json("\"fooBar.baz,qux\"").unwrap());
+        assert_eq!(FieldMask::default(), FieldMask::decode_json("\"\"").unwrap());
+
+        assert!(FieldMask { paths: vec!["fooBar".to_string()] }.encode_json().is_err());
+        assert!(FieldMask { paths: vec!["foo__bar".to_string()] }.encode_json().is_err());
+        assert!(FieldMask::decode_json("\"foo_bar\"").is_err());
+    }
+
+    #[test]
+    fn value_json() {
+        let json = r#"{"a":null,"b":[true,1.5,"c"],"d":{}}"#;
+        let value = Value::decode_json(json).unwrap();
+        assert_eq!(json, value.encode_json().unwrap());
+
+        assert!(Value::from(JsonValue::Number(::std::f64::NAN)).encode_json().is_err());
+        assert!(Value::default().encode_json().is_err());
+    }
+
+    #[test]
+    fn wrapper_json() {
+        assert_eq!("\"1\"", Int64Value { value: 1 }.encode_json().unwrap());
+        assert_eq!(UInt32Value { value: 7 }, UInt32Value::decode_json("7").unwrap());
+        assert_eq!(BytesValue { value: b"foo".to_vec() }, BytesValue::decode_json("\"Zm9v\"").unwrap());
+    }
+}
@@ -0,0 +1,361 @@
+#![doc(html_root_url = "https://docs.rs/prost-types/0.1.1")]
+
+//! Protocol Buffers well-known types.
+//!
+//! Rust types for the messages defined in the `google/protobuf/timestamp.proto`,
+//! `duration.proto`, `struct.proto`, `wrappers.proto`, `empty.proto` and `field_mask.proto`
+//! files. `prost-codegen` refers to these types for fields of the well-known types, rather than
+//! generating them. Map fields are generated as `BTreeMap`s, so that the encoding of a `Struct`
+//! is deterministic.
+//!
+//! See the [Protobuf reference][1] for more information about the well-known types.
+//!
+//! [1]: https://developers.google.com/protocol-buffers/docs/reference/google.protobuf
+
+#[macro_use]
+extern crate prost_derive;
+
+extern crate bytes;
+extern crate prost;
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::time;
+
+include!("protobuf.rs");
+
+mod json;
+
+const NANOS_PER_SECOND: i32 = 1_000_000_000;
+
+/// The maximum number of seconds in a `Duration`, approximately 10,000 years.
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+/// The number of seconds from the Unix epoch to 0001-01-01T00:00:00Z.
+const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+
+/// The number of seconds from the Unix epoch to 9999-12-31T23:59:59Z.
+const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+impl Duration {
+
+    /// Normalizes the duration to a canonical format: `nanos` is in the range
+    /// `(-1_000_000_000, 1_000_000_000)`, and has the same sign as `seconds` when `seconds` is
+    /// non-zero. Durations which are out of the range of an `i64` number of seconds saturate.
+    pub fn normalize(&mut self) {
+        if self.nanos <= -NANOS_PER_SECOND || self.nanos >= NANOS_PER_SECOND {
+            self.seconds = self.seconds.saturating_add((self.nanos / NANOS_PER_SECOND) as i64);
+            self.nanos %= NANOS_PER_SECOND;
+        }
+
+        if self.seconds < 0 && self.nanos > 0 {
+            self.seconds += 1;
+            self.nanos -= NANOS_PER_SECOND;
+        } else if self.seconds > 0 && self.nanos < 0 {
+            self.seconds -= 1;
+            self.nanos += NANOS_PER_SECOND;
+        }
+    }
+
+    /// Converts a `std::time::Duration` to a `Duration`, failing if it exceeds the maximum
+    /// `Duration` of 315,576,000,000 seconds.
+    pub fn from_std(duration: time::Duration) -> Result<Duration, DurationError> {
+        if duration.as_secs() > MAX_DURATION_SECONDS as u64 {
+            return Err(DurationError::OutOfRange);
+        }
+        Ok(Duration {
+            seconds: duration.as_secs() as i64,
+            nanos: duration.subsec_nanos() as i32,
+        })
+    }
+
+    /// Converts the normalized duration to a `std::time::Duration`, failing if the duration is out
+    /// of range or negative. The magnitude of a negative duration is returned in the error.
+    pub fn to_std(&self) -> Result<time::Duration, DurationError> {
+        let mut duration = self.clone();
+        duration.normalize();
+        if duration.seconds < -MAX_DURATION_SECONDS || duration.seconds > MAX_DURATION_SECONDS {
+            return Err(DurationError::OutOfRange);
+        }
+        if duration.seconds < 0 || duration.nanos < 0 {
+            return Err(DurationError::Negative(time::Duration::new(-duration.seconds as u64,
+                                                                   -duration.nanos as u32)));
+        }
+        Ok(time::Duration::new(duration.seconds as u64, duration.nanos as u32))
+    }
+}
+
+/// The reason that a `Duration` could not be converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DurationError {
+    /// The duration is outside of the range of a Protobuf `Duration`.
+    OutOfRange,
+    /// The duration is negative, and can not be represented by a `std::time::Duration`. The
+    /// magnitude of the duration is included.
+    Negative(time::Duration),
+}
+
+impl fmt::Display for DurationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DurationError::OutOfRange => f.write_str("duration is out of range"),
+            DurationError::Negative(duration) => write!(f, "duration is negative: -{:?}", duration),
+        }
+    }
+}
+
+impl error::Error for DurationError {
+    fn description(&self) -> &str {
+        "failed to convert duration"
+    }
+}
+
+impl Timestamp {
+
+    /// Normalizes the timestamp to a canonical format: `nanos` is in the range
+    /// `[0, 1_000_000_000)`. Timestamps which are out of the range of an `i64` number of seconds
+    /// saturate.
+    pub fn normalize(&mut self) {
+        if self.nanos <= -NANOS_PER_SECOND || self.nanos >= NANOS_PER_SECOND {
+            self.seconds = self.seconds.saturating_add((self.nanos / NANOS_PER_SECOND) as i64);
+            self.nanos %= NANOS_PER_SECOND;
+        }
+
+        if self.nanos < 0 {
+            self.seconds = self.seconds.saturating_sub(1);
+            self.nanos += NANOS_PER_SECOND;
+        }
+    }
+
+    /// Converts a `SystemTime` to a `Timestamp`, failing if it is outside of the range of a
+    /// `Timestamp`, 0001-01-01T00:00:00Z to 9999-12-31T23:59:59.999999999Z.
+    pub fn from_system_time(system_time: time::SystemTime) -> Result<Timestamp, TimestampError> {
+        let mut timestamp = match system_time.duration_since(time::UNIX_EPOCH) {
+            Ok(duration) => {
+                if duration.as_secs() > MAX_TIMESTAMP_SECONDS as u64 {
+                    return Err(TimestampError::OutOfRange);
+                }
+                Timestamp {
+                    seconds: duration.as_secs() as i64,
+                    nanos: duration.subsec_nanos() as i32,
+                }
+            },
+            Err(error) => {
+                let duration = error.duration();
+                if duration.as_secs() > -MIN_TIMESTAMP_SECONDS as u64 {
+                    return Err(TimestampError::OutOfRange);
+                }
+                Timestamp {
+                    seconds: -(duration.as_secs() as i64),
+                    nanos: -(duration.subsec_nanos() as i32),
+                }
+            },
+        };
+        timestamp.normalize();
+        if timestamp.seconds < MIN_TIMESTAMP_SECONDS {
+            return Err(TimestampError::OutOfRange);
+        }
+        Ok(timestamp)
+    }
+
+    /// Converts the normalized timestamp to a `SystemTime`, failing if it is outside of the range
+    /// of a `Timestamp`, or can not be represented by a `SystemTime` on the current platform.
+    pub fn to_system_time(&self) -> Result<time::SystemTime, TimestampError> {
+        let mut timestamp = self.clone();
+        timestamp.normalize();
+        if timestamp.seconds < MIN_TIMESTAMP_SECONDS || timestamp.seconds > MAX_TIMESTAMP_SECONDS {
+            return Err(TimestampError::OutOfRange);
+        }
+        let nanos = time::Duration::new(0, timestamp.nanos as u32);
+        let system_time = if timestamp.seconds >= 0 {
+            time::UNIX_EPOCH.checked_add(time::Duration::new(timestamp.seconds as u64, 0))
+        } else {
+            time::UNIX_EPOCH.checked_sub(time::Duration::new(-timestamp.seconds as u64, 0))
+        };
+        system_time.and_then(|system_time| system_time.checked_add(nanos))
+                   .ok_or(TimestampError::OutOfRange)
+    }
+}
+
+/// The reason that a `Timestamp` could not be converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimestampError {
+    /// The timestamp is outside of the range of a Protobuf `Timestamp`, or of a `SystemTime`.
+    OutOfRange,
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimestampError::OutOfRange => f.write_str("timestamp is out of range"),
+        }
+    }
+}
+
+impl error::Error for TimestampError {
+    fn description(&self) -> &str {
+        "failed to convert timestamp"
+    }
+}
+
+/// A dynamically typed value, equivalent to a Protobuf `Value`.
+///
+/// `JsonValue` is a tree of native Rust values, which is simpler to construct and match on than
+/// the `Struct`, `Value` and `ListValue` messages.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    /// A null value.
+    Null,
+    /// A boolean value.
+    Bool(bool),
+    /// A number value.
+    Number(f64),
+    /// A string value.
+    String(String),
+    /// A list of values.
+    List(Vec<JsonValue>),
+    /// A structured value, with fields mapping to values.
+    Struct(BTreeMap<String, JsonValue>),
+}
+
+/// Converts a `Value` to a `JsonValue`. A `Value` with no kind is converted to `JsonValue::Null`.
+impl From<Value> for JsonValue {
+    fn from(value: Value) -> JsonValue {
+        match value.kind {
+            None | Some(value::Kind::NullValue(_)) => JsonValue::Null,
+            Some(value::Kind::BoolValue(value)) => JsonValue::Bool(value),
+            Some(value::Kind::NumberValue(value)) => JsonValue::Number(value),
+            Some(value::Kind::StringValue(value)) => JsonValue::String(value),
+            Some(value::Kind::ListValue(list)) => JsonValue::List(list.into()),
+            Some(value::Kind::StructValue(fields)) => JsonValue::Struct(fields.into()),
+        }
+    }
+}
+
+impl From<JsonValue> for Value {
+    fn from(value: JsonValue) -> Value {
+        let kind = match value {
+            JsonValue::Null => value::Kind::NullValue(NullValue::NullValue as i32),
+            JsonValue::Bool(value) => value::Kind::BoolValue(value),
+            JsonValue::Number(value) => value::Kind::NumberValue(value),
+            JsonValue::String(value) => value::Kind::StringValue(value),
+            JsonValue::List(values) => value::Kind::ListValue(values.into()),
+            JsonValue::Struct(fields) => value::Kind::StructValue(fields.into()),
+        };
+        Value { kind: Some(kind) }
+    }
+}
+
+impl From<ListValue> for Vec<JsonValue> {
+    fn from(list: ListValue) -> Vec<JsonValue> {
+        list.values.into_iter().map(JsonValue::from).collect()
+    }
+}
+
+impl From<Vec<JsonValue>> for ListValue {
+    fn from(values: Vec<JsonValue>) -> ListValue {
+        ListValue {
+            values: values.into_iter().map(Value::from).collect(),
+        }
+    }
+}
+
+impl From<Struct> for BTreeMap<String, JsonValue> {
+    fn from(fields: Struct) -> BTreeMap<String, JsonValue> {
+        fields.fields.into_iter().map(|(key, value)| (key, value.into())).collect()
+    }
+}
+
+impl From<BTreeMap<String, JsonValue>> for Struct {
+    fn from(fields: BTreeMap<String, JsonValue>) -> Struct {
+        Struct {
+            fields: fields.into_iter().map(|(key, value)| (key, value.into())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::time;
+
+    use super::*;
+
+    #[test]
+    fn duration_normalize() {
+        let cases = [
+            ((0, 0), (0, 0)),
+            ((1, -1), (0, 999_999_999)),
+            ((-1, 1), (0, -999_999_999)),
+            ((0, 1_500_000_000), (1, 500_000_000)),
+            ((0, -1_500_000_000), (-1, -500_000_000)),
+            ((i64::max_value(), 1_000_000_000), (i64::max_value(), 0)),
+        ];
+        for &((seconds, nanos), (expected_seconds, expected_nanos)) in &cases {
+            let mut duration = Duration { seconds: seconds, nanos: nanos };
+            duration.normalize();
+            assert_eq!(duration, Duration { seconds: expected_seconds, nanos: expected_nanos });
+        }
+    }
+
+    #[test]
+    fn duration_conversion() {
+        let std_duration = time::Duration::new(10, 20);
+        let duration = Duration::from_std(std_duration).unwrap();
+        assert_eq!(duration, Duration { seconds: 10, nanos: 20 });
+        assert_eq!(duration.to_std(), Ok(std_duration));
+
+        assert_eq!(Duration { seconds: 0, nanos: 2_000_000_001 }.to_std(),
+                   Ok(time::Duration::new(2, 1)));
+        assert_eq!(Duration { seconds: -1, nanos: -5 }.to_std(),
+                   Err(DurationError::Negative(time::Duration::new(1, 5))));
+        assert_eq!(Duration { seconds: MAX_DURATION_SECONDS + 1, nanos: 0 }.to_std(),
+                   Err(DurationError::OutOfRange));
+        assert_eq!(Duration::from_std(time::Duration::new(MAX_DURATION_SECONDS as u64 + 1, 0)),
+                   Err(DurationError::OutOfRange));
+    }
+
+    #[test]
+    fn timestamp_conversion() {
+        let system_time = time::UNIX_EPOCH + time::Duration::new(1_500_000_000, 123);
+        let timestamp = Timestamp::from_system_time(system_time).unwrap();
+        assert_eq!(timestamp, Timestamp { seconds: 1_500_000_000, nanos: 123 });
+        assert_eq!(timestamp.to_system_time(), Ok(system_time));
+
+        // Times before the epoch have non-negative nanos.
+        let system_time = time::UNIX_EPOCH - time::Duration::new(10, 1);
+        let timestamp = Timestamp::from_system_time(system_time).unwrap();
+        assert_eq!(timestamp, Timestamp { seconds: -11, nanos: 999_999_999 });
+        assert_eq!(timestamp.to_system_time(), Ok(system_time));
+
+        assert_eq!(Timestamp { seconds: 0, nanos: -1 }.to_system_time(),
+                   Ok(time::UNIX_EPOCH - time::Duration::new(0, 1)));
+        assert_eq!(Timestamp { seconds: MAX_TIMESTAMP_SECONDS + 1, nanos: 0 }.to_system_time(),
+                   Err(TimestampError::OutOfRange));
+        assert_eq!(Timestamp { seconds: MIN_TIMESTAMP_SECONDS - 1, nanos: 0 }.to_system_time(),
+                   Err(TimestampError::OutOfRange));
+        let system_time = time::UNIX_EPOCH + time::Duration::new(MAX_TIMESTAMP_SECONDS as u64 + 1, 0);
+        assert_eq!(Timestamp::from_system_time(system_time), Err(TimestampError::OutOfRange));
+    }
+
+    #[test]
+    fn json_value_conversion() {
+        let mut fields = BTreeMap::new();
+        fields.insert("null".to_owned(), JsonValue::Null);
+        fields.insert("bool".to_owned(), JsonValue::Bool(true));
+        fields.insert("number".to_owned(), JsonValue::Number(4.2));
+        fields.insert("string".to_owned(), JsonValue::String("fourty two".to_owned()));
+        fields.insert("list".to_owned(), JsonValue::List(vec![JsonValue::Null,
+                                                               JsonValue::Struct(BTreeMap::new())]));
+        let json = JsonValue::Struct(fields);
+
+        let value = Value::from(json.clone());
+        match value.kind {
+            Some(value::Kind::StructValue(ref fields)) => assert_eq!(fields.fields.len(), 5),
+            ref kind => panic!("unexpected value kind: {:?}", kind),
+        }
+        assert_eq!(JsonValue::from(value), json);
+        assert_eq!(JsonValue::from(Value::default()), JsonValue::Null);
+    }
+}
@@ -0,0 +1,225 @@
+/// A Timestamp represents a point in time independent of any time zone
+/// or calendar, represented as seconds and fractions of seconds at
+/// nanosecond resolution in UTC Epoch time. It is encoded using the
+/// Proleptic Gregorian Calendar which extends the Gregorian calendar
+/// backwards to year one. The range is from 0001-01-01T00:00:00Z to
+/// 9999-12-31T23:59:59.999999999Z.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Timestamp {
+    /// Represents seconds of UTC time since Unix epoch
+    /// 1970-01-01T00:00:00Z. Must be from 0001-01-01T00:00:00Z to
+    /// 9999-12-31T23:59:59Z inclusive.
+    #[prost(int64, tag="1")]
+    pub seconds: i64,
+    /// Non-negative fractions of a second at nanosecond resolution. Negative
+    /// second values with fractions must still have non-negative nanos values
+    /// that count forward in time. Must be from 0 to 999,999,999
+    /// inclusive.
+    #[prost(int32, tag="2")]
+    pub nanos: i32,
+}
+/// A Duration represents a signed, fixed-length span of time represented
+/// as a count of seconds and fractions of seconds at nanosecond
+/// resolution. It is independent of any calendar and concepts like "day"
+/// or "month". Range is approximately +-10,000 years.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Duration {
+    /// Signed seconds of the span of time. Must be from -315,576,000,000
+    /// to +315,576,000,000 inclusive.
+    #[prost(int64, tag="1")]
+    pub seconds: i64,
+    /// Signed fractions of a second at nanosecond resolution of the span
+    /// of time. Durations less than one second are represented with a 0
+    /// `seconds` field and a positive or negative `nanos` field. For durations
+    /// of one second or more, a non-zero value for the `nanos` field must be
+    /// of the same sign as the `seconds` field. Must be from -999,999,999
+    /// to +999,999,999 inclusive.
+    #[prost(int32, tag="2")]
+    pub nanos: i32,
+}
+/// `Struct` represents a structured data value, consisting of fields
+/// which map to dynamically typed values. In some languages, `Struct`
+/// might be supported by a native representation. For example, in
+/// scripting languages like JS a struct is represented as an
+/// object.
+///
+/// The JSON representation for `Struct` is JSON object.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Struct {
+    /// Unordered map of dynamically typed values.
+    #[prost(btree_map="string, message", tag="1")]
+    pub fields: ::std::collections::BTreeMap<String, Value>,
+}
+/// `Value` represents a dynamically typed value which can be either
+/// null, a number, a string, a boolean, a recursive struct value, or a
+/// list of values. A producer of value is expected to set one of that
+/// variants, absence of any variant indicates an error.
+///
+/// The JSON representation for `Value` is JSON value.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Value {
+    /// The kind of value.
+    #[prost(oneof="value::Kind", tags="1, 2, 3, 4, 5, 6")]
+    pub kind: Option<value::Kind>,
+}
+pub mod value {
+    /// The kind of value.
+    #[derive(Clone, Debug, Oneof, PartialEq)]
+    pub enum Kind {
+        /// Represents a null value.
+        #[prost(enumeration="super::NullValue", tag="1")]
+        NullValue(i32),
+        /// Represents a double value.
+        #[prost(double, tag="2")]
+        NumberValue(f64),
+        /// Represents a string value.
+        #[prost(string, tag="3")]
+        StringValue(String),
+        /// Represents a boolean value.
+        #[prost(bool, tag="4")]
+        BoolValue(bool),
+        /// Represents a structured value.
+        #[prost(message, tag="5")]
+        StructValue(super::Struct),
+        /// Represents a repeated `Value`.
+        #[prost(message, tag="6")]
+        ListValue(super::ListValue),
+    }
+}
+/// `ListValue` is a wrapper around a repeated field of values.
+///
+/// The JSON representation for `ListValue` is JSON array.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct ListValue {
+    /// Repeated field of dynamically typed values.
+    #[prost(message, repeated, tag="1")]
+    pub values: Vec<Value>,
+}
+/// `NullValue` is a singleton enumeration to represent the null value for the
+/// `Value` type union.
+///
+///  The JSON representation for `NullValue` is JSON `null`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+pub enum NullValue {
+    /// Null value.
+    NullValue = 0,
+}
+/// Wrapper message for `double`.
+///
+/// The JSON representation for `DoubleValue` is JSON number.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct DoubleValue {
+    /// The double value.
+    #[prost(double, tag="1")]
+    pub value: f64,
+}
+/// Wrapper message for `float`.
+///
+/// The JSON representation for `FloatValue` is JSON number.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct FloatValue {
+    /// The float value.
+    #[prost(float, tag="1")]
+    pub value: f32,
+}
+/// Wrapper message for `int64`.
+///
+/// The JSON representation for `Int64Value` is JSON string.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Int64Value {
+    /// The int64 value.
+    #[prost(int64, tag="1")]
+    pub value: i64,
+}
+/// Wrapper message for `uint64`.
+///
+/// The JSON representation for `UInt64Value` is JSON string.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct UInt64Value {
+    /// The uint64 value.
+    #[prost(uint64, tag="1")]
+    pub value: u64,
+}
+/// Wrapper message for `int32`.
+///
+/// The JSON representation for `Int32Value` is JSON number.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct Int32Value {
+    /// The int32 value.
+    #[prost(int32, tag="1")]
+    pub value: i32,
+}
+/// Wrapper message for `uint32`.
+///
+/// The JSON representation for `UInt32Value` is JSON number.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct UInt32Value {
+    /// The uint32 value.
+    #[prost(uint32, tag="1")]
+    pub value: u32,
+}
+/// Wrapper message for `bool`.
+///
+/// The JSON representation for `BoolValue` is JSON `true` and `false`.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct BoolValue {
+    /// The bool value.
+    #[prost(bool, tag="1")]
+    pub value: bool,
+}
+/// Wrapper message for `string`.
+///
+/// The JSON representation for `StringValue` is JSON string.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct StringValue {
+    /// The string value.
+    #[prost(string, tag="1")]
+    pub value: String,
+}
+/// Wrapper message for `bytes`.
+///
+/// The JSON representation for `BytesValue` is JSON string.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct BytesValue {
+    /// The bytes value.
+    #[prost(bytes, tag="1")]
+    pub value: Vec<u8>,
+}
+/// A generic empty message that you can re-use to avoid defining duplicated
+/// empty messages in your APIs.
+///
+/// The JSON representation for `Empty` is empty JSON object `{}`.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct Empty {
+}
+/// `FieldMask` represents a set of symbolic field paths, for example:
+///
+/// ```text
+/// paths: "f.a"
+/// paths: "f.b.d"
+/// ```
+///
+/// Here `f` represents a field in some root message, `a` and `b`
+/// fields in the message found in `f`, and `d` a field found in the
+/// message in `f.b`.
+#[derive(Clone, Debug, PartialEq, Message)]
+#[prost(custom_json)]
+pub struct FieldMask {
+    /// The set of field mask paths.
+    #[prost(string, repeated, tag="1")]
+    pub paths: Vec<String>,
+}
@@ -1,12 +1,11 @@
 //! Utility functions and types for encoding and decoding Protobuf types.
 
-use std::cmp::min;
-use std::error;
-use std::io::{
-    Result,
-    Error,
-    ErrorKind,
+use std::cell::{
+    Cell,
+    RefCell,
 };
+use std::cmp::min;
+use std::io::Cursor;
 use std::str;
 use std::u32;
 use std::usize;
@@ -14,27 +13,17 @@ use std::usize;
 use bytes::{
     Buf,
     BufMut,
+    Bytes,
     LittleEndian,
     Take,
 };
 
+use DecodeError;
+use DecodeErrorKind;
+use ExtensionRegistry;
+use Merge;
 use Message;
 
-/// Returns an invalid data IO error wrapping the provided cause.
-///
-/// This should be used primarily when decoding a Protobuf type fails.
-pub fn invalid_data<E>(error: E) -> Error where E: Into<Box<error::Error + Send + Sync>> {
-    Error::new(ErrorKind::InvalidData, error.into())
-}
-
-/// Returns an invalid input IO error wrapping the provided cause.
-///
-/// This should be used primarily when encoding a Protobuf type fails due to
-/// insufficient output buffer space.
-pub fn invalid_input<E>(error: E) -> Error where E: Into<Box<error::Error + Send + Sync>> {
-    Error::new(ErrorKind::InvalidInput, error.into())
-}
-
 /// Encodes an integer value into LEB128 variable length format, and writes it to the buffer.
 /// The buffer must have enough remaining space (maximum 10 bytes).
 #[inline]
@@ -68,9 +57,10 @@ pub fn encode_varint<B>(mut value: u64, buf: &mut B) where B: BufMut {
 
 /// Decodes a LEB128-encoded variable length integer from the buffer.
 #[inline]
-pub fn decode_varint<B>(buf: &mut B) -> Result<u64> where B: Buf {
+pub fn decode_varint<B>(buf: &mut B) -> Result<u64, DecodeError> where B: Buf {
     let mut value = 0;
-    for count in 0..min(10, buf.remaining()) {
+    let len = min(10, buf.remaining());
+    for count in 0..len {
         let byte = buf.get_u8();
         value |= ((byte & 0x7F) as u64) << (count * 7);
         if byte <= 0x7F {
@@ -78,7 +68,11 @@ pub fn decode_varint<B>(buf: &mut B) -> Result<u64> where B: Buf {
         }
     }
 
-    Err(invalid_data("failed to decode varint"))
+    if len == 10 {
+        Err(DecodeError::new(DecodeErrorKind::VarintOverflow))
+    } else {
+        Err(DecodeError::new(DecodeErrorKind::TruncatedVarint))
+    }
 }
 
 /// Returns the encoded length of the value in LEB128 variable length format.
@@ -97,28 +91,207 @@ pub fn encoded_len_varint(value: u64) -> usize {
     else { 10 }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum WireType {
     Varint = 0,
     SixtyFourBit = 1,
     LengthDelimited = 2,
-    ThirtyTwoBit = 5
+    StartGroup = 3,
+    EndGroup = 4,
+    ThirtyTwoBit = 5,
 }
 
 pub const MIN_TAG: u32 = 1;
 pub const MAX_TAG: u32 = (1 << 29) - 1;
 
+/// The default maximum nesting depth of messages when decoding, matching the
+/// limit used by the reference Protobuf implementation.
+pub const DEFAULT_RECURSION_LIMIT: u32 = 100;
+
+/// Additional information passed to every decode and merge function.
+///
+/// The context tracks how many more levels of nested messages may be
+/// decoded, which prevents malicious or corrupt input from overflowing the
+/// stack. It also optionally holds the `Bytes` buffer being decoded, which
+/// allows `Bytes` fields to share the input buffer's memory, and the registry
+/// of extensions which are recognized while decoding.
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeContext<'a> {
+    /// How many more times a nested message may be entered before decoding
+    /// fails with `DecodeErrorKind::RecursionLimitReached`.
+    recurse_count: u32,
+    /// The buffer being decoded, if it is a `Bytes`.
+    source: Option<&'a Bytes>,
+    /// The extensions which are recognized while decoding.
+    extension_registry: Option<&'a ExtensionRegistry>,
+}
+
+impl <'a> Default for DecodeContext<'a> {
+    #[inline]
+    fn default() -> DecodeContext<'a> {
+        DecodeContext::with_recursion_limit(DEFAULT_RECURSION_LIMIT)
+    }
+}
+
+impl <'a> DecodeContext<'a> {
+
+    /// Creates a new decode context which allows messages to be nested up to
+    /// `limit` levels deep.
+    #[inline]
+    pub fn with_recursion_limit(limit: u32) -> DecodeContext<'a> {
+        DecodeContext {
+            recurse_count: limit,
+            source: None,
+            extension_registry: None,
+        }
+    }
+
+    /// Returns a copy of the context which decodes `Bytes` fields without
+    /// copying, by slicing `source` when the value being decoded lies within
+    /// it.
+    #[inline]
+    pub fn with_source(self, source: &'a Bytes) -> DecodeContext<'a> {
+        DecodeContext {
+            recurse_count: self.recurse_count,
+            source: Some(source),
+            extension_registry: self.extension_registry,
+        }
+    }
+
+    /// Returns a copy of the context which recognizes the extensions in the
+    /// registry while decoding.
+    #[inline]
+    pub fn with_extension_registry(self, registry: &'a ExtensionRegistry) -> DecodeContext<'a> {
+        DecodeContext {
+            recurse_count: self.recurse_count,
+            source: self.source,
+            extension_registry: Some(registry),
+        }
+    }
+
+    /// Returns the registry of extensions which are recognized while
+    /// decoding, if the context has one.
+    #[inline]
+    pub fn extension_registry(&self) -> Option<&'a ExtensionRegistry> {
+        self.extension_registry
+    }
+
+    /// Returns the number of further levels of nested messages which may be
+    /// decoded with this context.
+    #[inline]
+    pub fn recursion_limit(&self) -> u32 {
+        self.recurse_count
+    }
+
+    /// Returns a copy of the context for decoding a nested message, with one
+    /// less level of recursion remaining.
+    ///
+    /// Callers must check `limit_reached` before entering a nested message.
+    #[inline]
+    pub fn enter_recursion(&self) -> DecodeContext<'a> {
+        DecodeContext {
+            recurse_count: self.recurse_count - 1,
+            source: self.source,
+            extension_registry: self.extension_registry,
+        }
+    }
+
+    /// Checks whether a nested message may be decoded with this context, or
+    /// returns an error result.
+    #[inline]
+    pub fn limit_reached(&self) -> Result<(), DecodeError> {
+        if self.recurse_count == 0 {
+            Err(DecodeError::new(DecodeErrorKind::RecursionLimitReached))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the first `len` bytes of `chunk` as a slice of the source
+    /// buffer, or `None` if the context has no source buffer, or if the bytes
+    /// do not lie within it.
+    fn slice_source(&self, chunk: &[u8], len: usize) -> Option<Bytes> {
+        let source = match self.source {
+            Some(source) => source,
+            None => return None,
+        };
+        if chunk.len() < len {
+            return None;
+        }
+        let start = source.as_ptr() as usize;
+        let end = start + source.len();
+        let offset = chunk.as_ptr() as usize;
+        if offset < start || offset + len > end {
+            return None;
+        }
+        let offset = offset - start;
+        Some(source.slice(offset, offset + len))
+    }
+}
+
+/// The encoded lengths of the nested messages of a message being encoded.
+///
+/// The length of a nested message is written before the message itself, so computing lengths on
+/// demand while encoding re-walks every subtree once per level of nesting. Instead,
+/// `Message::encode` makes a sizing pass over the message which records the length of each
+/// nested message in the order they are encoded, and then an encoding pass which consumes the
+/// recorded lengths.
+///
+/// Meant to be used only by `Message` implementations.
+#[derive(Debug, Default)]
+pub struct SizeCache {
+    /// The recorded lengths, in encoding order.
+    sizes: RefCell<Vec<usize>>,
+    /// The index of the next length to be consumed by the encoding pass.
+    next: Cell<usize>,
+}
+
+impl SizeCache {
+
+    /// Creates a new, empty size cache.
+    pub fn new() -> SizeCache {
+        SizeCache::default()
+    }
+
+    /// Reserves a slot for the length of a nested message during the sizing pass. The slot must
+    /// be reserved before the lengths of the message's own nested messages are recorded.
+    fn reserve(&self) -> usize {
+        let mut sizes = self.sizes.borrow_mut();
+        sizes.push(0);
+        sizes.len() - 1
+    }
+
+    /// Records the length of a nested message in a reserved slot.
+    fn record(&self, slot: usize, len: usize) {
+        self.sizes.borrow_mut()[slot] = len;
+    }
+
+    /// Returns the length of the next nested message to be encoded, without consuming it.
+    fn peek(&self) -> usize {
+        self.sizes.borrow()[self.next.get()]
+    }
+
+    /// Returns and consumes the length of the next nested message to be encoded.
+    fn take(&self) -> usize {
+        let len = self.peek();
+        self.next.set(self.next.get() + 1);
+        len
+    }
+}
+
 impl WireType {
     // TODO: impl TryFrom<u8> when stable.
     #[inline]
-    pub fn try_from(val: u8) -> Result<WireType> {
+    pub fn try_from(val: u8) -> Result<WireType, DecodeError> {
         match val {
             0 => Ok(WireType::Varint),
             1 => Ok(WireType::SixtyFourBit),
             2 => Ok(WireType::LengthDelimited),
+            3 => Ok(WireType::StartGroup),
+            4 => Ok(WireType::EndGroup),
             5 => Ok(WireType::ThirtyTwoBit),
-            _ => Err(invalid_data(format!("invalid wire type value: {}", val))),
+            _ => Err(DecodeError::new(DecodeErrorKind::InvalidWireType(val))),
         }
     }
 }
@@ -135,16 +308,16 @@ pub fn encode_key<B>(tag: u32, wire_type: WireType, buf: &mut B) where B: BufMut
 /// Decodes a Protobuf field key, which consists of a wire type designator and
 /// the field tag.
 #[inline]
-pub fn decode_key<B>(buf: &mut B) -> Result<(u32, WireType)> where B: Buf {
+pub fn decode_key<B>(buf: &mut B) -> Result<(u32, WireType), DecodeError> where B: Buf {
     let key = decode_varint(buf)?;
     if key > u32::MAX as u64 {
-        return Err(invalid_data("failed to decode field key: u32 overflow"));
+        return Err(DecodeError::new(DecodeErrorKind::InvalidKey(key)));
     }
     let wire_type = WireType::try_from(key as u8 & 0x07)?;
     let tag = key as u32 >> 3;
 
     if tag < MIN_TAG {
-        return Err(invalid_data("invalid zero tag value"));
+        return Err(DecodeError::new(DecodeErrorKind::InvalidKey(key)));
     }
 
     Ok((tag, wire_type))
@@ -160,43 +333,89 @@ pub fn key_len(tag: u32) -> usize {
 /// Checks that the expected wire type matches the actual wire type,
 /// or returns an error result.
 #[inline]
-pub fn check_wire_type(expected: WireType, actual: WireType) -> Result<()> {
+pub fn check_wire_type(expected: WireType, actual: WireType) -> Result<(), DecodeError> {
     if expected != actual {
-        return Err(invalid_data(format!("invalid wire type: {:?} (expected {:?})", actual, expected)));
+        return Err(DecodeError::new(DecodeErrorKind::UnexpectedWireType {
+            expected: expected,
+            actual: actual,
+        }));
     }
     Ok(())
 }
 
-pub fn skip_field<B>(wire_type: WireType, buf: &mut B) -> Result<()> where B: Buf {
+/// Returns a buffer underflow decode error.
+#[inline]
+fn underflow() -> DecodeError {
+    DecodeError::new(DecodeErrorKind::BufferUnderflow)
+}
+
+/// Restores the limit of a buffer after decoding a length-delimited value of `len` bytes, which
+/// was bounded by temporarily setting the limit to `len`.
+///
+/// If decoding failed part way through the value, the restored limit accounts for only the bytes
+/// which were consumed, so that the position in the buffer at which the error occurred can be
+/// reported.
+#[inline]
+fn restore_limit<B>(buf: &mut Take<B>, limit: usize, len: usize) where B: Buf {
+    let unconsumed = buf.limit();
+    buf.set_limit(limit - len + unconsumed);
+}
+
+/// Skips the value of a field with the given tag and wire type. Groups are skipped along with all
+/// of their nested fields.
+pub fn skip_field<B>(wire_type: WireType,
+                     tag: u32,
+                     buf: &mut B,
+                     ctx: DecodeContext)
+                     -> Result<(), DecodeError> where B: Buf {
     match wire_type {
         WireType::Varint => {
-            decode_varint(buf).map_err(|error| {
-                Error::new(error.kind(), format!("failed to skip varint field: {}", error))
-            })?;
+            decode_varint(buf)?;
         },
         WireType::SixtyFourBit => {
             if buf.remaining() < 8 {
-                return Err(invalid_data("failed to skip 64-bit field: buffer underflow"));
+                return Err(underflow());
             }
             buf.advance(8);
         },
         WireType::ThirtyTwoBit => {
             if buf.remaining() < 4 {
-                return Err(invalid_data("failed to skip 32-bit field: buffer underflow"));
+                return Err(underflow());
             }
             buf.advance(4);
         },
         WireType::LengthDelimited => {
             let len = decode_varint(buf)?;
             if len > buf.remaining() as u64 {
-                return Err(invalid_data("failed to skip length delimited field: buffer underflow"));
+                return Err(underflow());
             }
             buf.advance(len as usize);
         },
+        WireType::StartGroup => {
+            ctx.limit_reached()?;
+            loop {
+                let (field_tag, field_wire_type) = decode_key(buf)?;
+                if field_wire_type == WireType::EndGroup {
+                    check_end_group(tag, field_tag)?;
+                    break;
+                }
+                skip_field(field_wire_type, field_tag, buf, ctx.enter_recursion())?;
+            }
+        },
+        WireType::EndGroup => return Err(DecodeError::new(DecodeErrorKind::UnexpectedEndGroup(tag))),
     };
     Ok(())
 }
 
+/// Checks that the tag of an end group key matches the tag of the group being decoded.
+#[inline]
+pub fn check_end_group(expected: u32, actual: u32) -> Result<(), DecodeError> {
+    if expected != actual {
+        return Err(DecodeError::new(DecodeErrorKind::UnexpectedEndGroup(actual)));
+    }
+    Ok(())
+}
+
 /// Helper macro which emits an `encode_repeated` function for the type.
 macro_rules! encode_repeated {
     ($ty:ty) => (
@@ -216,27 +435,31 @@ macro_rules! merge_repeated_numeric {
      $merge_repeated:ident) => (
         pub fn $merge_repeated<B>(wire_type: WireType,
                                   values: &mut Vec<$ty>,
-                                  buf: &mut Take<B>)
-                                  -> Result<()> where B: Buf {
+                                  buf: &mut Take<B>,
+                                  ctx: DecodeContext)
+                                  -> Result<(), DecodeError> where B: Buf {
             if wire_type == WireType::LengthDelimited {
                 let len = decode_varint(buf)?;
                 if len > buf.remaining() as u64 {
-                    return Err(invalid_data("buffer underflow"));
+                    return Err(underflow());
                 }
                 let len = len as usize;
                 let limit = buf.limit();
                 buf.set_limit(len);
 
                 while buf.has_remaining() {
-                let mut value = Default::default();
-                $merge($wire_type, &mut value, buf)?;
-                values.push(value);
+                    let mut value = Default::default();
+                    if let Err(error) = $merge($wire_type, &mut value, buf, ctx) {
+                        restore_limit(buf, limit, len);
+                        return Err(error);
+                    }
+                    values.push(value);
                 }
                 buf.set_limit(limit - len);
             } else {
                 check_wire_type($wire_type, wire_type)?;
                 let mut value = Default::default();
-                $merge(wire_type, &mut value, buf)?;
+                $merge(wire_type, &mut value, buf, ctx)?;
                 values.push(value);
             }
             Ok(())
@@ -268,7 +491,7 @@ macro_rules! varint {
                 encode_varint($to_uint64, buf);
             }
 
-            pub fn merge<B>(wire_type: WireType, value: &mut $ty, buf: &mut B) -> Result<()> where B: Buf {
+            pub fn merge<B>(wire_type: WireType, value: &mut $ty, buf: &mut B, _ctx: DecodeContext) -> Result<(), DecodeError> where B: Buf {
                 check_wire_type(WireType::Varint, wire_type)?;
                 let $from_uint64_value = decode_varint(buf)?;
                 *value = $from_uint64;
@@ -368,6 +591,65 @@ varint!(i64, sint64,
             ((value >> 1) as i64) ^ (-((value & 1) as i64))
         });
 
+/// Encoding functions for open enumeration fields, which are encoded as `int32` values.
+pub mod open_enum {
+    use ::encoding::*;
+    use Enumeration;
+    use OpenEnum;
+
+    pub fn encode<E, B>(tag: u32, value: &OpenEnum<E>, buf: &mut B) where E: Enumeration, B: BufMut {
+        int32::encode(tag, &value.to_i32(), buf);
+    }
+
+    pub fn merge<E, B>(wire_type: WireType,
+                       value: &mut OpenEnum<E>,
+                       buf: &mut B,
+                       ctx: DecodeContext)
+                       -> Result<(), DecodeError> where E: Enumeration, B: Buf {
+        let mut raw = 0;
+        int32::merge(wire_type, &mut raw, buf, ctx)?;
+        *value = OpenEnum::from_i32(raw);
+        Ok(())
+    }
+
+    pub fn encode_repeated<E, B>(tag: u32, values: &Vec<OpenEnum<E>>, buf: &mut B)
+    where E: Enumeration, B: BufMut {
+        for value in values {
+            encode(tag, value, buf);
+        }
+    }
+
+    pub fn encode_packed<E, B>(tag: u32, values: &Vec<OpenEnum<E>>, buf: &mut B)
+    where E: Enumeration, B: BufMut {
+        int32::encode_packed(tag, &values.iter().map(OpenEnum::to_i32).collect(), buf);
+    }
+
+    pub fn merge_repeated<E, B>(wire_type: WireType,
+                                values: &mut Vec<OpenEnum<E>>,
+                                buf: &mut Take<B>,
+                                ctx: DecodeContext)
+                                -> Result<(), DecodeError> where E: Enumeration, B: Buf {
+        // The values are only added once the whole field is decoded, so that the field is left
+        // unchanged by a decode error.
+        let mut raw = Vec::new();
+        int32::merge_repeated(wire_type, &mut raw, buf, ctx)?;
+        values.extend(raw.into_iter().map(OpenEnum::from_i32));
+        Ok(())
+    }
+
+    pub fn encoded_len<E>(tag: u32, value: &OpenEnum<E>) -> usize where E: Enumeration {
+        int32::encoded_len(tag, &value.to_i32())
+    }
+
+    pub fn encoded_len_repeated<E>(tag: u32, values: &Vec<OpenEnum<E>>) -> usize where E: Enumeration {
+        values.iter().map(|value| encoded_len(tag, value)).sum()
+    }
+
+    pub fn encoded_len_packed<E>(tag: u32, values: &Vec<OpenEnum<E>>) -> usize where E: Enumeration {
+        int32::encoded_len_packed(tag, &values.iter().map(OpenEnum::to_i32).collect())
+    }
+}
+
 /// Macro which emits a module containing a set of encoding functions for a
 /// fixed width numeric type.
 macro_rules! fixed_width {
@@ -385,10 +667,10 @@ macro_rules! fixed_width {
                 buf.$put::<LittleEndian>(*value);
             }
 
-            pub fn merge<B>(wire_type: WireType, value: &mut $ty, buf: &mut B) -> Result<()> where B: Buf {
+            pub fn merge<B>(wire_type: WireType, value: &mut $ty, buf: &mut B, _ctx: DecodeContext) -> Result<(), DecodeError> where B: Buf {
                 check_wire_type($wire_type, wire_type)?;
                 if buf.remaining() < $width {
-                    return Err(invalid_data("buffer underflow"));
+                    return Err(underflow());
                 }
                 *value = buf.$get::<LittleEndian>();
                 Ok(())
@@ -464,115 +746,314 @@ fixed_width!(u64, 8, WireType::SixtyFourBit, fixed64, put_u64, get_u64);
 fixed_width!(i32, 4, WireType::ThirtyTwoBit, sfixed32, put_i32, get_i32);
 fixed_width!(i64, 8, WireType::SixtyFourBit, sfixed64, put_i64, get_i64);
 
-/// Macro which emits encoding functions for a length-delimited type.
-macro_rules! length_delimited {
-    ($ty:ty) => (
-
-        encode_repeated!($ty);
-
-         pub fn merge_repeated<B>(wire_type: WireType, values: &mut Vec<$ty>, buf: &mut Take<B>) -> Result<()> where B: Buf {
-                check_wire_type(WireType::LengthDelimited, wire_type)?;
-                let mut value = Default::default();
-                merge(wire_type, &mut value, buf)?;
-                values.push(value);
-                Ok(())
-         }
-
-         pub fn encoded_len(tag: u32, value: &$ty) -> usize {
-             key_len(tag) + encoded_len_varint(value.len() as u64) + value.len()
-         }
-
-         pub fn encoded_len_repeated(tag: u32, values: &Vec<$ty>) -> usize {
-             key_len(tag) * values.len() + values.iter().map(|value| {
-                 encoded_len_varint(value.len() as u64) + value.len()
-             }).sum::<usize>()
-         }
-
-         #[cfg(test)]
-         mod test {
-            use quickcheck::TestResult;
-
-            use super::*;
-            use super::super::test::{
-                check_collection_type,
-                check_type,
-            };
-
-             quickcheck! {
-                 fn check(value: $ty, tag: u32) -> TestResult {
-                     super::test::check_type(value, tag, WireType::LengthDelimited,
-                                             encode, merge, encoded_len)
-                 }
-                 fn check_repeated(value: Vec<$ty>, tag: u32) -> TestResult {
-                     super::test::check_collection_type(value, tag, WireType::LengthDelimited,
-                                                        encode_repeated, merge_repeated,
-                                                        encoded_len_repeated)
-                 }
-             }
-         }
-    )
+/// Returns the next `len` bytes of a borrowed buffer as a slice of the underlying memory, and
+/// advances the buffer past them. The buffer must contain at least `len` remaining bytes.
+fn take_borrowed<'a>(buf: &mut Take<Cursor<&'a [u8]>>, len: usize) -> &'a [u8] {
+    let value = {
+        let source: &'a [u8] = *buf.get_ref().get_ref();
+        let position = buf.get_ref().position() as usize;
+        &source[position..position + len]
+    };
+    buf.advance(len);
+    value
 }
 
 pub mod string {
     use super::*;
 
-    pub fn encode<B>(tag: u32,
-                     value: &String,
-                     buf: &mut B) where B: BufMut {
+    pub fn encode<S, B>(tag: u32, value: &S, buf: &mut B) where S: AsRef<str>, B: BufMut {
+        let value = value.as_ref();
         encode_key(tag, WireType::LengthDelimited, buf);
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_bytes());
     }
+
     pub fn merge<B>(wire_type: WireType,
                     value: &mut String,
-                    buf: &mut Take<B>) -> Result<()> where B: Buf {
+                    buf: &mut Take<B>,
+                    ctx: DecodeContext) -> Result<(), DecodeError> where B: Buf {
         unsafe {
             // String::as_mut_vec is unsafe because it doesn't check that the bytes
             // inserted into it the resulting vec are valid UTF-8. We check
             // explicitly in order to ensure this is safe.
-            super::bytes::merge(wire_type, value.as_mut_vec(), buf)?;
-            str::from_utf8(value.as_bytes()).map_err(|_| {
-                invalid_data("failed to decode string: data is not UTF-8 encoded")
-            })?;
+            super::bytes::merge(wire_type, value.as_mut_vec(), buf, ctx)?;
+            if str::from_utf8(value.as_bytes()).is_err() {
+                value.as_mut_vec().clear();
+                return Err(DecodeError::new(DecodeErrorKind::InvalidUtf8));
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges a string value which borrows from the buffer being decoded.
+    pub fn merge_borrowed<'a>(wire_type: WireType,
+                              value: &mut &'a str,
+                              buf: &mut Take<Cursor<&'a [u8]>>,
+                              ctx: DecodeContext) -> Result<(), DecodeError> {
+        let mut bytes: &'a [u8] = &[];
+        super::bytes::merge_borrowed(wire_type, &mut bytes, buf, ctx)?;
+        *value = str::from_utf8(bytes).map_err(|_| DecodeError::new(DecodeErrorKind::InvalidUtf8))?;
+        Ok(())
+    }
+
+    pub fn encode_repeated<S, B>(tag: u32, values: &Vec<S>, buf: &mut B) where S: AsRef<str>, B: BufMut {
+        for value in values {
+            encode(tag, value, buf);
         }
+    }
+
+    pub fn merge_repeated<B>(wire_type: WireType,
+                             values: &mut Vec<String>,
+                             buf: &mut Take<B>,
+                             ctx: DecodeContext) -> Result<(), DecodeError> where B: Buf {
+        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        let mut value = String::new();
+        merge(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
         Ok(())
     }
 
-    length_delimited!(String);
+    pub fn merge_borrowed_repeated<'a>(wire_type: WireType,
+                                       values: &mut Vec<&'a str>,
+                                       buf: &mut Take<Cursor<&'a [u8]>>,
+                                       ctx: DecodeContext) -> Result<(), DecodeError> {
+        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        let mut value = "";
+        merge_borrowed(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
+        Ok(())
+    }
+
+    pub fn encoded_len<S>(tag: u32, value: &S) -> usize where S: AsRef<str> {
+        let len = value.as_ref().len();
+        key_len(tag) + encoded_len_varint(len as u64) + len
+    }
+
+    pub fn encoded_len_repeated<S>(tag: u32, values: &Vec<S>) -> usize where S: AsRef<str> {
+        key_len(tag) * values.len() + values.iter().map(|value| {
+            let len = value.as_ref().len();
+            encoded_len_varint(len as u64) + len
+        }).sum::<usize>()
+    }
+
+    #[cfg(test)]
+    mod test {
+        use quickcheck::TestResult;
+
+        use super::*;
+        use super::super::test::{
+            check_collection_type,
+            check_type,
+        };
+
+        quickcheck! {
+            fn check(value: String, tag: u32) -> TestResult {
+                check_type(value, tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)
+            }
+            fn check_repeated(value: Vec<String>, tag: u32) -> TestResult {
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated,
+                                      encoded_len_repeated)
+            }
+        }
+
+        #[test]
+        fn borrowed() {
+            let mut buf = Vec::new();
+            encode(1, &"hello", &mut buf);
+            let mut buf = Cursor::new(&buf[..]).take(buf.len());
+
+            let (_, wire_type) = decode_key(&mut buf).unwrap();
+            let mut value = "";
+            merge_borrowed(wire_type, &mut value, &mut buf, DecodeContext::default()).unwrap();
+            assert_eq!(value, "hello");
+
+            let invalid = [0x0A, 0x01, 0xFF];
+            let mut buf = Cursor::new(&invalid[..]).take(invalid.len());
+            let (_, wire_type) = decode_key(&mut buf).unwrap();
+            let error = merge_borrowed(wire_type, &mut value, &mut buf, DecodeContext::default()).unwrap_err();
+            assert_eq!(error.kind(), &DecodeErrorKind::InvalidUtf8);
+        }
+    }
+}
+
+/// A type which can be used as the Rust representation of a Protobuf `bytes` field.
+///
+/// `bytes` fields may be represented as either a `Vec<u8>`, or as a reference-counted
+/// `bytes::Bytes`. When decoding from a `Bytes` buffer, `Bytes` fields share the memory of the
+/// input buffer instead of copying the field value.
+pub trait BytesAdapter: AsRef<[u8]> + Default + Sized + 'static {
+
+    /// Replaces the value with the next `len` bytes of the buffer. The buffer must contain at
+    /// least `len` remaining bytes.
+    fn replace_with<B>(&mut self, buf: &mut Take<B>, len: usize, ctx: DecodeContext) where B: Buf;
+}
+
+impl BytesAdapter for Vec<u8> {
+    fn replace_with<B>(&mut self, buf: &mut Take<B>, len: usize, _ctx: DecodeContext) where B: Buf {
+        self.clear();
+        self.reserve_exact(len);
+        while self.len() < len {
+            let chunk_len = {
+                let chunk = buf.bytes();
+                let chunk_len = min(chunk.len(), len - self.len());
+                self.extend_from_slice(&chunk[..chunk_len]);
+                chunk_len
+            };
+            buf.advance(chunk_len);
+        }
+    }
+}
+
+impl BytesAdapter for Bytes {
+    fn replace_with<B>(&mut self, buf: &mut Take<B>, len: usize, ctx: DecodeContext) where B: Buf {
+        if let Some(bytes) = ctx.slice_source(buf.bytes(), len) {
+            *self = bytes;
+            buf.advance(len);
+        } else {
+            let mut value = Vec::new();
+            value.replace_with(buf, len, ctx);
+            *self = Bytes::from(value);
+        }
+    }
 }
 
 pub mod bytes {
     use super::*;
 
-    pub fn encode<B>(tag: u32, value: &Vec<u8>, buf: &mut B) where B: BufMut {
+    pub fn encode<A, B>(tag: u32, value: &A, buf: &mut B) where A: AsRef<[u8]>, B: BufMut {
+        let value = value.as_ref();
         encode_key(tag, WireType::LengthDelimited, buf);
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value);
     }
 
-    pub fn merge<B>(wire_type: WireType, value: &mut Vec<u8>, buf: &mut Take<B>) -> Result<()> where B: Buf {
+    pub fn merge<A, B>(wire_type: WireType,
+                       value: &mut A,
+                       buf: &mut Take<B>,
+                       ctx: DecodeContext)
+                       -> Result<(), DecodeError> where A: BytesAdapter, B: Buf {
         check_wire_type(WireType::LengthDelimited, wire_type)?;
         let len = decode_varint(buf)?;
         if (buf.remaining() as u64) < len {
-            return Err(invalid_data("buffer underflow"));
+            return Err(underflow());
         }
-        let limit = buf.limit();
-        buf.set_limit(len as usize);
-        value.clear();
-        value.reserve_exact(len as usize);
-        while buf.has_remaining() {
-            let len = {
-                let bytes = buf.bytes();
-                value.extend_from_slice(bytes);
-                bytes.len()
-            };
-            buf.advance(len);
+        value.replace_with(buf, len as usize, ctx);
+        Ok(())
+    }
+
+    /// Merges a bytes value which borrows from the buffer being decoded.
+    pub fn merge_borrowed<'a>(wire_type: WireType,
+                              value: &mut &'a [u8],
+                              buf: &mut Take<Cursor<&'a [u8]>>,
+                              _ctx: DecodeContext)
+                              -> Result<(), DecodeError> {
+        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        let len = decode_varint(buf)?;
+        if (buf.remaining() as u64) < len {
+            return Err(underflow());
+        }
+        *value = take_borrowed(buf, len as usize);
+        Ok(())
+    }
+
+    pub fn encode_repeated<A, B>(tag: u32, values: &Vec<A>, buf: &mut B) where A: AsRef<[u8]>, B: BufMut {
+        for value in values {
+            encode(tag, value, buf);
         }
-        buf.set_limit(limit - len as usize);
+    }
+
+    pub fn merge_repeated<A, B>(wire_type: WireType,
+                                values: &mut Vec<A>,
+                                buf: &mut Take<B>,
+                                ctx: DecodeContext)
+                                -> Result<(), DecodeError> where A: BytesAdapter, B: Buf {
+        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        let mut value = Default::default();
+        merge(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
+        Ok(())
+    }
+
+    pub fn merge_borrowed_repeated<'a>(wire_type: WireType,
+                                       values: &mut Vec<&'a [u8]>,
+                                       buf: &mut Take<Cursor<&'a [u8]>>,
+                                       ctx: DecodeContext)
+                                       -> Result<(), DecodeError> {
+        check_wire_type(WireType::LengthDelimited, wire_type)?;
+        let mut value: &'a [u8] = &[];
+        merge_borrowed(wire_type, &mut value, buf, ctx)?;
+        values.push(value);
         Ok(())
     }
 
-    length_delimited!(Vec<u8>);
+    pub fn encoded_len<A>(tag: u32, value: &A) -> usize where A: AsRef<[u8]> {
+        let len = value.as_ref().len();
+        key_len(tag) + encoded_len_varint(len as u64) + len
+    }
+
+    pub fn encoded_len_repeated<A>(tag: u32, values: &Vec<A>) -> usize where A: AsRef<[u8]> {
+        key_len(tag) * values.len() + values.iter().map(|value| {
+            let len = value.as_ref().len();
+            encoded_len_varint(len as u64) + len
+        }).sum::<usize>()
+    }
+
+    #[cfg(test)]
+    mod test {
+        use quickcheck::TestResult;
+
+        use super::*;
+        use super::super::test::{
+            check_collection_type,
+            check_type,
+        };
+
+        quickcheck! {
+            fn check_vec(value: Vec<u8>, tag: u32) -> TestResult {
+                check_type(value, tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)
+            }
+            fn check_bytes(value: Vec<u8>, tag: u32) -> TestResult {
+                check_type(Bytes::from(value), tag, WireType::LengthDelimited,
+                           encode, merge, encoded_len)
+            }
+            fn check_repeated_vec(value: Vec<Vec<u8>>, tag: u32) -> TestResult {
+                check_collection_type(value, tag, WireType::LengthDelimited,
+                                      encode_repeated, merge_repeated,
+                                      encoded_len_repeated)
+            }
+        }
+
+        #[test]
+        fn zero_copy() {
+            let mut buf = Vec::new();
+            encode(1, &vec![0xAB; 64], &mut buf);
+            let source = Bytes::from(buf);
+            let mut buf = Cursor::new(&source).take(source.len());
+            let ctx = DecodeContext::default().with_source(&source);
+
+            let (_, wire_type) = decode_key(&mut buf).unwrap();
+            let mut value = Bytes::new();
+            merge(wire_type, &mut value, &mut buf, ctx).unwrap();
+            assert_eq!(&value[..], &[0xAB; 64][..]);
+            assert_eq!(value.as_ptr(), source[2..].as_ptr());
+        }
+
+        #[test]
+        fn borrowed() {
+            let mut buf = Vec::new();
+            encode(1, &vec![0xAB; 64], &mut buf);
+            let mut source = Cursor::new(&buf[..]).take(buf.len());
+
+            let (_, wire_type) = decode_key(&mut source).unwrap();
+            let mut value: &[u8] = &[];
+            merge_borrowed(wire_type, &mut value, &mut source, DecodeContext::default()).unwrap();
+            assert_eq!(value, &[0xAB; 64][..]);
+            assert_eq!(value.as_ptr(), buf[2..].as_ptr());
+        }
+    }
 }
 
 pub mod message {
@@ -586,21 +1067,22 @@ pub mod message {
         msg.encode_raw(buf);
     }
 
-    pub fn merge<M, B>(wire_type: WireType, msg: &mut M, buf: &mut Take<B>) -> Result<()>
-    where M: Message,
+    pub fn merge<M, B>(wire_type: WireType, msg: &mut M, buf: &mut Take<B>, ctx: DecodeContext) -> Result<(), DecodeError>
+    where M: Merge<B>,
         B: Buf {
         check_wire_type(WireType::LengthDelimited, wire_type)?;
+        ctx.limit_reached()?;
         let len = decode_varint(buf)?;
         if len > buf.remaining() as u64 {
-            return Err(invalid_data("buffer underflow"));
+            return Err(underflow());
         }
 
         let len = len as usize;
         let limit = buf.limit();
         buf.set_limit(len);
-        msg.merge(buf)?;
-        buf.set_limit(limit - len);
-        Ok(())
+        let result = msg.merge_with_context(buf, ctx.enter_recursion());
+        restore_limit(buf, limit, len);
+        result
     }
 
     pub fn encode_repeated<M, B>(tag: u32, messages: &[M], buf: &mut B)
@@ -611,19 +1093,19 @@ pub mod message {
         }
     }
 
-    pub fn merge_repeated<M, B>(wire_type: WireType, messages: &mut Vec<M>, buf: &mut Take<B>) -> Result<()>
-    where M: Message,
+    pub fn merge_repeated<M, B>(wire_type: WireType, messages: &mut Vec<M>, buf: &mut Take<B>, ctx: DecodeContext) -> Result<(), DecodeError>
+    where M: Merge<B>,
         B: Buf {
         check_wire_type(WireType::LengthDelimited, wire_type)?;
         let mut msg = M::default();
-        merge(WireType::LengthDelimited, &mut msg, buf)?;
+        merge(WireType::LengthDelimited, &mut msg, buf, ctx)?;
         messages.push(msg);
         Ok(())
     }
 
     pub fn encoded_len<M>(tag: u32, msg: &M) -> usize where M: Message {
         let len = msg.encoded_len();
-        key_len(tag) + encoded_len_varint(len as u64) + msg.encoded_len()
+        key_len(tag) + encoded_len_varint(len as u64) + len
     }
 
     pub fn encoded_len_repeated<M>(tag: u32, messages: &[M]) -> usize where M: Message {
@@ -633,6 +1115,135 @@ pub mod message {
                       .map(|len| len + encoded_len_varint(len as u64))
                       .sum::<usize>()
     }
+
+    /// Encodes a message using the length recorded for it by `encoded_len_cached`.
+    pub fn encode_cached<M, B>(tag: u32, msg: &M, buf: &mut B, cache: &SizeCache)
+    where M: Message,
+        B: BufMut {
+        encode_key(tag, WireType::LengthDelimited, buf);
+        encode_varint(cache.take() as u64, buf);
+        msg.encode_cached(buf, cache);
+    }
+
+    pub fn encode_repeated_cached<M, B>(tag: u32, messages: &[M], buf: &mut B, cache: &SizeCache)
+    where M: Message,
+        B: BufMut {
+        for msg in messages {
+            encode_cached(tag, msg, buf, cache);
+        }
+    }
+
+    /// Returns the encoded length of a message, and records the length of the message and of
+    /// its nested messages in the cache.
+    pub fn encoded_len_cached<M>(tag: u32, msg: &M, cache: &SizeCache) -> usize where M: Message {
+        let slot = cache.reserve();
+        let len = msg.encoded_len_cached(cache);
+        cache.record(slot, len);
+        key_len(tag) + encoded_len_varint(len as u64) + len
+    }
+
+    pub fn encoded_len_repeated_cached<M>(tag: u32, messages: &[M], cache: &SizeCache) -> usize
+    where M: Message {
+        messages.iter().map(|msg| encoded_len_cached(tag, msg, cache)).sum::<usize>()
+    }
+
+    /// Returns the encoded length of the next message to be encoded with `encode_cached`.
+    pub fn peek_encoded_len(tag: u32, cache: &SizeCache) -> usize {
+        let len = cache.peek();
+        key_len(tag) + encoded_len_varint(len as u64) + len
+    }
+}
+
+/// Groups are encoded as a start group key, followed by the fields of the group's message, and
+/// terminated by an end group key with the same tag. Unlike nested messages, groups have no
+/// length prefix.
+pub mod group {
+    use super::*;
+
+    pub fn encode<M, B>(tag: u32, msg: &M, buf: &mut B)
+    where M: Message,
+        B: BufMut {
+        encode_key(tag, WireType::StartGroup, buf);
+        msg.encode_raw(buf);
+        encode_key(tag, WireType::EndGroup, buf);
+    }
+
+    pub fn merge<M, B>(tag: u32,
+                       wire_type: WireType,
+                       msg: &mut M,
+                       buf: &mut Take<B>,
+                       ctx: DecodeContext)
+                       -> Result<(), DecodeError>
+    where M: Merge<B>,
+        B: Buf {
+        check_wire_type(WireType::StartGroup, wire_type)?;
+        ctx.limit_reached()?;
+        let ctx = ctx.enter_recursion();
+        loop {
+            let (field_tag, field_wire_type) = decode_key(buf)?;
+            if field_wire_type == WireType::EndGroup {
+                return check_end_group(tag, field_tag);
+            }
+            msg.merge_field(field_tag, field_wire_type, buf, ctx)?;
+        }
+    }
+
+    pub fn encode_repeated<M, B>(tag: u32, messages: &[M], buf: &mut B)
+    where M: Message,
+        B: BufMut {
+        for msg in messages {
+            encode(tag, msg, buf);
+        }
+    }
+
+    pub fn merge_repeated<M, B>(tag: u32,
+                                wire_type: WireType,
+                                messages: &mut Vec<M>,
+                                buf: &mut Take<B>,
+                                ctx: DecodeContext)
+                                -> Result<(), DecodeError>
+    where M: Merge<B>,
+        B: Buf {
+        check_wire_type(WireType::StartGroup, wire_type)?;
+        let mut msg = M::default();
+        merge(tag, WireType::StartGroup, &mut msg, buf, ctx)?;
+        messages.push(msg);
+        Ok(())
+    }
+
+    pub fn encoded_len<M>(tag: u32, msg: &M) -> usize where M: Message {
+        2 * key_len(tag) + msg.encoded_len()
+    }
+
+    pub fn encoded_len_repeated<M>(tag: u32, messages: &[M]) -> usize where M: Message {
+        2 * key_len(tag) * messages.len()
+            + messages.iter().map(Message::encoded_len).sum::<usize>()
+    }
+
+    pub fn encode_cached<M, B>(tag: u32, msg: &M, buf: &mut B, cache: &SizeCache)
+    where M: Message,
+        B: BufMut {
+        encode_key(tag, WireType::StartGroup, buf);
+        msg.encode_cached(buf, cache);
+        encode_key(tag, WireType::EndGroup, buf);
+    }
+
+    pub fn encode_repeated_cached<M, B>(tag: u32, messages: &[M], buf: &mut B, cache: &SizeCache)
+    where M: Message,
+        B: BufMut {
+        for msg in messages {
+            encode_cached(tag, msg, buf, cache);
+        }
+    }
+
+    pub fn encoded_len_cached<M>(tag: u32, msg: &M, cache: &SizeCache) -> usize where M: Message {
+        2 * key_len(tag) + msg.encoded_len_cached(cache)
+    }
+
+    pub fn encoded_len_repeated_cached<M>(tag: u32, messages: &[M], cache: &SizeCache) -> usize
+    where M: Message {
+        messages.iter().map(|msg| encoded_len_cached(tag, msg, cache)).sum::<usize>()
+    }
 }
 
 /// Rust doesn't have a `Map` trait, so macros are currently the best way to be
@@ -667,14 +1278,15 @@ macro_rules! map {
         pub fn merge<K, V, B, KM, VM>(key_merge: KM,
                                       val_merge: VM,
                                       values: &mut $map_ty<K, V>,
-                                      buf: &mut Take<B>)
-                                      -> Result<()>
+                                      buf: &mut Take<B>,
+                                      ctx: DecodeContext)
+                                      -> Result<(), DecodeError>
         where K: Default + Eq + Hash + Ord,
               V: Default,
               B: Buf,
-              KM: Fn(WireType, &mut K, &mut Take<B>) -> Result<()>,
-              VM: Fn(WireType, &mut V, &mut Take<B>) -> Result<()> {
-            merge_with_default(key_merge, val_merge, V::default(), values, buf)
+              KM: Fn(WireType, &mut K, &mut Take<B>, DecodeContext) -> Result<(), DecodeError>,
+              VM: Fn(WireType, &mut V, &mut Take<B>, DecodeContext) -> Result<(), DecodeError> {
+            merge_with_default(key_merge, val_merge, V::default(), values, buf, ctx)
         }
 
         /// Generic protobuf map encode function.
@@ -736,34 +1348,41 @@ macro_rules! map {
                                                    val_merge: VM,
                                                    val_default: V,
                                                    values: &mut $map_ty<K, V>,
-                                                   buf: &mut Take<B>)
-                                                   -> Result<()>
+                                                   buf: &mut Take<B>,
+                                                   ctx: DecodeContext)
+                                                   -> Result<(), DecodeError>
         where K: Default + Eq + Hash + Ord,
               B: Buf,
-              KM: Fn(WireType, &mut K, &mut Take<B>) -> Result<()>,
-              VM: Fn(WireType, &mut V, &mut Take<B>) -> Result<()> {
+              KM: Fn(WireType, &mut K, &mut Take<B>, DecodeContext) -> Result<(), DecodeError>,
+              VM: Fn(WireType, &mut V, &mut Take<B>, DecodeContext) -> Result<(), DecodeError> {
+            ctx.limit_reached()?;
             let len = decode_varint(buf)?;
             if len > buf.remaining() as u64 {
-                return Err(invalid_data("buffer underflow"));
+                return Err(underflow());
             }
             let len = len as usize;
             let limit = buf.limit();
             buf.set_limit(len);
 
+            let ctx = ctx.enter_recursion();
             let mut key = Default::default();
             let mut val = val_default;
 
-            while buf.has_remaining() {
-                let (tag, wire_type) = decode_key(buf)?;
-                match tag {
-                    1 => key_merge(wire_type, &mut key, buf)?,
-                    2 => val_merge(wire_type, &mut val, buf)?,
-                    _ => (),
+            let result: Result<(), DecodeError> = (|| {
+                while buf.has_remaining() {
+                    let (tag, wire_type) = decode_key(buf)?;
+                    match tag {
+                        1 => key_merge(wire_type, &mut key, buf, ctx)?,
+                        2 => val_merge(wire_type, &mut val, buf, ctx)?,
+                        _ => (),
+                    }
                 }
-            }
+                Ok(())
+            })();
+            restore_limit(buf, limit, len);
+            result?;
 
             values.insert(key, val);
-            buf.set_limit(limit - len);
             Ok(())
         }
 
@@ -812,7 +1431,7 @@ mod test {
                          tag: u32,
                          wire_type: WireType,
                          encode: fn(u32, &T, &mut BytesMut),
-                         merge: fn(WireType, &mut T, &mut Take<Cursor<Bytes>>) -> Result<()>,
+                         merge: fn(WireType, &mut T, &mut Take<Cursor<Bytes>>, DecodeContext) -> Result<(), DecodeError>,
                          encoded_len: fn(u32, &T) -> usize)
                          -> TestResult
     where T: Debug + Default + PartialEq {
@@ -870,7 +1489,7 @@ mod test {
         }
 
         let mut roundtrip_value = T::default();
-        if let Err(error) = merge(wire_type, &mut roundtrip_value, &mut buf) {
+        if let Err(error) = merge(wire_type, &mut roundtrip_value, &mut buf, DecodeContext::default()) {
             return TestResult::error(error.to_string());
         };
 
@@ -895,7 +1514,7 @@ mod test {
                                              -> TestResult
     where T: Debug + Default + PartialEq,
           E: FnOnce(u32, &T, &mut BytesMut),
-          M: FnMut(WireType, &mut T, &mut Take<Cursor<Bytes>>) -> Result<()>,
+          M: FnMut(WireType, &mut T, &mut Take<Cursor<Bytes>>, DecodeContext) -> Result<(), DecodeError>,
           L: FnOnce(u32, &T) -> usize {
 
         if tag > MAX_TAG || tag < MIN_TAG {
@@ -934,7 +1553,7 @@ mod test {
                             wire_type, decoded_wire_type));
             }
 
-            if let Err(error) = merge(wire_type, &mut roundtrip_value, &mut buf) {
+            if let Err(error) = merge(wire_type, &mut roundtrip_value, &mut buf, DecodeContext::default()) {
                 return TestResult::error(error.to_string());
             };
         }
@@ -971,6 +1590,68 @@ mod test {
         check(16_384, &[0b1000_0000, 0b1000_0000, 0b0000_0001]);
     }
 
+    #[test]
+    fn decode_errors() {
+        let mut buf = Bytes::from(&[0x80, 0x80][..]).into_buf();
+        assert_eq!(decode_varint(&mut buf).unwrap_err().kind(), &DecodeErrorKind::TruncatedVarint);
+
+        let mut buf = Bytes::from(&[0xFF; 11][..]).into_buf();
+        assert_eq!(decode_varint(&mut buf).unwrap_err().kind(), &DecodeErrorKind::VarintOverflow);
+
+        let mut buf = Bytes::from(&[0x0E][..]).into_buf();
+        assert_eq!(decode_key(&mut buf).unwrap_err().kind(), &DecodeErrorKind::InvalidWireType(6));
+
+        let mut buf = Bytes::from(&[0x00][..]).into_buf();
+        assert_eq!(decode_key(&mut buf).unwrap_err().kind(), &DecodeErrorKind::InvalidKey(0));
+
+        assert_eq!(check_wire_type(WireType::Varint, WireType::LengthDelimited).unwrap_err().kind(),
+                   &DecodeErrorKind::UnexpectedWireType {
+                       expected: WireType::Varint,
+                       actual: WireType::LengthDelimited,
+                   });
+
+        let mut buf = Bytes::from(&[0x02, 0xC3, 0x28][..]).into_buf().take(3);
+        let mut value = String::new();
+        assert_eq!(string::merge(WireType::LengthDelimited, &mut value, &mut buf,
+                                 DecodeContext::default()).unwrap_err().kind(),
+                   &DecodeErrorKind::InvalidUtf8);
+
+        let mut buf = Bytes::from(&[0x03, 0x00][..]).into_buf().take(2);
+        let mut value = Vec::new();
+        assert_eq!(bytes::merge(WireType::LengthDelimited, &mut value, &mut buf,
+                                DecodeContext::default()).unwrap_err().kind(),
+                   &DecodeErrorKind::BufferUnderflow);
+    }
+
+    #[test]
+    fn skip_group() {
+        // Group 1 containing a varint, a nested group 2, and a length-delimited field, followed by
+        // a trailing varint field.
+        let mut buf = Bytes::from(&[0x08, 0x96, 0x01,
+                                    0x13, 0x18, 0x01, 0x14,
+                                    0x22, 0x01, 0x00,
+                                    0x0C,
+                                    0x28, 0x01][..]).into_buf();
+        skip_field(WireType::StartGroup, 1, &mut buf, DecodeContext::default()).unwrap();
+        assert_eq!(decode_key(&mut buf).unwrap(), (5, WireType::Varint));
+
+        // Mismatched end group tag.
+        let mut buf = Bytes::from(&[0x08, 0x01, 0x14][..]).into_buf();
+        assert_eq!(skip_field(WireType::StartGroup, 1, &mut buf,
+                              DecodeContext::default()).unwrap_err().kind(),
+                   &DecodeErrorKind::UnexpectedEndGroup(2));
+
+        // Unmatched end group key.
+        let mut buf = Bytes::from(&[][..]).into_buf();
+        assert_eq!(skip_field(WireType::EndGroup, 1, &mut buf,
+                              DecodeContext::default()).unwrap_err().kind(),
+                   &DecodeErrorKind::UnexpectedEndGroup(1));
+
+        // Truncated group.
+        let mut buf = Bytes::from(&[0x08, 0x01][..]).into_buf();
+        assert!(skip_field(WireType::StartGroup, 1, &mut buf, DecodeContext::default()).is_err());
+    }
+
     /// This big bowl o' macro soup generates a quickcheck encoding test for each
     /// combination of map type, scalar map key, and value type.
     /// TODO: these tests take a long time to compile, can this be improved?
@@ -1019,12 +1700,13 @@ mod test {
                                                                     values,
                                                                     buf)
                                               },
-                                              |wire_type, values, buf| {
+                                              |wire_type, values, buf, ctx| {
                                                   check_wire_type(WireType::LengthDelimited, wire_type)?;
                                                   $mod_name::merge($key_proto::merge,
                                                                    $val_proto::merge,
                                                                    values,
-                                                                   buf)
+                                                                   buf,
+                                                                   ctx)
                                               },
                                               |tag, values| {
                                                   $mod_name::encoded_len($key_proto::encoded_len,
@@ -0,0 +1,123 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::hash::{
+    Hash,
+    Hasher,
+};
+
+/// A Protocol Buffers enumeration.
+///
+/// `#[derive(Enumeration)]` implements `Enumeration` for Rust enums which correspond to Protobuf
+/// enumerations. Enumeration fields are held as `i32` values in messages, so that values which
+/// are not known variants are preserved, and the associated functions of `Enumeration` operate
+/// on `i32` values.
+pub trait Enumeration: Copy + Debug + Default + Into<i32> {
+
+    /// Returns the enumeration variant with the value, or `None` if the value is not a variant of
+    /// the enumeration.
+    fn from_i32(value: i32) -> Option<Self>;
+
+    /// Returns the Protobuf name of the enumeration value, as it is written in the `.proto` file,
+    /// or `None` if the value is not a variant of the enumeration.
+    fn value_name(value: i32) -> Option<&'static str>;
+
+    /// Returns the value of the enumeration variant with the Protobuf name, or `None` if the
+    /// enumeration has no such variant.
+    fn from_value_name(name: &str) -> Option<i32>;
+}
+
+/// An enumeration field value which is either a known variant of the enumeration, or an unknown
+/// `i32` value.
+///
+/// Open enumerations are an alternative to holding enumeration fields as `i32` values. Unknown
+/// values are preserved, so that they round-trip through decoding and encoding. Open
+/// enumerations are compared and hashed by their `i32` value.
+#[derive(Clone, Copy, Debug)]
+pub enum OpenEnum<E> {
+    /// A known variant of the enumeration.
+    Known(E),
+    /// A value which is not a variant of the enumeration.
+    Unknown(i32),
+}
+
+impl <E> OpenEnum<E> where E: Enumeration {
+
+    /// Returns the open enumeration holding the value.
+    pub fn from_i32(value: i32) -> OpenEnum<E> {
+        match E::from_i32(value) {
+            Some(known) => OpenEnum::Known(known),
+            None => OpenEnum::Unknown(value),
+        }
+    }
+
+    /// Returns the `i32` value of the open enumeration.
+    pub fn to_i32(&self) -> i32 {
+        match *self {
+            OpenEnum::Known(known) => known.into(),
+            OpenEnum::Unknown(value) => value,
+        }
+    }
+
+    /// Returns the known variant, or `None` if the value is unknown.
+    pub fn known(&self) -> Option<E> {
+        match *self {
+            OpenEnum::Known(known) => Some(known),
+            OpenEnum::Unknown(_) => None,
+        }
+    }
+
+    /// Returns `true` if the value is a known variant of the enumeration.
+    pub fn is_known(&self) -> bool {
+        self.known().is_some()
+    }
+}
+
+impl <E> Default for OpenEnum<E> where E: Enumeration {
+    fn default() -> OpenEnum<E> {
+        OpenEnum::Known(E::default())
+    }
+}
+
+impl <E> From<E> for OpenEnum<E> where E: Enumeration {
+    fn from(known: E) -> OpenEnum<E> {
+        OpenEnum::Known(known)
+    }
+}
+
+impl <E> From<OpenEnum<E>> for i32 where E: Enumeration {
+    fn from(value: OpenEnum<E>) -> i32 {
+        value.to_i32()
+    }
+}
+
+impl <E> PartialEq for OpenEnum<E> where E: Enumeration {
+    fn eq(&self, other: &OpenEnum<E>) -> bool {
+        self.to_i32() == other.to_i32()
+    }
+}
+
+impl <E> Eq for OpenEnum<E> where E: Enumeration {}
+
+impl <E> PartialEq<E> for OpenEnum<E> where E: Enumeration {
+    fn eq(&self, other: &E) -> bool {
+        self.to_i32() == (*other).into()
+    }
+}
+
+impl <E> PartialOrd for OpenEnum<E> where E: Enumeration {
+    fn partial_cmp(&self, other: &OpenEnum<E>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl <E> Ord for OpenEnum<E> where E: Enumeration {
+    fn cmp(&self, other: &OpenEnum<E>) -> Ordering {
+        self.to_i32().cmp(&other.to_i32())
+    }
+}
+
+impl <E> Hash for OpenEnum<E> where E: Enumeration {
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        self.to_i32().hash(state);
+    }
+}
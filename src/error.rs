@@ -0,0 +1,236 @@
+//! Protobuf encoding and decoding errors.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io;
+
+use encoding::WireType;
+
+/// The reason that decoding a Protobuf message failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The buffer ended in the middle of a varint.
+    TruncatedVarint,
+    /// A varint was longer than the maximum of 10 bytes.
+    VarintOverflow,
+    /// A field key was not valid, either because the tag was zero, or because the key overflowed
+    /// a `u32`.
+    InvalidKey(u64),
+    /// A field key contained a wire type value which does not correspond to a known wire type.
+    InvalidWireType(u8),
+    /// A field was encoded with a wire type which does not match the field's type.
+    UnexpectedWireType {
+        expected: WireType,
+        actual: WireType,
+    },
+    /// An end group key did not match the tag of the group being decoded, or occurred outside of
+    /// a group.
+    UnexpectedEndGroup(u32),
+    /// A `string` field contained data which is not valid UTF-8.
+    InvalidUtf8,
+    /// A length-delimited or fixed-width value extended past the end of the buffer.
+    BufferUnderflow,
+    /// Nested messages exceeded the recursion limit of the `DecodeContext`.
+    RecursionLimitReached,
+    /// Required fields of the message, or of its nested messages, were not set. Holds the paths
+    /// of the missing fields, e.g. `foo.bar`, `foo.baz[2].qux` or `foo.map["key"].bar`.
+    MissingRequiredFields(Vec<String>),
+    /// A failure which is not covered by the other error kinds.
+    Other(Cow<'static, str>),
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeErrorKind::TruncatedVarint => f.write_str("truncated varint"),
+            DecodeErrorKind::VarintOverflow => f.write_str("varint overflow"),
+            DecodeErrorKind::InvalidKey(key) => write!(f, "invalid field key: {}", key),
+            DecodeErrorKind::InvalidWireType(value) => write!(f, "invalid wire type value: {}", value),
+            DecodeErrorKind::UnexpectedWireType { expected, actual } => {
+                write!(f, "invalid wire type: {:?} (expected {:?})", actual, expected)
+            },
+            DecodeErrorKind::UnexpectedEndGroup(tag) => write!(f, "unexpected end group tag: {}", tag),
+            DecodeErrorKind::InvalidUtf8 => f.write_str("invalid string value: data is not UTF-8 encoded"),
+            DecodeErrorKind::BufferUnderflow => f.write_str("buffer underflow"),
+            DecodeErrorKind::RecursionLimitReached => f.write_str("recursion limit reached"),
+            DecodeErrorKind::MissingRequiredFields(ref paths) => {
+                write!(f, "missing required fields: {}", paths.join(", "))
+            },
+            DecodeErrorKind::Other(ref description) => f.write_str(description),
+        }
+    }
+}
+
+/// A Protobuf message decoding error.
+///
+/// `DecodeError` indicates that the input buffer does not contain a valid Protobuf message. The
+/// error details are available through `kind`, `stack` and `offset`, and should be considered
+/// 'best effort': in general it is not possible to exactly pinpoint why data is malformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    /// A 'call stack' of the message and field names which were being decoded when the error
+    /// occurred, ordered from the innermost message to the outermost.
+    stack: Vec<(&'static str, &'static str)>,
+    offset: Option<usize>,
+}
+
+impl DecodeError {
+
+    /// Creates a new `DecodeError` of the given kind.
+    pub fn new(kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            kind: kind,
+            stack: Vec::new(),
+            offset: None,
+        }
+    }
+
+    /// Creates a new `DecodeError` with a free-form description.
+    pub fn other<S>(description: S) -> DecodeError where S: Into<Cow<'static, str>> {
+        DecodeError::new(DecodeErrorKind::Other(description.into()))
+    }
+
+    /// Returns the reason that decoding failed.
+    pub fn kind(&self) -> &DecodeErrorKind {
+        &self.kind
+    }
+
+    /// Returns the `(message, field)` names which were being decoded when the error occurred,
+    /// ordered from the innermost message to the outermost.
+    pub fn stack(&self) -> &[(&'static str, &'static str)] {
+        &self.stack
+    }
+
+    /// Returns the offset, in bytes from the start of the input buffer, at which decoding failed.
+    ///
+    /// The offset is only known when decoding through `Message::decode`,
+    /// `Message::decode_length_delimited`, or `Message::merge_length_delimited`.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Pushes a (message, field) name location pair on to the location stack.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn push(&mut self, message: &'static str, field: &'static str) {
+        self.stack.push((message, field));
+    }
+
+    /// Sets the offset in the input buffer at which decoding failed.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = Some(offset);
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("failed to decode Protobuf message: ")?;
+        for &(message, field) in self.stack.iter().rev() {
+            write!(f, "{}.{}: ", message, field)?;
+        }
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset {
+            write!(f, " (at byte offset {})", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for DecodeError {
+    fn description(&self) -> &str {
+        "failed to decode Protobuf message"
+    }
+}
+
+impl From<DecodeErrorKind> for DecodeError {
+    fn from(kind: DecodeErrorKind) -> DecodeError {
+        DecodeError::new(kind)
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(error: DecodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// A Protobuf message encoding error.
+///
+/// `EncodeError` always indicates that a message failed to encode because the provided buffer had
+/// insufficient capacity. Message encoding is otherwise infallible.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EncodeError {
+    required: usize,
+    remaining: usize,
+}
+
+impl EncodeError {
+
+    /// Creates a new `EncodeError`.
+    pub fn new(required: usize, remaining: usize) -> EncodeError {
+        EncodeError {
+            required: required,
+            remaining: remaining,
+        }
+    }
+
+    /// Returns the required buffer capacity to encode the message.
+    pub fn required_capacity(&self) -> usize {
+        self.required
+    }
+
+    /// Returns the remaining length in the provided buffer at the time of encoding.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to encode Protobuf message; insufficient buffer capacity (required: {}, remaining: {})",
+               self.required, self.remaining)
+    }
+}
+
+impl error::Error for EncodeError {
+    fn description(&self) -> &str {
+        "failed to encode Protobuf message: insufficient buffer capacity"
+    }
+}
+
+impl From<EncodeError> for io::Error {
+    fn from(error: EncodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// An error returned when an `i32` value or a Protobuf name does not correspond to a variant of
+/// an enumeration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownEnumValue {
+    /// An `i32` value which is not a variant of the enumeration.
+    Value(i32),
+    /// A name which is not the Protobuf name of a variant of the enumeration.
+    Name(String),
+}
+
+impl fmt::Display for UnknownEnumValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownEnumValue::Value(value) => write!(f, "unknown enumeration value: {}", value),
+            UnknownEnumValue::Name(ref name) => write!(f, "unknown enumeration value name: {}", name),
+        }
+    }
+}
+
+impl error::Error for UnknownEnumValue {
+    fn description(&self) -> &str {
+        "unknown enumeration value"
+    }
+}
@@ -0,0 +1,415 @@
+//! Support for proto2 extensions.
+//!
+//! Extension fields are declared outside of the message they extend, in `extend` blocks. Each
+//! extension is described by a typed `Extension` descriptor, which is generated by
+//! `prost-codegen`. Messages which declare extension ranges hold an `ExtensionSet`, and implement
+//! `Extendable` to provide typed access to the extension fields in the set.
+//!
+//! Extension fields are only recognized while decoding if their descriptor has been added to the
+//! `ExtensionRegistry` held by the `DecodeContext`. Unregistered extension fields are treated as
+//! unknown fields.
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Cursor;
+use std::marker::PhantomData;
+
+use bytes::{
+    Buf,
+    BufMut,
+    Take,
+};
+
+use encoding::*;
+use DecodeError;
+use Message;
+use UnknownFields;
+
+/// A typed descriptor of an extension field of type `T`, which extends message `M`.
+///
+/// Extension descriptors are generated by `prost-codegen` as constants.
+pub struct Extension<M, T> {
+    /// The fully-qualified Protobuf name of the extension field.
+    pub name: &'static str,
+    /// The extension field tag.
+    pub tag: u32,
+    /// Encodes an extension value, including the field key.
+    pub encode: fn(u32, &T, &mut Vec<u8>),
+    /// Decodes an extension value, and merges it into the existing value.
+    pub merge: fn(WireType, &mut T, &mut Take<Cursor<&[u8]>>, DecodeContext) -> Result<(), DecodeError>,
+    /// The extended message type.
+    pub extendee: PhantomData<M>,
+}
+
+impl <M, T> Extension<M, T> where T: Default {
+
+    /// Decodes the extension value from the encoded fields of the extension.
+    fn decode(&self, buf: &[u8], ctx: DecodeContext) -> Result<T, DecodeError> {
+        let mut value = T::default();
+        let mut buf = Cursor::new(buf).take(buf.len());
+        while buf.has_remaining() {
+            let (_, wire_type) = decode_key(&mut buf)?;
+            (self.merge)(wire_type, &mut value, &mut buf, ctx)?;
+        }
+        Ok(value)
+    }
+}
+
+impl <M, T> Clone for Extension<M, T> {
+    fn clone(&self) -> Extension<M, T> {
+        Extension {
+            name: self.name,
+            tag: self.tag,
+            encode: self.encode,
+            merge: self.merge,
+            extendee: PhantomData,
+        }
+    }
+}
+
+impl <M, T> Copy for Extension<M, T> {}
+
+impl <M, T> fmt::Debug for Extension<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extension")
+         .field("name", &self.name)
+         .field("tag", &self.tag)
+         .finish()
+    }
+}
+
+/// The extension fields of a message.
+///
+/// Extension fields are held in their encoded form, and are decoded when accessed through
+/// `Extendable::get_extension`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionSet {
+    /// The encoded fields, including keys, of each extension, by tag.
+    fields: BTreeMap<u32, Vec<u8>>,
+}
+
+impl ExtensionSet {
+
+    /// Creates a new, empty set of extensions.
+    pub fn new() -> ExtensionSet {
+        ExtensionSet::default()
+    }
+
+    /// Returns `true` if no extensions are set.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the number of extensions which are set.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns `true` if the extension with the given tag is set.
+    pub fn contains(&self, tag: u32) -> bool {
+        self.fields.contains_key(&tag)
+    }
+
+    /// Removes all extensions.
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// Merges the extensions of `other` into `self`. Since extensions are held in their encoded
+    /// form, the encoded fields of `other` are appended, which has the same result as merging
+    /// the decoded values.
+    pub fn merge_from(&mut self, other: &ExtensionSet) {
+        for (&tag, field) in &other.fields {
+            self.fields.entry(tag).or_insert_with(Vec::new).extend_from_slice(field);
+        }
+    }
+
+    /// Encodes the extensions, and writes them to the buffer.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode<B>(&self, buf: &mut B) where B: BufMut {
+        for field in self.fields.values() {
+            buf.put_slice(field);
+        }
+    }
+
+    /// Decodes the value of an extension field of message `M` from the buffer, and retains it.
+    /// Returns `false` without consuming the value if the extension is not registered with the
+    /// decode context.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn merge_field<M, B>(&mut self,
+                             tag: u32,
+                             wire_type: WireType,
+                             buf: &mut Take<B>,
+                             ctx: DecodeContext)
+                             -> Result<bool, DecodeError> where M: Any, B: Buf {
+        let extension = match ctx.extension_registry().and_then(|registry| registry.get::<M>(tag)) {
+            Some(extension) => extension,
+            None => return Ok(false),
+        };
+
+        let mut field = UnknownFields::new();
+        field.merge_field(tag, wire_type, buf, ctx)?;
+        let mut encoded = Vec::with_capacity(field.encoded_len());
+        field.encode(&mut encoded);
+        (extension.validate)(&encoded, ctx).map_err(|mut error| {
+            error.push("extension", extension.name);
+            error
+        })?;
+
+        self.fields.entry(tag).or_insert_with(Vec::new).extend_from_slice(&encoded);
+        Ok(true)
+    }
+
+    /// Returns the encoded length of the extensions.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encoded_len(&self) -> usize {
+        self.fields.values().map(Vec::len).sum()
+    }
+}
+
+/// A message which declares extension ranges, and holds extension fields in an `ExtensionSet`.
+///
+/// `Extendable` is implemented by `#[derive(Message)]` for messages with a field annotated with
+/// `#[prost(extension_set)]`.
+pub trait Extendable: Message + Sized + 'static {
+
+    /// Returns the extension fields of the message.
+    #[doc(hidden)]
+    fn extension_set(&self) -> &ExtensionSet;
+
+    /// Returns the extension fields of the message.
+    #[doc(hidden)]
+    fn extension_set_mut(&mut self) -> &mut ExtensionSet;
+
+    /// Returns `true` if the extension is set.
+    fn has_extension<T>(&self, extension: &Extension<Self, T>) -> bool {
+        self.extension_set().contains(extension.tag)
+    }
+
+    /// Returns the value of the extension, or `None` if the extension is not set. An error is
+    /// returned if the value can not be decoded as the extension's type.
+    fn get_extension<T>(&self, extension: &Extension<Self, T>) -> Result<Option<T>, DecodeError>
+    where T: Default {
+        let field = match self.extension_set().fields.get(&extension.tag) {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        extension.decode(field, DecodeContext::default()).map(Some).map_err(|mut error| {
+            error.push("extension", extension.name);
+            error
+        })
+    }
+
+    /// Sets the value of the extension, replacing the existing value.
+    fn set_extension<T>(&mut self, extension: &Extension<Self, T>, value: T) {
+        let mut field = Vec::new();
+        (extension.encode)(extension.tag, &value, &mut field);
+        if field.is_empty() {
+            self.clear_extension(extension);
+        } else {
+            self.extension_set_mut().fields.insert(extension.tag, field);
+        }
+    }
+
+    /// Clears the value of the extension.
+    fn clear_extension<T>(&mut self, extension: &Extension<Self, T>) {
+        self.extension_set_mut().fields.remove(&extension.tag);
+    }
+}
+
+/// A type-erased extension descriptor held by an `ExtensionRegistry`.
+struct RegisteredExtension {
+    name: &'static str,
+    /// Checks that the encoded fields of the extension can be decoded.
+    validate: Box<Fn(&[u8], DecodeContext) -> Result<(), DecodeError> + Send + Sync>,
+}
+
+/// A set of extensions which are recognized while decoding.
+///
+/// The registry is passed to decode functions through the `DecodeContext`:
+///
+/// ```rust,ignore
+/// let mut registry = ExtensionRegistry::new();
+/// registry.register(&foo::BAR);
+/// let ctx = DecodeContext::default().with_extension_registry(&registry);
+/// let foo = Foo::decode_with_context(&mut buf, ctx)?;
+/// ```
+#[derive(Default)]
+pub struct ExtensionRegistry {
+    extensions: HashMap<(TypeId, u32), RegisteredExtension>,
+}
+
+impl ExtensionRegistry {
+
+    /// Creates a new, empty extension registry.
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry::default()
+    }
+
+    /// Adds an extension to the registry, replacing any extension of the same message with the
+    /// same tag.
+    pub fn register<M, T>(&mut self, extension: &Extension<M, T>)
+    where M: Extendable, T: Default + 'static {
+        let extension = *extension;
+        let registered = RegisteredExtension {
+            name: extension.name,
+            validate: Box::new(move |buf, ctx| extension.decode(buf, ctx).map(|_| ())),
+        };
+        self.extensions.insert((TypeId::of::<M>(), extension.tag), registered);
+    }
+
+    /// Returns `true` if the registry contains the extension of message `M` with the given tag.
+    pub fn contains<M>(&self, tag: u32) -> bool where M: Any {
+        self.get::<M>(tag).is_some()
+    }
+
+    /// Returns the number of extensions in the registry.
+    pub fn len(&self) -> usize {
+        self.extensions.len()
+    }
+
+    /// Returns `true` if the registry contains no extensions.
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    fn get<M>(&self, tag: u32) -> Option<&RegisteredExtension> where M: Any {
+        self.extensions.get(&(TypeId::of::<M>(), tag))
+    }
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+         .entries(self.extensions.values().map(|extension| extension.name))
+         .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::marker::PhantomData;
+
+    use bytes::Buf;
+
+    use Merge;
+    use super::*;
+
+    const TAG: u32 = 100;
+
+    /// An extendable message with no fields of its own.
+    #[derive(Debug, Default, PartialEq)]
+    struct Extendee {
+        extensions: ExtensionSet,
+    }
+
+    impl Message for Extendee {
+        fn encode_raw<B>(&self, buf: &mut B) where B: BufMut {
+            self.extensions.encode(buf);
+        }
+        fn encoded_len(&self) -> usize {
+            self.extensions.encoded_len()
+        }
+    }
+
+    impl <B> Merge<B> for Extendee where B: Buf {
+        fn merge_field(&mut self,
+                       tag: u32,
+                       wire_type: WireType,
+                       buf: &mut Take<B>,
+                       ctx: DecodeContext)
+                       -> Result<(), DecodeError> {
+            if !self.extensions.merge_field::<Extendee, _>(tag, wire_type, buf, ctx)? {
+                skip_field(wire_type, tag, buf, ctx)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Extendable for Extendee {
+        fn extension_set(&self) -> &ExtensionSet {
+            &self.extensions
+        }
+        fn extension_set_mut(&mut self) -> &mut ExtensionSet {
+            &mut self.extensions
+        }
+    }
+
+    const REPEATED: Extension<Extendee, Vec<i32>> = Extension {
+        name: "test.repeated",
+        tag: TAG,
+        encode: |tag, values, buf| int32::encode_packed(tag, values, buf),
+        merge: |wire_type, values, buf, ctx| int32::merge_repeated(wire_type, values, buf, ctx),
+        extendee: PhantomData,
+    };
+
+    const STRING: Extension<Extendee, String> = Extension {
+        name: "test.string",
+        tag: TAG,
+        encode: |tag, value, buf| string::encode(tag, value, buf),
+        merge: |wire_type, value, buf, ctx| string::merge(wire_type, value, buf, ctx),
+        extendee: PhantomData,
+    };
+
+    fn decode(buf: &[u8], registry: &ExtensionRegistry) -> Result<Extendee, DecodeError> {
+        let ctx = DecodeContext::default().with_extension_registry(registry);
+        Extendee::decode_with_context(&mut Cursor::new(buf).take(buf.len()), ctx)
+    }
+
+    #[test]
+    fn extensions() {
+        let mut msg = Extendee::default();
+        assert!(!msg.has_extension(&REPEATED));
+        assert_eq!(msg.get_extension(&REPEATED), Ok(None));
+
+        msg.set_extension(&REPEATED, vec![1, 2, 3]);
+        assert!(msg.has_extension(&REPEATED));
+        assert_eq!(msg.get_extension(&REPEATED), Ok(Some(vec![1, 2, 3])));
+
+        let mut buf = Vec::new();
+        msg.encode(&mut buf).unwrap();
+        assert_eq!(buf.len(), msg.encoded_len());
+
+        // Unregistered extensions are skipped.
+        let registry = ExtensionRegistry::new();
+        assert_eq!(decode(&buf, &registry).unwrap(), Extendee::default());
+
+        let mut registry = ExtensionRegistry::new();
+        registry.register(&REPEATED);
+        assert!(registry.contains::<Extendee>(TAG));
+        assert_eq!(decode(&buf, &registry).unwrap(), msg);
+
+        // Repeated occurrences of an extension field are merged.
+        let mut concatenated = buf.clone();
+        concatenated.extend_from_slice(&buf);
+        let roundtrip = decode(&concatenated, &registry).unwrap();
+        assert_eq!(roundtrip.get_extension(&REPEATED), Ok(Some(vec![1, 2, 3, 1, 2, 3])));
+
+        // Setting an empty repeated extension clears it.
+        msg.set_extension(&REPEATED, Vec::new());
+        assert!(!msg.has_extension(&REPEATED));
+        assert!(msg.extension_set().is_empty());
+
+        // Registered extensions are validated when decoding.
+        let mut buf = Vec::new();
+        bytes::encode(TAG, &vec![0xC3, 0x28], &mut buf);
+        let mut registry = ExtensionRegistry::new();
+        registry.register(&STRING);
+        let error = decode(&buf, &registry).unwrap_err();
+        assert_eq!(error.stack(), &[("extension", "test.string")]);
+
+        // Malformed values are reported when the extension is accessed.
+        let mut msg = Extendee::default();
+        msg.extensions.fields.insert(TAG, buf);
+        let error = msg.get_extension(&STRING).unwrap_err();
+        assert_eq!(error.stack(), &[("extension", "test.string")]);
+    }
+}
@@ -0,0 +1,1242 @@
+//! Encoding and decoding messages in the Protobuf JSON mapping.
+//!
+//! `#[derive(Message)]` implements `JsonMessage` for owned messages, which converts messages to
+//! and from JSON according to the [proto3 JSON mapping][1]:
+//!
+//!  * fields are keyed by their lowerCamelCase JSON name, and either the JSON name or the
+//!    original field name is accepted when decoding,
+//!  * fields with default values are omitted,
+//!  * enumeration values are encoded as their names,
+//!  * 64-bit integers are encoded as strings, and `bytes` values as base64 strings,
+//!  * non-finite floating point values are encoded as the strings `"NaN"`, `"Infinity"` and
+//!    `"-Infinity"`,
+//!  * `null` decodes to the default value of a field.
+//!
+//! Unknown fields and extension fields are not included in the JSON representation.
+//!
+//! [1]: https://developers.google.com/protocol-buffers/docs/proto3#json
+
+use std::borrow::Cow;
+use std::char;
+use std::error;
+use std::fmt::{
+    self,
+    Write,
+};
+
+use bytes::Bytes;
+
+use Enumeration;
+use Message;
+
+/// The maximum nesting depth of arrays and objects accepted by `Value::parse`.
+pub const RECURSION_LIMIT: u32 = 100;
+
+/// A JSON value.
+///
+/// Numbers hold their literal text, so that 64-bit integers are decoded without a loss of
+/// precision. Objects hold their members in order, including any duplicate keys.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+
+    /// Parses a JSON document.
+    ///
+    /// The document must be strictly valid JSON: comments, trailing commas, single quoted
+    /// strings, and unquoted object keys are rejected.
+    pub fn parse(json: &str) -> Result<Value, JsonError> {
+        let mut parser = Parser {
+            json: json,
+            pos: 0,
+        };
+        let value = parser.parse_value(RECURSION_LIMIT)?;
+        parser.skip_whitespace();
+        if parser.pos != json.len() {
+            return parser.error("trailing characters");
+        }
+        Ok(value)
+    }
+
+    /// Returns `true` if the value is `null`.
+    pub fn is_null(&self) -> bool {
+        match *self {
+            Value::Null => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the name of the value's JSON type, for use in error messages.
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Bool(..) => "bool",
+            Value::Number(..) => "number",
+            Value::String(..) => "string",
+            Value::Array(..) => "array",
+            Value::Object(..) => "object",
+        }
+    }
+}
+
+/// Writes the value as compact JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(ref value) => f.write_str(value),
+            Value::String(ref value) => write_string(value, f),
+            Value::Array(ref values) => {
+                f.write_char('[')?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            },
+            Value::Object(ref fields) => {
+                f.write_char('{')?;
+                for (idx, &(ref key, ref value)) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(key, f)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            },
+        }
+    }
+}
+
+/// Writes a quoted and escaped JSON string.
+fn write_string(value: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_char('"')?;
+    let mut start = 0;
+    for (idx, c) in value.char_indices() {
+        let escape = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\u{8}' => "\\b",
+            '\u{c}' => "\\f",
+            c if (c as u32) < 0x20 => "",
+            _ => continue,
+        };
+        f.write_str(&value[start..idx])?;
+        if escape.is_empty() {
+            write!(f, "\\u{:04x}", c as u32)?;
+        } else {
+            f.write_str(escape)?;
+        }
+        start = idx + c.len_utf8();
+    }
+    f.write_str(&value[start..])?;
+    f.write_char('"')
+}
+
+/// A strict JSON parser.
+struct Parser<'a> {
+    json: &'a str,
+    pos: usize,
+}
+
+impl <'a> Parser<'a> {
+
+    fn error<T>(&self, description: &str) -> Result<T, JsonError> {
+        Err(JsonError::new(format!("invalid JSON: {} at offset {}", description, self.pos)))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// Consumes the expected byte, after any whitespace.
+    fn expect(&mut self, expected: u8) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", expected as char))
+        }
+    }
+
+    fn parse_value(&mut self, depth: u32) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => self.error("unexpected end of input"),
+            Some(b'n') => self.parse_literal("null", Value::Null),
+            Some(b't') => self.parse_literal("true", Value::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Value::Bool(false)),
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(b'[') => {
+                if depth == 0 {
+                    return self.error("recursion limit reached");
+                }
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.parse_value(depth - 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(values));
+                        },
+                        _ => return self.error("expected ',' or ']'"),
+                    }
+                }
+            },
+            Some(b'{') => {
+                if depth == 0 {
+                    return self.error("recursion limit reached");
+                }
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return self.error("expected string key");
+                    }
+                    let key = self.parse_string()?;
+                    self.expect(b':')?;
+                    let value = self.parse_value(depth - 1)?;
+                    fields.push((key, value));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(fields));
+                        },
+                        _ => return self.error("expected ',' or '}'"),
+                    }
+                }
+            },
+            Some(b) if b == b'-' || is_digit(b) => {
+                match number_len(&self.json.as_bytes()[self.pos..]) {
+                    Some(len) => {
+                        let number = self.json[self.pos..self.pos + len].to_string();
+                        self.pos += len;
+                        Ok(Value::Number(number))
+                    },
+                    None => self.error("invalid number"),
+                }
+            },
+            Some(_) => self.error("unexpected character"),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, JsonError> {
+        if self.json[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            self.error("unexpected character")
+        }
+    }
+
+    /// Parses a string, starting at the opening quote.
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(b) = self.peek() {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The scan only stops at ASCII characters, which are always on a UTF-8 boundary.
+            value.push_str(&self.json[start..self.pos]);
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let c = self.parse_unicode_escape()?;
+                            value.push(c);
+                            continue;
+                        },
+                        _ => return self.error("invalid escape sequence"),
+                    };
+                    self.pos += 1;
+                    value.push(c);
+                },
+                Some(_) => return self.error("control character in string"),
+            }
+        }
+    }
+
+    /// Parses the hex digits of a `\u` escape sequence. UTF-16 surrogate pairs are combined, and
+    /// unpaired surrogates are rejected.
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.parse_hex4()?;
+        let code_point = if 0xD800 <= high && high < 0xDC00 {
+            if !self.json[self.pos..].starts_with("\\u") {
+                return self.error("unpaired surrogate");
+            }
+            self.pos += 2;
+            let low = self.parse_hex4()?;
+            if low < 0xDC00 || 0xE000 <= low {
+                return self.error("unpaired surrogate");
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else if 0xDC00 <= high && high < 0xE000 {
+            return self.error("unpaired surrogate");
+        } else {
+            high
+        };
+        Ok(char::from_u32(code_point).expect("invalid code point"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = match self.peek().and_then(|b| (b as char).to_digit(16)) {
+                Some(digit) => digit,
+                None => return self.error("invalid unicode escape sequence"),
+            };
+            value = value * 16 + digit;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Returns the length of the JSON number at the start of the input, or `None` if the input does
+/// not start with a valid number.
+fn number_len(input: &[u8]) -> Option<usize> {
+    fn digits(input: &[u8], pos: usize) -> usize {
+        input[pos..].iter().take_while(|&&b| is_digit(b)).count()
+    }
+
+    let mut pos = 0;
+    if input.first() == Some(&b'-') {
+        pos += 1;
+    }
+    match input.get(pos) {
+        Some(&b'0') => pos += 1,
+        Some(&b) if is_digit(b) => pos += digits(input, pos),
+        _ => return None,
+    }
+    if input.get(pos) == Some(&b'.') {
+        pos += 1;
+        match digits(input, pos) {
+            0 => return None,
+            n => pos += n,
+        }
+    }
+    if input.get(pos) == Some(&b'e') || input.get(pos) == Some(&b'E') {
+        pos += 1;
+        if input.get(pos) == Some(&b'+') || input.get(pos) == Some(&b'-') {
+            pos += 1;
+        }
+        match digits(input, pos) {
+            0 => return None,
+            n => pos += n,
+        }
+    }
+    Some(pos)
+}
+
+fn is_digit(b: u8) -> bool {
+    b'0' <= b && b <= b'9'
+}
+
+/// Returns `true` if the text is a valid JSON number.
+fn is_number(text: &str) -> bool {
+    number_len(text.as_bytes()) == Some(text.len())
+}
+
+/// Parses the text of a JSON number as an integer, returning the sign and magnitude. Numbers in
+/// fractional or exponent notation are accepted if their value is integral.
+///
+/// Returns `None` if the text is not a valid number, is not integral, or overflows a `u64`.
+fn parse_integer(text: &str) -> Option<(bool, u64)> {
+    if !is_number(text) {
+        return None;
+    }
+    let (negative, text) = if text.starts_with('-') { (true, &text[1..]) } else { (false, text) };
+    let (mantissa, exponent) = match text.find(|c| c == 'e' || c == 'E') {
+        Some(idx) => (&text[..idx], text[idx + 1..].parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let (integral, fraction) = match mantissa.find('.') {
+        Some(idx) => (&mantissa[..idx], &mantissa[idx + 1..]),
+        None => (mantissa, ""),
+    };
+
+    // The value is `digits * 10^exponent`.
+    let mut digits = format!("{}{}", integral, fraction);
+    let exponent = exponent.checked_sub(fraction.len() as i32)?;
+    if digits.bytes().all(|b| b == b'0') {
+        return Some((negative, 0));
+    } else if exponent < 0 {
+        let shift = -exponent as usize;
+        if shift > digits.len() || digits[digits.len() - shift..].bytes().any(|b| b != b'0') {
+            return None;
+        }
+        let len = digits.len() - shift;
+        digits.truncate(len);
+    } else if exponent > 20 {
+        return None;
+    } else {
+        digits.extend((0..exponent).map(|_| '0'));
+    }
+    digits.parse::<u64>().ok().map(|magnitude| (negative, magnitude))
+}
+
+/// Parses the text of a JSON number, or of a numeric JSON string, as a floating point value.
+/// The strings `"NaN"`, `"Infinity"` and `"-Infinity"` are accepted for non-finite values.
+fn parse_float(value: Value) -> Result<f64, JsonError> {
+    let text = match value {
+        Value::Number(text) => text,
+        Value::String(text) => {
+            match &text[..] {
+                "NaN" => return Ok(::std::f64::NAN),
+                "Infinity" => return Ok(::std::f64::INFINITY),
+                "-Infinity" => return Ok(::std::f64::NEG_INFINITY),
+                _ => text,
+            }
+        },
+        value => return Err(unexpected_type("number", &value)),
+    };
+    match text.parse::<f64>() {
+        Ok(value) if is_number(&text) && value.is_finite() => Ok(value),
+        _ => Err(JsonError::new(format!("invalid floating point value: {}", text))),
+    }
+}
+
+/// Converts a floating point value to JSON. Large and small magnitudes are written in exponent
+/// notation, and non-finite values as strings.
+macro_rules! float_to_json {
+    ($value:expr) => ({
+        let value = $value;
+        let abs = value.abs();
+        if value.is_nan() {
+            Value::String("NaN".to_string())
+        } else if value.is_infinite() {
+            Value::String(if value < 0.0 { "-Infinity" } else { "Infinity" }.to_string())
+        } else if abs != 0.0 && (abs < 1e-5 || abs >= 1e16) {
+            Value::Number(format!("{:e}", value))
+        } else {
+            Value::Number(value.to_string())
+        }
+    })
+}
+
+const BASE64_STANDARD: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded standard base64.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_STANDARD[(n >> (18 - 6 * i)) & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard or URL-safe base64, with or without padding.
+///
+/// Returns `None` if the input is not valid base64.
+pub fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut input = encoded.as_bytes();
+    if input.ends_with(b"=") {
+        if input.len() % 4 != 0 {
+            return None;
+        }
+        input = &input[..input.len() - 1];
+        if input.ends_with(b"=") {
+            input = &input[..input.len() - 1];
+        }
+    }
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for &b in input {
+        let value = match BASE64_STANDARD.iter().position(|&c| c == b) {
+            Some(value) => value as u32,
+            None if b == b'-' => 62,
+            None if b == b'_' => 63,
+            None => return None,
+        };
+        buf = buf << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buf >> bits) as u8);
+            buf &= (1 << bits) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// An error converting a message to or from JSON.
+///
+/// When decoding, `JsonError` indicates that the input is not valid JSON, or does not conform to
+/// the JSON mapping of the message type. When encoding, it indicates that a field holds a value
+/// which has no JSON representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    description: Cow<'static, str>,
+    /// A 'call stack' of the message and field names which were being converted when the error
+    /// occurred, ordered from the innermost message to the outermost.
+    stack: Vec<(&'static str, &'static str)>,
+}
+
+impl JsonError {
+
+    /// Creates a new `JsonError` with a free-form description.
+    pub fn new<S>(description: S) -> JsonError where S: Into<Cow<'static, str>> {
+        JsonError {
+            description: description.into(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns the `(message, field)` names which were being converted when the error occurred,
+    /// ordered from the innermost message to the outermost.
+    pub fn stack(&self) -> &[(&'static str, &'static str)] {
+        &self.stack
+    }
+
+    /// Pushes a (message, field) name location pair on to the location stack.
+    ///
+    /// Meant to be used only by `JsonMessage` implementations.
+    #[doc(hidden)]
+    pub fn push(&mut self, message: &'static str, field: &'static str) {
+        self.stack.push((message, field));
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("failed to convert Protobuf message to or from JSON: ")?;
+        for &(message, field) in self.stack.iter().rev() {
+            write!(f, "{}.{}: ", message, field)?;
+        }
+        f.write_str(&self.description)
+    }
+}
+
+impl error::Error for JsonError {
+    fn description(&self) -> &str {
+        "failed to convert Protobuf message to or from JSON"
+    }
+}
+
+/// Returns an error for a JSON value of an unexpected type.
+fn unexpected_type(expected: &str, value: &Value) -> JsonError {
+    JsonError::new(format!("invalid value: expected {}, found {}", expected, value.type_name()))
+}
+
+/// A Protocol Buffers message which can be converted to and from the Protobuf JSON mapping.
+pub trait JsonMessage: Message {
+
+    /// Encodes the message as a JSON string.
+    ///
+    /// An error is returned if the message holds a value which has no JSON representation.
+    fn encode_json(&self) -> Result<String, JsonError> {
+        self.to_json().map(|value| value.to_string())
+    }
+
+    /// Decodes an instance of the message from a JSON string.
+    fn decode_json(json: &str) -> Result<Self, JsonError> {
+        let mut message = Self::default();
+        message.merge_json(Value::parse(json)?)?;
+        Ok(message)
+    }
+
+    /// Converts the message to a JSON value.
+    fn to_json(&self) -> Result<Value, JsonError>;
+
+    /// Merges a JSON value into `self`.
+    fn merge_json(&mut self, value: Value) -> Result<(), JsonError>;
+
+    /// Returns the message which a JSON `null` represents, or `None` if `null` represents an
+    /// unset field. Only `google.protobuf.Value` has a JSON `null` representation.
+    #[doc(hidden)]
+    fn null_json() -> Option<Self> {
+        None
+    }
+}
+
+impl <M> JsonMessage for Box<M> where M: JsonMessage {
+    fn to_json(&self) -> Result<Value, JsonError> {
+        (**self).to_json()
+    }
+    fn merge_json(&mut self, value: Value) -> Result<(), JsonError> {
+        (**self).merge_json(value)
+    }
+    fn null_json() -> Option<Box<M>> {
+        M::null_json().map(Box::new)
+    }
+}
+
+/// Returns the members of a JSON object, or an error if the value is not an object.
+///
+/// Meant to be used only by `JsonMessage` implementations.
+#[doc(hidden)]
+pub fn object(value: Value) -> Result<Vec<(String, Value)>, JsonError> {
+    match value {
+        Value::Object(fields) => Ok(fields),
+        value => Err(unexpected_type("object", &value)),
+    }
+}
+
+/// Marks a field as decoded, returning an error if it was already decoded from another key of
+/// the same object.
+///
+/// Meant to be used only by `JsonMessage` implementations.
+#[doc(hidden)]
+pub fn check_duplicate(seen: &mut bool, key: &str) -> Result<(), JsonError> {
+    if *seen {
+        return Err(JsonError::new(format!("duplicate field: {}", key)));
+    }
+    *seen = true;
+    Ok(())
+}
+
+/// Returns an error for an object key which does not correspond to a field.
+///
+/// Meant to be used only by `JsonMessage` implementations.
+#[doc(hidden)]
+pub fn unknown_field(key: &str) -> JsonError {
+    JsonError::new(format!("unknown field: {}", key))
+}
+
+/// A scalar Protobuf value type with a JSON representation.
+pub trait JsonScalar: Sized {
+    /// Converts the value to JSON.
+    fn to_json(&self) -> Value;
+    /// Converts a non-`null` JSON value to a value of the type.
+    fn from_json(value: Value) -> Result<Self, JsonError>;
+}
+
+/// A Protobuf map key type. Map keys are encoded as the keys of a JSON object.
+pub trait JsonKey: Sized {
+    /// Converts the key to a JSON object key.
+    fn to_json_key(&self) -> String;
+    /// Converts a JSON object key to a map key.
+    fn from_json_key(key: &str) -> Result<Self, JsonError>;
+}
+
+macro_rules! integer {
+    ($ty:ident, $quoted:expr) => (
+        impl JsonScalar for $ty {
+            fn to_json(&self) -> Value {
+                if $quoted {
+                    Value::String(self.to_string())
+                } else {
+                    Value::Number(self.to_string())
+                }
+            }
+
+            fn from_json(value: Value) -> Result<$ty, JsonError> {
+                match value {
+                    Value::Number(ref text) | Value::String(ref text) => $ty::from_json_key(text),
+                    value => Err(unexpected_type("integer", &value)),
+                }
+            }
+        }
+
+        impl JsonKey for $ty {
+            fn to_json_key(&self) -> String {
+                self.to_string()
+            }
+
+            fn from_json_key(key: &str) -> Result<$ty, JsonError> {
+                let value = match parse_integer(key) {
+                    Some((false, magnitude)) if magnitude <= $ty::max_value() as u64 => {
+                        Some(magnitude as $ty)
+                    },
+                    Some((true, magnitude)) if magnitude <= ($ty::min_value() as i64).wrapping_neg() as u64 => {
+                        Some((magnitude as $ty).wrapping_neg())
+                    },
+                    _ => None,
+                };
+                value.ok_or_else(|| {
+                    JsonError::new(format!(concat!("invalid ", stringify!($ty), " value: {}"), key))
+                })
+            }
+        }
+    );
+}
+
+integer!(i32, false);
+integer!(i64, true);
+integer!(u32, false);
+integer!(u64, true);
+
+impl JsonScalar for f32 {
+    fn to_json(&self) -> Value {
+        float_to_json!(*self)
+    }
+
+    fn from_json(value: Value) -> Result<f32, JsonError> {
+        let value = parse_float(value)?;
+        if value.is_finite() && (value as f32).is_infinite() {
+            return Err(JsonError::new(format!("float value out of range: {}", value)));
+        }
+        Ok(value as f32)
+    }
+}
+
+impl JsonScalar for f64 {
+    fn to_json(&self) -> Value {
+        float_to_json!(*self)
+    }
+
+    fn from_json(value: Value) -> Result<f64, JsonError> {
+        parse_float(value)
+    }
+}
+
+impl JsonScalar for bool {
+    fn to_json(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_json(value: Value) -> Result<bool, JsonError> {
+        match value {
+            Value::Bool(value) => Ok(value),
+            value => Err(unexpected_type("bool", &value)),
+        }
+    }
+}
+
+impl JsonKey for bool {
+    fn to_json_key(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_json_key(key: &str) -> Result<bool, JsonError> {
+        match key {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(JsonError::new(format!("invalid bool value: {}", key))),
+        }
+    }
+}
+
+impl JsonScalar for String {
+    fn to_json(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_json(value: Value) -> Result<String, JsonError> {
+        match value {
+            Value::String(value) => Ok(value),
+            value => Err(unexpected_type("string", &value)),
+        }
+    }
+}
+
+impl JsonKey for String {
+    fn to_json_key(&self) -> String {
+        self.clone()
+    }
+
+    fn from_json_key(key: &str) -> Result<String, JsonError> {
+        Ok(key.to_string())
+    }
+}
+
+impl JsonScalar for Vec<u8> {
+    fn to_json(&self) -> Value {
+        Value::String(encode_base64(self))
+    }
+
+    fn from_json(value: Value) -> Result<Vec<u8>, JsonError> {
+        match value {
+            Value::String(value) => decode_base64(&value).ok_or_else(|| {
+                JsonError::new(format!("invalid base64 value: {}", value))
+            }),
+            value => Err(unexpected_type("string", &value)),
+        }
+    }
+}
+
+impl JsonScalar for Bytes {
+    fn to_json(&self) -> Value {
+        Value::String(encode_base64(self))
+    }
+
+    fn from_json(value: Value) -> Result<Bytes, JsonError> {
+        Vec::<u8>::from_json(value).map(Bytes::from)
+    }
+}
+
+/// Merges a JSON value into a field. `null` leaves the field unchanged.
+fn merge<T, F>(field: &mut T, value: Value, from_json: F) -> Result<(), JsonError>
+where F: FnOnce(Value) -> Result<T, JsonError> {
+    if !value.is_null() {
+        *field = from_json(value)?;
+    }
+    Ok(())
+}
+
+/// Merges a JSON array into a repeated field. `null` leaves the field unchanged.
+fn merge_repeated<T, F>(field: &mut Vec<T>, value: Value, from_json: F) -> Result<(), JsonError>
+where F: Fn(Value) -> Result<T, JsonError> {
+    match value {
+        Value::Null => Ok(()),
+        Value::Array(values) => {
+            field.reserve(values.len());
+            for value in values {
+                field.push(from_json(value)?);
+            }
+            Ok(())
+        },
+        value => Err(unexpected_type("array", &value)),
+    }
+}
+
+/// Conversions of scalar fields.
+pub mod scalar {
+    use super::*;
+
+    pub fn to_json<T>(value: &T) -> Value where T: JsonScalar {
+        value.to_json()
+    }
+
+    pub fn to_json_repeated<T>(values: &[T]) -> Value where T: JsonScalar {
+        Value::Array(values.iter().map(JsonScalar::to_json).collect())
+    }
+
+    pub fn from_json<T>(value: Value) -> Result<T, JsonError> where T: JsonScalar {
+        T::from_json(value)
+    }
+
+    /// Converts the JSON value of a oneof field, or returns `None` if the value is `null`.
+    pub fn from_json_oneof<T>(value: Value) -> Result<Option<T>, JsonError> where T: JsonScalar {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            T::from_json(value).map(Some)
+        }
+    }
+
+    pub fn merge<T>(field: &mut T, value: Value) -> Result<(), JsonError> where T: JsonScalar {
+        super::merge(field, value, T::from_json)
+    }
+
+    pub fn merge_optional<T>(field: &mut Option<T>, value: Value) -> Result<(), JsonError>
+    where T: JsonScalar {
+        super::merge(field, value, |value| T::from_json(value).map(Some))
+    }
+
+    pub fn merge_repeated<T>(field: &mut Vec<T>, value: Value) -> Result<(), JsonError>
+    where T: JsonScalar {
+        super::merge_repeated(field, value, T::from_json)
+    }
+}
+
+/// Conversions of enumeration fields. Known values are encoded as their names, and unknown
+/// values as numbers. Either form is accepted when decoding.
+pub mod enumeration {
+    use super::*;
+
+    pub fn to_json<E>(value: &i32) -> Value where E: Enumeration {
+        match E::value_name(*value) {
+            Some(name) => Value::String(name.to_string()),
+            None => Value::Number(value.to_string()),
+        }
+    }
+
+    pub fn to_json_repeated<E>(values: &[i32]) -> Value where E: Enumeration {
+        Value::Array(values.iter().map(to_json::<E>).collect())
+    }
+
+    pub fn from_json<E>(value: Value) -> Result<i32, JsonError> where E: Enumeration {
+        match value {
+            Value::String(name) => E::from_value_name(&name).ok_or_else(|| {
+                JsonError::new(format!("unknown enumeration value: {}", name))
+            }),
+            Value::Number(ref text) => i32::from_json_key(text),
+            value => Err(unexpected_type("enumeration value", &value)),
+        }
+    }
+
+    /// Converts the JSON value of a oneof field, or returns `None` if the value is `null`.
+    pub fn from_json_oneof<E>(value: Value) -> Result<Option<i32>, JsonError> where E: Enumeration {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            from_json::<E>(value).map(Some)
+        }
+    }
+
+    pub fn merge<E>(field: &mut i32, value: Value) -> Result<(), JsonError> where E: Enumeration {
+        super::merge(field, value, from_json::<E>)
+    }
+
+    pub fn merge_optional<E>(field: &mut Option<i32>, value: Value) -> Result<(), JsonError>
+    where E: Enumeration {
+        super::merge(field, value, |value| from_json::<E>(value).map(Some))
+    }
+
+    pub fn merge_repeated<E>(field: &mut Vec<i32>, value: Value) -> Result<(), JsonError>
+    where E: Enumeration {
+        super::merge_repeated(field, value, from_json::<E>)
+    }
+}
+
+/// Conversions of open enumeration fields, in the same forms as enumeration fields.
+pub mod open_enum {
+    use super::*;
+    use OpenEnum;
+
+    pub fn to_json<E>(value: &OpenEnum<E>) -> Value where E: Enumeration {
+        enumeration::to_json::<E>(&value.to_i32())
+    }
+
+    pub fn to_json_repeated<E>(values: &[OpenEnum<E>]) -> Value where E: Enumeration {
+        Value::Array(values.iter().map(to_json).collect())
+    }
+
+    pub fn from_json<E>(value: Value) -> Result<OpenEnum<E>, JsonError> where E: Enumeration {
+        enumeration::from_json::<E>(value).map(OpenEnum::from_i32)
+    }
+
+    /// Converts the JSON value of a oneof field, or returns `None` if the value is `null`.
+    pub fn from_json_oneof<E>(value: Value) -> Result<Option<OpenEnum<E>>, JsonError>
+    where E: Enumeration {
+        enumeration::from_json_oneof::<E>(value).map(|value| value.map(OpenEnum::from_i32))
+    }
+
+    pub fn merge<E>(field: &mut OpenEnum<E>, value: Value) -> Result<(), JsonError>
+    where E: Enumeration {
+        super::merge(field, value, from_json)
+    }
+
+    pub fn merge_optional<E>(field: &mut Option<OpenEnum<E>>, value: Value) -> Result<(), JsonError>
+    where E: Enumeration {
+        super::merge(field, value, |value| from_json(value).map(Some))
+    }
+
+    pub fn merge_repeated<E>(field: &mut Vec<OpenEnum<E>>, value: Value) -> Result<(), JsonError>
+    where E: Enumeration {
+        super::merge_repeated(field, value, from_json)
+    }
+}
+
+/// Conversions of message fields.
+pub mod message {
+    use super::*;
+
+    pub fn to_json<M>(msg: &M) -> Result<Value, JsonError> where M: JsonMessage {
+        msg.to_json()
+    }
+
+    pub fn to_json_repeated<M>(messages: &[M]) -> Result<Value, JsonError> where M: JsonMessage {
+        messages.iter().map(JsonMessage::to_json).collect::<Result<_, _>>().map(Value::Array)
+    }
+
+    pub fn from_json<M>(value: Value) -> Result<M, JsonError> where M: JsonMessage {
+        if value.is_null() {
+            return M::null_json().ok_or_else(|| unexpected_type("object", &value));
+        }
+        let mut msg = M::default();
+        msg.merge_json(value)?;
+        Ok(msg)
+    }
+
+    /// Converts the JSON value of a oneof field, or returns `None` if the value is `null` and
+    /// `null` does not represent a message of the type.
+    pub fn from_json_oneof<M>(value: Value) -> Result<Option<M>, JsonError> where M: JsonMessage {
+        if value.is_null() {
+            Ok(M::null_json())
+        } else {
+            from_json(value).map(Some)
+        }
+    }
+
+    pub fn merge<M>(msg: &mut M, value: Value) -> Result<(), JsonError> where M: JsonMessage {
+        if value.is_null() {
+            if let Some(null) = M::null_json() {
+                *msg = null;
+            }
+            Ok(())
+        } else {
+            msg.merge_json(value)
+        }
+    }
+
+    pub fn merge_optional<M>(field: &mut Option<M>, value: Value) -> Result<(), JsonError>
+    where M: JsonMessage {
+        if value.is_null() {
+            if let Some(null) = M::null_json() {
+                *field = Some(null);
+            }
+            return Ok(());
+        }
+        // TODO(rustlang/rust#39288): Use Option::get_or_insert_with when available.
+        if field.is_none() {
+            *field = Some(M::default());
+        }
+        match *field {
+            Some(ref mut msg) => msg.merge_json(value),
+            None => unreachable!(),
+        }
+    }
+
+    pub fn merge_repeated<M>(messages: &mut Vec<M>, value: Value) -> Result<(), JsonError>
+    where M: JsonMessage {
+        super::merge_repeated(messages, value, from_json)
+    }
+}
+
+/// Rust doesn't have a `Map` trait, so macros are currently the best way to be
+/// generic over `HashMap` and `BTreeMap`.
+macro_rules! map {
+    ($map_ty:ident) => (
+        use std::collections::$map_ty;
+        use std::hash::Hash;
+
+        use ::json::*;
+
+        /// Converts a map to a JSON object, using `value_to_json` to convert the values.
+        pub fn to_json<K, V, F>(values: &$map_ty<K, V>, value_to_json: F) -> Result<Value, JsonError>
+        where K: JsonKey + Eq + Hash + Ord,
+              F: Fn(&V) -> Result<Value, JsonError> {
+            let mut fields = Vec::with_capacity(values.len());
+            for (key, value) in values {
+                fields.push((key.to_json_key(), value_to_json(value)?));
+            }
+            Ok(Value::Object(fields))
+        }
+
+        /// Merges the entries of a JSON object into a map, using `value_from_json` to convert the
+        /// values. Map values may not be `null`, unless `null` represents a value of the type.
+        pub fn merge<K, V, F>(values: &mut $map_ty<K, V>,
+                              value: Value,
+                              value_from_json: F)
+                              -> Result<(), JsonError>
+        where K: JsonKey + Eq + Hash + Ord,
+              F: Fn(Value) -> Result<V, JsonError> {
+            match value {
+                Value::Null => Ok(()),
+                Value::Object(fields) => {
+                    for (key, value) in fields {
+                        let key = K::from_json_key(&key)?;
+                        let value = value_from_json(value)?;
+                        values.insert(key, value);
+                    }
+                    Ok(())
+                },
+                value => Err(unexpected_type("object", &value)),
+            }
+        }
+    )
+}
+
+/// Conversions of `HashMap` fields.
+pub mod hash_map {
+    map!(HashMap);
+}
+
+/// Conversions of `BTreeMap` fields.
+pub mod btree_map {
+    map!(BTreeMap);
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+    use std::f64;
+
+    use quickcheck::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let value = Value::parse(" {\"a\": [1, -2.5e3, true, false, null], \"b\" : {}, \"c\": \"\\u00e9\\ud83d\\ude00\\n\"} ")
+            .unwrap();
+        assert_eq!(Value::Object(vec![
+            ("a".to_string(), Value::Array(vec![Value::Number("1".to_string()),
+                                                Value::Number("-2.5e3".to_string()),
+                                                Value::Bool(true),
+                                                Value::Bool(false),
+                                                Value::Null])),
+            ("b".to_string(), Value::Object(Vec::new())),
+            ("c".to_string(), Value::String("\u{e9}\u{1F600}\n".to_string())),
+        ]), value);
+
+        let invalid = [
+            "", "{", "[1,]", "{\"a\":1,}", "{a:1}", "'a'", "01", "1.", ".5", "-", "1e", "+1",
+            "\"\\ud83d\"", "\"\\ude00\"", "\"\\x\"", "\"\\u12\"", "\"\t\"", "nul", "[] []",
+            "// comment\n{}", "NaN", "Infinity",
+        ];
+        for json in &invalid {
+            assert!(Value::parse(json).is_err(), "{:?}", json);
+        }
+
+        let mut nested = String::new();
+        for _ in 0..RECURSION_LIMIT + 1 {
+            nested.push('[');
+        }
+        assert!(Value::parse(&nested).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let json = "{\"a\":[1,-2.5e3,true,false,null],\"b\":{},\"c\":\"\\\"\\\\\\n\\u0001\u{e9}\"}";
+        assert_eq!(json, Value::parse(json).unwrap().to_string());
+    }
+
+    quickcheck! {
+        fn string_roundtrip(value: String) -> bool {
+            let json = Value::String(value.clone()).to_string();
+            Value::parse(&json) == Ok(Value::String(value))
+        }
+
+        fn base64_roundtrip(value: Vec<u8>) -> bool {
+            decode_base64(&encode_base64(&value)) == Some(value)
+        }
+
+        fn i64_roundtrip(value: i64) -> bool {
+            i64::from_json(value.to_json()) == Ok(value)
+        }
+
+        fn u64_roundtrip(value: u64) -> bool {
+            u64::from_json(Value::Number(value.to_string())) == Ok(value)
+        }
+
+        fn f32_roundtrip(value: f32) -> TestResult {
+            if value.is_nan() {
+                return TestResult::discard();
+            }
+            TestResult::from_bool(f32::from_json(value.to_json()) == Ok(value))
+        }
+
+        fn f64_roundtrip(value: f64) -> TestResult {
+            if value.is_nan() {
+                return TestResult::discard();
+            }
+            TestResult::from_bool(f64::from_json(value.to_json()) == Ok(value))
+        }
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!("", encode_base64(b""));
+        assert_eq!("Zg==", encode_base64(b"f"));
+        assert_eq!("Zm8=", encode_base64(b"fo"));
+        assert_eq!("Zm9v", encode_base64(b"foo"));
+        assert_eq!("+/8=", encode_base64(&[0xFB, 0xFF]));
+
+        assert_eq!(Some(b"fo".to_vec()), decode_base64("Zm8="));
+        assert_eq!(Some(b"fo".to_vec()), decode_base64("Zm8"));
+        assert_eq!(Some(vec![0xFB, 0xFF]), decode_base64("-_8"));
+        assert_eq!(None, decode_base64("Zm8=="));
+        assert_eq!(None, decode_base64("Z"));
+        assert_eq!(None, decode_base64("-><"));
+    }
+
+    #[test]
+    fn integers() {
+        let number = |text: &str| Value::Number(text.to_string());
+        let string = |text: &str| Value::String(text.to_string());
+
+        assert_eq!(Ok(100000), i32::from_json(number("1e5")));
+        assert_eq!(Ok(100000), i32::from_json(number("100000.000")));
+        assert_eq!(Ok(-12), i32::from_json(number("-1.2e1")));
+        assert_eq!(Ok(2147483647), i32::from_json(string("2147483647")));
+        assert_eq!(Ok(-2147483648), i32::from_json(number("-2147483648")));
+        assert_eq!(Ok(0), u32::from_json(number("-0")));
+        assert_eq!(Ok(9223372036854775807), i64::from_json(number("9223372036854775807")));
+        assert_eq!(Ok(-9223372036854775808), i64::from_json(string("-9223372036854775808")));
+        assert_eq!(Ok(18446744073709551615), u64::from_json(number("18446744073709551615")));
+
+        assert!(i32::from_json(number("2147483648")).is_err());
+        assert!(i32::from_json(number("0.5")).is_err());
+        assert!(i32::from_json(string(" 1")).is_err());
+        assert!(i32::from_json(string("01")).is_err());
+        assert!(u32::from_json(number("-1")).is_err());
+        assert!(u64::from_json(number("1e20")).is_err());
+        assert!(i32::from_json(Value::Bool(true)).is_err());
+
+        assert_eq!(string("-1"), (-1i64).to_json());
+        assert_eq!(number("-1"), (-1i32).to_json());
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(Value::String("NaN".to_string()), f64::NAN.to_json());
+        assert_eq!(Value::String("-Infinity".to_string()), f32::NEG_INFINITY.to_json());
+        assert_eq!(Value::Number("1.5".to_string()), 1.5f64.to_json());
+        assert_eq!(Value::Number("1e300".to_string()), 1e300f64.to_json());
+
+        assert!(f64::from_json(Value::String("NaN".to_string())).unwrap().is_nan());
+        assert_eq!(Ok(f64::INFINITY), f64::from_json(Value::String("Infinity".to_string())));
+        assert_eq!(Ok(1.5), f32::from_json(Value::String("1.5".to_string())));
+        assert_eq!(Ok(f32::MAX), f32::from_json(Value::Number("3.4028235e38".to_string())));
+        assert!(f32::from_json(Value::Number("3.502823e+38".to_string())).is_err());
+        assert!(f64::from_json(Value::Number("1.89769e+308".to_string())).is_err());
+        assert!(f64::from_json(Value::String("inf".to_string())).is_err());
+    }
+}
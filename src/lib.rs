@@ -7,8 +7,40 @@ extern crate bytes;
 #[macro_use]
 extern crate quickcheck;
 
+mod enumeration;
+mod error;
+mod extension;
 mod message;
+mod unknown;
 
 pub mod encoding;
+pub mod json;
+pub mod raw;
+pub mod text;
 
-pub use message::Message;
+pub use enumeration::{
+    Enumeration,
+    OpenEnum,
+};
+pub use error::{
+    DecodeError,
+    DecodeErrorKind,
+    EncodeError,
+    UnknownEnumValue,
+};
+pub use extension::{
+    Extendable,
+    Extension,
+    ExtensionRegistry,
+    ExtensionSet,
+};
+pub use json::JsonMessage;
+pub use message::{
+    Merge,
+    Message,
+};
+pub use text::TextMessage;
+pub use unknown::{
+    UnknownField,
+    UnknownFields,
+};
@@ -1,40 +1,50 @@
 use std::fmt::Debug;
-use std::io::Result;
+use std::io::Cursor;
+use std::u32;
 use std::usize;
 
 use bytes::{
     Buf,
     BufMut,
+    Bytes,
     Take,
 };
 
 use encoding::*;
+use DecodeError;
+use DecodeErrorKind;
+use EncodeError;
 
 /// A Protocol Buffers message.
 pub trait Message: Debug + Default + PartialEq /*+ PartialOrd*/ + Send + Sync {
 
     /// Encodes the message, and writes it to the buffer. An error will be
     /// returned if the buffer does not have sufficient capacity.
-    fn encode<B>(&self, buf: &mut B) -> Result<()> where B: BufMut {
-        let len = self.encoded_len();
-        if len > buf.remaining_mut() {
-            return Err(invalid_input("failed to encode message: insufficient buffer capacity"));
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError> where B: BufMut {
+        let cache = SizeCache::new();
+        let required = self.encoded_len_cached(&cache);
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
         }
 
-        self.encode_raw(buf);
+        self.encode_cached(buf, &cache);
         Ok(())
     }
 
     /// Encodes the message, and writes it with a length-delimiter prefix to
     /// the buffer. An error will be returned if the buffer does not have
     /// sufficient capacity.
-    fn encode_length_delimited<B>(&self, buf: &mut B) -> Result<()> where B: BufMut {
-        let len = self.encoded_len();
-        if len + encoded_len_varint(len as u64) > buf.remaining_mut() {
-            return Err(invalid_input("failed to encode message: insufficient buffer capacity"));
+    fn encode_length_delimited<B>(&self, buf: &mut B) -> Result<(), EncodeError> where B: BufMut {
+        let cache = SizeCache::new();
+        let len = self.encoded_len_cached(&cache);
+        let required = len + encoded_len_varint(len as u64);
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
         }
         encode_varint(len as u64, buf);
-        self.encode_raw(buf);
+        self.encode_cached(buf, &cache);
         Ok(())
     }
 
@@ -46,36 +56,174 @@ pub trait Message: Debug + Default + PartialEq /*+ PartialOrd*/ + Send + Sync {
     #[doc(hidden)]
     fn encode_raw<B>(&self, buf: &mut B) where B: BufMut;
 
+    /// Encodes the message, writing it to the buffer, using the nested
+    /// message lengths recorded in the cache by `encoded_len_cached`.
+    ///
+    /// This method will panic if the buffer has insufficient capacity.
+    #[doc(hidden)]
+    fn encode_cached<B>(&self, buf: &mut B, _cache: &SizeCache) where B: BufMut {
+        self.encode_raw(buf)
+    }
+
     /// Decodes an instance of the message from the buffer.
     /// The entire buffer will be consumed.
-    fn decode<B>(buf: &mut Take<B>) -> Result<Self> where B: Buf, Self: Default {
+    ///
+    /// An error will be returned if a required field of the message, or of
+    /// one of its nested messages, is missing.
+    fn decode<B>(buf: &mut Take<B>) -> Result<Self, DecodeError> where B: Buf, Self: Merge<B> {
+        Self::decode_with_context(buf, DecodeContext::default())
+    }
+
+    /// Decodes an instance of the message from the buffer using the provided
+    /// decode context. The entire buffer will be consumed.
+    ///
+    /// The context may be used to raise or lower the limit on the depth of
+    /// nested messages, which defaults to `DEFAULT_RECURSION_LIMIT`.
+    fn decode_with_context<B>(buf: &mut Take<B>, ctx: DecodeContext) -> Result<Self, DecodeError>
+    where B: Buf, Self: Merge<B> {
+        let message = Self::decode_partial_with_context(buf, ctx)?;
+        message.check_initialized()?;
+        Ok(message)
+    }
+
+    /// Decodes an instance of the message from the buffer, without checking
+    /// that required fields are set. The entire buffer will be consumed.
+    fn decode_partial<B>(buf: &mut Take<B>) -> Result<Self, DecodeError> where B: Buf, Self: Merge<B> {
+        Self::decode_partial_with_context(buf, DecodeContext::default())
+    }
+
+    /// Decodes an instance of the message from the buffer using the provided
+    /// decode context, without checking that required fields are set. The
+    /// entire buffer will be consumed.
+    fn decode_partial_with_context<B>(buf: &mut Take<B>, ctx: DecodeContext) -> Result<Self, DecodeError>
+    where B: Buf, Self: Merge<B> {
         let mut message = Self::default();
-        Self::merge(&mut message, buf).map(|_| message)
+        let start = buf.remaining();
+        match message.merge_with_context(buf, ctx) {
+            Ok(()) => Ok(message),
+            Err(error) => Err(with_offset(error, start - buf.remaining())),
+        }
+    }
+
+    /// Decodes an instance of the message from a `Bytes` buffer. The entire
+    /// buffer will be consumed.
+    ///
+    /// `bytes` fields represented as a `Bytes` share the memory of the buffer
+    /// rather than copying the field value.
+    fn decode_bytes(buf: &Bytes) -> Result<Self, DecodeError> where Self: for<'b> Merge<Cursor<&'b Bytes>> {
+        let ctx = DecodeContext::default().with_source(buf);
+        Self::decode_with_context(&mut Cursor::new(buf).take(buf.len()), ctx)
     }
 
     /// Decodes a length-delimited instance of the message from the buffer.
-    fn decode_length_delimited<B>(buf: &mut B) -> Result<Self> where B: Buf, Self: Default {
+    fn decode_length_delimited<B>(buf: &mut B) -> Result<Self, DecodeError>
+    where B: Buf, Self: for<'b> Merge<&'b mut B> {
         let mut message = Self::default();
         message.merge_length_delimited(buf)?;
+        message.check_initialized()?;
         Ok(message)
     }
 
     /// Decodes an instance of the message from the buffer, and merges
     /// it into `self`. The entire buffer will be consumed.
-    fn merge<B>(&mut self, buf: &mut Take<B>) -> Result<()> where B: Buf;
+    ///
+    /// Merging does not check that required fields are set, since they may
+    /// be set by a later merge. Use `check_initialized` once the message is
+    /// complete.
+    fn merge<B>(&mut self, buf: &mut Take<B>) -> Result<(), DecodeError> where B: Buf, Self: Merge<B> {
+        self.merge_with_context(buf, DecodeContext::default())
+    }
+
+    /// Decodes an instance of the message from the buffer using the provided
+    /// decode context, and merges it into `self`. The entire buffer will be
+    /// consumed.
+    fn merge_with_context<B>(&mut self, buf: &mut Take<B>, ctx: DecodeContext) -> Result<(), DecodeError>
+    where B: Buf, Self: Merge<B> {
+        while buf.has_remaining() {
+            let (tag, wire_type) = decode_key(buf)?;
+            self.merge_field(tag, wire_type, buf, ctx)?;
+        }
+        Ok(())
+    }
 
     /// Decodes a length-delimited instance of the message from the
     /// buffer, and merges it into `self`.
-    fn merge_length_delimited<B>(&mut self, buf: &mut B) -> Result<()> where B: Buf {
-        let len = decode_varint(buf)?;
+    fn merge_length_delimited<B>(&mut self, buf: &mut B) -> Result<(), DecodeError>
+    where B: Buf, Self: for<'b> Merge<&'b mut B> {
+        let start = buf.remaining();
+        let len = decode_varint(buf).map_err(|error| with_offset(error, start - buf.remaining()))?;
         if len > buf.remaining() as u64 {
-            return Err(invalid_input("failed to merge message: buffer underflow"));
+            return Err(with_offset(DecodeError::new(DecodeErrorKind::BufferUnderflow),
+                                   start - buf.remaining()));
         }
-        self.merge(&mut buf.take(len as usize))
+        let result = self.merge(&mut buf.take(len as usize));
+        result.map_err(|error| with_offset(error, start - buf.remaining()))
     }
 
     /// The encoded length of the message without a length delimiter.
     fn encoded_len(&self) -> usize;
+
+    /// The encoded length of the message without a length delimiter. The
+    /// lengths of nested messages are recorded in the cache, so that they
+    /// need not be recomputed by `encode_cached`.
+    #[doc(hidden)]
+    fn encoded_len_cached(&self, _cache: &SizeCache) -> usize {
+        self.encoded_len()
+    }
+
+    /// Merges the fields of `other` into `self`, with the same result as
+    /// merging the encoded form of `other`: set scalar fields are
+    /// overwritten, repeated fields are appended to, map entries are
+    /// replaced by key, and message fields and matching oneof message
+    /// variants are merged recursively.
+    ///
+    /// The default implementation encodes `other` and merges the encoded
+    /// message without a recursion limit, since `other` is already in
+    /// memory. `#[derive(Message)]` merges the fields directly. Borrowed
+    /// messages can not be merged, since their fields borrow from the buffer
+    /// they were decoded from.
+    fn merge_from(&mut self, other: &Self) where Self: for<'b> Merge<Cursor<&'b [u8]>> {
+        let mut buf = Vec::with_capacity(other.encoded_len());
+        other.encode_raw(&mut buf);
+        let ctx = DecodeContext::with_recursion_limit(u32::MAX);
+        self.merge_with_context(&mut Cursor::new(&buf[..]).take(buf.len()), ctx)
+            .expect("failed to merge an encoded message");
+    }
+
+    /// Resets all fields of the message to their default values.
+    ///
+    /// `#[derive(Message)]` keeps the allocated capacity of `string`,
+    /// `bytes`, repeated and map fields, so that a message can be reused
+    /// to decode many buffers.
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns `true` if all required fields of the message, and of its
+    /// nested messages, are set.
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    /// Checks that all required fields of the message, and of its nested
+    /// messages, are set. If any are missing, the returned error holds their
+    /// paths.
+    fn check_initialized(&self) -> Result<(), DecodeError> {
+        if self.is_initialized() {
+            return Ok(());
+        }
+        let mut missing = Vec::new();
+        self.missing_fields("", &mut missing);
+        Err(DecodeError::new(DecodeErrorKind::MissingRequiredFields(missing)))
+    }
+
+    /// Appends the paths of the missing required fields of the message, and
+    /// of its nested messages, to `missing`. Each path is prefixed with
+    /// `prefix`.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    fn missing_fields(&self, _prefix: &str, _missing: &mut Vec<String>) {}
 }
 
 impl <M> Message for Box<M> where M: Message {
@@ -84,11 +232,66 @@ impl <M> Message for Box<M> where M: Message {
         (**self).encode_raw(buf)
     }
     #[inline]
-    fn merge<B>(&mut self, buf: &mut Take<B>) -> Result<()> where B: Buf {
-        (**self).merge(buf)
+    fn encode_cached<B>(&self, buf: &mut B, cache: &SizeCache) where B: BufMut {
+        (**self).encode_cached(buf, cache)
     }
     #[inline]
     fn encoded_len(&self) -> usize {
         (**self).encoded_len()
     }
+    #[inline]
+    fn encoded_len_cached(&self, cache: &SizeCache) -> usize {
+        (**self).encoded_len_cached(cache)
+    }
+    #[inline]
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+    #[inline]
+    fn is_initialized(&self) -> bool {
+        (**self).is_initialized()
+    }
+    #[inline]
+    fn missing_fields(&self, prefix: &str, missing: &mut Vec<String>) {
+        (**self).missing_fields(prefix, missing)
+    }
+}
+
+/// Decoding of the fields of a message from buffers of type `B`.
+///
+/// `#[derive(Message)]` implements `Merge<B>` for any buffer type. Borrowed
+/// messages, which have a lifetime parameter `'a` and borrow their `string`
+/// and `bytes` fields from the buffer they are decoded from, implement only
+/// `Merge<Cursor<&'a [u8]>>`.
+pub trait Merge<B>: Message where B: Buf {
+
+    /// Decodes the value of a single field from the buffer, and merges it
+    /// into `self`. The field key must already have been consumed.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    fn merge_field(&mut self,
+                   tag: u32,
+                   wire_type: WireType,
+                   buf: &mut Take<B>,
+                   ctx: DecodeContext)
+                   -> Result<(), DecodeError>;
+}
+
+impl <M, B> Merge<B> for Box<M> where M: Merge<B>, B: Buf {
+    #[inline]
+    fn merge_field(&mut self,
+                   tag: u32,
+                   wire_type: WireType,
+                   buf: &mut Take<B>,
+                   ctx: DecodeContext)
+                   -> Result<(), DecodeError> {
+        (**self).merge_field(tag, wire_type, buf, ctx)
+    }
+}
+
+/// Records the offset into the input buffer at which a decode error occurred.
+fn with_offset(mut error: DecodeError, offset: usize) -> DecodeError {
+    error.set_offset(offset);
+    error
 }
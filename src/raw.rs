@@ -0,0 +1,305 @@
+//! Decoding messages without a schema.
+//!
+//! `decode_raw` walks an encoded message and produces the tree of its fields, in the manner of
+//! `protoc --decode_raw`. Since the wire format does not describe the type of length-delimited
+//! values, they are classified heuristically: values which are printable UTF-8 text are taken to
+//! be strings, values which decode as a non-empty message are taken to be nested messages, and
+//! values which decode as a sequence of varints are taken to be packed repeated fields. Other
+//! length-delimited values are kept as bytes.
+//!
+//! `print_raw` prints the tree in the format used by `protoc --decode_raw`, with field tags in
+//! place of field names.
+
+use std::io::Cursor;
+use std::str;
+
+use bytes::Buf;
+
+use encoding::*;
+use text::{
+    Printer,
+    TextScalar,
+};
+use DecodeError;
+use DecodeErrorKind;
+
+/// A field decoded without a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawField {
+    /// The field tag.
+    pub tag: u32,
+    /// The field value.
+    pub value: RawValue,
+}
+
+/// The value of a field decoded without a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawValue {
+    /// A varint value.
+    Varint(u64),
+    /// A 64-bit fixed-width value.
+    Fixed64(u64),
+    /// A 32-bit fixed-width value.
+    Fixed32(u32),
+    /// A length-delimited value holding printable UTF-8 text.
+    String(String),
+    /// A length-delimited value holding an encoded message.
+    Message(Vec<RawField>),
+    /// A length-delimited value holding a sequence of varints.
+    Packed(Vec<u64>),
+    /// A length-delimited value which is not recognized as a string, message or packed varints.
+    Bytes(Vec<u8>),
+    /// A group, holding the fields between the start and end group keys.
+    Group(Vec<RawField>),
+}
+
+impl RawValue {
+
+    /// Returns the wire type of the value.
+    pub fn wire_type(&self) -> WireType {
+        match *self {
+            RawValue::Varint(_) => WireType::Varint,
+            RawValue::Fixed64(_) => WireType::SixtyFourBit,
+            RawValue::Fixed32(_) => WireType::ThirtyTwoBit,
+            RawValue::String(_)
+                | RawValue::Message(_)
+                | RawValue::Packed(_)
+                | RawValue::Bytes(_) => WireType::LengthDelimited,
+            RawValue::Group(_) => WireType::StartGroup,
+        }
+    }
+}
+
+/// Decodes the fields of an encoded message without a schema, consuming the buffer.
+pub fn decode_raw<B>(buf: &mut B) -> Result<Vec<RawField>, DecodeError> where B: Buf {
+    let mut data = vec![0; buf.remaining()];
+    buf.copy_to_slice(&mut data);
+    decode_fields(&mut Cursor::new(&data[..]), None, DecodeContext::default())
+}
+
+/// Prints fields decoded by `decode_raw` in the format used by `protoc --decode_raw`.
+pub fn print_raw(fields: &[RawField]) -> String {
+    let mut printer = Printer::new();
+    print_fields(&mut printer, fields);
+    printer.finish()
+}
+
+/// Decodes fields until the end of the buffer or, if decoding a group, until its end group key.
+fn decode_fields(buf: &mut Cursor<&[u8]>,
+                 group: Option<u32>,
+                 ctx: DecodeContext)
+                 -> Result<Vec<RawField>, DecodeError> {
+    let mut fields = Vec::new();
+    while buf.has_remaining() {
+        let (tag, wire_type) = decode_key(buf)?;
+        let value = match wire_type {
+            WireType::Varint => RawValue::Varint(decode_varint(buf)?),
+            WireType::SixtyFourBit => {
+                let mut value = 0;
+                fixed64::merge(wire_type, &mut value, buf, ctx)?;
+                RawValue::Fixed64(value)
+            },
+            WireType::ThirtyTwoBit => {
+                let mut value = 0;
+                fixed32::merge(wire_type, &mut value, buf, ctx)?;
+                RawValue::Fixed32(value)
+            },
+            WireType::LengthDelimited => {
+                let len = decode_varint(buf)?;
+                if len > buf.remaining() as u64 {
+                    return Err(DecodeError::new(DecodeErrorKind::BufferUnderflow));
+                }
+                let start = buf.position() as usize;
+                let value = &buf.get_ref()[start..start + len as usize];
+                buf.advance(len as usize);
+                length_delimited(value, ctx)
+            },
+            WireType::StartGroup => {
+                ctx.limit_reached()?;
+                RawValue::Group(decode_fields(buf, Some(tag), ctx.enter_recursion())?)
+            },
+            WireType::EndGroup => {
+                return match group {
+                    Some(group) => check_end_group(group, tag).map(|()| fields),
+                    None => Err(DecodeError::new(DecodeErrorKind::UnexpectedEndGroup(tag))),
+                };
+            },
+        };
+        fields.push(RawField { tag: tag, value: value });
+    }
+    match group {
+        Some(_) => Err(DecodeError::new(DecodeErrorKind::BufferUnderflow)),
+        None => Ok(fields),
+    }
+}
+
+/// Classifies a length-delimited value.
+fn length_delimited(value: &[u8], ctx: DecodeContext) -> RawValue {
+    if let Some(text) = printable_text(value) {
+        return RawValue::String(text.to_string());
+    }
+    if ctx.limit_reached().is_ok() && is_message(value, ctx.enter_recursion()) {
+        let fields = decode_fields(&mut Cursor::new(value), None, ctx.enter_recursion())
+                         .expect("failed to decode a validated message");
+        return RawValue::Message(fields);
+    }
+    match packed_varints(value) {
+        Some(values) => RawValue::Packed(values),
+        None => RawValue::Bytes(value.to_vec()),
+    }
+}
+
+/// Returns the value as a string if it is UTF-8 text without control characters other than
+/// whitespace.
+fn printable_text(value: &[u8]) -> Option<&str> {
+    str::from_utf8(value).ok().and_then(|text| {
+        if text.chars().all(|c| !c.is_control() || c == '\n' || c == '\r' || c == '\t') {
+            Some(text)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns `true` if the value is a non-empty sequence of well-formed fields.
+fn is_message(value: &[u8], ctx: DecodeContext) -> bool {
+    let mut buf = Cursor::new(value);
+    while buf.has_remaining() {
+        match decode_key(&mut buf) {
+            Ok((tag, wire_type)) if wire_type != WireType::EndGroup => {
+                if skip_field(wire_type, tag, &mut buf, ctx).is_err() {
+                    return false;
+                }
+            },
+            _ => return false,
+        }
+    }
+    !value.is_empty()
+}
+
+/// Decodes the value as a sequence of varints, if it is one.
+fn packed_varints(value: &[u8]) -> Option<Vec<u64>> {
+    let mut buf = Cursor::new(value);
+    let mut values = Vec::new();
+    while buf.has_remaining() {
+        values.push(decode_varint(&mut buf).ok()?);
+    }
+    Some(values)
+}
+
+fn print_fields(printer: &mut Printer, fields: &[RawField]) {
+    for field in fields {
+        let name = field.tag.to_string();
+        match field.value {
+            RawValue::Varint(value) => printer.scalar(&name, &value.to_string()),
+            RawValue::Fixed64(value) => printer.scalar(&name, &format!("0x{:016x}", value)),
+            RawValue::Fixed32(value) => printer.scalar(&name, &format!("0x{:08x}", value)),
+            RawValue::String(ref value) => printer.scalar(&name, &value.to_text()),
+            RawValue::Bytes(ref value) => printer.scalar(&name, &value.to_text()),
+            RawValue::Packed(ref values) => {
+                let values = values.iter().map(u64::to_string).collect::<Vec<_>>();
+                printer.scalar(&name, &format!("[{}]", values.join(", ")));
+            },
+            RawValue::Message(ref fields) | RawValue::Group(ref fields) => {
+                printer.message(&name, |printer| print_fields(printer, fields));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn decode(data: &[u8]) -> Result<Vec<RawField>, DecodeError> {
+        decode_raw(&mut Cursor::new(data))
+    }
+
+    #[test]
+    fn check_decode_raw() {
+        let data = [
+            0x08, 0x96, 0x01,                                   // 1: 150
+            0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g', // 2: "testing"
+            0x1a, 0x05, 0x08, 0x96, 0x01, 0x10, 0x01,           // 3 { 1: 150 2: 1 }
+            0x22, 0x03, 0x01, 0x02, 0x96,                       // 4: bytes
+            0x2a, 0x03, 0x01, 0x02, 0x03,                       // 5: [1, 2, 3]
+            0x31, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f,                 // 6: 1.0f64
+            0x3d, 0xdb, 0x0f, 0x49, 0x40,                       // 7: 3.14159f32
+            0x43, 0x08, 0x01, 0x44,                             // 8 { 1: 1 }
+            0x4a, 0x00,                                         // 9: ""
+        ];
+        let fields = decode(&data).unwrap();
+        assert_eq!(fields, vec![
+            RawField { tag: 1, value: RawValue::Varint(150) },
+            RawField { tag: 2, value: RawValue::String("testing".to_string()) },
+            RawField { tag: 3, value: RawValue::Message(vec![
+                RawField { tag: 1, value: RawValue::Varint(150) },
+                RawField { tag: 2, value: RawValue::Varint(1) },
+            ]) },
+            RawField { tag: 4, value: RawValue::Bytes(vec![1, 2, 0x96]) },
+            RawField { tag: 5, value: RawValue::Packed(vec![1, 2, 3]) },
+            RawField { tag: 6, value: RawValue::Fixed64(0x3ff0000000000000) },
+            RawField { tag: 7, value: RawValue::Fixed32(0x40490fdb) },
+            RawField { tag: 8, value: RawValue::Group(vec![RawField { tag: 1, value: RawValue::Varint(1) }]) },
+            RawField { tag: 9, value: RawValue::String(String::new()) },
+        ]);
+        assert_eq!(fields[7].value.wire_type(), WireType::StartGroup);
+
+        assert_eq!(print_raw(&fields), "\
+1: 150
+2: \"testing\"
+3 {
+  1: 150
+  2: 1
+}
+4: \"\\001\\002\\226\"
+5: [1, 2, 3]
+6: 0x3ff0000000000000
+7: 0x40490fdb
+8 {
+  1: 1
+}
+9: \"\"
+");
+    }
+
+    #[test]
+    fn check_decode_raw_errors() {
+        // Truncated varint.
+        assert!(decode(&[0x08, 0x96]).is_err());
+        // Truncated length-delimited value.
+        assert!(decode(&[0x12, 0x07, b't']).is_err());
+        // Unterminated group.
+        assert!(decode(&[0x43, 0x08, 0x01]).is_err());
+        // Mismatched end group.
+        assert!(decode(&[0x43, 0x08, 0x01, 0x4c]).is_err());
+        // Tag 0.
+        assert!(decode(&[0x00]).is_err());
+
+        assert_eq!(decode(&[]).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn check_nested_recursion_limit() {
+        // Nested messages beyond the recursion limit are not decoded as messages.
+        let mut data = vec![0x08, 0x01];
+        for _ in 0..(DEFAULT_RECURSION_LIMIT + 1) {
+            let mut nested = vec![0x0a];
+            encode_varint(data.len() as u64, &mut nested);
+            nested.extend_from_slice(&data);
+            data = nested;
+        }
+        let mut fields = decode(&data).unwrap();
+        let mut depth = 0;
+        loop {
+            match fields.pop().unwrap().value {
+                RawValue::Message(nested) => fields = nested,
+                _ => break,
+            }
+            depth += 1;
+        }
+        assert_eq!(depth, DEFAULT_RECURSION_LIMIT);
+    }
+}
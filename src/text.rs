@@ -0,0 +1,1103 @@
+//! Printing and parsing messages in the Protobuf text format.
+//!
+//! `#[derive(Message)]` implements `TextMessage` for owned messages, which prints messages in the
+//! human-readable text format used by `protoc --decode` and `.textproto` files, and parses that
+//! format back into messages:
+//!
+//!  * fields are written as `name: value`, and message fields as `name { ... }` blocks,
+//!  * fields with default values are omitted,
+//!  * enumeration values are written as their names,
+//!  * `string` and `bytes` values are written as quoted strings, with non-printable bytes escaped,
+//!  * map fields are written as a repeated message with `key` and `value` fields.
+//!
+//! The parser also accepts `name: { ... }` and `name < ... >` message fields, `name: [a, b]`
+//! lists of repeated values, `,` and `;` field separators, `#` comments, single quoted and
+//! adjacent strings, and hexadecimal and octal integers. Unknown fields, extension fields and
+//! expanded `google.protobuf.Any` messages are not supported.
+
+use std::borrow::Cow;
+use std::char;
+use std::error;
+use std::fmt;
+
+use bytes::Bytes;
+
+use Enumeration;
+use Message;
+
+/// The maximum nesting depth of messages accepted by `Value::parse`.
+pub const RECURSION_LIMIT: u32 = 100;
+
+/// A field value parsed from text format.
+///
+/// Scalar values are held as their tokens, since the type of a value is only known once the
+/// field it belongs to is known.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An identifier, such as an enumeration value name or `true`. Negated identifiers, such as
+    /// `-inf`, include the `-` sign.
+    Identifier(String),
+    /// The literal text of a number, including a `-` sign if it is negative.
+    Number(String),
+    /// A string, with escapes resolved and adjacent strings concatenated. Strings may hold
+    /// arbitrary bytes, which are only required to be UTF-8 for `string` fields.
+    String(Vec<u8>),
+    /// A message, as its fields in order.
+    Message(Vec<(String, Value)>),
+}
+
+impl Value {
+
+    /// Parses the fields of a message from text format. Lists of repeated values are flattened
+    /// into a field for each value.
+    pub fn parse(text: &str) -> Result<Vec<(String, Value)>, TextError> {
+        let mut parser = Parser { text: text, pos: 0 };
+        parser.parse_fields(None, RECURSION_LIMIT)
+    }
+
+    /// Returns the name of the value's type, for error messages.
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Identifier(..) => "identifier",
+            Value::Number(..) => "number",
+            Value::String(..) => "string",
+            Value::Message(..) => "message",
+        }
+    }
+}
+
+/// A text format parser.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl <'a> Parser<'a> {
+
+    fn error<T>(&self, description: &str) -> Result<T, TextError> {
+        let consumed = &self.text.as_bytes()[..self.pos];
+        let line = consumed.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = self.pos - consumed.iter().rposition(|&b| b == b'\n').map_or(0, |idx| idx + 1) + 1;
+        Err(TextError::new(format!("invalid text format: {} at line {}, column {}",
+                                   description, line, column)))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' | b'\x0B' | b'\x0C' => self.pos += 1,
+                b'#' => {
+                    while let Some(b) = self.peek() {
+                        if b == b'\n' {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                },
+                _ => break,
+            }
+        }
+    }
+
+    /// Consumes the byte if it is next, after any whitespace.
+    fn consume(&mut self, expected: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses fields until the end delimiter, or until the end of the input if there is none.
+    fn parse_fields(&mut self, end: Option<u8>, depth: u32) -> Result<Vec<(String, Value)>, TextError> {
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match (self.peek(), end) {
+                (None, None) => return Ok(fields),
+                (None, Some(_)) => return self.error("unexpected end of input"),
+                (Some(b), Some(end)) if b == end => {
+                    self.pos += 1;
+                    return Ok(fields);
+                },
+                _ => (),
+            }
+
+            let name = self.parse_field_name()?;
+            let colon = self.consume(b':');
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'{') | Some(b'<') => {
+                    let value = self.parse_message(depth)?;
+                    fields.push((name, value));
+                },
+                Some(b'[') if colon => {
+                    self.pos += 1;
+                    if !self.consume(b']') {
+                        loop {
+                            let value = self.parse_value(depth)?;
+                            fields.push((name.clone(), value));
+                            if self.consume(b']') {
+                                break;
+                            } else if !self.consume(b',') {
+                                return self.error("expected ',' or ']'");
+                            }
+                        }
+                    }
+                },
+                _ if colon => {
+                    let value = self.parse_scalar()?;
+                    fields.push((name, value));
+                },
+                _ => return self.error("expected ':'"),
+            }
+
+            // Fields may be followed by a separator.
+            if !self.consume(b',') {
+                self.consume(b';');
+            }
+        }
+    }
+
+    fn parse_field_name(&mut self) -> Result<String, TextError> {
+        if self.peek() == Some(b'[') {
+            // Extension names and `Any` type URLs are kept with their brackets, so that they
+            // can't be mistaken for regular fields.
+            let start = self.pos;
+            while let Some(b) = self.peek() {
+                self.pos += 1;
+                if b == b']' {
+                    return Ok(self.text[start..self.pos].to_string());
+                }
+            }
+            return self.error("unterminated field name");
+        }
+        match self.parse_identifier() {
+            Some(name) => Ok(name),
+            None => self.error("expected field name"),
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(b) if is_identifier_start(b) => (),
+            _ => return None,
+        }
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if !is_identifier_start(b) && !is_digit(b) {
+                break;
+            }
+            self.pos += 1;
+        }
+        Some(self.text[start..self.pos].to_string())
+    }
+
+    fn parse_value(&mut self, depth: u32) -> Result<Value, TextError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') | Some(b'<') => self.parse_message(depth),
+            _ => self.parse_scalar(),
+        }
+    }
+
+    /// Parses a message, starting at the opening delimiter.
+    fn parse_message(&mut self, depth: u32) -> Result<Value, TextError> {
+        if depth == 0 {
+            return self.error("recursion limit reached");
+        }
+        let end = if self.peek() == Some(b'{') { b'}' } else { b'>' };
+        self.pos += 1;
+        self.parse_fields(Some(end), depth - 1).map(Value::Message)
+    }
+
+    fn parse_scalar(&mut self) -> Result<Value, TextError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'"') | Some(b'\'') => {
+                let mut value = Vec::new();
+                loop {
+                    self.parse_string(&mut value)?;
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b'"') | Some(b'\'') => (),
+                        _ => return Ok(Value::String(value)),
+                    }
+                }
+            },
+            Some(b'-') => {
+                self.pos += 1;
+                self.skip_whitespace();
+                match self.parse_unsigned()? {
+                    Value::Number(number) => Ok(Value::Number(format!("-{}", number))),
+                    Value::Identifier(identifier) => Ok(Value::Identifier(format!("-{}", identifier))),
+                    _ => unreachable!(),
+                }
+            },
+            _ => self.parse_unsigned(),
+        }
+    }
+
+    /// Parses a number or an identifier.
+    fn parse_unsigned(&mut self) -> Result<Value, TextError> {
+        match self.peek() {
+            Some(b) if is_digit(b) || b == b'.' => {
+                let start = self.pos;
+                let bytes = self.text.as_bytes();
+                let hex = bytes.get(start + 1).map_or(false, |&b| b == b'x' || b == b'X');
+                while let Some(b) = self.peek() {
+                    let exponent_sign = (b == b'+' || b == b'-') && !hex &&
+                                        (bytes[self.pos - 1] == b'e' || bytes[self.pos - 1] == b'E');
+                    if !is_identifier_start(b) && !is_digit(b) && b != b'.' && !exponent_sign {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                Ok(Value::Number(self.text[start..self.pos].to_string()))
+            },
+            _ => match self.parse_identifier() {
+                Some(identifier) => Ok(Value::Identifier(identifier)),
+                None => self.error("expected value"),
+            },
+        }
+    }
+
+    /// Parses a quoted string, starting at the opening quote, and appends it to `value`.
+    fn parse_string(&mut self, value: &mut Vec<u8>) -> Result<(), TextError> {
+        let quote = self.peek();
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => return self.error("unterminated string"),
+                Some(b) if Some(b) == quote => {
+                    self.pos += 1;
+                    return Ok(());
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    self.parse_escape(value)?;
+                },
+                Some(b) => {
+                    self.pos += 1;
+                    value.push(b);
+                },
+            }
+        }
+    }
+
+    /// Parses an escape sequence, following the backslash, and appends it to `value`.
+    fn parse_escape(&mut self, value: &mut Vec<u8>) -> Result<(), TextError> {
+        let b = match self.peek() {
+            Some(b) => b,
+            None => return self.error("unterminated string"),
+        };
+        self.pos += 1;
+        let escaped = match b {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'a' => b'\x07',
+            b'b' => b'\x08',
+            b'f' => b'\x0C',
+            b'v' => b'\x0B',
+            b'\\' | b'\'' | b'"' | b'?' => b,
+            b'0' | b'1' | b'2' | b'3' | b'4' | b'5' | b'6' | b'7' => {
+                let mut code = (b - b'0') as u32;
+                for _ in 0..2 {
+                    match self.peek() {
+                        Some(b) if b'0' <= b && b <= b'7' => {
+                            code = code * 8 + (b - b'0') as u32;
+                            self.pos += 1;
+                        },
+                        _ => break,
+                    }
+                }
+                if code > 0xFF {
+                    return self.error("invalid octal escape");
+                }
+                code as u8
+            },
+            b'x' | b'X' => {
+                let code = self.parse_hex(1, 2)?;
+                code as u8
+            },
+            b'u' | b'U' => {
+                let len = if b == b'u' { 4 } else { 8 };
+                let code = self.parse_hex(len, len)?;
+                match char::from_u32(code) {
+                    Some(c) => {
+                        let mut buf = [0; 4];
+                        value.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        return Ok(());
+                    },
+                    None => return self.error("invalid unicode escape"),
+                }
+            },
+            _ => return self.error("invalid escape"),
+        };
+        value.push(escaped);
+        Ok(())
+    }
+
+    /// Parses between `min` and `max` hexadecimal digits.
+    fn parse_hex(&mut self, min: usize, max: usize) -> Result<u32, TextError> {
+        let mut code = 0;
+        let mut len = 0;
+        while len < max {
+            match self.peek().and_then(|b| (b as char).to_digit(16)) {
+                Some(digit) => {
+                    code = code * 16 + digit;
+                    self.pos += 1;
+                    len += 1;
+                },
+                None => break,
+            }
+        }
+        if len < min {
+            return self.error("invalid hexadecimal escape");
+        }
+        Ok(code)
+    }
+}
+
+fn is_digit(b: u8) -> bool {
+    b'0' <= b && b <= b'9'
+}
+
+fn is_identifier_start(b: u8) -> bool {
+    (b'a' <= b && b <= b'z') || (b'A' <= b && b <= b'Z') || b == b'_'
+}
+
+/// Writes a string or bytes value as a quoted string. Printable ASCII characters are written
+/// as-is, and all other bytes are escaped.
+fn escape(value: &[u8]) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for &b in value {
+        match b {
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            _ if b' ' <= b && b <= b'~' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parses the text of an integer in decimal, hexadecimal (`0x` prefix) or octal (`0` prefix)
+/// notation, returning the sign and magnitude.
+///
+/// Returns `None` if the text is not a valid integer, or overflows a `u64`.
+fn parse_integer(text: &str) -> Option<(bool, u64)> {
+    let (negative, text) = if text.starts_with('-') { (true, &text[1..]) } else { (false, text) };
+    let magnitude = if text.starts_with("0x") || text.starts_with("0X") {
+        u64::from_str_radix(&text[2..], 16)
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8)
+    } else {
+        u64::from_str_radix(text, 10)
+    };
+    // `from_str_radix` accepts a leading `+`, which is not valid in text format.
+    match magnitude {
+        Ok(magnitude) if !text.contains('+') => Some((negative, magnitude)),
+        _ => None,
+    }
+}
+
+/// Parses a floating point value from a number, which may have an `f` suffix, or from one of
+/// the identifiers `inf`, `infinity` and `nan`, which may be negated.
+fn parse_float(value: Value) -> Result<f64, TextError> {
+    let text = match value {
+        Value::Number(text) => text,
+        Value::Identifier(text) => {
+            let (negative, identifier) = if text.starts_with('-') {
+                (true, text[1..].to_lowercase())
+            } else {
+                (false, text.to_lowercase())
+            };
+            let value = match &identifier[..] {
+                "inf" | "infinity" => ::std::f64::INFINITY,
+                "nan" => ::std::f64::NAN,
+                _ => return Err(TextError::new(format!("invalid floating point value: {}", text))),
+            };
+            return Ok(if negative { -value } else { value });
+        },
+        value => return Err(unexpected_type("number", &value)),
+    };
+    let number = if text.ends_with('f') || text.ends_with('F') {
+        &text[..text.len() - 1]
+    } else {
+        &text[..]
+    };
+    // Hexadecimal and octal integers are not valid floating point values.
+    let digits = if number.starts_with('-') { &number[1..] } else { number };
+    let octal = digits.len() > 1 && digits.starts_with('0') && digits.bytes().all(is_digit);
+    match number.parse::<f64>() {
+        Ok(value) if !octal => Ok(value),
+        _ => Err(TextError::new(format!("invalid floating point value: {}", text))),
+    }
+}
+
+/// Converts a floating point value to text. Large and small magnitudes are written in exponent
+/// notation.
+macro_rules! float_to_text {
+    ($value:expr) => ({
+        let value = $value;
+        let abs = value.abs();
+        if value.is_nan() {
+            "nan".to_string()
+        } else if value.is_infinite() {
+            if value < 0.0 { "-inf" } else { "inf" }.to_string()
+        } else if abs != 0.0 && (abs < 1e-5 || abs >= 1e16) {
+            format!("{:e}", value)
+        } else {
+            value.to_string()
+        }
+    })
+}
+
+/// An error printing or parsing a message in text format.
+///
+/// `TextError` indicates that the input is not valid text format, or does not match the fields
+/// of the message type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextError {
+    description: Cow<'static, str>,
+    /// A 'call stack' of the message and field names which were being parsed when the error
+    /// occurred, ordered from the innermost message to the outermost.
+    stack: Vec<(&'static str, &'static str)>,
+}
+
+impl TextError {
+
+    /// Creates a new `TextError` with a free-form description.
+    pub fn new<S>(description: S) -> TextError where S: Into<Cow<'static, str>> {
+        TextError {
+            description: description.into(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns the `(message, field)` names which were being parsed when the error occurred,
+    /// ordered from the innermost message to the outermost.
+    pub fn stack(&self) -> &[(&'static str, &'static str)] {
+        &self.stack
+    }
+
+    /// Pushes a (message, field) name location pair on to the location stack.
+    ///
+    /// Meant to be used only by `TextMessage` implementations.
+    #[doc(hidden)]
+    pub fn push(&mut self, message: &'static str, field: &'static str) {
+        self.stack.push((message, field));
+    }
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("failed to parse Protobuf message from text format: ")?;
+        for &(message, field) in self.stack.iter().rev() {
+            write!(f, "{}.{}: ", message, field)?;
+        }
+        f.write_str(&self.description)
+    }
+}
+
+impl error::Error for TextError {
+    fn description(&self) -> &str {
+        "failed to parse Protobuf message from text format"
+    }
+}
+
+/// Returns an error for a value of an unexpected type.
+fn unexpected_type(expected: &str, value: &Value) -> TextError {
+    TextError::new(format!("invalid value: expected {}, found {}", expected, value.type_name()))
+}
+
+/// Writes the fields of messages in text format, one field per line.
+pub struct Printer {
+    buf: String,
+    indent: usize,
+}
+
+impl Printer {
+
+    /// Creates a new `Printer`.
+    pub fn new() -> Printer {
+        Printer {
+            buf: String::new(),
+            indent: 0,
+        }
+    }
+
+    /// Writes a field with a scalar value.
+    pub fn scalar(&mut self, name: &str, value: &str) {
+        self.write_indent();
+        self.buf.push_str(name);
+        self.buf.push_str(": ");
+        self.buf.push_str(value);
+        self.buf.push('\n');
+    }
+
+    /// Writes a message field, calling `fields` to write the fields of the message.
+    pub fn message<F>(&mut self, name: &str, fields: F) where F: FnOnce(&mut Printer) {
+        self.write_indent();
+        self.buf.push_str(name);
+        self.buf.push_str(" {\n");
+        self.indent += 1;
+        fields(self);
+        self.indent -= 1;
+        self.write_indent();
+        self.buf.push_str("}\n");
+    }
+
+    /// Returns the printed text.
+    pub fn finish(self) -> String {
+        self.buf
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.buf.push_str("  ");
+        }
+    }
+}
+
+/// A Protocol Buffers message which can be printed and parsed in text format.
+pub trait TextMessage: Message {
+
+    /// Prints the message in text format.
+    fn encode_text(&self) -> String {
+        let mut printer = Printer::new();
+        self.print_text(&mut printer);
+        printer.finish()
+    }
+
+    /// Decodes an instance of the message from text format.
+    fn decode_text(text: &str) -> Result<Self, TextError> {
+        let mut message = Self::default();
+        message.merge_text(text)?;
+        Ok(message)
+    }
+
+    /// Merges text format into `self`. Singular fields which are present in the text replace the
+    /// existing value, and repeated fields are appended to.
+    fn merge_text(&mut self, text: &str) -> Result<(), TextError> {
+        self.merge_text_fields(Value::parse(text)?)
+    }
+
+    /// Writes the fields of the message.
+    fn print_text(&self, printer: &mut Printer);
+
+    /// Merges parsed fields into `self`.
+    fn merge_text_fields(&mut self, fields: Vec<(String, Value)>) -> Result<(), TextError>;
+}
+
+impl <M> TextMessage for Box<M> where M: TextMessage {
+    fn print_text(&self, printer: &mut Printer) {
+        (**self).print_text(printer)
+    }
+    fn merge_text_fields(&mut self, fields: Vec<(String, Value)>) -> Result<(), TextError> {
+        (**self).merge_text_fields(fields)
+    }
+}
+
+/// Marks a singular field as parsed, returning an error if it was already parsed from the same
+/// message.
+///
+/// Meant to be used only by `TextMessage` implementations.
+#[doc(hidden)]
+pub fn check_duplicate(seen: &mut bool, name: &str) -> Result<(), TextError> {
+    if *seen {
+        return Err(TextError::new(format!("non-repeated field specified multiple times: {}", name)));
+    }
+    *seen = true;
+    Ok(())
+}
+
+/// Returns an error for a field name which does not correspond to a field.
+///
+/// Meant to be used only by `TextMessage` implementations.
+#[doc(hidden)]
+pub fn unknown_field(name: &str) -> TextError {
+    TextError::new(format!("unknown field: {}", name))
+}
+
+/// A scalar Protobuf value type with a text format representation.
+pub trait TextScalar: Sized {
+    /// Converts the value to text.
+    fn to_text(&self) -> String;
+    /// Converts a parsed value to a value of the type.
+    fn from_text(value: Value) -> Result<Self, TextError>;
+}
+
+macro_rules! integer {
+    ($ty:ident) => (
+        impl TextScalar for $ty {
+            fn to_text(&self) -> String {
+                self.to_string()
+            }
+
+            fn from_text(value: Value) -> Result<$ty, TextError> {
+                let text = match value {
+                    Value::Number(text) => text,
+                    value => return Err(unexpected_type("integer", &value)),
+                };
+                let value = match parse_integer(&text) {
+                    Some((false, magnitude)) if magnitude <= $ty::max_value() as u64 => {
+                        Some(magnitude as $ty)
+                    },
+                    Some((true, magnitude)) if magnitude <= ($ty::min_value() as i64).wrapping_neg() as u64 => {
+                        Some((magnitude as $ty).wrapping_neg())
+                    },
+                    _ => None,
+                };
+                value.ok_or_else(|| {
+                    TextError::new(format!(concat!("invalid ", stringify!($ty), " value: {}"), text))
+                })
+            }
+        }
+    );
+}
+
+integer!(i32);
+integer!(i64);
+integer!(u32);
+integer!(u64);
+
+impl TextScalar for f32 {
+    fn to_text(&self) -> String {
+        float_to_text!(*self)
+    }
+
+    fn from_text(value: Value) -> Result<f32, TextError> {
+        parse_float(value).map(|value| value as f32)
+    }
+}
+
+impl TextScalar for f64 {
+    fn to_text(&self) -> String {
+        float_to_text!(*self)
+    }
+
+    fn from_text(value: Value) -> Result<f64, TextError> {
+        parse_float(value)
+    }
+}
+
+impl TextScalar for bool {
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_text(value: Value) -> Result<bool, TextError> {
+        match value {
+            Value::Identifier(ref text) if text == "true" || text == "True" || text == "t" => Ok(true),
+            Value::Identifier(ref text) if text == "false" || text == "False" || text == "f" => Ok(false),
+            Value::Number(ref text) if text == "1" => Ok(true),
+            Value::Number(ref text) if text == "0" => Ok(false),
+            Value::Identifier(text) | Value::Number(text) => {
+                Err(TextError::new(format!("invalid bool value: {}", text)))
+            },
+            value => Err(unexpected_type("bool", &value)),
+        }
+    }
+}
+
+impl TextScalar for String {
+    fn to_text(&self) -> String {
+        escape(self.as_bytes())
+    }
+
+    fn from_text(value: Value) -> Result<String, TextError> {
+        match value {
+            Value::String(value) => String::from_utf8(value).map_err(|_| {
+                TextError::new("invalid string value: data is not UTF-8 encoded")
+            }),
+            value => Err(unexpected_type("string", &value)),
+        }
+    }
+}
+
+impl TextScalar for Vec<u8> {
+    fn to_text(&self) -> String {
+        escape(self)
+    }
+
+    fn from_text(value: Value) -> Result<Vec<u8>, TextError> {
+        match value {
+            Value::String(value) => Ok(value),
+            value => Err(unexpected_type("string", &value)),
+        }
+    }
+}
+
+impl TextScalar for Bytes {
+    fn to_text(&self) -> String {
+        escape(self)
+    }
+
+    fn from_text(value: Value) -> Result<Bytes, TextError> {
+        Vec::<u8>::from_text(value).map(Bytes::from)
+    }
+}
+
+/// Conversions of scalar fields.
+pub mod scalar {
+    use super::*;
+
+    pub fn print<T>(printer: &mut Printer, name: &str, value: &T) where T: TextScalar {
+        printer.scalar(name, &value.to_text());
+    }
+
+    pub fn print_repeated<T>(printer: &mut Printer, name: &str, values: &[T]) where T: TextScalar {
+        for value in values {
+            print(printer, name, value);
+        }
+    }
+
+    pub fn from_text<T>(value: Value) -> Result<T, TextError> where T: TextScalar {
+        T::from_text(value)
+    }
+
+    pub fn merge<T>(field: &mut T, value: Value) -> Result<(), TextError> where T: TextScalar {
+        *field = T::from_text(value)?;
+        Ok(())
+    }
+
+    pub fn merge_optional<T>(field: &mut Option<T>, value: Value) -> Result<(), TextError>
+    where T: TextScalar {
+        *field = Some(T::from_text(value)?);
+        Ok(())
+    }
+
+    pub fn merge_repeated<T>(field: &mut Vec<T>, value: Value) -> Result<(), TextError>
+    where T: TextScalar {
+        field.push(T::from_text(value)?);
+        Ok(())
+    }
+}
+
+/// Conversions of enumeration fields. Known values are written as their names, and unknown
+/// values as numbers. Either form is accepted when parsing.
+pub mod enumeration {
+    use super::*;
+
+    pub fn to_text<E>(value: &i32) -> String where E: Enumeration {
+        match E::value_name(*value) {
+            Some(name) => name.to_string(),
+            None => value.to_string(),
+        }
+    }
+
+    pub fn print<E>(printer: &mut Printer, name: &str, value: &i32) where E: Enumeration {
+        printer.scalar(name, &to_text::<E>(value));
+    }
+
+    pub fn print_repeated<E>(printer: &mut Printer, name: &str, values: &[i32]) where E: Enumeration {
+        for value in values {
+            print::<E>(printer, name, value);
+        }
+    }
+
+    pub fn from_text<E>(value: Value) -> Result<i32, TextError> where E: Enumeration {
+        match value {
+            Value::Identifier(name) => E::from_value_name(&name).ok_or_else(|| {
+                TextError::new(format!("unknown enumeration value: {}", name))
+            }),
+            value => i32::from_text(value),
+        }
+    }
+
+    pub fn merge<E>(field: &mut i32, value: Value) -> Result<(), TextError> where E: Enumeration {
+        *field = from_text::<E>(value)?;
+        Ok(())
+    }
+
+    pub fn merge_optional<E>(field: &mut Option<i32>, value: Value) -> Result<(), TextError>
+    where E: Enumeration {
+        *field = Some(from_text::<E>(value)?);
+        Ok(())
+    }
+
+    pub fn merge_repeated<E>(field: &mut Vec<i32>, value: Value) -> Result<(), TextError>
+    where E: Enumeration {
+        field.push(from_text::<E>(value)?);
+        Ok(())
+    }
+}
+
+/// Conversions of open enumeration fields, in the same forms as enumeration fields.
+pub mod open_enum {
+    use super::*;
+    use OpenEnum;
+
+    pub fn to_text<E>(value: &OpenEnum<E>) -> String where E: Enumeration {
+        enumeration::to_text::<E>(&value.to_i32())
+    }
+
+    pub fn print<E>(printer: &mut Printer, name: &str, value: &OpenEnum<E>) where E: Enumeration {
+        printer.scalar(name, &to_text(value));
+    }
+
+    pub fn print_repeated<E>(printer: &mut Printer, name: &str, values: &[OpenEnum<E>])
+    where E: Enumeration {
+        for value in values {
+            print(printer, name, value);
+        }
+    }
+
+    pub fn from_text<E>(value: Value) -> Result<OpenEnum<E>, TextError> where E: Enumeration {
+        enumeration::from_text::<E>(value).map(OpenEnum::from_i32)
+    }
+
+    pub fn merge<E>(field: &mut OpenEnum<E>, value: Value) -> Result<(), TextError>
+    where E: Enumeration {
+        *field = from_text(value)?;
+        Ok(())
+    }
+
+    pub fn merge_optional<E>(field: &mut Option<OpenEnum<E>>, value: Value) -> Result<(), TextError>
+    where E: Enumeration {
+        *field = Some(from_text(value)?);
+        Ok(())
+    }
+
+    pub fn merge_repeated<E>(field: &mut Vec<OpenEnum<E>>, value: Value) -> Result<(), TextError>
+    where E: Enumeration {
+        field.push(from_text(value)?);
+        Ok(())
+    }
+}
+
+/// Conversions of message fields.
+pub mod message {
+    use super::*;
+
+    pub fn print<M>(printer: &mut Printer, name: &str, msg: &M) where M: TextMessage {
+        printer.message(name, |printer| msg.print_text(printer));
+    }
+
+    pub fn print_repeated<M>(printer: &mut Printer, name: &str, messages: &[M]) where M: TextMessage {
+        for msg in messages {
+            print(printer, name, msg);
+        }
+    }
+
+    pub fn from_text<M>(value: Value) -> Result<M, TextError> where M: TextMessage {
+        let mut msg = M::default();
+        merge(&mut msg, value)?;
+        Ok(msg)
+    }
+
+    pub fn merge<M>(msg: &mut M, value: Value) -> Result<(), TextError> where M: TextMessage {
+        match value {
+            Value::Message(fields) => msg.merge_text_fields(fields),
+            value => Err(unexpected_type("message", &value)),
+        }
+    }
+
+    pub fn merge_optional<M>(field: &mut Option<M>, value: Value) -> Result<(), TextError>
+    where M: TextMessage {
+        // TODO(rustlang/rust#39288): Use Option::get_or_insert_with when available.
+        if field.is_none() {
+            *field = Some(M::default());
+        }
+        match *field {
+            Some(ref mut msg) => merge(msg, value),
+            None => unreachable!(),
+        }
+    }
+
+    pub fn merge_repeated<M>(messages: &mut Vec<M>, value: Value) -> Result<(), TextError>
+    where M: TextMessage {
+        messages.push(from_text(value)?);
+        Ok(())
+    }
+}
+
+/// Rust doesn't have a `Map` trait, so macros are currently the best way to be
+/// generic over `HashMap` and `BTreeMap`.
+macro_rules! map {
+    ($map_ty:ident) => (
+        use std::collections::$map_ty;
+        use std::hash::Hash;
+
+        use ::text::*;
+
+        /// Writes a map as a repeated message field with `key` and `value` fields, using
+        /// `print_value` to write the values.
+        pub fn print<K, V, F>(printer: &mut Printer, name: &str, values: &$map_ty<K, V>, print_value: F)
+        where K: TextScalar + Eq + Hash + Ord,
+              F: Fn(&mut Printer, &str, &V) {
+            for (key, value) in values {
+                printer.message(name, |printer| {
+                    printer.scalar("key", &key.to_text());
+                    print_value(printer, "value", value);
+                });
+            }
+        }
+
+        /// Merges a map entry message into a map, using `value_from_text` to convert the value.
+        /// A missing key or value is the default value of its type.
+        pub fn merge<K, V, F>(values: &mut $map_ty<K, V>,
+                              value: Value,
+                              value_from_text: F)
+                              -> Result<(), TextError>
+        where K: TextScalar + Default + Eq + Hash + Ord,
+              V: Default,
+              F: Fn(Value) -> Result<V, TextError> {
+            let fields = match value {
+                Value::Message(fields) => fields,
+                value => return Err(unexpected_type("message", &value)),
+            };
+            let mut key = None;
+            let mut val = None;
+            for (name, value) in fields {
+                match &name[..] {
+                    "key" if key.is_none() => key = Some(K::from_text(value)?),
+                    "value" if val.is_none() => val = Some(value_from_text(value)?),
+                    "key" | "value" => return Err(TextError::new(format!(
+                        "non-repeated field specified multiple times: {}", name))),
+                    _ => return Err(unknown_field(&name)),
+                }
+            }
+            values.insert(key.unwrap_or_default(), val.unwrap_or_default());
+            Ok(())
+        }
+    )
+}
+
+/// Conversions of `HashMap` fields.
+pub mod hash_map {
+    map!(HashMap);
+}
+
+/// Conversions of `BTreeMap` fields.
+pub mod btree_map {
+    map!(BTreeMap);
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+    use std::f64;
+
+    use quickcheck::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let fields = Value::parse("a: 1 b { c: -2.5e3, d: 'x' \"\\n\\101\\x42\\u00e9\" } # comment\n\
+                                   e: [-inf, FOO]; f < > g: { } [foo.bar]: 0x1F")
+                          .unwrap();
+        assert_eq!(vec![
+            ("a".to_string(), Value::Number("1".to_string())),
+            ("b".to_string(), Value::Message(vec![
+                ("c".to_string(), Value::Number("-2.5e3".to_string())),
+                ("d".to_string(), Value::String(b"x\nAB\xC3\xA9".to_vec())),
+            ])),
+            ("e".to_string(), Value::Identifier("-inf".to_string())),
+            ("e".to_string(), Value::Identifier("FOO".to_string())),
+            ("f".to_string(), Value::Message(Vec::new())),
+            ("g".to_string(), Value::Message(Vec::new())),
+            ("[foo.bar]".to_string(), Value::Number("0x1F".to_string())),
+        ], fields);
+
+        let invalid = [
+            "a", "a 1", "a: ", "a: {", "a { b: 1 >", "a: [1,]", "a: \"b", "a: \"\\q\"", "a: \"\\400\"",
+            "a: \"\\x\"", "{}", "1: 2", "a: 1 }", "a: [{]",
+        ];
+        for text in &invalid {
+            assert!(Value::parse(text).is_err(), "{:?}", text);
+        }
+
+        let error = Value::parse("a: 1\nb: ").unwrap_err();
+        assert_eq!(error.to_string(), "failed to parse Protobuf message from text format: \
+                                       invalid text format: expected value at line 2, column 4");
+
+        let mut nested = String::new();
+        for _ in 0..RECURSION_LIMIT + 1 {
+            nested.push_str("a {");
+        }
+        assert!(Value::parse(&nested).is_err());
+    }
+
+    #[test]
+    fn integers() {
+        let number = |text: &str| Value::Number(text.to_string());
+        assert_eq!(i32::from_text(number("-2147483648")), Ok(i32::min_value()));
+        assert_eq!(i32::from_text(number("0x7FFFFFFF")), Ok(i32::max_value()));
+        assert_eq!(i32::from_text(number("-0x10")), Ok(-16));
+        assert_eq!(u32::from_text(number("017")), Ok(15));
+        assert_eq!(u64::from_text(number("18446744073709551615")), Ok(u64::max_value()));
+        assert_eq!(i64::from_text(number("0")), Ok(0));
+
+        for text in &["2147483648", "-2147483649", "1.0", "1e3", "08", "0x", "+1", "1f"] {
+            assert!(i32::from_text(number(text)).is_err(), "{}", text);
+        }
+        assert!(u32::from_text(number("-1")).is_err());
+        assert!(u32::from_text(Value::Identifier("one".to_string())).is_err());
+    }
+
+    #[test]
+    fn floats() {
+        let number = |text: &str| Value::Number(text.to_string());
+        let identifier = |text: &str| Value::Identifier(text.to_string());
+        assert_eq!(f64::from_text(number("1.5")), Ok(1.5));
+        assert_eq!(f64::from_text(number("-.5e-3")), Ok(-0.0005));
+        assert_eq!(f64::from_text(number("10")), Ok(10.0));
+        assert_eq!(f32::from_text(number("2.5f")), Ok(2.5));
+        assert_eq!(f64::from_text(identifier("-Infinity")), Ok(f64::NEG_INFINITY));
+        assert!(f64::from_text(identifier("nan")).unwrap().is_nan());
+
+        for text in &["0x10", "010", "1.5.5", "e5"] {
+            assert!(f64::from_text(number(text)).is_err(), "{}", text);
+        }
+        assert!(f64::from_text(identifier("infinite")).is_err());
+
+        assert_eq!("1e-7", 1e-7f64.to_text());
+        assert_eq!("-inf", f32::NEG_INFINITY.to_text());
+        assert_eq!("nan", f64::NAN.to_text());
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!("\"a\\\"\\'\\\\\\n\\001\\303\\251\"", "a\"'\\\n\u{1}\u{e9}".to_string().to_text());
+        assert!(String::from_text(Value::String(vec![0xC3])).is_err());
+    }
+
+    quickcheck! {
+        fn bytes_roundtrip(value: Vec<u8>) -> bool {
+            let text = format!("a: {}", value.to_text());
+            Value::parse(&text) == Ok(vec![("a".to_string(), Value::String(value))])
+        }
+
+        fn i64_roundtrip(value: i64) -> bool {
+            i64::from_text(Value::Number(value.to_text())) == Ok(value)
+        }
+
+        fn f64_roundtrip(value: f64) -> TestResult {
+            if value.is_nan() {
+                return TestResult::discard();
+            }
+            let text = format!("a: {}", value.to_text());
+            let fields = Value::parse(&text).unwrap();
+            let parsed = f64::from_text(fields.into_iter().next().unwrap().1);
+            TestResult::from_bool(parsed == Ok(value))
+        }
+    }
+}
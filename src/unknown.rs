@@ -0,0 +1,169 @@
+//! Storage for fields which are not recognized while decoding a message.
+
+use std::slice;
+
+use bytes::{
+    Buf,
+    BufMut,
+    LittleEndian,
+    Take,
+};
+
+use encoding::*;
+use DecodeError;
+use DecodeErrorKind;
+
+/// A field which was not recognized while decoding a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownField {
+    /// The field tag.
+    pub tag: u32,
+    /// The wire type of the encoded field value.
+    pub wire_type: WireType,
+    /// The encoded field value, not including the field key. Length-delimited
+    /// values do not include the length prefix, and groups do not include the
+    /// end group key.
+    pub value: Vec<u8>,
+}
+
+/// A set of fields which were not recognized while decoding a message.
+///
+/// Messages which contain an `UnknownFields` field annotated with
+/// `#[prost(unknown_fields)]` retain unrecognized fields when decoding, and
+/// write them back out after the known fields when encoding. This preserves
+/// fields added in newer versions of a schema across a decode and encode
+/// round-trip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownFields {
+    fields: Vec<UnknownField>,
+}
+
+impl UnknownFields {
+
+    /// Creates a new, empty set of unknown fields.
+    pub fn new() -> UnknownFields {
+        UnknownFields::default()
+    }
+
+    /// Returns `true` if no unknown fields have been retained.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the number of retained unknown fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns an iterator over the retained unknown fields, in the order
+    /// they were decoded.
+    pub fn iter(&self) -> slice::Iter<UnknownField> {
+        self.fields.iter()
+    }
+
+    /// Appends an unknown field.
+    pub fn push(&mut self, field: UnknownField) {
+        self.fields.push(field);
+    }
+
+    /// Removes all unknown fields.
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// Appends the unknown fields of `other`.
+    pub fn merge_from(&mut self, other: &UnknownFields) {
+        self.fields.extend(other.fields.iter().cloned());
+    }
+
+    /// Encodes the unknown fields, and writes them to the buffer.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encode<B>(&self, buf: &mut B) where B: BufMut {
+        for field in &self.fields {
+            encode_key(field.tag, field.wire_type, buf);
+            if field.wire_type == WireType::LengthDelimited {
+                encode_varint(field.value.len() as u64, buf);
+            }
+            buf.put_slice(&field.value);
+            if field.wire_type == WireType::StartGroup {
+                encode_key(field.tag, WireType::EndGroup, buf);
+            }
+        }
+    }
+
+    /// Decodes the value of an unrecognized field from the buffer, and
+    /// retains it.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn merge_field<B>(&mut self,
+                          tag: u32,
+                          wire_type: WireType,
+                          buf: &mut Take<B>,
+                          ctx: DecodeContext)
+                          -> Result<(), DecodeError> where B: Buf {
+        let mut value = Vec::new();
+        match wire_type {
+            WireType::Varint => {
+                encode_varint(decode_varint(buf)?, &mut value);
+            },
+            WireType::SixtyFourBit => {
+                let mut fixed = 0;
+                fixed64::merge(wire_type, &mut fixed, buf, ctx)?;
+                value.put_u64::<LittleEndian>(fixed);
+            },
+            WireType::ThirtyTwoBit => {
+                let mut fixed = 0;
+                fixed32::merge(wire_type, &mut fixed, buf, ctx)?;
+                value.put_u32::<LittleEndian>(fixed);
+            },
+            WireType::LengthDelimited => bytes::merge(wire_type, &mut value, buf, ctx)?,
+            WireType::StartGroup => {
+                ctx.limit_reached()?;
+                let mut group = UnknownFields::new();
+                loop {
+                    let (field_tag, field_wire_type) = decode_key(buf)?;
+                    if field_wire_type == WireType::EndGroup {
+                        check_end_group(tag, field_tag)?;
+                        break;
+                    }
+                    group.merge_field(field_tag, field_wire_type, buf, ctx.enter_recursion())?;
+                }
+                group.encode(&mut value);
+            },
+            WireType::EndGroup => return Err(DecodeError::new(DecodeErrorKind::UnexpectedEndGroup(tag))),
+        }
+        self.fields.push(UnknownField {
+            tag: tag,
+            wire_type: wire_type,
+            value: value,
+        });
+        Ok(())
+    }
+
+    /// Returns the encoded length of the unknown fields.
+    ///
+    /// Meant to be used only by `Message` implementations.
+    #[doc(hidden)]
+    pub fn encoded_len(&self) -> usize {
+        self.fields.iter().map(|field| {
+            let len = field.value.len();
+            key_len(field.tag) + len + match field.wire_type {
+                WireType::LengthDelimited => encoded_len_varint(len as u64),
+                WireType::StartGroup => key_len(field.tag),
+                _ => 0,
+            }
+        }).sum()
+    }
+}
+
+impl <'a> IntoIterator for &'a UnknownFields {
+    type Item = &'a UnknownField;
+    type IntoIter = slice::Iter<'a, UnknownField>;
+
+    fn into_iter(self) -> slice::Iter<'a, UnknownField> {
+        self.fields.iter()
+    }
+}
@@ -1,17 +1,18 @@
 extern crate bytes;
 extern crate prost;
+extern crate prost_types;
 #[macro_use] extern crate prost_derive;
 
 pub mod protobuf_test_messages {
     #[allow(non_snake_case)]
     pub mod proto3 {
-        include!(concat!(env!("OUT_DIR"), "/proto3.rs"));
+        include!(concat!(env!("OUT_DIR"), "/protobuf_test_messages.proto3.rs"));
     }
 }
 
 pub mod google {
     pub mod protobuf {
-        include!(concat!(env!("OUT_DIR"), "/protobuf.rs"));
+        include!(concat!(env!("OUT_DIR"), "/google.protobuf.rs"));
     }
 }
 
@@ -20,18 +21,20 @@ use std::io::{
     Cursor,
     Error,
     ErrorKind,
-    Result,
 };
 
 use bytes::Buf;
-use prost::Message;
+use prost::{
+    Merge,
+    Message,
+};
 
 pub enum RoundtripResult {
     /// The roundtrip succeeded.
     Ok(Vec<u8>),
     /// The data could not be decoded. This could indicate a bug in prost,
     /// or it could indicate that the input was bogus.
-    DecodeError(Error),
+    DecodeError(prost::DecodeError),
     /// Re-encoding or validating the data failed.  This indicates a bug in `prost`.
     Error(Error),
 }
@@ -47,7 +50,7 @@ impl RoundtripResult {
     }
 
     /// Unwrap the roundtrip result. Panics if the result was a validation or re-encoding error.
-    pub fn unwrap_error(self) -> Result<Vec<u8>> {
+    pub fn unwrap_error(self) -> Result<Vec<u8>, prost::DecodeError> {
         match self {
             RoundtripResult::Ok(buf) => Ok(buf),
             RoundtripResult::DecodeError(error) => Err(error),
@@ -63,7 +66,7 @@ impl RoundtripResult {
 
 /// Tests round-tripping a message type. The message should be compiled with `BTreeMap` fields,
 /// otherwise the comparison may fail due to inconsistent `HashMap` entry encoding ordering.
-pub fn roundtrip<M>(data: &[u8]) -> RoundtripResult where M: Message {
+pub fn roundtrip<M>(data: &[u8]) -> RoundtripResult where M: Message + for<'a> Merge<Cursor<&'a [u8]>> {
     // Try to decode a message from the data. If decoding fails, continue.
     let len = data.len();
     let all_types = match M::decode(&mut Buf::take(Cursor::new(data), len)) {
@@ -78,7 +81,7 @@ pub fn roundtrip<M>(data: &[u8]) -> RoundtripResult where M: Message {
 
     let mut buf1 = Vec::new();
     if let Err(error) = all_types.encode(&mut buf1) {
-        return RoundtripResult::Error(error);
+        return RoundtripResult::Error(error.into());
     }
     if encoded_len != buf1.len() {
         return RoundtripResult::error(
@@ -86,14 +89,14 @@ pub fn roundtrip<M>(data: &[u8]) -> RoundtripResult where M: Message {
                     encoded_len, buf1.len()));
     }
 
-    let roundtrip = match M::decode(&mut Buf::take(Cursor::new(&buf1), encoded_len)) {
+    let roundtrip = match M::decode(&mut Buf::take(Cursor::new(&buf1[..]), encoded_len)) {
         Ok(roundtrip) => roundtrip,
-        Err(error) => return RoundtripResult::Error(error),
+        Err(error) => return RoundtripResult::Error(error.into()),
     };
 
     let mut buf2 = Vec::new();
     if let Err(error) = roundtrip.encode(&mut buf2) {
-        return RoundtripResult::Error(error);
+        return RoundtripResult::Error(error.into());
     }
 
     /*
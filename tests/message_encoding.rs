@@ -10,12 +10,29 @@ extern crate env_logger;
 
 use std::io::Cursor;
 
-use bytes::Buf;
+use bytes::{
+    Buf,
+    Bytes,
+};
 
-use prost::Message;
+use prost::{
+    DecodeErrorKind,
+    Enumeration,
+    Extendable,
+    Extension,
+    ExtensionRegistry,
+    JsonMessage,
+    Merge,
+    Message,
+    OpenEnum,
+    TextMessage,
+    UnknownEnumValue,
+    UnknownFields,
+};
+use prost::encoding::DecodeContext;
 
 // Creates a checker function for each field trait.
-fn check_message<M>(msg: M) where M: Message + PartialEq {
+fn check_message<M>(msg: M) where M: Message + for<'a> Merge<Cursor<&'a [u8]>> + PartialEq {
     let expected_len = msg.encoded_len();
 
     let mut buf = Vec::with_capacity(18);
@@ -25,7 +42,7 @@ fn check_message<M>(msg: M) where M: Message + PartialEq {
 
     info!("encoded message: {:?}", buf);
 
-    let mut buf = Cursor::new(&mut buf).take(expected_len);
+    let mut buf = Cursor::new(&buf[..]).take(expected_len);
     let roundtrip = M::decode(&mut buf).unwrap();
 
     if buf.has_remaining() {
@@ -59,7 +76,24 @@ fn check_repeated_floats() {
 #[test]
 fn check_scalar_types() {
     let _ = env_logger::init();
-    let scalar_types = ScalarTypes::default();
+    let scalar_types = ScalarTypes {
+        required_int32: Some(0),
+        required_int64: Some(0),
+        required_uint32: Some(0),
+        required_uint64: Some(0),
+        required_sint32: Some(0),
+        required_sint64: Some(0),
+        required_fixed32: Some(0),
+        required_fixed64: Some(0),
+        required_sfixed32: Some(0),
+        required_sfixed64: Some(0),
+        required_float: Some(0.0),
+        required_double: Some(0.0),
+        required_bool: Some(false),
+        required_string: Some(String::new()),
+        required_bytes: Some(Vec::new()),
+        ..ScalarTypes::default()
+    };
     check_message(scalar_types);
 }
 
@@ -98,35 +132,35 @@ pub struct ScalarTypes {
     pub bytes: Vec<u8>,
 
     #[prost(int32, required, tag="101")]
-    pub required_int32: i32,
+    pub required_int32: Option<i32>,
     #[prost(int64, required, tag="102")]
-    pub required_int64: i64,
+    pub required_int64: Option<i64>,
     #[prost(uint32, required, tag="103")]
-    pub required_uint32: u32,
+    pub required_uint32: Option<u32>,
     #[prost(uint64, required, tag="104")]
-    pub required_uint64: u64,
+    pub required_uint64: Option<u64>,
     #[prost(sint32, required, tag="105")]
-    pub required_sint32: i32,
+    pub required_sint32: Option<i32>,
     #[prost(sint64, required, tag="106")]
-    pub required_sint64: i64,
+    pub required_sint64: Option<i64>,
     #[prost(fixed32, required, tag="107")]
-    pub required_fixed32: u32,
+    pub required_fixed32: Option<u32>,
     #[prost(fixed64, required, tag="108")]
-    pub required_fixed64: u64,
+    pub required_fixed64: Option<u64>,
     #[prost(sfixed32, required, tag="109")]
-    pub required_sfixed32: i32,
+    pub required_sfixed32: Option<i32>,
     #[prost(sfixed64, required, tag="110")]
-    pub required_sfixed64: i64,
+    pub required_sfixed64: Option<i64>,
     #[prost(float, required, tag="111")]
-    pub required_float: f32,
+    pub required_float: Option<f32>,
     #[prost(double, required, tag="112")]
-    pub required_double: f64,
+    pub required_double: Option<f64>,
     #[prost(bool, required, tag="113")]
-    pub required_bool: bool,
+    pub required_bool: Option<bool>,
     #[prost(string, required, tag="114")]
-    pub required_string: String,
+    pub required_string: Option<String>,
     #[prost(bytes, required, tag="115")]
-    pub required_bytes: Vec<u8>,
+    pub required_bytes: Option<Vec<u8>>,
 
     #[prost(int32, optional, tag="201")]
     pub optional_int32: Option<i32>,
@@ -224,6 +258,42 @@ pub struct ScalarTypes {
     pub packed_bytes: Vec<Vec<u8>>,
 }
 
+/// A message which contains a subset of the `ScalarTypes` fields, and retains the rest as
+/// unknown fields.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct ScalarTypesSubset {
+    #[prost(int32, tag="001")]
+    pub int32: i32,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFields,
+}
+
+#[test]
+fn check_unknown_fields() {
+    let mut scalar_types = ScalarTypes::default();
+    scalar_types.int32 = 42;
+    scalar_types.fixed32 = 7;
+    scalar_types.sfixed64 = -99;
+    scalar_types.string = "fourty two".to_owned();
+    scalar_types.optional_bytes = Some(vec![1, 2, 3]);
+    scalar_types.packed_sint64 = vec![-1, 0, 1];
+
+    let mut buf = Vec::new();
+    scalar_types.encode(&mut buf).unwrap();
+
+    let subset = ScalarTypesSubset::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(subset.int32, 42);
+    assert!(!subset.unknown_fields.is_empty());
+    assert!(subset.unknown_fields.iter().all(|field| field.tag != 1));
+
+    let mut roundtrip = Vec::new();
+    subset.encode(&mut roundtrip).unwrap();
+    assert_eq!(subset.encoded_len(), roundtrip.len());
+    assert_eq!(buf, roundtrip);
+
+    check_message(subset);
+}
+
 /// A prost message with default value.
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct DefaultValues {
@@ -244,6 +314,27 @@ pub struct DefaultValues {
 
     #[prost(enumeration="BasicEnumeration", repeated, tag="6")]
     pub repeated_enumeration: Vec<i32>,
+
+    #[prost(sint64, optional, tag="7", default="-42")]
+    pub optional_sint64: Option<i64>,
+
+    #[prost(uint64, optional, tag="8", default="18446744073709551615")]
+    pub optional_uint64: Option<u64>,
+
+    #[prost(float, optional, tag="9", default="-inf")]
+    pub optional_float: Option<f32>,
+
+    #[prost(double, optional, tag="10", default="nan")]
+    pub optional_double: Option<f64>,
+
+    #[prost(bool, optional, tag="11", default="true")]
+    pub optional_bool: Option<bool>,
+
+    #[prost(bytes, optional, tag="12", default="b\"\\x00\\\"\\xff\"")]
+    pub optional_bytes: Option<Vec<u8>>,
+
+    #[prost(double, tag="13", default="-1.5")]
+    pub double: f64,
 }
 
 #[test]
@@ -255,7 +346,213 @@ fn check_default_values() {
     assert_eq!(default.enumeration, BasicEnumeration::ONE as i32);
     assert_eq!(default.optional_enumeration, None);
     assert_eq!(&default.repeated_enumeration, &[]);
+    assert_eq!(default.optional_sint64(), -42);
+    assert_eq!(default.optional_uint64(), u64::max_value());
+    assert_eq!(default.optional_float(), ::std::f32::NEG_INFINITY);
+    assert!(default.optional_double().is_nan());
+    assert_eq!(default.optional_bool(), true);
+    assert_eq!(default.optional_bytes(), b"\0\"\xff");
+    assert_eq!(default.double, -1.5);
+    assert_eq!(0, default.encoded_len());
+}
+
+/// A message which contains `bytes` fields represented as both `Vec<u8>` and `Bytes`.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct BytesFields {
+    #[prost(bytes, tag="1")]
+    pub vec: Vec<u8>,
+    #[prost(bytes="bytes", tag="2")]
+    pub bytes: Bytes,
+    #[prost(bytes="bytes", optional, tag="3")]
+    pub optional_bytes: Option<Bytes>,
+    #[prost(bytes="bytes", repeated, tag="4")]
+    pub repeated_bytes: Vec<Bytes>,
+    #[prost(bytes="bytes", tag="5", default="b\"fourty two\"")]
+    pub default_bytes: Bytes,
+}
+
+#[test]
+fn check_bytes_fields() {
+    let default = BytesFields::default();
+    assert_eq!(&default.default_bytes[..], b"fourty two");
+    assert_eq!(default.optional_bytes(), b"");
+    assert_eq!(0, default.encoded_len());
+
+    let msg = BytesFields {
+        vec: vec![1; 64],
+        bytes: Bytes::from(vec![2; 64]),
+        optional_bytes: Some(Bytes::from(vec![3; 64])),
+        repeated_bytes: vec![Bytes::from(vec![4; 64]), Bytes::new()],
+        default_bytes: Bytes::from(&b"fourty three"[..]),
+    };
+    check_message(msg.clone());
+
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    let buf = Bytes::from(buf);
+    let roundtrip = BytesFields::decode_bytes(&buf).unwrap();
+    assert_eq!(msg, roundtrip);
+
+    // Bytes fields decoded from a Bytes buffer share its memory.
+    let start = buf.as_ptr() as usize;
+    let end = start + buf.len();
+    let ptr = roundtrip.bytes.as_ptr() as usize;
+    assert!(ptr >= start && ptr < end);
+}
+
+/// A message which holds its enumeration fields as `OpenEnum` values.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct OpenEnumFields {
+    #[prost(open_enum="BasicEnumeration", tag="1")]
+    pub plain: OpenEnum<BasicEnumeration>,
+    #[prost(open_enum="BasicEnumeration", optional, tag="2", default="TWO")]
+    pub optional: Option<OpenEnum<BasicEnumeration>>,
+    #[prost(open_enum="BasicEnumeration", repeated, tag="3")]
+    pub repeated: Vec<OpenEnum<BasicEnumeration>>,
+    #[prost(map="string, open_enum(BasicEnumeration)", tag="4")]
+    pub map: ::std::collections::HashMap<String, OpenEnum<BasicEnumeration>>,
+    #[prost(oneof="OpenEnumOneof", tags="5")]
+    pub oneof: Option<OpenEnumOneof>,
+}
+
+#[derive(Clone, Debug, PartialEq, Oneof)]
+pub enum OpenEnumOneof {
+    #[prost(open_enum="BasicEnumeration", tag="5")]
+    Enumeration(OpenEnum<BasicEnumeration>),
+}
+
+/// The same message as `OpenEnumFields`, with its enumeration fields held as `i32` values.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct Int32EnumFields {
+    #[prost(enumeration="BasicEnumeration", tag="1")]
+    pub plain: i32,
+    #[prost(enumeration="BasicEnumeration", optional, tag="2")]
+    pub optional: Option<i32>,
+    #[prost(enumeration="BasicEnumeration", repeated, tag="3")]
+    pub repeated: Vec<i32>,
+    #[prost(map="string, enumeration(BasicEnumeration)", tag="4")]
+    pub map: ::std::collections::HashMap<String, i32>,
+    #[prost(int32, optional, tag="5")]
+    pub oneof: Option<i32>,
+}
+
+#[test]
+fn check_open_enum_fields() {
+    let default = OpenEnumFields::default();
+    assert_eq!(default.plain, BasicEnumeration::ZERO);
+    assert_eq!(default.optional(), OpenEnum::Known(BasicEnumeration::TWO));
+    assert_eq!(0, default.encoded_len());
+
+    let mut msg = OpenEnumFields {
+        plain: OpenEnum::Unknown(7),
+        optional: Some(BasicEnumeration::THREE.into()),
+        repeated: vec![OpenEnum::Known(BasicEnumeration::ONE), OpenEnum::Unknown(-1)],
+        map: ::std::collections::HashMap::new(),
+        oneof: Some(OpenEnumOneof::Enumeration(OpenEnum::Unknown(42))),
+    };
+    msg.map.insert("zero".to_string(), OpenEnum::Known(BasicEnumeration::ZERO));
+    msg.map.insert("unknown".to_string(), OpenEnum::Unknown(9));
+    check_message(msg.clone());
+
+    // Open enumerations are encoded in the same way as `i32` enumeration fields.
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    let int32 = Int32EnumFields::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(int32.plain, 7);
+    assert_eq!(int32.optional, Some(3));
+    assert_eq!(int32.repeated, vec![1, -1]);
+    assert_eq!(int32.map["zero"], 0);
+    assert_eq!(int32.map["unknown"], 9);
+    assert_eq!(int32.oneof, Some(42));
+
+    let mut int32_buf = Vec::new();
+    int32.encode(&mut int32_buf).unwrap();
+    assert_eq!(OpenEnumFields::decode(&mut Cursor::new(&int32_buf[..]).take(int32_buf.len())).unwrap(), msg);
+
+    assert_eq!(msg, OpenEnumFields::decode_json(&msg.encode_json().unwrap()).unwrap());
+    assert_eq!(msg, OpenEnumFields::decode_text(&msg.encode_text()).unwrap());
+    let text = OpenEnumFields::decode_text("plain: THREE repeated: [TWO, 5]").unwrap();
+    assert_eq!(text.plain, OpenEnum::Known(BasicEnumeration::THREE));
+    assert_eq!(text.repeated, vec![OpenEnum::Known(BasicEnumeration::TWO), OpenEnum::Unknown(5)]);
+
+    // A packed field which fails to decode leaves the repeated field unchanged.
+    let mut merged = OpenEnumFields::default();
+    merged.repeated.push(OpenEnum::Known(BasicEnumeration::ONE));
+    let truncated = [0x1A, 0x02, 0x02, 0x80];
+    assert!(merged.merge(&mut Cursor::new(&truncated[..]).take(truncated.len())).is_err());
+    assert_eq!(merged.repeated, vec![OpenEnum::Known(BasicEnumeration::ONE)]);
+}
+
+/// A message which borrows its `string` and `bytes` fields from the buffer it is decoded from.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct BorrowedFields<'a> {
+    #[prost(string, tag="1")]
+    pub name: &'a str,
+    #[prost(bytes, tag="2")]
+    pub payload: &'a [u8],
+    #[prost(string, optional, tag="3", default="anonymous")]
+    pub alias: Option<&'a str>,
+    #[prost(string, repeated, tag="4")]
+    pub tags: Vec<&'a str>,
+    #[prost(message, repeated, tag="5")]
+    pub children: Vec<BorrowedFields<'a>>,
+    #[prost(btree_map="string, bytes", tag="6")]
+    pub attributes: ::std::collections::BTreeMap<&'a str, &'a [u8]>,
+    #[prost(oneof="BorrowedOneof", tags="7, 8")]
+    pub value: Option<BorrowedOneof<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Oneof)]
+pub enum BorrowedOneof<'a> {
+    #[prost(int32, tag="7")]
+    Int(i32),
+    #[prost(string, tag="8")]
+    String(&'a str),
+}
+
+#[test]
+fn check_borrowed_fields() {
+    let default = BorrowedFields::default();
+    assert_eq!(default.alias(), "anonymous");
     assert_eq!(0, default.encoded_len());
+
+    let mut attributes = ::std::collections::BTreeMap::new();
+    attributes.insert("key", &b"value"[..]);
+    let msg = BorrowedFields {
+        name: "foo",
+        payload: &[1; 64],
+        alias: Some("bar"),
+        tags: vec!["a", "", "c"],
+        children: vec![
+            BorrowedFields {
+                name: "child",
+                value: Some(BorrowedOneof::Int(42)),
+                .. BorrowedFields::default()
+            },
+        ],
+        attributes: attributes,
+        value: Some(BorrowedOneof::String("baz")),
+    };
+
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    assert_eq!(buf.len(), msg.encoded_len());
+
+    let roundtrip = BorrowedFields::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(msg, roundtrip);
+
+    // String and bytes fields point into the encoded buffer.
+    let start = buf.as_ptr() as usize;
+    let end = start + buf.len();
+    for ptr in &[roundtrip.name.as_ptr(), roundtrip.payload.as_ptr(), roundtrip.children[0].name.as_ptr()] {
+        assert!(*ptr as usize >= start && (*ptr as usize) < end);
+    }
+
+    let mut invalid = Vec::new();
+    BorrowedFields { payload: b"\xFF", .. BorrowedFields::default() }.encode(&mut invalid).unwrap();
+    invalid[0] = (1 << 3) | 2;
+    let error = BorrowedFields::decode(&mut Cursor::new(&invalid[..]).take(invalid.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::InvalidUtf8);
 }
 
 /// A protobuf enum.
@@ -267,6 +564,36 @@ pub enum BasicEnumeration {
     THREE = 3,
 }
 
+/// A protobuf enum with variants renamed from their Protobuf names.
+#[derive(Clone, Copy, Debug, PartialEq, Enumeration)]
+pub enum RenamedEnumeration {
+    Unspecified = 0,
+    FooBar = 2,
+    #[prost(name="baz_1")]
+    Baz1 = 1,
+}
+
+#[test]
+fn check_enumeration_names() {
+    use std::convert::TryFrom;
+
+    assert_eq!(RenamedEnumeration::FooBar.as_str_name(), "FOO_BAR");
+    assert_eq!(RenamedEnumeration::Baz1.as_str_name(), "baz_1");
+    assert_eq!(RenamedEnumeration::from_str_name("FOO_BAR"), Some(RenamedEnumeration::FooBar));
+    assert_eq!(RenamedEnumeration::from_str_name("FooBar"), None);
+
+    assert_eq!(RenamedEnumeration::Unspecified.to_string(), "UNSPECIFIED");
+    assert_eq!("baz_1".parse(), Ok(RenamedEnumeration::Baz1));
+    assert_eq!("BAZ_1".parse::<RenamedEnumeration>(),
+               Err(UnknownEnumValue::Name("BAZ_1".to_string())));
+
+    assert_eq!(RenamedEnumeration::values().collect::<Vec<_>>(),
+               vec![RenamedEnumeration::Unspecified, RenamedEnumeration::FooBar, RenamedEnumeration::Baz1]);
+
+    assert_eq!(RenamedEnumeration::try_from(2), Ok(RenamedEnumeration::FooBar));
+    assert_eq!(RenamedEnumeration::try_from(3), Err(UnknownEnumValue::Value(3)));
+}
+
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct Basic {
     #[prost(int32, tag="1")]
@@ -306,7 +633,7 @@ pub struct Compound {
     pub optional_message: Option<Basic>,
 
     #[prost(message, required, tag="2")]
-    pub required_message: Basic,
+    pub required_message: Option<Basic>,
 
     #[prost(message, repeated, tag="3")]
     pub repeated_message: Vec<Basic>,
@@ -318,6 +645,332 @@ pub struct Compound {
     pub message_btree_map: ::std::collections::BTreeMap<i32, Basic>,
 }
 
+#[derive(Clone, Debug, PartialEq, Oneof)]
+pub enum MessageOneof {
+    #[prost(message, tag="1")]
+    Message(Basic),
+    #[prost(int32, tag="2")]
+    Int(i32),
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct MessageOneofs {
+    #[prost(oneof="MessageOneof", tags="1, 2")]
+    pub oneof: Option<MessageOneof>,
+}
+
+/// A recursive message, which refers to itself through a boxed oneof variant.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct RecursiveOneof {
+    #[prost(int32, tag="1")]
+    pub depth: i32,
+    #[prost(oneof="RecursiveOneofKind", tags="2, 3")]
+    pub kind: Option<RecursiveOneofKind>,
+}
+
+#[derive(Clone, Debug, PartialEq, Oneof)]
+pub enum RecursiveOneofKind {
+    #[prost(message, boxed, tag="2")]
+    Child(Box<RecursiveOneof>),
+    #[prost(string, tag="3")]
+    Name(String),
+}
+
+#[test]
+fn check_oneof_merge() {
+    let first = MessageOneofs { oneof: Some(MessageOneof::Message(Basic { int32: 1, ..Basic::default() })) };
+    let second = MessageOneofs {
+        oneof: Some(MessageOneof::Message(Basic { string: "b".to_string(), ..Basic::default() })),
+    };
+
+    // Repeated occurrences of a message variant are merged.
+    let mut buf = Vec::new();
+    first.encode(&mut buf).unwrap();
+    second.encode(&mut buf).unwrap();
+    let merged = MessageOneofs::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(merged, MessageOneofs {
+        oneof: Some(MessageOneof::Message(Basic { int32: 1, string: "b".to_string(), ..Basic::default() })),
+    });
+
+    // A different variant replaces the existing value.
+    MessageOneofs { oneof: Some(MessageOneof::Int(2)) }.encode(&mut buf).unwrap();
+    let merged = MessageOneofs::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(merged.oneof, Some(MessageOneof::Int(2)));
+
+    let recursive = RecursiveOneof {
+        depth: 0,
+        kind: Some(RecursiveOneofKind::Child(Box::new(RecursiveOneof {
+            depth: 1,
+            kind: Some(RecursiveOneofKind::Name("leaf".to_string())),
+        }))),
+    };
+    check_message(recursive.clone());
+    assert_eq!(recursive, RecursiveOneof::decode_json(&recursive.encode_json().unwrap()).unwrap());
+    assert_eq!(recursive, RecursiveOneof::decode_text(&recursive.encode_text()).unwrap());
+}
+
+#[test]
+fn check_merge_from() {
+    // Checks that `merge_from` has the same result as merging the encoded message.
+    fn check<M>(msg: M, other: M) where M: Message + for<'a> Merge<Cursor<&'a [u8]>> + Clone {
+        let mut merged = msg.clone();
+        merged.merge_from(&other);
+
+        let mut buf = Vec::new();
+        other.encode(&mut buf).unwrap();
+        let mut expected = msg;
+        expected.merge(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+        assert_eq!(merged, expected);
+    }
+
+    let mut a = Basic::default();
+    a.int32 = 1;
+    a.bools = vec![true];
+    a.string = "a".to_string();
+    a.enumeration = BasicEnumeration::TWO as i32;
+    a.enumeration_map.insert(1, BasicEnumeration::ONE as i32);
+    a.string_btree_map.insert("a".to_string(), "a".to_string());
+    a.oneof = Some(BasicOneof::Int(3));
+
+    let mut b = Basic::default();
+    b.bools = vec![false, true];
+    b.string = "b".to_string();
+    b.optional_string = Some("b".to_string());
+    b.enumeration_map.insert(1, BasicEnumeration::THREE as i32);
+    b.enumeration_map.insert(2, BasicEnumeration::TWO as i32);
+    b.oneof = Some(BasicOneof::String("b".to_string()));
+
+    check(a.clone(), b.clone());
+    check(b.clone(), a.clone());
+    check(a.clone(), Basic::default());
+
+    let mut compound_a = Compound::default();
+    compound_a.optional_message = Some(a.clone());
+    compound_a.required_message = Some(a.clone());
+    compound_a.repeated_message = vec![a.clone()];
+    compound_a.message_map.insert(1, a.clone());
+
+    let mut compound_b = Compound::default();
+    compound_b.optional_message = Some(b.clone());
+    compound_b.required_message = Some(b.clone());
+    compound_b.repeated_message = vec![b.clone(), a.clone()];
+    compound_b.message_map.insert(1, b.clone());
+    compound_b.message_btree_map.insert(2, b.clone());
+
+    check(compound_a.clone(), compound_b.clone());
+    check(Compound::default(), compound_b.clone());
+
+    let message = |msg: &Basic| MessageOneofs { oneof: Some(MessageOneof::Message(msg.clone())) };
+    let int = MessageOneofs { oneof: Some(MessageOneof::Int(4)) };
+    check(message(&a), message(&b));
+    check(message(&a), int.clone());
+    check(int.clone(), message(&b));
+
+    // Matching message variants are merged rather than replaced.
+    let mut merged = message(&a);
+    merged.merge_from(&message(&b));
+    match merged.oneof {
+        Some(MessageOneof::Message(ref msg)) => assert_eq!(msg.int32, 1),
+        ref oneof => panic!("unexpected oneof: {:?}", oneof),
+    }
+
+    // Clearing a message resets its fields, and keeps the capacity of its repeated fields.
+    compound_b.clear();
+    assert_eq!(compound_b, Compound::default());
+    assert!(compound_b.repeated_message.capacity() >= 2);
+    let mut cleared = a.clone();
+    cleared.clear();
+    assert_eq!(cleared, Basic::default());
+    assert!(cleared.string.capacity() > 0);
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct RequiredName {
+    #[prost(string, required, tag="1")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Oneof)]
+pub enum RequiredOneof {
+    #[prost(message, tag="6")]
+    Message(RequiredName),
+    #[prost(int32, tag="7")]
+    Int(i32),
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct RequiredFields {
+    #[prost(int32, required, tag="1")]
+    pub id: Option<i32>,
+    #[prost(message, required, tag="2")]
+    pub required_message: Option<RequiredName>,
+    #[prost(message, optional, tag="3")]
+    pub optional_message: Option<RequiredName>,
+    #[prost(message, repeated, tag="4")]
+    pub repeated_message: Vec<RequiredName>,
+    #[prost(btree_map="string, message", tag="5")]
+    pub message_map: ::std::collections::BTreeMap<String, RequiredName>,
+    #[prost(oneof="RequiredOneof", tags="6, 7")]
+    pub oneof: Option<RequiredOneof>,
+}
+
+#[test]
+fn check_required_fields() {
+    let decode = |buf: &[u8]| RequiredFields::decode(&mut Cursor::new(buf).take(buf.len()));
+    let decode_partial = |buf: &[u8]| RequiredFields::decode_partial(&mut Cursor::new(buf).take(buf.len()));
+    let missing = |paths: &[&str]| {
+        DecodeErrorKind::MissingRequiredFields(paths.iter().map(|path| path.to_string()).collect())
+    };
+
+    let error = decode(&[]).unwrap_err();
+    assert_eq!(error.kind(), &missing(&["id", "required_message"]));
+    assert_eq!(error.to_string(),
+               "failed to decode Protobuf message: missing required fields: id, required_message");
+    assert_eq!(decode_partial(&[]).unwrap(), RequiredFields::default());
+    assert!(!RequiredFields::default().is_initialized());
+
+    let set = RequiredName { name: Some("a".to_string()) };
+    let unset = RequiredName::default();
+    let mut msg = RequiredFields {
+        id: Some(0),
+        required_message: Some(set.clone()),
+        optional_message: Some(unset.clone()),
+        repeated_message: vec![set.clone(), unset.clone()],
+        message_map: ::std::collections::BTreeMap::new(),
+        oneof: Some(RequiredOneof::Message(unset.clone())),
+    };
+    msg.message_map.insert("a".to_string(), unset.clone());
+    msg.message_map.insert("b".to_string(), set.clone());
+
+    // Missing fields of nested, repeated, map and oneof messages are reported by path.
+    let expected = missing(&["optional_message.name",
+                             "repeated_message[1].name",
+                             "message_map[\"a\"].name",
+                             "message.name"]);
+    assert!(!msg.is_initialized());
+    assert_eq!(msg.check_initialized().unwrap_err().kind(), &expected);
+
+    // Unset required fields are not encoded, so the message only decodes partially.
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    assert_eq!(decode(&buf).unwrap_err().kind(), &expected);
+    assert_eq!(decode_partial(&buf).unwrap(), msg);
+
+    msg.optional_message = Some(set.clone());
+    msg.repeated_message[1] = set.clone();
+    msg.message_map.insert("a".to_string(), set.clone());
+    msg.oneof = Some(RequiredOneof::Int(0));
+    assert!(msg.is_initialized());
+    assert_eq!(msg.check_initialized(), Ok(()));
+    check_message(msg);
+}
+
+#[test]
+fn check_decode_error() {
+    // Compound.required_message.string contains invalid UTF-8.
+    let buf = [0x12, 0x04, 0x1A, 0x02, 0xC3, 0x28];
+    let error = Compound::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::InvalidUtf8);
+    assert_eq!(error.stack(), &[("Basic", "string"), ("Compound", "required_message")]);
+    assert_eq!(error.offset(), Some(6));
+    assert_eq!(error.to_string(),
+               "failed to decode Protobuf message: Compound.required_message: Basic.string: \
+                invalid string value: data is not UTF-8 encoded (at byte offset 6)");
+
+    // Compound.required_message is truncated.
+    let buf = [0x12, 0x05, 0x08, 0x01];
+    let error = Compound::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::BufferUnderflow);
+    assert_eq!(error.stack(), &[("Compound", "required_message")]);
+    assert_eq!(error.offset(), Some(2));
+
+    let error = ::std::io::Error::from(error);
+    assert_eq!(error.kind(), ::std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn check_encode_error() {
+    let mut basic = Basic::default();
+    basic.string = "fourty two".to_owned();
+
+    let mut buf = [0u8; 4];
+    let error = basic.encode(&mut Cursor::new(&mut buf[..])).unwrap_err();
+    assert_eq!(error.required_capacity(), 12);
+    assert_eq!(error.remaining(), 4);
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct Recursive {
+    #[prost(message, optional, tag="1")]
+    pub child: Option<Box<Recursive>>,
+}
+
+/// Returns an encoded `Recursive` message with `depth` levels of nested children.
+fn encode_recursive(depth: usize) -> Vec<u8> {
+    let mut msg = Recursive::default();
+    for _ in 0..depth {
+        msg = Recursive { child: Some(Box::new(msg)) };
+    }
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn check_recursion_limit() {
+    let buf = encode_recursive(100);
+    Recursive::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+
+    let buf = encode_recursive(101);
+    let error = Recursive::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::RecursionLimitReached);
+    assert_eq!(error.stack().len(), 101);
+
+    let ctx = DecodeContext::with_recursion_limit(101);
+    Recursive::decode_with_context(&mut Cursor::new(&buf[..]).take(buf.len()), ctx).unwrap();
+
+    let buf = encode_recursive(5);
+    let ctx = DecodeContext::with_recursion_limit(4);
+    let error = Recursive::decode_with_context(&mut Cursor::new(&buf[..]).take(buf.len()), ctx)
+                          .unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::RecursionLimitReached);
+}
+
+#[test]
+fn check_nested_encoding() {
+    // Each level of a `Recursive` message is a length-delimited field 1 containing its child.
+    let mut expected = Vec::new();
+    for _ in 0..200 {
+        let mut level = vec![0x0A];
+        prost::encoding::encode_varint(expected.len() as u64, &mut level);
+        level.extend_from_slice(&expected);
+        expected = level;
+    }
+    let buf = encode_recursive(200);
+    assert_eq!(buf, expected);
+
+    let mut basic = Basic::default();
+    basic.string = "foo".to_owned();
+    let mut compound = Compound {
+        optional_message: Some(basic.clone()),
+        required_message: Some(Basic::default()),
+        repeated_message: vec![Basic::default(), basic.clone()],
+        message_map: ::std::collections::HashMap::new(),
+        message_btree_map: ::std::collections::BTreeMap::new(),
+    };
+    compound.message_map.insert(1, basic.clone());
+    compound.message_map.insert(2, Basic::default());
+    compound.message_btree_map.insert(0, basic.clone());
+    compound.message_btree_map.insert(3, Basic::default());
+    check_message(compound.clone());
+
+    let mut raw = Vec::new();
+    compound.encode_raw(&mut raw);
+    let mut buf = Vec::new();
+    compound.encode(&mut buf).unwrap();
+    assert_eq!(raw, buf);
+}
+
 #[derive(Clone, Debug, PartialEq, Oneof)]
 pub enum BasicOneof {
     #[prost(int32, tag="8")]
@@ -325,3 +978,305 @@ pub enum BasicOneof {
     #[prost(string, tag="9")]
     String(String),
 }
+
+/// A proto2 message containing group fields.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct Groups {
+    #[prost(group, optional, tag="1")]
+    pub optional_group: Option<Basic>,
+    #[prost(group, repeated, tag="2")]
+    pub repeated_group: Vec<Basic>,
+    #[prost(int32, tag="3")]
+    pub int32: i32,
+}
+
+/// A message which retains the groups of `Groups` as unknown fields.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct GroupsSubset {
+    #[prost(int32, tag="3")]
+    pub int32: i32,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFields,
+}
+
+/// A message which skips the groups of `Groups`.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct GroupsSkipped {
+    #[prost(int32, tag="3")]
+    pub int32: i32,
+}
+
+#[test]
+fn check_groups() {
+    let mut basic = Basic::default();
+    basic.int32 = 42;
+    basic.string = "fourty two".to_owned();
+    basic.oneof = Some(BasicOneof::Int(7));
+
+    let groups = Groups {
+        optional_group: Some(basic.clone()),
+        repeated_group: vec![Basic::default(), basic],
+        int32: 99,
+    };
+
+    let mut buf = Vec::new();
+    groups.encode(&mut buf).unwrap();
+    assert_eq!(buf[0], 0x0B);
+    check_message(groups.clone());
+
+    let subset = GroupsSubset::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(subset.int32, 99);
+    assert_eq!(subset.unknown_fields.len(), 3);
+    let mut roundtrip = Vec::new();
+    subset.encode(&mut roundtrip).unwrap();
+    assert_eq!(subset.encoded_len(), roundtrip.len());
+    assert_eq!(Groups::decode(&mut Cursor::new(&roundtrip[..]).take(roundtrip.len())).unwrap(),
+               groups);
+
+    let skipped = GroupsSkipped::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert_eq!(skipped.int32, 99);
+
+    // Groups.optional_group is terminated by the end group key of a different field.
+    let buf = [0x0B, 0x08, 0x01, 0x14];
+    let error = Groups::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::UnexpectedEndGroup(2));
+    assert_eq!(error.stack(), &[("Groups", "optional_group")]);
+
+    // Unmatched end group key.
+    let buf = [0x0C];
+    let error = GroupsSkipped::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap_err();
+    assert_eq!(error.kind(), &DecodeErrorKind::UnexpectedEndGroup(1));
+}
+
+/// A proto2 message which declares an extension range.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct Extended {
+    #[prost(int32, optional, tag="1")]
+    pub int32: Option<i32>,
+    #[prost(extension_set)]
+    pub extensions: prost::ExtensionSet,
+    #[prost(unknown_fields)]
+    pub unknown_fields: UnknownFields,
+}
+
+pub const SCALAR_EXTENSION: Extension<Extended, i32> = Extension {
+    name: "test.scalar_extension",
+    tag: 100,
+    encode: |tag, value, buf| prost::encoding::sint32::encode(tag, value, buf),
+    merge: |wire_type, value, buf, ctx| prost::encoding::sint32::merge(wire_type, value, buf, ctx),
+    extendee: ::std::marker::PhantomData,
+};
+
+pub const MESSAGE_EXTENSION: Extension<Extended, Vec<Basic>> = Extension {
+    name: "test.message_extension",
+    tag: 101,
+    encode: |tag, value, buf| prost::encoding::message::encode_repeated(tag, value, buf),
+    merge: |wire_type, value, buf, ctx| prost::encoding::message::merge_repeated(wire_type, value, buf, ctx),
+    extendee: ::std::marker::PhantomData,
+};
+
+pub const GROUP_EXTENSION: Extension<Extended, Basic> = Extension {
+    name: "test.group_extension",
+    tag: 102,
+    encode: |tag, value, buf| prost::encoding::group::encode(tag, value, buf),
+    merge: |wire_type, value, buf, ctx| prost::encoding::group::merge(102, wire_type, value, buf, ctx),
+    extendee: ::std::marker::PhantomData,
+};
+
+#[test]
+fn check_extensions() {
+    let mut basic = Basic::default();
+    basic.string = "fourty two".to_owned();
+
+    let mut msg = Extended::default();
+    msg.int32 = Some(42);
+    msg.set_extension(&SCALAR_EXTENSION, -7);
+    msg.set_extension(&MESSAGE_EXTENSION, vec![basic.clone(), Basic::default()]);
+    msg.set_extension(&GROUP_EXTENSION, basic.clone());
+    assert_eq!(msg.get_extension(&SCALAR_EXTENSION), Ok(Some(-7)));
+    assert_eq!(msg.get_extension(&MESSAGE_EXTENSION), Ok(Some(vec![basic.clone(), Basic::default()])));
+    assert_eq!(msg.get_extension(&GROUP_EXTENSION), Ok(Some(basic.clone())));
+
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    assert_eq!(msg.encoded_len(), buf.len());
+
+    // Without a registry, extension fields are retained as unknown fields.
+    let unregistered = Extended::decode(&mut Cursor::new(&buf[..]).take(buf.len())).unwrap();
+    assert!(!unregistered.has_extension(&SCALAR_EXTENSION));
+    assert_eq!(unregistered.unknown_fields.len(), 4);
+
+    let mut registry = ExtensionRegistry::new();
+    registry.register(&SCALAR_EXTENSION);
+    registry.register(&MESSAGE_EXTENSION);
+    registry.register(&GROUP_EXTENSION);
+    let ctx = DecodeContext::default().with_extension_registry(&registry);
+    let roundtrip = Extended::decode_with_context(&mut Cursor::new(&buf[..]).take(buf.len()), ctx)
+                             .unwrap();
+    assert!(roundtrip.unknown_fields.is_empty());
+    assert_eq!(roundtrip, msg);
+
+    let mut reencoded = Vec::new();
+    roundtrip.encode(&mut reencoded).unwrap();
+    assert_eq!(buf, reencoded);
+
+    msg.clear_extension(&MESSAGE_EXTENSION);
+    assert!(!msg.has_extension(&MESSAGE_EXTENSION));
+    assert_eq!(msg.get_extension(&MESSAGE_EXTENSION), Ok(None));
+}
+
+/// A message with field names which differ from the defaults.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct JsonNames {
+    #[prost(int32, name="type", tag="1")]
+    pub type_: i32,
+    #[prost(string, json_name="FOO", tag="2")]
+    pub foo_bar: String,
+}
+
+#[test]
+fn check_json() {
+    let mut basic = Basic::default();
+    assert_eq!("{}", basic.encode_json().unwrap());
+
+    basic.int32 = -1;
+    basic.bools = vec![true, false];
+    basic.optional_string = Some(String::new());
+    basic.enumeration = BasicEnumeration::TWO as i32;
+    basic.enumeration_btree_map.insert(1, BasicEnumeration::ONE as i32);
+    basic.enumeration_btree_map.insert(2, 42);
+    basic.oneof = Some(BasicOneof::Int(0));
+    let json = basic.encode_json().unwrap();
+    assert_eq!(json, r#"{"int32":-1,"bools":[true,false],"optionalString":"","enumeration":"TWO","int":0,"enumerationBtreeMap":{"1":"ONE","2":42}}"#);
+    assert_eq!(basic, Basic::decode_json(&json).unwrap());
+
+    // Original field names, enumeration numbers and quoted integers are accepted.
+    let basic = Basic::decode_json(r#"{"optional_string": "a", "enumeration": 3, "int32": "7"}"#).unwrap();
+    assert_eq!(basic.optional_string, Some("a".to_string()));
+    assert_eq!(basic.enumeration(), Some(BasicEnumeration::THREE));
+    assert_eq!(basic.int32, 7);
+
+    // A null oneof field leaves the oneof unset.
+    assert_eq!(Basic::decode_json(r#"{"int": null}"#).unwrap().oneof, None);
+
+    for json in &[r#"{"unknown":1}"#,
+                  r#"{"int32":1,"int32":2}"#,
+                  r#"{"optionalString":"a","optional_string":"b"}"#,
+                  r#"{"int":1,"int":2}"#,
+                  r#"{"enumeration":"FOUR"}"#,
+                  r#"{"bools":true}"#,
+                  r#"[]"#] {
+        assert!(Basic::decode_json(json).is_err(), "{}", json);
+    }
+
+    let error = Compound::decode_json(r#"{"requiredMessage":{"int32":"x"}}"#).unwrap_err();
+    assert_eq!(error.stack(), &[("Basic", "int32"), ("Compound", "required_message")]);
+
+    let names = JsonNames { type_: 1, foo_bar: "x".to_string() };
+    assert_eq!(r#"{"type":1,"FOO":"x"}"#, names.encode_json().unwrap());
+    assert_eq!(names, JsonNames::decode_json(r#"{"type":1,"foo_bar":"x"}"#).unwrap());
+    assert!(JsonNames::decode_json(r#"{"fooBar":"x"}"#).is_err());
+
+    assert_eq!(BasicEnumeration::value_name(1), Some("ONE"));
+    assert_eq!(BasicEnumeration::value_name(4), None);
+    assert_eq!(BasicEnumeration::from_value_name("THREE"), Some(3));
+    assert_eq!(BasicEnumeration::from_value_name("three"), None);
+}
+
+#[test]
+fn check_text() {
+    let mut compound = Compound::default();
+    assert_eq!("", compound.encode_text());
+    compound.required_message = Some(Basic::default());
+    assert_eq!("required_message {\n}\n", compound.encode_text());
+
+    let mut basic = Basic::default();
+    basic.int32 = -1;
+    basic.bools = vec![true, false];
+    basic.optional_string = Some("a\"\n\u{0}".to_string());
+    basic.enumeration = BasicEnumeration::TWO as i32;
+    basic.enumeration_btree_map.insert(1, BasicEnumeration::ONE as i32);
+    basic.enumeration_btree_map.insert(2, 42);
+    basic.oneof = Some(BasicOneof::Int(0));
+    compound.optional_message = Some(basic.clone());
+    compound.repeated_message = vec![Basic::default(), basic];
+    compound.message_btree_map.insert(-3, Basic::default());
+
+    let text = compound.encode_text();
+    assert_eq!(text, "\
+optional_message {
+  int32: -1
+  bools: true
+  bools: false
+  optional_string: \"a\\\"\\n\\000\"
+  enumeration: TWO
+  int: 0
+  enumeration_btree_map {
+    key: 1
+    value: ONE
+  }
+  enumeration_btree_map {
+    key: 2
+    value: 42
+  }
+}
+required_message {
+}
+repeated_message {
+}
+repeated_message {
+  int32: -1
+  bools: true
+  bools: false
+  optional_string: \"a\\\"\\n\\000\"
+  enumeration: TWO
+  int: 0
+  enumeration_btree_map {
+    key: 1
+    value: ONE
+  }
+  enumeration_btree_map {
+    key: 2
+    value: 42
+  }
+}
+message_btree_map {
+  key: -3
+  value {
+  }
+}
+");
+    assert_eq!(compound, Compound::decode_text(&text).unwrap());
+
+    // Alternative syntax is accepted, and repeated fields may be given as lists.
+    let basic = Basic::decode_text("int32: 0x10, bools: [t, 0]; enumeration: 3 # comment
+                                    string_map < key: 'a' > string: 'b' \"c\"").unwrap();
+    assert_eq!(basic.int32, 16);
+    assert_eq!(basic.bools, vec![true, false]);
+    assert_eq!(basic.enumeration(), Some(BasicEnumeration::THREE));
+    assert_eq!(basic.string_map.get("a"), Some(&String::new()));
+    assert_eq!(basic.string, "bc");
+
+    // Merging appends repeated fields and replaces singular fields.
+    let mut merged = basic.clone();
+    merged.merge_text("bools: true int32: 1").unwrap();
+    assert_eq!(merged.bools, vec![true, false, true]);
+    assert_eq!(merged.int32, 1);
+
+    for text in &["unknown: 1",
+                  "int32: 1 int32: 2",
+                  "int: 1 string: \"a\" int: 2",
+                  "enumeration: FOUR",
+                  "int32: \"1\"",
+                  "optional_string { }",
+                  "int32 1"] {
+        assert!(Basic::decode_text(text).is_err(), "{}", text);
+    }
+
+    let error = Compound::decode_text("required_message { int32: x }").unwrap_err();
+    assert_eq!(error.stack(), &[("Basic", "int32"), ("Compound", "required_message")]);
+
+    let names = JsonNames { type_: 1, foo_bar: "x".to_string() };
+    assert_eq!("type: 1\nfoo_bar: \"x\"\n", names.encode_text());
+    assert_eq!(names, JsonNames::decode_text("type: 1 foo_bar: 'x'").unwrap());
+}